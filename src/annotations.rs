@@ -0,0 +1,546 @@
+// PDF annotations (sticky notes, markup comments and their review replies), read through MuPDF so
+// their text can be shown in a popover and listed in the comments sidebar. MuPDF already paints the
+// appearance streams; this only surfaces what the appearance can't show. Rects are page-local
+// top-left points, matching MuPDF's coordinate space.
+
+use gtk::prelude::*;
+use mupdf::pdf::{PdfAnnotationType, PdfPage};
+use mupdf::Document;
+
+use crate::markup::{Edit, MarkupKind, Stroke};
+use crate::page::Rectangle;
+
+// Replies chained deeper than this are treated as top-level (guards against /IRT cycles).
+const MAX_REPLY_DEPTH: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    // 0-based page the annotation sits on
+    pub page: i32,
+    pub kind: &'static str,
+    pub author: Option<String>,
+    pub contents: String,
+    // modification date as "YYYY-MM-DD HH:MM:SS", or the raw /M string if it doesn't parse
+    pub modified: Option<String>,
+    pub rect: Rectangle,
+    // the marked-up areas of a text markup annotation (highlight, underline, ...); empty otherwise
+    pub quads: Vec<Rectangle>,
+    // annotations whose /IRT points (directly or through other replies) at this one, in page order
+    pub replies: Vec<Annotation>,
+}
+
+impl Annotation {
+    // Whether there is anything to show beyond the painted appearance.
+    pub fn has_comment(&self) -> bool {
+        !self.contents.trim().is_empty() || !self.replies.is_empty()
+    }
+}
+
+// A saved ink annotation, for the eraser to hit.
+#[derive(Debug, Clone)]
+pub struct SavedInk {
+    pub xref: i32,
+    pub rect: Rectangle,
+    // at the annotation's line width; the eraser doesn't care about their colour
+    pub strokes: Vec<Stroke>,
+}
+
+#[derive(Default, Debug)]
+pub struct Annotations {
+    current_page: i32,
+    loaded: bool,
+    annotations: Vec<Annotation>,
+}
+
+impl Annotations {
+    pub(crate) fn clear(&mut self) {
+        self.annotations.clear();
+        self.loaded = false;
+        self.current_page = -1;
+    }
+
+    // Commented annotation at (x, y) in page-local top-left points, loading this page's annotations
+    // on first hit. The topmost (last painted) annotation wins where they overlap.
+    pub fn get_annotation(
+        &mut self,
+        uri: &str,
+        page_num: i32,
+        x: f64,
+        y: f64,
+    ) -> Option<&Annotation> {
+        if !self.loaded || page_num != self.current_page {
+            self.annotations =
+                crate::mupdf_render::with_doc(uri, |doc| Some(page_annotations(doc, page_num)))
+                    .unwrap_or_default();
+            self.current_page = page_num;
+            self.loaded = true;
+        }
+        self.annotations
+            .iter()
+            .rev()
+            .find(|annot| annot.has_comment() && annot.rect.contains(x, y))
+    }
+}
+
+// Every commented annotation in the document, in page order, for the comments sidebar.
+pub fn comments(uri: &str) -> Vec<Annotation> {
+    all(uri)
+        .into_iter()
+        .filter(Annotation::has_comment)
+        .collect()
+}
+
+// Every top-level annotation in the document, in page order.
+pub fn all(uri: &str) -> Vec<Annotation> {
+    crate::mupdf_render::with_doc(uri, |doc| {
+        let n_pages = doc.page_count().ok()?;
+        Some(
+            (0..n_pages)
+                .flat_map(|page_num| page_annotations(doc, page_num))
+                .collect(),
+        )
+    })
+    .unwrap_or_default()
+}
+
+// The saved ink annotations on a page.
+pub fn saved_ink(uri: &str, page_num: i32) -> Vec<SavedInk> {
+    crate::mupdf_render::with_doc(uri, |doc| {
+        if !doc.is_pdf() {
+            return None;
+        }
+        let page = PdfPage::try_from(doc.load_page(page_num).ok()?).ok()?;
+        let ink = page
+            .annotations()
+            .filter(|annot| {
+                annot
+                    .r#type()
+                    .is_ok_and(|kind| kind == PdfAnnotationType::Ink)
+            })
+            .filter_map(|annot| {
+                let width = f64::from(annot.border_width().ok()?);
+                let strokes = annot
+                    .ink_list()
+                    .ok()?
+                    .into_iter()
+                    .map(|points| Stroke {
+                        color: 0,
+                        width,
+                        points: points
+                            .iter()
+                            .map(|p| (f64::from(p.x), f64::from(p.y), 1.0))
+                            .collect(),
+                    })
+                    .collect();
+                let b = annot.bounds().ok()?;
+                Some(SavedInk {
+                    xref: annot.xref().ok()?,
+                    rect: Rectangle::new(b.x0 as f64, b.y0 as f64, b.x1 as f64, b.y1 as f64),
+                    strokes,
+                })
+            })
+            .collect();
+        Some(ink)
+    })
+    .unwrap_or_default()
+}
+
+// `saved` with the annotations this session's unsaved `pending` edits will make, in page order, for
+// readers of annotations (notes export, printing) to take in what isn't saved yet.
+pub fn with_pending(mut saved: Vec<Annotation>, pending: &[Edit]) -> Vec<Annotation> {
+    saved.extend(pending.iter().filter_map(from_edit));
+    // stable: a page's saved annotations stay ahead of its new ones
+    saved.sort_by_key(|annot| annot.page);
+    saved
+}
+
+// The annotation saving `edit` makes. Ink strokes and erasures carry no text and are left out.
+fn from_edit(edit: &Edit) -> Option<Annotation> {
+    let (kind, contents, rect, quads) = match edit {
+        Edit::Markup { kind, rects, .. } => {
+            let kind = match kind {
+                MarkupKind::Highlight => "Highlight",
+                MarkupKind::Underline => "Underline",
+                MarkupKind::StrikeOut => "Strikeout",
+            };
+            let rect = rects.iter().copied().reduce(|a, b| {
+                Rectangle::new(
+                    a.x1.min(b.x1),
+                    a.y1.min(b.y1),
+                    a.x2.max(b.x2),
+                    a.y2.max(b.y2),
+                )
+            })?;
+            (kind, String::new(), rect, rects.clone())
+        }
+        Edit::Note { x, y, text, .. } => {
+            let size = crate::markup::NOTE_SIZE;
+            let rect = Rectangle::new(*x, *y, x + size, y + size);
+            ("Note", text.clone(), rect, Vec::new())
+        }
+        Edit::Ink { .. } | Edit::Erase { .. } => return None,
+    };
+    Some(Annotation {
+        page: edit.page(),
+        kind,
+        author: None,
+        contents,
+        modified: None,
+        rect,
+        quads,
+        replies: Vec::new(),
+    })
+}
+
+// Top-level annotations of a page with their replies folded in. Links, form widgets and popups are
+// left out: they are handled elsewhere or only carry their parent's text.
+fn page_annotations(doc: &Document, page_num: i32) -> Vec<Annotation> {
+    if !doc.is_pdf() {
+        return Vec::new();
+    }
+    let Ok(page) = doc.load_page(page_num) else {
+        return Vec::new();
+    };
+    let Ok(page) = PdfPage::try_from(page) else {
+        return Vec::new();
+    };
+
+    // (own xref, /IRT xref, annotation)
+    let mut entries = Vec::new();
+    for annot in page.annotations() {
+        let Ok(kind) = annot.r#type() else {
+            continue;
+        };
+        if matches!(
+            kind,
+            PdfAnnotationType::Link | PdfAnnotationType::Popup | PdfAnnotationType::Widget
+        ) {
+            continue;
+        }
+        let Ok(b) = annot.bounds() else {
+            continue;
+        };
+        let quads = match kind {
+            PdfAnnotationType::Highlight
+            | PdfAnnotationType::Underline
+            | PdfAnnotationType::Squiggly
+            | PdfAnnotationType::StrikeOut => annot
+                .quad_points()
+                .unwrap_or_default()
+                .iter()
+                .map(quad_rect)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let obj = annot.object();
+        let irt = obj
+            .get_dict("IRT")
+            .ok()
+            .flatten()
+            .and_then(|irt| irt.as_indirect().ok())
+            .filter(|&xref| xref > 0);
+        let modified = obj
+            .get_dict("M")
+            .ok()
+            .flatten()
+            .and_then(|m| m.as_string().ok())
+            .map(|m| format_pdf_date(&m));
+        entries.push((
+            annot.xref().unwrap_or(0),
+            irt,
+            Annotation {
+                page: page_num,
+                kind: kind_name(kind),
+                author: annot.author().ok().flatten().map(str::to_owned),
+                contents: annot
+                    .contents()
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default()
+                    .to_owned(),
+                modified,
+                rect: Rectangle::new(b.x0 as f64, b.y0 as f64, b.x1 as f64, b.y1 as f64),
+                quads,
+                replies: Vec::new(),
+            },
+        ));
+    }
+    thread(entries)
+}
+
+// Fold replies into the annotation at the root of their /IRT chain. A reply whose parent is not on
+// this page (or is a cycle) stays top-level rather than vanishing.
+fn thread(entries: Vec<(i32, Option<i32>, Annotation)>) -> Vec<Annotation> {
+    let index_of = |xref: i32| {
+        entries
+            .iter()
+            .position(|(own, _, _)| *own == xref && xref > 0)
+    };
+    let roots: Vec<Option<usize>> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, _)| {
+            let mut cur = i;
+            for _ in 0..MAX_REPLY_DEPTH {
+                match entries[cur].1.and_then(index_of) {
+                    Some(parent) if parent != i => cur = parent,
+                    Some(_) => return None,
+                    None => return (cur != i).then_some(cur),
+                }
+            }
+            None
+        })
+        .collect();
+
+    let mut top: Vec<Option<Annotation>> = Vec::with_capacity(entries.len());
+    let mut replies = Vec::new();
+    for ((_, _, annot), root) in entries.into_iter().zip(&roots) {
+        match root {
+            Some(root) => {
+                replies.push((*root, annot));
+                top.push(None);
+            }
+            None => top.push(Some(annot)),
+        }
+    }
+    for (root, reply) in replies {
+        if let Some(Some(parent)) = top.get_mut(root) {
+            parent.replies.push(reply);
+        }
+    }
+    top.into_iter().flatten().collect()
+}
+
+fn quad_rect(q: &mupdf::Quad) -> Rectangle {
+    let xs = [q.ul.x, q.ur.x, q.ll.x, q.lr.x];
+    let ys = [q.ul.y, q.ur.y, q.ll.y, q.lr.y];
+    let min = |v: [f32; 4]| v.into_iter().fold(f32::INFINITY, f32::min) as f64;
+    let max = |v: [f32; 4]| v.into_iter().fold(f32::NEG_INFINITY, f32::max) as f64;
+    Rectangle::new(min(xs), min(ys), max(xs), max(ys))
+}
+
+fn kind_name(kind: PdfAnnotationType) -> &'static str {
+    match kind {
+        PdfAnnotationType::Text => "Note",
+        PdfAnnotationType::FreeText => "Text box",
+        PdfAnnotationType::Highlight => "Highlight",
+        PdfAnnotationType::Underline => "Underline",
+        PdfAnnotationType::Squiggly => "Squiggly",
+        PdfAnnotationType::StrikeOut => "Strikeout",
+        PdfAnnotationType::Ink => "Ink",
+        PdfAnnotationType::Stamp => "Stamp",
+        PdfAnnotationType::Caret => "Caret",
+        PdfAnnotationType::FileAttachment => "Attachment",
+        PdfAnnotationType::Square
+        | PdfAnnotationType::Circle
+        | PdfAnnotationType::Line
+        | PdfAnnotationType::Polygon
+        | PdfAnnotationType::PolyLine => "Shape",
+        _ => "Annotation",
+    }
+}
+
+// A PDF date, "D:20230415093000+02'00'", as "2023-04-15 09:30:00 +02:00"; anything unparsable as
+// given. Fields after the year are optional. Also used by the properties dialog.
+pub(crate) fn format_pdf_date(raw: &str) -> String {
+    let date = raw.strip_prefix("D:").unwrap_or(raw);
+    let digits = date.bytes().take_while(u8::is_ascii_digit).count();
+    if digits < 4 || digits % 2 != 0 || digits > 14 {
+        return raw.to_string();
+    }
+    let field = |at: usize, default: &'static str| {
+        date.get(at..at + 2)
+            .filter(|_| at + 2 <= digits)
+            .unwrap_or(default)
+    };
+    let mut out = format!(
+        "{}-{}-{} {}:{}:{}",
+        &date[..4],
+        field(4, "01"),
+        field(6, "01"),
+        field(8, "00"),
+        field(10, "00"),
+        field(12, "00"),
+    );
+    let zone = &date[digits..];
+    if zone.starts_with('Z') {
+        out.push_str(" UTC");
+    } else if let Some(sign) = zone.chars().next().filter(|c| matches!(c, '+' | '-')) {
+        let offset: String = zone[1..].chars().filter(char::is_ascii_digit).collect();
+        if offset.len() >= 2 {
+            let minutes = offset.get(2..4).unwrap_or("00");
+            out.push_str(&format!(" {sign}{}:{minutes}", &offset[..2]));
+        }
+    }
+    out
+}
+
+// Header line for a comment: "Note · Alice · 2024-03-01 09:30:00".
+pub fn heading(annot: &Annotation) -> String {
+    let mut parts = vec![annot.kind];
+    if let Some(author) = annot.author.as_deref().filter(|a| !a.is_empty()) {
+        parts.push(author);
+    }
+    if let Some(modified) = annot.modified.as_deref() {
+        parts.push(modified);
+    }
+    parts.join(" · ")
+}
+
+// Comment thread as widgets: the annotation's heading and text, then each reply indented below.
+// Shared by the page popover and the comments sidebar.
+pub(crate) fn comment_widget(annot: &Annotation) -> gtk::Box {
+    let thread = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    append_comment(&thread, annot, 0);
+    for reply in &annot.replies {
+        append_comment(&thread, reply, 16);
+    }
+    thread
+}
+
+fn append_comment(thread: &gtk::Box, annot: &Annotation, indent: i32) {
+    let heading = gtk::Label::new(Some(&heading(annot)));
+    heading.set_xalign(0.0);
+    heading.set_margin_start(indent);
+    heading.add_css_class("dim-label");
+    heading.add_css_class("caption");
+    thread.append(&heading);
+
+    if !annot.contents.trim().is_empty() {
+        let text = gtk::Label::new(Some(annot.contents.trim()));
+        text.set_xalign(0.0);
+        text.set_wrap(true);
+        text.set_max_width_chars(48);
+        text.set_selectable(true);
+        text.set_margin_start(indent);
+        thread.append(&text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 200x200 page with a sticky note over PDF rect [20 150 40 170], a reply to it (4 0 R), a
+    // highlight without a comment, a link that must not show up as an annotation and an ink stroke
+    // bending down from (10, 10) through (50, 10) to (50, 60) in top-left points.
+    const ANNOT_PDF: &[u8] = b"%PDF-1.4\n\
+1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>\nendobj\n\
+3 0 obj\n<< /Type /Page /Parent 2 0 R /Annots [4 0 R 5 0 R 6 0 R 7 0 R 8 0 R] >>\nendobj\n\
+4 0 obj\n<< /Type /Annot /Subtype /Text /Rect [20 150 40 170] /T (Alice) /Contents (Check this figure) /M (D:20240301093000Z) >>\nendobj\n\
+5 0 obj\n<< /Type /Annot /Subtype /Text /Rect [20 150 40 170] /T (Bob) /Contents (Fixed in v2) /IRT 4 0 R >>\nendobj\n\
+6 0 obj\n<< /Type /Annot /Subtype /Highlight /Rect [50 60 150 90] /QuadPoints [50 90 150 90 50 60 150 60] >>\nendobj\n\
+7 0 obj\n<< /Type /Annot /Subtype /Link /Rect [50 100 150 120] /A << /S /URI /URI (https://example.com) >> >>\nendobj\n\
+8 0 obj\n<< /Type /Annot /Subtype /Ink /Rect [8 138 52 192] /InkList [[10 190 50 190 50 140]] /BS << /W 2 >> >>\nendobj\n\
+trailer\n<< /Root 1 0 R >>\n%%EOF";
+
+    fn annot_pdf_uri() -> String {
+        let dir = std::env::temp_dir().join("scrolex_annotations_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("annotations.pdf");
+        std::fs::write(&path, ANNOT_PDF).unwrap();
+        format!("file://{}", path.display())
+    }
+
+    #[gtk::test]
+    fn note_carries_its_reply() {
+        let uri = annot_pdf_uri();
+        let mut annotations = Annotations::default();
+        // PDF rect [20 150 40 170] flips to top-left y (30..50) on a 200-tall page
+        let note = annotations
+            .get_annotation(&uri, 0, 30.0, 40.0)
+            .expect("the note is hit");
+        assert_eq!(note.kind, "Note");
+        assert_eq!(note.author.as_deref(), Some("Alice"));
+        assert_eq!(note.contents, "Check this figure");
+        assert_eq!(note.modified.as_deref(), Some("2024-03-01 09:30:00 UTC"));
+        assert_eq!(note.replies.len(), 1);
+        assert_eq!(note.replies[0].author.as_deref(), Some("Bob"));
+        assert_eq!(note.replies[0].contents, "Fixed in v2");
+
+        // an uncommented highlight and a link have nothing to pop up
+        assert!(annotations.get_annotation(&uri, 0, 100.0, 125.0).is_none());
+        assert!(annotations.get_annotation(&uri, 0, 100.0, 90.0).is_none());
+    }
+
+    #[gtk::test]
+    fn comments_list_skips_replies_and_bare_markup() {
+        let comments = comments(&annot_pdf_uri());
+        let got: Vec<_> = comments
+            .iter()
+            .map(|c| (c.page, c.contents.as_str(), c.replies.len()))
+            .collect();
+        assert_eq!(got, vec![(0, "Check this figure", 1)]);
+    }
+
+    #[gtk::test]
+    fn unsaved_markup_and_notes_join_the_saved_in_page_order() {
+        let pending = [
+            Edit::Note {
+                page: 1,
+                x: 10.0,
+                y: 20.0,
+                text: "Later".into(),
+            },
+            Edit::Markup {
+                page: 0,
+                kind: MarkupKind::Underline,
+                rects: vec![
+                    Rectangle::new(10.0, 10.0, 90.0, 20.0),
+                    Rectangle::new(10.0, 22.0, 50.0, 32.0),
+                ],
+            },
+        ];
+        let all = with_pending(all(&annot_pdf_uri()), &pending);
+        let got: Vec<_> = all
+            .iter()
+            .map(|a| (a.page, a.kind, a.contents.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (0, "Note", "Check this figure"),
+                (0, "Highlight", ""),
+                (0, "Ink", ""),
+                (0, "Underline", ""),
+                (1, "Note", "Later"),
+            ]
+        );
+        assert_eq!(all[3].rect, Rectangle::new(10.0, 10.0, 90.0, 32.0));
+        assert_eq!(all[3].quads.len(), 2);
+    }
+
+    #[gtk::test]
+    fn saved_ink_is_read_for_the_eraser() {
+        let ink = saved_ink(&annot_pdf_uri(), 0);
+        assert_eq!(ink.len(), 1);
+        assert_eq!(ink[0].xref, 8);
+        let [stroke] = ink[0].strokes.as_slice() else {
+            panic!("one stroke expected, got {:?}", ink[0].strokes);
+        };
+        assert_eq!(stroke.width, 2.0);
+        assert!(stroke.passes_near(30.0, 11.0, 1.0));
+        assert!(stroke.passes_near(51.0, 40.0, 1.0));
+        assert!(!stroke.passes_near(30.0, 40.0, 1.0));
+    }
+
+    #[test]
+    fn pdf_dates_are_readable() {
+        assert_eq!(
+            format_pdf_date("D:20230415093000+02'00'"),
+            "2023-04-15 09:30:00 +02:00"
+        );
+        assert_eq!(
+            format_pdf_date("D:20230415093000Z"),
+            "2023-04-15 09:30:00 UTC"
+        );
+        assert_eq!(format_pdf_date("D:2023"), "2023-01-01 00:00:00");
+        assert_eq!(
+            format_pdf_date("D:202304-05'30"),
+            "2023-04-01 00:00:00 -05:30"
+        );
+        assert_eq!(format_pdf_date("last Tuesday"), "last Tuesday");
+        assert_eq!(format_pdf_date(""), "");
+    }
+}
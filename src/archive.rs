@@ -0,0 +1,315 @@
+// Comic archives that MuPDF doesn't read (CBZ it does): tar is unpacked here, RAR, 7z and compressed
+// tars through libarchive, which is loaded at runtime like djvulibre. An archive is unpacked once,
+// when it opens, and its pages are then read like a folder's.
+
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use libloading::Library;
+use once_cell::sync::Lazy;
+
+use crate::dylib::symbol;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    // an uncompressed (ustar) tar, as CBT comics are
+    Tar,
+    // RAR, 7z or a compressed tar; needs libarchive
+    Packed,
+}
+
+// The archive `header`, a file's first 512 bytes, starts, if any. ZIP isn't one: MuPDF opens CBZ.
+pub(crate) fn kind(header: &[u8]) -> Option<Kind> {
+    const PACKED: [&[u8]; 6] = [
+        b"Rar!\x1a\x07",       // RAR 4 and 5
+        b"7z\xbc\xaf\x27\x1c", // 7z
+        b"\x1f\x8b",           // gzip
+        b"BZh",                // bzip2
+        b"\xfd7zXZ\0",         // xz
+        b"\x28\xb5\x2f\xfd",   // zstd
+    ];
+    if header.get(257..262) == Some(b"ustar") {
+        Some(Kind::Tar)
+    } else if PACKED.iter().any(|magic| header.starts_with(magic)) {
+        Some(Kind::Packed)
+    } else {
+        None
+    }
+}
+
+// Whether archives of `kind` can be unpacked here.
+pub(crate) fn can_unpack(kind: Kind) -> bool {
+    kind == Kind::Tar || LIBARCHIVE.is_some()
+}
+
+// Unpack the regular files of the archive at `path` into `dir`, numbered in archive order (so no
+// entry name can reach outside it) and keeping their extension. Each file's name in the archive
+// and where it went; None if the archive can't be read.
+pub(crate) fn unpack(path: &Path, kind: Kind, dir: &Path) -> Option<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    let mut add = |name: String, data: &mut dyn Read| -> Option<()> {
+        let extension = Path::new(&name)
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        let target = dir.join(format!("{:05}{extension}", files.len()));
+        std::io::copy(data, &mut File::create(&target).ok()?).ok()?;
+        files.push((name, target));
+        Some(())
+    };
+    match kind {
+        Kind::Tar => unpack_tar(File::open(path).ok()?, &mut add)?,
+        Kind::Packed => unpack_packed(path, &mut add)?,
+    }
+    Some(files)
+}
+
+type AddFile<'a> = dyn FnMut(String, &mut dyn Read) -> Option<()> + 'a;
+
+// Walk ustar headers, handing each regular file to `add`. GNU long names ('L') and pax path records
+// ('x') name the entry after them.
+fn unpack_tar(mut tar: impl Read, add: &mut AddFile) -> Option<()> {
+    let mut long_name = None;
+    loop {
+        let mut header = [0u8; 512];
+        tar.read_exact(&mut header).ok()?;
+        if header.iter().all(|&byte| byte == 0) {
+            return Some(()); // end of archive
+        }
+        let size = octal(&header[124..136])?;
+        let padded = size.div_ceil(512) * 512;
+        let mut data = (&mut tar).take(size);
+        match header[156] {
+            b'0' | 0 => {
+                let name = long_name.take().unwrap_or_else(|| ustar_name(&header));
+                add(name, &mut data)?;
+            }
+            b'L' => {
+                let mut name = Vec::new();
+                data.read_to_end(&mut name).ok()?;
+                long_name = Some(cstr(&name));
+            }
+            b'x' => {
+                let mut records = String::new();
+                data.read_to_string(&mut records).ok()?;
+                long_name = pax_path(&records).or(long_name);
+            }
+            _ => {}
+        }
+        // whatever `add` left of the entry, and the padding to the next header
+        std::io::copy(&mut data, &mut std::io::sink()).ok()?;
+        std::io::copy(&mut (&mut tar).take(padded - size), &mut std::io::sink()).ok()?;
+    }
+}
+
+// A ustar entry's name: its prefix, if any, then its name.
+fn ustar_name(header: &[u8; 512]) -> String {
+    let name = cstr(&header[0..100]);
+    match cstr(&header[345..500]) {
+        prefix if prefix.is_empty() => name,
+        prefix => format!("{prefix}/{name}"),
+    }
+}
+
+fn cstr(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+// A NUL- or space-terminated octal number.
+fn octal(field: &[u8]) -> Option<u64> {
+    let digits = cstr(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(digits, 8).ok()
+}
+
+// The path record of pax extended header records ("<length> path=<value>\n" ...).
+fn pax_path(records: &str) -> Option<String> {
+    records.lines().find_map(|record| {
+        let (_, field) = record.split_once(' ')?;
+        field.strip_prefix("path=").map(str::to_string)
+    })
+}
+
+// archive_read_next_header's ARCHIVE_OK, ARCHIVE_EOF and ARCHIVE_WARN (usable, with a complaint).
+const ARCHIVE_OK: c_int = 0;
+const ARCHIVE_EOF: c_int = 1;
+const ARCHIVE_WARN: c_int = -20;
+// archive_entry_filetype's AE_IFREG and AE_IFMT
+const AE_IFREG: c_int = 0o100000;
+const AE_IFMT: c_int = 0o170000;
+
+// The soname libarchive has shipped under since 3.0, then the unversioned development link.
+const LIBRARY_NAMES: [&str; 3] = ["libarchive.so.13", "libarchive.13.dylib", "libarchive.so"];
+
+type Handle = *mut c_void;
+
+// The libarchive calls in use, from archive.h and archive_entry.h.
+struct Api {
+    read_new: unsafe extern "C" fn() -> Handle,
+    read_support_filter_all: unsafe extern "C" fn(Handle) -> c_int,
+    read_support_format_all: unsafe extern "C" fn(Handle) -> c_int,
+    read_open_filename: unsafe extern "C" fn(Handle, *const c_char, usize) -> c_int,
+    read_next_header: unsafe extern "C" fn(Handle, *mut Handle) -> c_int,
+    read_data: unsafe extern "C" fn(Handle, *mut c_void, usize) -> isize,
+    read_free: unsafe extern "C" fn(Handle) -> c_int,
+    entry_pathname: unsafe extern "C" fn(Handle) -> *const c_char,
+    entry_filetype: unsafe extern "C" fn(Handle) -> c_int,
+    // the functions above point into it
+    _library: Library,
+}
+
+static LIBARCHIVE: Lazy<Option<Api>> = Lazy::new(|| {
+    let library = crate::dylib::open(&LIBRARY_NAMES)?;
+    // SAFETY: each symbol is given its prototype from archive.h / archive_entry.h
+    unsafe {
+        Some(Api {
+            read_new: symbol(&library, b"archive_read_new\0")?,
+            read_support_filter_all: symbol(&library, b"archive_read_support_filter_all\0")?,
+            read_support_format_all: symbol(&library, b"archive_read_support_format_all\0")?,
+            read_open_filename: symbol(&library, b"archive_read_open_filename\0")?,
+            read_next_header: symbol(&library, b"archive_read_next_header\0")?,
+            read_data: symbol(&library, b"archive_read_data\0")?,
+            read_free: symbol(&library, b"archive_read_free\0")?,
+            entry_pathname: symbol(&library, b"archive_entry_pathname\0")?,
+            entry_filetype: symbol(&library, b"archive_entry_filetype\0")?,
+            _library: library,
+        })
+    }
+});
+
+// An entry's data, read through libarchive.
+struct EntryData<'a> {
+    api: &'a Api,
+    archive: Handle,
+}
+
+impl Read for EntryData<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // SAFETY: libarchive writes at most buf.len() bytes into buf
+        let read =
+            unsafe { (self.api.read_data)(self.archive, buf.as_mut_ptr().cast(), buf.len()) };
+        usize::try_from(read).map_err(|_| std::io::Error::other("archive data is corrupt"))
+    }
+}
+
+fn unpack_packed(path: &Path, add: &mut AddFile) -> Option<()> {
+    let api = LIBARCHIVE.as_ref()?;
+    let filename = CString::new(path.to_str()?).ok()?;
+    // SAFETY: the archive is freed before returning; entries are only used until the next header
+    unsafe {
+        let archive = (api.read_new)();
+        if archive.is_null() {
+            return None;
+        }
+        (api.read_support_filter_all)(archive);
+        (api.read_support_format_all)(archive);
+        let mut result = ((api.read_open_filename)(archive, filename.as_ptr(), 64 * 1024)
+            == ARCHIVE_OK)
+            .then_some(());
+        while result.is_some() {
+            let mut entry: Handle = std::ptr::null_mut();
+            match (api.read_next_header)(archive, &mut entry) {
+                ARCHIVE_OK | ARCHIVE_WARN => {}
+                ARCHIVE_EOF => break,
+                _ => {
+                    result = None;
+                    break;
+                }
+            }
+            if (api.entry_filetype)(entry) & AE_IFMT != AE_IFREG {
+                continue; // libarchive skips unread data itself
+            }
+            let name = (api.entry_pathname)(entry);
+            let name = if name.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(name).to_string_lossy().into_owned()
+            };
+            result = add(name, &mut EntryData { api, archive });
+        }
+        (api.read_free)(archive);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two files, "b/10.txt" ("ten") and "b/9.txt" ("nine"), as ustar and as a gzipped tar.
+    const NUMBERS_TAR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/numbers.tar");
+    const NUMBERS_TGZ: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/numbers.tgz");
+
+    fn header(path: &str) -> Vec<u8> {
+        let mut header = Vec::new();
+        File::open(path)
+            .unwrap()
+            .take(512)
+            .read_to_end(&mut header)
+            .unwrap();
+        header
+    }
+
+    fn unpacked(path: &str, kind: Kind) -> Vec<(String, String, String)> {
+        let dir = tempfile::tempdir().unwrap();
+        unpack(Path::new(path), kind, dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|(name, file)| {
+                let content = std::fs::read_to_string(&file).unwrap();
+                let file = file.file_name().unwrap().to_string_lossy().into_owned();
+                (name, file, content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recognises_archive_headers() {
+        assert_eq!(kind(&header(NUMBERS_TAR)), Some(Kind::Tar));
+        assert_eq!(kind(&header(NUMBERS_TGZ)), Some(Kind::Packed));
+        assert_eq!(kind(b"Rar!\x1a\x07\x01\x00"), Some(Kind::Packed));
+        assert_eq!(kind(b"7z\xbc\xaf\x27\x1c\x00\x04"), Some(Kind::Packed));
+        assert_eq!(kind(b"PK\x03\x04"), None);
+        assert_eq!(kind(b"%PDF-1.7"), None);
+    }
+
+    #[test]
+    fn unpacks_tar_entries_under_numbered_names() {
+        assert_eq!(
+            unpacked(NUMBERS_TAR, Kind::Tar),
+            vec![
+                ("b/10.txt".into(), "00000.txt".into(), "ten".into()),
+                ("b/9.txt".into(), "00001.txt".into(), "nine".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_tar_header_fields() {
+        assert_eq!(octal(b"0000000144\0 "), Some(100));
+        assert_eq!(octal(b"\0\0\0"), Some(0));
+        assert_eq!(octal(b"12x"), None);
+        assert_eq!(
+            pax_path("30 mtime=1700000000.123\n27 path=a/very/long/name.png\n"),
+            Some("a/very/long/name.png".into())
+        );
+    }
+
+    // Through libarchive itself; skipped where it isn't installed.
+    #[test]
+    fn unpacks_compressed_tars_through_libarchive() {
+        if !can_unpack(Kind::Packed) {
+            return;
+        }
+        assert_eq!(
+            unpacked(NUMBERS_TGZ, Kind::Packed),
+            unpacked(NUMBERS_TAR, Kind::Tar)
+        );
+    }
+}
@@ -0,0 +1,324 @@
+// Files embedded in a PDF: those in the document's /EmbeddedFiles name tree and those attached to
+// pages by FileAttachment annotations. Read through MuPDF from the document as it renders (working
+// copy included); an attachment is found again by the object number of its embedded file stream.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use gtk::gio;
+use mupdf::pdf::{PdfAnnotationType, PdfDocument, PdfObject};
+use mupdf::Colorspace;
+
+// name trees nest through /Kids; anything deeper is a broken or looping file
+const MAX_NAME_TREE_DEPTH: usize = 16;
+
+// Attachments that would run rather than open in a viewer, refused by name...
+const RUNNABLE_EXTENSIONS: &[&str] = &[
+    "appimage",
+    "apk",
+    "bash",
+    "bat",
+    "bin",
+    "cmd",
+    "com",
+    "command",
+    "csh",
+    "deb",
+    "desktop",
+    "exe",
+    "flatpak",
+    "flatpakref",
+    "jar",
+    "js",
+    "ksh",
+    "lnk",
+    "msi",
+    "php",
+    "pl",
+    "ps1",
+    "py",
+    "rb",
+    "rpm",
+    "run",
+    "scr",
+    "sh",
+    "vbs",
+    "zsh",
+];
+// ...and by the content type their name and bytes suggest.
+const RUNNABLE_TYPES: &[&str] = &[
+    "application/x-executable",
+    "application/x-sharedlib",
+    "application/x-shellscript",
+    "application/x-desktop",
+    "application/x-ms-dos-executable",
+    "application/x-msdownload",
+    "application/x-msi",
+    "application/x-java-archive",
+    "application/x-python-bytecode",
+    "text/x-python",
+    "text/x-perl",
+];
+
+// The temporary directories of copies handed to other applications, which read a copy after the
+// launch returns and may keep it open; removed at quit by remove_opened_copies.
+static OPENED_COPIES: Mutex<Vec<tempfile::TempDir>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    // file name only, without any directories the file spec carries
+    pub name: String,
+    pub description: String,
+    // uncompressed size, when the file records it
+    pub size: Option<u64>,
+    // 1-based page of the annotation carrying the file; None for document-level files
+    pub page: Option<i32>,
+    stream: i32,
+}
+
+// Every embedded file: the document-level ones in name-tree order, then the page annotations' in
+// page order. A file both named and attached to a page is listed once, as a document-level file.
+pub fn list(uri: &str) -> Vec<Attachment> {
+    let Ok(doc) = open(uri) else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    let mut seen = HashSet::new();
+
+    let tree = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get_dict("Names").ok().flatten())
+        .and_then(|names| names.get_dict("EmbeddedFiles").ok().flatten());
+    if let Some(tree) = tree {
+        let mut entries = Vec::new();
+        name_tree_entries(&tree, 0, &mut entries);
+        for (key, spec) in entries {
+            if let Some(attachment) = attachment(&spec, &key, "", None) {
+                if seen.insert(attachment.stream) {
+                    found.push(attachment);
+                }
+            }
+        }
+    }
+
+    let n_pages = doc.page_count().unwrap_or(0);
+    for page_num in 0..n_pages {
+        let Ok(page) = doc.load_pdf_page(page_num) else {
+            continue;
+        };
+        for annot in page.annotations() {
+            if !matches!(annot.r#type(), Ok(PdfAnnotationType::FileAttachment)) {
+                continue;
+            }
+            let obj = annot.object();
+            let Some(spec) = obj.get_dict("FS").ok().flatten() else {
+                continue;
+            };
+            let contents = text(&obj, "Contents").unwrap_or_default();
+            if let Some(attachment) = attachment(&spec, "", &contents, Some(page_num + 1)) {
+                if seen.insert(attachment.stream) {
+                    found.push(attachment);
+                }
+            }
+        }
+    }
+    found
+}
+
+// The attachment's bytes, decoded from the embedded file stream.
+pub fn contents(uri: &str, attachment: &Attachment) -> Result<Vec<u8>, String> {
+    let doc = open(uri)?;
+    doc.new_indirect(attachment.stream, 0)
+        .and_then(|stream| stream.read_stream())
+        .map_err(|err| err.to_string())
+}
+
+// Whether opening a file named `name` holding `data` could run it instead of showing it.
+pub fn is_runnable(name: &str, data: &[u8]) -> bool {
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    if extension.is_some_and(|extension| RUNNABLE_EXTENSIONS.contains(&extension.as_str())) {
+        return true;
+    }
+    let (content_type, _) = gio::content_type_guess(Some(name), data);
+    RUNNABLE_TYPES
+        .iter()
+        .any(|runnable| gio::content_type_is_a(&content_type, runnable))
+}
+
+// Copy the attachment to a file of its own for another application to open; the copy's path.
+// Anything that could run is refused. Reads and writes, so not for the main thread.
+pub fn open_copy(uri: &str, attachment: &Attachment) -> Result<PathBuf, String> {
+    let data = contents(uri, attachment)?;
+    if is_runnable(&attachment.name, &data) {
+        return Err(format!(
+            "\"{}\" could run as a program, so it isn't opened. Save it instead.",
+            attachment.name
+        ));
+    }
+    let dir = tempfile::Builder::new()
+        .prefix("scrolex-attachment-")
+        .tempdir()
+        .map_err(|err| err.to_string())?;
+    let path = dir.path().join(&attachment.name);
+    std::fs::write(&path, data).map_err(|err| err.to_string())?;
+    OPENED_COPIES.lock().unwrap().push(dir);
+    Ok(path)
+}
+
+// Delete the copies open_copy made.
+pub fn remove_opened_copies() {
+    OPENED_COPIES.lock().unwrap().clear();
+}
+
+fn open(uri: &str) -> Result<PdfDocument, String> {
+    let path = crate::mupdf_render::local_path(uri).ok_or("the document is not readable")?;
+    let path = path.to_str().ok_or("the document path is not UTF-8")?;
+    let _ctx = Colorspace::device_bgr();
+    PdfDocument::open(path).map_err(|err| err.to_string())
+}
+
+// The (key, file spec) pairs of a name tree node and its descendants, in key order.
+fn name_tree_entries(node: &PdfObject, depth: usize, out: &mut Vec<(String, PdfObject)>) {
+    if depth > MAX_NAME_TREE_DEPTH {
+        return;
+    }
+    if let Ok(Some(names)) = node.get_dict("Names") {
+        let len = names.len().unwrap_or(0) as i32;
+        for i in (0..len - 1).step_by(2) {
+            let key = names.get_array(i).ok().flatten();
+            let value = names.get_array(i + 1).ok().flatten();
+            if let (Some(key), Some(value)) = (key, value) {
+                out.push((key.as_string().unwrap_or_default(), value));
+            }
+        }
+    }
+    if let Ok(Some(kids)) = node.get_dict("Kids") {
+        if let Ok(kids) = kids.array_iter() {
+            for kid in kids.flatten() {
+                name_tree_entries(&kid, depth + 1, out);
+            }
+        }
+    }
+}
+
+// An embedded file from its file spec. Specs that only point at an external file are skipped.
+// `key` and `contents` stand in for a missing file name and description.
+fn attachment(
+    spec: &PdfObject,
+    key: &str,
+    contents: &str,
+    page: Option<i32>,
+) -> Option<Attachment> {
+    let ef = spec.get_dict("EF").ok()??;
+    let stream = ef
+        .get_dict("UF")
+        .ok()
+        .flatten()
+        .or_else(|| ef.get_dict("F").ok().flatten())?;
+    // the embedded file must be an indirect stream to be read back later
+    let xref = stream.as_indirect().ok().filter(|&xref| xref > 0)?;
+    let name = text(spec, "UF")
+        .or_else(|| text(spec, "F"))
+        .unwrap_or_else(|| key.to_string());
+    // saved and opened under this name, so never empty or a directory
+    let name = match file_name(&name) {
+        "" | "." | ".." => "attachment",
+        name => name,
+    };
+    let size = stream
+        .get_dict("Params")
+        .ok()
+        .flatten()
+        .and_then(|params| params.get_dict("Size").ok().flatten())
+        .and_then(|size| size.as_int().ok())
+        .and_then(|size| u64::try_from(size).ok());
+    Some(Attachment {
+        name: name.to_string(),
+        description: text(spec, "Desc").unwrap_or_else(|| contents.to_string()),
+        size,
+        page,
+        stream: xref,
+    })
+}
+
+fn text(obj: &PdfObject, key: &str) -> Option<String> {
+    let value = obj.get_dict(key).ok()??.as_string().ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+// The last component of a file spec's path, which may use either separator.
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\'])
+        .find(|part| !part.is_empty())
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 200x200 page. The name tree holds "notes.txt" (with a description and size) under a kid
+    // node; the page has a FileAttachment annotation for "data/table.csv" described only by its
+    // /Contents, and a second annotation for the same stream as notes.txt.
+    const ATTACH_PDF: &[u8] = b"%PDF-1.7\n\
+1 0 obj\n<< /Type /Catalog /Pages 2 0 R /Names << /EmbeddedFiles << /Kids [4 0 R] >> >> >>\nendobj\n\
+2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>\nendobj\n\
+3 0 obj\n<< /Type /Page /Parent 2 0 R /Annots [8 0 R 9 0 R] >>\nendobj\n\
+4 0 obj\n<< /Names [(notes.txt) 5 0 R] >>\nendobj\n\
+5 0 obj\n<< /Type /Filespec /F (notes.txt) /UF (notes.txt) /Desc (Reading notes) /EF << /F 6 0 R >> >>\nendobj\n\
+6 0 obj\n<< /Type /EmbeddedFile /Params << /Size 11 >> /Length 11 >>\nstream\nhello notes\nendstream\nendobj\n\
+7 0 obj\n<< /Type /EmbeddedFile /Length 6 >>\nstream\na,b\n1,\nendstream\nendobj\n\
+8 0 obj\n<< /Type /Annot /Subtype /FileAttachment /Rect [10 10 30 30] /Contents (The table) \
+/FS << /Type /Filespec /F (data/table.csv) /EF << /F 7 0 R >> >> >>\nendobj\n\
+9 0 obj\n<< /Type /Annot /Subtype /FileAttachment /Rect [40 10 60 30] /FS 5 0 R >>\nendobj\n\
+trailer\n<< /Root 1 0 R >>\n%%EOF";
+
+    fn attach_pdf_uri() -> String {
+        let dir = std::env::temp_dir().join("scrolex_attachments_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("attach.pdf");
+        std::fs::write(&path, ATTACH_PDF).unwrap();
+        format!("file://{}", path.display())
+    }
+
+    #[test]
+    fn file_names_drop_directories() {
+        assert_eq!(file_name("data/table.csv"), "table.csv");
+        assert_eq!(file_name("C:\\docs\\report.pdf"), "report.pdf");
+        assert_eq!(file_name("plain.txt"), "plain.txt");
+        assert_eq!(file_name("dir/"), "dir");
+    }
+
+    #[test]
+    fn programs_and_launchers_are_runnable() {
+        assert!(is_runnable("setup.desktop", b"[Desktop Entry]\n"));
+        assert!(is_runnable("Install.EXE", b"MZ"));
+        assert!(is_runnable("fix.sh", b"echo"));
+        assert!(!is_runnable("notes.txt", b"hello notes"));
+        assert!(!is_runnable("table.csv", b"a,b\n1,"));
+    }
+
+    #[gtk::test]
+    fn lists_named_and_annotated_files_once_and_reads_them() {
+        let uri = attach_pdf_uri();
+        let files = list(&uri);
+        let got: Vec<_> = files
+            .iter()
+            .map(|f| (f.name.as_str(), f.description.as_str(), f.size, f.page))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("notes.txt", "Reading notes", Some(11), None),
+                ("table.csv", "The table", None, Some(1)),
+            ]
+        );
+        assert_eq!(contents(&uri, &files[0]).unwrap(), b"hello notes");
+        assert_eq!(contents(&uri, &files[1]).unwrap(), b"a,b\n1,");
+    }
+}
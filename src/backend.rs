@@ -0,0 +1,374 @@
+// Document formats behind one interface. A backend is picked for each document when it loads (by
+// `select`) and looked up by uri from then on, so rendering, text, links and layout don't care what
+// kind of file they are reading. MuPDF serves PDFs and the other formats it reads, djvulibre serves
+// DjVu, the gallery serves folders and archives of images, and the emulator its synthetic document.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+
+use crate::links::LinkTarget;
+use crate::mupdf_render::{Cancel, PagePixels, PixelRect};
+use crate::outline::OutlineEntry;
+use crate::page::Rectangle;
+use crate::search::Match;
+use crate::selection::Glyph;
+
+// Everything the viewer asks of a document. Page numbers are 0-based and sizes are in points, with
+// a top-left origin. Pixels are cairo Rgb24 (BGRx), recoloured for dark mode when `dark` is set.
+// Render methods run on pool workers, so a backend keeps any per-thread handles itself.
+pub(crate) trait RenderBackend: Send + Sync {
+    // Page count and the tallest height among (at least) the first `pages` pages, from one open of
+    // the staged bytes at `path`. The rest are measured after the document shows.
+    fn probe(&self, path: &Path, pages: i32) -> Option<(i32, Option<f64>)>;
+
+    // Content fingerprint of the staged bytes, naming the document's previews on disk. None keeps
+    // its previews off the disk.
+    fn fingerprint(&self, path: &Path) -> Option<String> {
+        crate::preview_store::fingerprint(path)
+    }
+
+    fn page_count(&self, uri: &str) -> Option<i32>;
+
+    fn page_size(&self, uri: &str, page_num: i32) -> Option<(f64, f64)>;
+
+    // The whole page at `scale`*`dsf`, sized from `page_pt` when given (see
+    // mupdf_render::render_page_pixels). A cancelled render returns None.
+    #[allow(clippy::too_many_arguments)]
+    fn render_page(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        page_pt: Option<(f64, f64)>,
+        dark: bool,
+        cancel: Option<&Cancel>,
+    ) -> Option<PagePixels>;
+
+    // A low-resolution preview; only a backend whose previews cost something else than a small
+    // render needs to tell them apart.
+    #[allow(clippy::too_many_arguments)]
+    fn render_preview(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        page_pt: Option<(f64, f64)>,
+        dark: bool,
+        cancel: Option<&Cancel>,
+    ) -> Option<PagePixels> {
+        self.render_page(uri, page_num, scale, dsf, page_pt, dark, cancel)
+    }
+
+    // One viewport tile (device pixels on the page-anchored grid).
+    #[allow(clippy::too_many_arguments)]
+    fn render_tile(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        region: PixelRect,
+        dark: bool,
+        cancel: Option<&Cancel>,
+    ) -> Option<PagePixels>;
+
+    // Several regions of one page, for snapshots, printing and export.
+    fn render_regions(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        regions: &[PixelRect],
+        dark: bool,
+    ) -> Option<Vec<PagePixels>>;
+
+    // The page's glyphs in reading order, numbered by line and block. None if the page has no text
+    // layer to read.
+    fn text_page(&self, uri: &str, page_num: i32) -> Option<Vec<Glyph>>;
+
+    // Hits of `query` on the page, case-insensitive, one rect per line each spans. Found in the
+    // page's glyphs unless the backend searches for itself.
+    fn search(&self, uri: &str, page_num: i32, query: &str) -> Vec<Match> {
+        self.text_page(uri, page_num)
+            .map(|glyphs| crate::search::search_glyphs(&glyphs, query))
+            .unwrap_or_default()
+    }
+
+    // Link rects on the page and where they lead.
+    fn links(&self, uri: &str, page_num: i32) -> Vec<(Rectangle, LinkTarget)>;
+
+    fn outline(&self, uri: &str) -> Vec<OutlineEntry>;
+
+    // Bounding box of the page's non-white content, None for a blank page.
+    fn content_bbox(&self, uri: &str, page_num: i32) -> Option<(f64, f64, f64, f64)>;
+
+    // Whether annotations, ink and form fields can be saved into the document.
+    fn annotatable(&self, _uri: &str) -> bool {
+        false
+    }
+}
+
+// The backend each loaded uri was opened with.
+static INSTALLED: Lazy<Mutex<HashMap<String, Arc<dyn RenderBackend>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// The backend that reads the staged file at `path`, by its first bytes: djvulibre for DjVu (when
+// it's installed), the gallery for a folder or a comic archive MuPDF can't open, MuPDF for
+// everything else.
+pub(crate) fn select(path: &Path) -> Arc<dyn RenderBackend> {
+    if path.is_dir() {
+        return Arc::new(crate::gallery::Gallery::default());
+    }
+    let mut header = Vec::new();
+    let read = std::fs::File::open(path).and_then(|file| file.take(512).read_to_end(&mut header));
+    if read.is_err() {
+        return Arc::new(crate::mupdf_render::Mupdf);
+    }
+    if crate::djvu::is_djvu(&header) {
+        if crate::djvu::available() {
+            return Arc::new(crate::djvu::Djvu::default());
+        }
+        log::warn!(
+            "{} is a DjVu document, which needs djvulibre (libdjvulibre.so.21) installed",
+            path.display()
+        );
+    }
+    if let Some(kind) = crate::archive::kind(&header) {
+        if crate::archive::can_unpack(kind) {
+            return Arc::new(crate::gallery::Gallery::default());
+        }
+        log::warn!(
+            "{} is an archive that needs libarchive (libarchive.so.13) installed",
+            path.display()
+        );
+    }
+    Arc::new(crate::mupdf_render::Mupdf)
+}
+
+// Serve `uri` from `backend` from now on (on load commit).
+pub(crate) fn install(uri: &str, backend: Arc<dyn RenderBackend>) {
+    INSTALLED.lock().unwrap().insert(uri.to_string(), backend);
+}
+
+// The backend for `uri`: the one it was loaded with, else one selected for its file now and kept
+// (exports from the command line, tests, benchmarks), else MuPDF if the file can't be read.
+pub(crate) fn get(uri: &str) -> Arc<dyn RenderBackend> {
+    if let Some(backend) = INSTALLED.lock().unwrap().get(uri) {
+        return backend.clone();
+    }
+    let Some(path) = crate::mupdf_render::local_path(uri) else {
+        return Arc::new(crate::mupdf_render::Mupdf);
+    };
+    let backend = select(&path);
+    INSTALLED
+        .lock()
+        .unwrap()
+        .entry(uri.to_string())
+        .or_insert(backend)
+        .clone()
+}
+
+// Page count, or None if the document can't be opened.
+pub(crate) fn page_count(uri: &str) -> Option<i32> {
+    get(uri).page_count(uri)
+}
+
+// Page size in points (width, height), or None.
+pub fn page_size(uri: &str, page_num: i32) -> Option<(f64, f64)> {
+    get(uri).page_size(uri, page_num)
+}
+
+// Whether edits to the document can be saved into it (only PDFs, through MuPDF).
+pub(crate) fn annotatable(uri: &str) -> bool {
+    get(uri).annotatable(uri)
+}
+
+// Bounding box of the page's content for crop-to-content, or None for a blank page.
+pub(crate) fn content_bbox(uri: &str, page_num: i32) -> Option<(f64, f64, f64, f64)> {
+    get(uri).content_bbox(uri, page_num)
+}
+
+// Rasterize the page area `rect` (page points, top-left origin) at `dpi` for a snapshot: rendered at
+// that resolution, not scaled from what's on screen. Dark-mode recolouring only if `dark` asks for it,
+// whatever the pages show.
+pub fn render_snapshot(
+    uri: &str,
+    page_num: i32,
+    rect: (f64, f64, f64, f64),
+    dpi: f64,
+    dark: bool,
+) -> Option<PagePixels> {
+    let scale = dpi / 72.0;
+    let region = snapshot_region(rect, scale)?;
+    get(uri)
+        .render_regions(uri, page_num, scale, 1.0, &[region], dark)?
+        .pop()
+}
+
+// A page for image export at `dpi`: the whole page, or just `area` (page points) when cropping.
+// Recoloured for dark mode only if `dark` asks for it, like a snapshot.
+pub fn render_export(
+    uri: &str,
+    page_num: i32,
+    dpi: f64,
+    area: Option<(f64, f64, f64, f64)>,
+    dark: bool,
+) -> Option<PagePixels> {
+    match area {
+        Some(rect) => render_snapshot(uri, page_num, rect, dpi, dark),
+        None => get(uri).render_page(uri, page_num, dpi / 72.0, 1.0, None, dark, None),
+    }
+}
+
+// The pixels covering `rect` at `scale`, rounded outward so the whole area is kept. None if empty.
+fn snapshot_region(rect: (f64, f64, f64, f64), scale: f64) -> Option<PixelRect> {
+    let region = PixelRect::new(
+        (rect.0 * scale).floor() as i32,
+        (rect.1 * scale).floor() as i32,
+        (rect.2 * scale).ceil() as i32,
+        (rect.3 * scale).ceil() as i32,
+    );
+    (region.x0 < region.x1 && region.y0 < region.y1).then_some(region)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A document with no file behind it: two 100x50 pages of solid grey, one link, one outline
+    // entry. What a UI test would install to run without MuPDF.
+    struct Grey;
+
+    impl RenderBackend for Grey {
+        fn probe(&self, _path: &Path, _pages: i32) -> Option<(i32, Option<f64>)> {
+            Some((2, Some(50.0)))
+        }
+
+        fn fingerprint(&self, _path: &Path) -> Option<String> {
+            None
+        }
+
+        fn page_count(&self, _uri: &str) -> Option<i32> {
+            Some(2)
+        }
+
+        fn page_size(&self, _uri: &str, page_num: i32) -> Option<(f64, f64)> {
+            (0..2).contains(&page_num).then_some((100.0, 50.0))
+        }
+
+        fn render_page(
+            &self,
+            _uri: &str,
+            page_num: i32,
+            scale: f64,
+            dsf: f64,
+            _page_pt: Option<(f64, f64)>,
+            _dark: bool,
+            _cancel: Option<&Cancel>,
+        ) -> Option<PagePixels> {
+            let (w, h) = self.page_size("", page_num)?;
+            let region = PixelRect::new(0, 0, (w * scale * dsf) as i32, (h * scale * dsf) as i32);
+            Some(grey(region))
+        }
+
+        fn render_tile(
+            &self,
+            _uri: &str,
+            _page_num: i32,
+            _scale: f64,
+            _dsf: f64,
+            region: PixelRect,
+            _dark: bool,
+            _cancel: Option<&Cancel>,
+        ) -> Option<PagePixels> {
+            Some(grey(region))
+        }
+
+        fn render_regions(
+            &self,
+            _uri: &str,
+            _page_num: i32,
+            _scale: f64,
+            _dsf: f64,
+            regions: &[PixelRect],
+            _dark: bool,
+        ) -> Option<Vec<PagePixels>> {
+            Some(regions.iter().copied().map(grey).collect())
+        }
+
+        fn text_page(&self, _uri: &str, _page_num: i32) -> Option<Vec<Glyph>> {
+            None
+        }
+
+        fn links(&self, _uri: &str, page_num: i32) -> Vec<(Rectangle, LinkTarget)> {
+            if page_num == 0 {
+                vec![(Rectangle::new(10.0, 10.0, 30.0, 20.0), LinkTarget::Page(2))]
+            } else {
+                Vec::new()
+            }
+        }
+
+        fn outline(&self, _uri: &str) -> Vec<OutlineEntry> {
+            vec![OutlineEntry {
+                title: "Second".into(),
+                depth: 0,
+                page: Some(2),
+            }]
+        }
+
+        fn content_bbox(&self, _uri: &str, page_num: i32) -> Option<(f64, f64, f64, f64)> {
+            let (w, h) = self.page_size("", page_num)?;
+            Some((0.0, 0.0, w, h))
+        }
+    }
+
+    fn grey(region: PixelRect) -> PagePixels {
+        let (width, height) = (region.x1 - region.x0, region.y1 - region.y0);
+        PagePixels {
+            data: vec![0x80; (width * height * 4) as usize],
+            width,
+            height,
+            stride: width * 4,
+        }
+    }
+
+    #[test]
+    fn an_installed_backend_serves_its_uri_and_only_that() {
+        let uri = "test:///grey-backend";
+        install(uri, Arc::new(Grey));
+
+        assert_eq!(page_count(uri), Some(2));
+        assert_eq!(page_size(uri, 1), Some((100.0, 50.0)));
+        assert_eq!(content_bbox(uri, 0), Some((0.0, 0.0, 100.0, 50.0)));
+        assert_eq!(get(uri).outline(uri)[0].page, Some(2));
+        let shot = render_snapshot(uri, 0, (10.0, 10.0, 20.0, 15.0), 144.0, false).unwrap();
+        assert_eq!((shot.width, shot.height), (20, 10));
+        let page = render_export(uri, 1, 72.0, None, false).unwrap();
+        assert_eq!((page.width, page.height), (100, 50));
+
+        let mut links = crate::links::Links::default();
+        assert!(matches!(
+            links.get_link(uri, 0, 20.0, 15.0),
+            Some(LinkTarget::Page(2))
+        ));
+
+        // anything else still opens with MuPDF, which has no such file
+        assert_eq!(page_size("test:///elsewhere", 0), None);
+    }
+
+    #[test]
+    fn snapshot_region_rounds_outward_and_rejects_empty_areas() {
+        assert_eq!(
+            snapshot_region((10.2, 20.7, 30.1, 40.0), 2.0),
+            Some(PixelRect::new(20, 41, 61, 80))
+        );
+        assert_eq!(snapshot_region((10.0, 10.0, 10.0, 40.0), 2.0), None);
+    }
+}
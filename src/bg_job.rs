@@ -1,20 +1,28 @@
 // Background worker pool for page rendering. Jobs are self-contained closures (each opens/reuses its
 // own MuPDF Document via the renderer's thread-local), so the pool holds no document itself. One
-// pool serves every kind of job - visible-page renders and low-res previews.
+// pool serves every kind of job - visible-page renders, low-res previews and background sweeps over
+// the whole document. Every job carries a Cancel whose MuPDF cookie stops it mid-render once the
+// page is no longer wanted.
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::mupdf_render::Cancel;
 
 // Priority of a queued job. Visible (on-screen full renders) outrank the low-res previews: the
 // current page's own blur (VisiblePreview) still comes first. Full pages ahead run before distant
-// previews, so nearby pages stay sharp during fast input.
+// previews, so nearby pages stay sharp during fast input. Background work (a sweep over every page)
+// runs only when nothing else waits.
 #[derive(Clone, Copy)]
 pub(crate) enum RenderPriority {
     VisiblePreview,
     Visible,
     Preview,
     Prefetch,
+    Background,
 }
 
 impl RenderPriority {
@@ -25,6 +33,12 @@ impl RenderPriority {
         )
     }
 
+    // Previews mask flung-past pages and a sweep covers the whole document, so neither is limited
+    // to the window's wanted range.
+    fn ignores_wanted_range(self) -> bool {
+        !matches!(self, RenderPriority::Visible | RenderPriority::Prefetch)
+    }
+
     // Short tag for logging what kind of work a render was.
     pub(crate) fn label(self) -> &'static str {
         match self {
@@ -32,20 +46,32 @@ impl RenderPriority {
             RenderPriority::Visible => "on-demand (visible)",
             RenderPriority::Preview => "low-res (prefetch)",
             RenderPriority::Prefetch => "on-demand (prefetch)",
+            RenderPriority::Background => "background",
         }
     }
 }
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+type Job = Box<dyn FnOnce(&Cancel) + Send + 'static>;
 
 struct RenderRequest {
     uri: String,
     // requesting window (its State id); the wanted-range filter is per-window
     client: u64,
     page: i32,
+    // previews and background jobs ignore the wanted range, queued or running
+    unranged: bool,
+    cancel: Cancel,
     job: Job,
 }
 
+// A popped job while a worker runs it, so it can be cancelled from outside.
+struct Running {
+    client: u64,
+    page: i32,
+    unranged: bool,
+    cancel: Cancel,
+}
+
 struct RenderQueue {
     // Each is a LIFO stack (newest at the end): when scrolling fast, the page
     // just landed on renders before ones scrolled past. Oldest entries are
@@ -55,6 +81,8 @@ struct RenderQueue {
     visible: Vec<RenderRequest>,
     preview: Vec<RenderRequest>,
     prefetch: Vec<RenderRequest>,
+    // uncapped: a sweep queues each page once per load, and a reload clears it
+    background: Vec<RenderRequest>,
     max_visible_preview: usize,
     max_visible: usize,
     max_preview: usize,
@@ -65,6 +93,15 @@ struct RenderQueue {
     // worker count bookkeeping for set_size: live threads and how many should exit next
     live_threads: usize,
     stop_requested: usize,
+    // jobs the workers are running now
+    running: Vec<Running>,
+    // worker threads that haven't returned yet, which shutdown waits on
+    alive: usize,
+    // handles of the workers not known to be finished, joined at shutdown so their thread-local
+    // MuPDF document and context are gone before the process exits
+    workers: Vec<thread::JoinHandle<()>>,
+    // set by shutdown: every worker exits after its current job, and nothing new is queued
+    shutting_down: bool,
 }
 
 impl RenderQueue {
@@ -79,6 +116,7 @@ impl RenderQueue {
             visible: Vec::new(),
             preview: Vec::new(),
             prefetch: Vec::new(),
+            background: Vec::new(),
             max_visible_preview,
             max_visible,
             max_preview,
@@ -86,6 +124,10 @@ impl RenderQueue {
             wanted: HashMap::new(),
             live_threads: 0,
             stop_requested: 0,
+            running: Vec::new(),
+            alive: 0,
+            workers: Vec::new(),
+            shutting_down: false,
         }
     }
 
@@ -102,13 +144,31 @@ impl RenderQueue {
         self.prefetch.retain(|req| req.client != client);
     }
 
-    // Drop all of a window's queued renders, previews included (document switch or window close, when
-    // nothing of the old view is worth rendering).
+    // Drop all of a window's queued renders, previews included, and abort its running ones (document
+    // switch or window close, when nothing of the old view is worth rendering).
     fn clear_all(&mut self, client: u64) {
         self.visible_preview.retain(|req| req.client != client);
         self.visible.retain(|req| req.client != client);
         self.preview.retain(|req| req.client != client);
         self.prefetch.retain(|req| req.client != client);
+        self.background.retain(|req| req.client != client);
+        for job in self.running.iter().filter(|job| job.client == client) {
+            job.cancel.cancel();
+        }
+    }
+
+    // Set a window's wanted range and abort its running full renders outside it; the queued ones
+    // drop on pop.
+    fn set_wanted(&mut self, client: u64, range: Option<(i32, i32)>) {
+        match range {
+            Some(range) => self.wanted.insert(client, range),
+            None => self.wanted.remove(&client),
+        };
+        for job in &self.running {
+            if job.client == client && !job.unranged && !self.in_wanted(client, job.page) {
+                job.cancel.cancel();
+            }
+        }
     }
 
     fn push(&mut self, priority: RenderPriority, req: RenderRequest) {
@@ -117,6 +177,7 @@ impl RenderQueue {
             RenderPriority::Visible => (&mut self.visible, self.max_visible),
             RenderPriority::Preview => (&mut self.preview, self.max_preview),
             RenderPriority::Prefetch => (&mut self.prefetch, self.max_prefetch),
+            RenderPriority::Background => (&mut self.background, usize::MAX),
         };
         stack.push(req);
         while stack.len() > max {
@@ -141,7 +202,7 @@ impl RenderQueue {
         if let Some(req) = self.preview.pop() {
             return Some(req);
         }
-        None
+        self.background.pop()
     }
 }
 
@@ -201,12 +262,17 @@ impl RenderPool {
     ) {
         let (lock, cvar) = &*self.inner;
         let mut queue = lock.lock().unwrap();
+        if queue.shutting_down {
+            return;
+        }
         queue.push(
             priority,
             RenderRequest {
                 uri: uri.to_string(),
                 client,
                 page,
+                unranged: priority.ignores_wanted_range(),
+                cancel: Cancel::new(),
                 job,
             },
         );
@@ -215,11 +281,7 @@ impl RenderPool {
 
     pub(crate) fn set_wanted(&self, client: u64, range: Option<(i32, i32)>) {
         let (lock, _cvar) = &*self.inner;
-        let mut queue = lock.lock().unwrap();
-        match range {
-            Some(range) => queue.wanted.insert(client, range),
-            None => queue.wanted.remove(&client),
-        };
+        lock.lock().unwrap().set_wanted(client, range);
     }
 
     // Drop this window's queued full renders (zoom: previews survive, rescaled).
@@ -228,34 +290,95 @@ impl RenderPool {
         lock.lock().unwrap().clear_full(client);
     }
 
-    // Drop all of this window's queued renders (document switch / window close).
+    // Drop all of this window's queued renders and abort its running ones (document switch /
+    // window close).
     pub(crate) fn clear_all(&self, client: u64) {
         let (lock, _cvar) = &*self.inner;
         lock.lock().unwrap().clear_all(client);
     }
 
+    // Drop every queued render, abort the running ones and wait up to `grace` for the workers to
+    // exit. False if one is still inside MuPDF by then (a single huge image decode doesn't poll
+    // the cookie).
+    pub(crate) fn shutdown(&self, grace: Duration) -> bool {
+        let (lock, cvar) = &*self.inner;
+        let mut queue = lock.lock().unwrap();
+        queue.shutting_down = true;
+        queue.visible_preview.clear();
+        queue.visible.clear();
+        queue.preview.clear();
+        queue.prefetch.clear();
+        queue.background.clear();
+        for job in &queue.running {
+            job.cancel.cancel();
+        }
+        cvar.notify_all();
+
+        let deadline = Instant::now() + grace;
+        while queue.alive > 0 {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                return false;
+            };
+            queue = cvar.wait_timeout(queue, left).unwrap().0;
+        }
+        // every worker is past its last job; joining waits out their thread-local teardown
+        let workers = std::mem::take(&mut queue.workers);
+        drop(queue);
+        for worker in workers {
+            let _ = worker.join();
+        }
+        true
+    }
+
     fn spawn_bg_thread(inner: Arc<(Mutex<RenderQueue>, Condvar)>) {
-        thread::spawn(move || {
+        inner.0.lock().unwrap().alive += 1;
+        let worker_inner = inner.clone();
+        let worker = thread::spawn(move || {
+            let inner = worker_inner;
+            let (lock, cvar) = &*inner;
             loop {
                 let req = {
-                    let (lock, cvar) = &*inner;
                     let mut queue = lock.lock().unwrap();
                     loop {
+                        if queue.shutting_down {
+                            break None;
+                        }
                         if queue.stop_requested > 0 {
                             queue.stop_requested -= 1;
-                            return; // pool shrank: exit and drop this thread's render document
+                            break None; // pool shrank: exit and drop this thread's render document
                         }
                         if let Some(req) = queue.pop() {
-                            break req;
+                            queue.running.push(Running {
+                                client: req.client,
+                                page: req.page,
+                                unranged: req.unranged,
+                                cancel: req.cancel.clone(),
+                            });
+                            break Some(req);
                         }
                         queue = cvar.wait(queue).unwrap();
                     }
                 };
+                let Some(req) = req else {
+                    break;
+                };
 
                 log::trace!("render job: {}", req.uri);
-                (req.job)();
+                let cancel = req.cancel;
+                (req.job)(&cancel);
+                lock.lock()
+                    .unwrap()
+                    .running
+                    .retain(|job| !job.cancel.same(&cancel));
             }
+            crate::mupdf_render::release_thread_resources();
+            crate::djvu::release_thread_resources();
+            lock.lock().unwrap().alive -= 1;
+            cvar.notify_all();
         });
+        let mut queue = inner.0.lock().unwrap();
+        queue.workers.retain(|worker| !worker.is_finished());
+        queue.workers.push(worker);
     }
 }
 
@@ -284,6 +407,46 @@ fn plan_resize(live: usize, pending_stops: usize, target: usize) -> ResizePlan {
     }
 }
 
+// Threads beside the pool that read a document start to finish (search sweeps, page measuring,
+// exports, saves). Tracked so shutdown can stop and join them too: a thread still inside MuPDF
+// when the C library destructors run crashes the exit.
+static DOCUMENT_THREADS: Mutex<Vec<thread::JoinHandle<()>>> = Mutex::new(Vec::new());
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+// Run `f` on a tracked thread of its own, releasing its per-thread Documents on the way out.
+pub(crate) fn spawn_document_thread(f: impl FnOnce() + Send + 'static) {
+    let handle = thread::spawn(move || {
+        f();
+        crate::mupdf_render::release_thread_resources();
+        crate::djvu::release_thread_resources();
+    });
+    let mut threads = DOCUMENT_THREADS.lock().unwrap();
+    threads.retain(|thread| !thread.is_finished());
+    threads.push(handle);
+}
+
+// Set at shutdown; a document thread checks it between pages and gives up.
+pub(crate) fn stopping() -> bool {
+    STOPPING.load(Ordering::Relaxed)
+}
+
+// Stop the document threads and wait up to `grace` for them. True once they have all exited.
+pub(crate) fn stop_document_threads(grace: Duration) -> bool {
+    STOPPING.store(true, Ordering::Relaxed);
+    let deadline = Instant::now() + grace;
+    let threads = std::mem::take(&mut *DOCUMENT_THREADS.lock().unwrap());
+    for thread in threads {
+        while !thread.is_finished() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        let _ = thread.join();
+    }
+    true
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -294,7 +457,9 @@ mod tests {
             uri: tag.to_string(),
             client: 0,
             page: 0,
-            job: Box::new(|| {}),
+            unranged: false,
+            cancel: Cancel::new(),
+            job: Box::new(|_| {}),
         }
     }
 
@@ -303,7 +468,18 @@ mod tests {
             uri: String::new(),
             client,
             page,
-            job: Box::new(|| {}),
+            unranged: false,
+            cancel: Cancel::new(),
+            job: Box::new(|_| {}),
+        }
+    }
+
+    fn running(client: u64, page: i32, unranged: bool) -> Running {
+        Running {
+            client,
+            page,
+            unranged,
+            cancel: Cancel::new(),
         }
     }
 
@@ -334,11 +510,12 @@ mod tests {
         q.push(RenderPriority::Preview, req("pv2"));
         q.push(RenderPriority::Visible, req("v2"));
         q.push(RenderPriority::VisiblePreview, req("vp2"));
+        q.push(RenderPriority::Background, req("bg1"));
 
-        // Visible work comes first. Full nearby pages precede distant previews.
+        // Visible work comes first. Full nearby pages precede distant previews; a sweep goes last.
         assert_eq!(
             drain(&mut q),
-            vec!["vp2", "vp1", "v2", "v1", "pf2", "pf1", "pv2", "pv1"]
+            vec!["vp2", "vp1", "v2", "v1", "pf2", "pf1", "pv2", "pv1", "bg1"]
         );
     }
 
@@ -380,6 +557,20 @@ mod tests {
         assert_eq!(drain_pages(&mut q), vec![200, 100]);
     }
 
+    #[test]
+    fn background_jobs_ignore_the_range_and_survive_a_zoom() {
+        let mut q = RenderQueue::new(4, 4, 4, 4);
+        q.wanted.insert(1, (10, 20));
+        for page in 0..6 {
+            let mut req = req_cp(1, page * 100);
+            req.unranged = RenderPriority::Background.ignores_wanted_range();
+            q.push(RenderPriority::Background, req);
+        }
+        q.clear_full(1);
+        // uncapped, unfiltered, newest first
+        assert_eq!(drain_pages(&mut q), vec![500, 400, 300, 200, 100, 0]);
+    }
+
     #[test]
     fn range_is_per_window() {
         let mut q = RenderQueue::new(4, 4, 4, 4);
@@ -411,6 +602,59 @@ mod tests {
         assert_eq!(drain_pages(&mut q), vec![4]);
     }
 
+    #[test]
+    fn leaving_the_wanted_range_aborts_running_full_renders() {
+        let mut q = RenderQueue::new(4, 4, 4, 4);
+        q.running.push(running(1, 15, false));
+        q.running.push(running(1, 100, false));
+        q.running.push(running(1, 200, true)); // a preview: kept
+        q.running.push(running(2, 300, false)); // another window: kept
+        q.set_wanted(1, Some((10, 20)));
+        let cancelled: Vec<_> = q
+            .running
+            .iter()
+            .map(|job| job.cancel.is_cancelled())
+            .collect();
+        assert_eq!(cancelled, vec![false, true, false, false]);
+    }
+
+    #[test]
+    fn clear_all_aborts_the_clients_running_jobs() {
+        let mut q = RenderQueue::new(4, 4, 4, 4);
+        q.running.push(running(1, 1, true));
+        q.running.push(running(2, 2, false));
+        q.clear_all(1);
+        let cancelled: Vec<_> = q
+            .running
+            .iter()
+            .map(|job| job.cancel.is_cancelled())
+            .collect();
+        assert_eq!(cancelled, vec![true, false]);
+    }
+
+    #[test]
+    fn shutdown_aborts_the_running_job_and_waits_for_the_workers() {
+        let pool = RenderPool::new(2, 4, 4, 4, 4);
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        pool.submit(
+            "",
+            1,
+            0,
+            RenderPriority::Visible,
+            Box::new(move |cancel| {
+                started_tx.send(()).unwrap();
+                while !cancel.is_cancelled() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }),
+        );
+        started_rx.recv().unwrap();
+        assert!(pool.shutdown(Duration::from_secs(5)));
+        // nothing is queued once the pool is shut down
+        pool.submit("", 1, 0, RenderPriority::Visible, Box::new(|_| panic!()));
+        assert!(drain(&mut pool.inner.0.lock().unwrap()).is_empty());
+    }
+
     #[test]
     fn grow_spawns_missing_threads() {
         let p = plan_resize(2, 0, 5);
@@ -15,13 +15,48 @@ pub const DEFAULT_RENDER_CACHE_MB: usize = 64;
 pub const MIN_RENDER_CACHE_MB: usize = 32;
 pub const MAX_RENDER_CACHE_MB: usize = 512;
 
+// Resolution region snapshots are rendered at: print-quality by default, without the pixel count of
+// a full-page 600 dpi render.
+pub const DEFAULT_SNAPSHOT_DPI: u32 = 150;
+pub const MIN_SNAPSHOT_DPI: u32 = 72;
+pub const MAX_SNAPSHOT_DPI: u32 = 600;
+
+// Pen for freehand ink: red, a little heavier than a hairline. Width in points.
+pub const DEFAULT_PEN_COLOR: u32 = 0xe0_1b_24;
+pub const DEFAULT_PEN_WIDTH: f64 = 2.0;
+pub const MIN_PEN_WIDTH: f64 = 0.5;
+pub const MAX_PEN_WIDTH: f64 = 20.0;
+
+// MuPDF anti-aliasing, in bits: 0 is off, 8 (its default) the smoothest.
+pub const MAX_AA_BITS: i32 = 8;
+
+// Gamma for grey pixels: 1.0 leaves pages as drawn; higher darkens thin fonts' grey edges (and any
+// greyscale picture with them).
+pub const MIN_GREY_GAMMA: f64 = 1.0;
+pub const MAX_GREY_GAMMA: f64 = 2.5;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Config {
     pub render_threads: usize,
     pub preview_cache_pages: usize,
     pub render_cache_mb: usize,
+    // resident size past which caches shrink as under a low-memory warning; None leaves it to the
+    // system's warnings
+    pub rss_limit_mb: Option<usize>,
     pub animate_scroll: bool,
     pub dark_mode: bool,
+    // anti-aliasing bits for line art and for text
+    pub graphics_aa: i32,
+    pub text_aa: i32,
+    pub grey_gamma: f64,
+    // stretched renders are filtered smoothly rather than showing their pixels
+    pub smooth_images: bool,
+    pub snapshot_dpi: u32,
+    // recolour region snapshots like dark-mode pages
+    pub snapshot_dark: bool,
+    // 0xRRGGBB
+    pub pen_color: u32,
+    pub pen_width: f64,
     pub dismissed_notice: Option<u64>,
     pub geometry: Option<Geometry>,
 }
@@ -40,14 +75,33 @@ impl Default for Config {
             render_threads: DEFAULT_RENDER_THREADS,
             preview_cache_pages: DEFAULT_PREVIEW_CACHE_PAGES,
             render_cache_mb: default_render_cache_mb(),
+            rss_limit_mb: None,
             animate_scroll: true,
             dark_mode: false,
+            graphics_aa: MAX_AA_BITS,
+            text_aa: MAX_AA_BITS,
+            grey_gamma: MIN_GREY_GAMMA,
+            smooth_images: true,
+            snapshot_dpi: DEFAULT_SNAPSHOT_DPI,
+            snapshot_dark: false,
+            pen_color: DEFAULT_PEN_COLOR,
+            pen_width: DEFAULT_PEN_WIDTH,
             dismissed_notice: None,
             geometry: None,
         }
     }
 }
 
+impl Config {
+    pub fn render_quality(&self) -> crate::mupdf_render::RenderQuality {
+        crate::mupdf_render::RenderQuality {
+            graphics_aa: self.graphics_aa,
+            text_aa: self.text_aa,
+            grey_gamma: self.grey_gamma,
+        }
+    }
+}
+
 fn config_file_path() -> Option<PathBuf> {
     #[cfg(test)]
     if let Some(path) = test_config_path() {
@@ -131,8 +185,17 @@ pub fn load_config() -> Config {
     let mut render_threads = DEFAULT_RENDER_THREADS;
     let mut preview_cache_pages = DEFAULT_PREVIEW_CACHE_PAGES;
     let mut render_cache_mb = default_render_cache_mb();
+    let mut rss_limit_mb = None;
     let mut animate_scroll = true;
     let mut dark_mode = false;
+    let mut graphics_aa = MAX_AA_BITS;
+    let mut text_aa = MAX_AA_BITS;
+    let mut grey_gamma = MIN_GREY_GAMMA;
+    let mut smooth_images = true;
+    let mut snapshot_dpi = DEFAULT_SNAPSHOT_DPI;
+    let mut snapshot_dark = false;
+    let mut pen_color = DEFAULT_PEN_COLOR;
+    let mut pen_width = DEFAULT_PEN_WIDTH;
     let mut dismissed_notice = None;
     let mut width = None;
     let mut height = None;
@@ -155,8 +218,43 @@ pub fn load_config() -> Config {
                     render_cache_mb = n;
                 }
             }
+            Some(("rss_limit_mb", v)) => {
+                rss_limit_mb = v.trim().parse::<usize>().ok().filter(|&mb| mb > 0);
+            }
             Some(("animate_scroll", v)) => animate_scroll = v.trim().parse().unwrap_or(true),
             Some(("dark_mode", v)) => dark_mode = v.trim().parse().unwrap_or(false),
+            Some(("graphics_aa", v)) => {
+                if let Ok(bits) = v.trim().parse() {
+                    graphics_aa = bits;
+                }
+            }
+            Some(("text_aa", v)) => {
+                if let Ok(bits) = v.trim().parse() {
+                    text_aa = bits;
+                }
+            }
+            Some(("grey_gamma", v)) => {
+                if let Some(gamma) = v.trim().parse::<f64>().ok().filter(|g| g.is_finite()) {
+                    grey_gamma = gamma;
+                }
+            }
+            Some(("smooth_images", v)) => smooth_images = v.trim().parse().unwrap_or(true),
+            Some(("snapshot_dpi", v)) => {
+                if let Ok(n) = v.trim().parse::<u32>() {
+                    snapshot_dpi = n;
+                }
+            }
+            Some(("snapshot_dark", v)) => snapshot_dark = v.trim().parse().unwrap_or(false),
+            Some(("pen_color", v)) => {
+                if let Ok(color) = u32::from_str_radix(v.trim(), 16) {
+                    pen_color = color & 0xff_ff_ff;
+                }
+            }
+            Some(("pen_width", v)) => {
+                if let Some(width) = v.trim().parse::<f64>().ok().filter(|w| w.is_finite()) {
+                    pen_width = width;
+                }
+            }
             Some(("dismissed_notice", v)) => {
                 dismissed_notice = u64::from_str_radix(v.trim(), 16).ok();
             }
@@ -180,8 +278,17 @@ pub fn load_config() -> Config {
         render_threads: render_threads.clamp(1, max_render_threads()),
         preview_cache_pages,
         render_cache_mb: render_cache_mb.clamp(MIN_RENDER_CACHE_MB, MAX_RENDER_CACHE_MB),
+        rss_limit_mb,
         animate_scroll,
         dark_mode,
+        graphics_aa: graphics_aa.clamp(0, MAX_AA_BITS),
+        text_aa: text_aa.clamp(0, MAX_AA_BITS),
+        grey_gamma: grey_gamma.clamp(MIN_GREY_GAMMA, MAX_GREY_GAMMA),
+        smooth_images,
+        snapshot_dpi: snapshot_dpi.clamp(MIN_SNAPSHOT_DPI, MAX_SNAPSHOT_DPI),
+        snapshot_dark,
+        pen_color,
+        pen_width: pen_width.clamp(MIN_PEN_WIDTH, MAX_PEN_WIDTH),
         dismissed_notice,
         geometry,
     }
@@ -199,8 +306,19 @@ pub fn save_config(config: &Config) -> io::Result<()> {
         config.preview_cache_pages
     ));
     out.push_str(&format!("render_cache_mb={}\n", config.render_cache_mb));
+    if let Some(mb) = config.rss_limit_mb {
+        out.push_str(&format!("rss_limit_mb={mb}\n"));
+    }
     out.push_str(&format!("animate_scroll={}\n", config.animate_scroll));
     out.push_str(&format!("dark_mode={}\n", config.dark_mode));
+    out.push_str(&format!("graphics_aa={}\n", config.graphics_aa));
+    out.push_str(&format!("text_aa={}\n", config.text_aa));
+    out.push_str(&format!("grey_gamma={}\n", config.grey_gamma));
+    out.push_str(&format!("smooth_images={}\n", config.smooth_images));
+    out.push_str(&format!("snapshot_dpi={}\n", config.snapshot_dpi));
+    out.push_str(&format!("snapshot_dark={}\n", config.snapshot_dark));
+    out.push_str(&format!("pen_color={:06x}\n", config.pen_color));
+    out.push_str(&format!("pen_width={}\n", config.pen_width));
     if let Some(notice) = config.dismissed_notice {
         out.push_str(&format!("dismissed_notice={notice:016x}\n"));
     }
@@ -245,8 +363,17 @@ mod tests {
             render_threads: 1,
             preview_cache_pages: 120,
             render_cache_mb: 256,
+            rss_limit_mb: Some(1500),
             animate_scroll: false,
             dark_mode: true,
+            graphics_aa: 4,
+            text_aa: 0,
+            grey_gamma: 1.6,
+            smooth_images: false,
+            snapshot_dpi: 300,
+            snapshot_dark: true,
+            pen_color: 0x33_66_99,
+            pen_width: 4.5,
             dismissed_notice: Some(0x1234_5678_90ab_cdef),
             geometry: Some(Geometry {
                 width: 1000,
@@ -259,8 +386,16 @@ mod tests {
         assert_eq!(loaded.render_threads, 1);
         assert_eq!(loaded.preview_cache_pages, 120);
         assert_eq!(loaded.render_cache_mb, 256);
+        assert_eq!(loaded.rss_limit_mb, Some(1500));
         assert!(!loaded.animate_scroll);
         assert!(loaded.dark_mode);
+        assert_eq!((loaded.graphics_aa, loaded.text_aa), (4, 0));
+        assert_eq!(loaded.grey_gamma, 1.6);
+        assert!(!loaded.smooth_images);
+        assert_eq!(loaded.snapshot_dpi, 300);
+        assert!(loaded.snapshot_dark);
+        assert_eq!(loaded.pen_color, 0x33_66_99);
+        assert_eq!(loaded.pen_width, 4.5);
         assert_eq!(loaded.dismissed_notice, Some(0x1234_5678_90ab_cdef));
         let g = loaded.geometry.expect("geometry persisted");
         assert_eq!((g.width, g.height, g.maximized), (1000, 700, true));
@@ -270,16 +405,32 @@ mod tests {
             render_threads: 9999,
             preview_cache_pages: DEFAULT_PREVIEW_CACHE_PAGES,
             render_cache_mb: DEFAULT_RENDER_CACHE_MB,
+            rss_limit_mb: None,
             animate_scroll: true,
             dark_mode: false,
+            graphics_aa: 16,
+            text_aa: -1,
+            grey_gamma: 9.0,
+            smooth_images: true,
+            snapshot_dpi: 5000,
+            snapshot_dark: false,
+            pen_color: DEFAULT_PEN_COLOR,
+            pen_width: 100.0,
             dismissed_notice: None,
             geometry: None,
         })
         .unwrap();
         let loaded = load_config();
         assert_eq!(loaded.render_threads, max_render_threads());
+        assert!(loaded.rss_limit_mb.is_none());
         assert!(loaded.animate_scroll);
         assert!(!loaded.dark_mode);
+        assert_eq!((loaded.graphics_aa, loaded.text_aa), (MAX_AA_BITS, 0));
+        assert_eq!(loaded.grey_gamma, MAX_GREY_GAMMA);
+        assert!(loaded.smooth_images);
+        assert_eq!(loaded.snapshot_dpi, MAX_SNAPSHOT_DPI);
+        assert!(!loaded.snapshot_dark);
+        assert_eq!(loaded.pen_width, MAX_PEN_WIDTH);
         assert!(loaded.dismissed_notice.is_none());
         assert!(loaded.geometry.is_none());
     }
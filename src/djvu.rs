@@ -0,0 +1,1047 @@
+// DjVu documents, read with djvulibre's ddjvuapi. The library is loaded at runtime so the viewer
+// builds and runs without it; a DjVu file just doesn't open when it's missing. Pages are rendered at
+// their INFO resolution scaled to the requested size, and the hidden text layer (word boxes) stands
+// in for MuPDF's structured text, for selection and search alike.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_uint, c_ulong, c_void, CStr, CString};
+use std::path::Path;
+use std::sync::RwLock;
+
+use libloading::Library;
+use once_cell::sync::Lazy;
+
+use crate::backend::RenderBackend;
+use crate::dylib::symbol;
+use crate::links::LinkTarget;
+use crate::mupdf_render::{Cancel, PagePixels, PixelRect};
+use crate::outline::OutlineEntry;
+use crate::page::Rectangle;
+use crate::selection::Glyph;
+
+// The soname djvulibre has shipped under since 3.5.22, then the unversioned development link.
+const LIBRARY_NAMES: [&str; 3] = [
+    "libdjvulibre.so.21",
+    "libdjvulibre.21.dylib",
+    "libdjvulibre.so",
+];
+
+// ddjvu_status_t: a job is done once its status reaches JOB_OK; anything past it is a failure.
+const JOB_OK: c_int = 2;
+
+// ddjvu_format_style_t DDJVU_FORMAT_RGBMASK32, ddjvu_render_mode_t DDJVU_RENDER_COLOR and
+// ddjvu_page_rotation_t DDJVU_ROTATE_0.
+const FORMAT_RGBMASK32: c_int = 3;
+const RENDER_COLOR: c_int = 0;
+const ROTATE_0: c_int = 0;
+
+// Resolution a page is taken to have when its INFO chunk leaves it out, as djvulibre does.
+const DEFAULT_DPI: i32 = 300;
+
+type Handle = *mut c_void;
+// miniexp_t, djvulibre's lisp value
+type Exp = *mut c_void;
+
+// miniexp_dummy: what a text or outline request returns while the data is still decoding.
+const DUMMY: Exp = 2 as Exp;
+
+#[repr(C)]
+#[derive(Default)]
+struct PageInfo {
+    width: c_int,
+    height: c_int,
+    dpi: c_int,
+    rotation: c_int,
+    version: c_int,
+}
+
+#[repr(C)]
+struct FileInfo {
+    kind: c_char,
+    pageno: c_int,
+    size: c_int,
+    id: *const c_char,
+    name: *const c_char,
+    title: *const c_char,
+}
+
+#[repr(C)]
+struct Rect {
+    x: c_int,
+    y: c_int,
+    w: c_uint,
+    h: c_uint,
+}
+
+// The ddjvuapi and miniexp entry points in use. Many of ddjvuapi.h's calls are macros over these
+// (ddjvu_document_get_pageinfo, ddjvu_page_release, ...), so they're spelled out at the call sites.
+struct Api {
+    context_create: unsafe extern "C" fn(*const c_char) -> Handle,
+    context_release: unsafe extern "C" fn(Handle),
+    message_wait: unsafe extern "C" fn(Handle) -> *const c_void,
+    message_peek: unsafe extern "C" fn(Handle) -> *const c_void,
+    message_pop: unsafe extern "C" fn(Handle),
+    document_create_by_filename_utf8: unsafe extern "C" fn(Handle, *const c_char, c_int) -> Handle,
+    document_job: unsafe extern "C" fn(Handle) -> Handle,
+    job_status: unsafe extern "C" fn(Handle) -> c_int,
+    job_release: unsafe extern "C" fn(Handle),
+    document_get_pagenum: unsafe extern "C" fn(Handle) -> c_int,
+    document_get_pageinfo_imp: unsafe extern "C" fn(Handle, c_int, *mut PageInfo, c_uint) -> c_int,
+    document_get_filenum: unsafe extern "C" fn(Handle) -> c_int,
+    document_get_fileinfo_imp: unsafe extern "C" fn(Handle, c_int, *mut FileInfo, c_uint) -> c_int,
+    document_get_pagetext: unsafe extern "C" fn(Handle, c_int, *const c_char) -> Exp,
+    document_get_outline: unsafe extern "C" fn(Handle) -> Exp,
+    miniexp_release: unsafe extern "C" fn(Handle, Exp),
+    page_create_by_pageno: unsafe extern "C" fn(Handle, c_int) -> Handle,
+    page_job: unsafe extern "C" fn(Handle) -> Handle,
+    page_set_rotation: unsafe extern "C" fn(Handle, c_int),
+    #[allow(clippy::type_complexity)]
+    page_render: unsafe extern "C" fn(
+        Handle,
+        c_int,
+        *const Rect,
+        *const Rect,
+        Handle,
+        c_ulong,
+        *mut c_char,
+    ) -> c_int,
+    format_create: unsafe extern "C" fn(c_int, c_int, *const c_uint) -> Handle,
+    format_set_row_order: unsafe extern "C" fn(Handle, c_int),
+    format_set_y_direction: unsafe extern "C" fn(Handle, c_int),
+    format_release: unsafe extern "C" fn(Handle),
+    miniexp_stringp: unsafe extern "C" fn(Exp) -> c_int,
+    miniexp_to_str: unsafe extern "C" fn(Exp) -> *const c_char,
+    miniexp_to_name: unsafe extern "C" fn(Exp) -> *const c_char,
+    // the functions above point into it
+    _library: Library,
+}
+
+static API: Lazy<Option<Api>> = Lazy::new(|| {
+    let library = crate::dylib::open(&LIBRARY_NAMES)?;
+    // SAFETY: each symbol is given its prototype from ddjvuapi.h / miniexp.h
+    unsafe {
+        Some(Api {
+            context_create: symbol(&library, b"ddjvu_context_create\0")?,
+            context_release: symbol(&library, b"ddjvu_context_release\0")?,
+            message_wait: symbol(&library, b"ddjvu_message_wait\0")?,
+            message_peek: symbol(&library, b"ddjvu_message_peek\0")?,
+            message_pop: symbol(&library, b"ddjvu_message_pop\0")?,
+            document_create_by_filename_utf8: symbol(
+                &library,
+                b"ddjvu_document_create_by_filename_utf8\0",
+            )?,
+            document_job: symbol(&library, b"ddjvu_document_job\0")?,
+            job_status: symbol(&library, b"ddjvu_job_status\0")?,
+            job_release: symbol(&library, b"ddjvu_job_release\0")?,
+            document_get_pagenum: symbol(&library, b"ddjvu_document_get_pagenum\0")?,
+            document_get_pageinfo_imp: symbol(&library, b"ddjvu_document_get_pageinfo_imp\0")?,
+            document_get_filenum: symbol(&library, b"ddjvu_document_get_filenum\0")?,
+            document_get_fileinfo_imp: symbol(&library, b"ddjvu_document_get_fileinfo_imp\0")?,
+            document_get_pagetext: symbol(&library, b"ddjvu_document_get_pagetext\0")?,
+            document_get_outline: symbol(&library, b"ddjvu_document_get_outline\0")?,
+            miniexp_release: symbol(&library, b"ddjvu_miniexp_release\0")?,
+            page_create_by_pageno: symbol(&library, b"ddjvu_page_create_by_pageno\0")?,
+            page_job: symbol(&library, b"ddjvu_page_job\0")?,
+            page_set_rotation: symbol(&library, b"ddjvu_page_set_rotation\0")?,
+            page_render: symbol(&library, b"ddjvu_page_render\0")?,
+            format_create: symbol(&library, b"ddjvu_format_create\0")?,
+            format_set_row_order: symbol(&library, b"ddjvu_format_set_row_order\0")?,
+            format_set_y_direction: symbol(&library, b"ddjvu_format_set_y_direction\0")?,
+            format_release: symbol(&library, b"ddjvu_format_release\0")?,
+            miniexp_stringp: symbol(&library, b"miniexp_stringp\0")?,
+            miniexp_to_str: symbol(&library, b"miniexp_to_str\0")?,
+            miniexp_to_name: symbol(&library, b"miniexp_to_name\0")?,
+            _library: library,
+        })
+    }
+});
+
+// Whether djvulibre could be loaded, i.e. DjVu files can be opened.
+pub(crate) fn available() -> bool {
+    API.is_some()
+}
+
+// Whether `header`, a file's first bytes, starts a DjVu document: an IFF FORM of a single page
+// (DJVU) or a bundled one (DJVM).
+pub(crate) fn is_djvu(header: &[u8]) -> bool {
+    header.len() >= 16
+        && header.starts_with(b"AT&TFORM")
+        && matches!(&header[12..16], b"DJVU" | b"DJVM")
+}
+
+// An open document and its context. ddjvuapi decodes on threads of its own and reports through the
+// context's message queue, which whoever waits on a job pumps.
+struct Document {
+    api: &'static Api,
+    context: Handle,
+    document: Handle,
+    // (width, height, dpi) of each page in pixels, read on first use
+    pages: Vec<Option<(i32, i32, i32)>>,
+}
+
+impl Document {
+    fn open(path: &Path) -> Option<Self> {
+        let api = API.as_ref()?;
+        let filename = CString::new(path.to_str()?).ok()?;
+        // SAFETY: the handles are released by Drop, once
+        unsafe {
+            let context = (api.context_create)(c"scrolex".as_ptr());
+            if context.is_null() {
+                return None;
+            }
+            let document = (api.document_create_by_filename_utf8)(context, filename.as_ptr(), 1);
+            if document.is_null() {
+                (api.context_release)(context);
+                return None;
+            }
+            let mut doc = Self {
+                api,
+                context,
+                document,
+                pages: Vec::new(),
+            };
+            if doc.wait((api.document_job)(document)) != JOB_OK {
+                return None;
+            }
+            doc.pages = vec![None; (api.document_get_pagenum)(document).max(0) as usize];
+            Some(doc)
+        }
+    }
+
+    // Pump messages until `job` is done; its final status.
+    fn wait(&self, job: Handle) -> c_int {
+        loop {
+            // SAFETY: `job` belongs to this document's context
+            let status = unsafe { (self.api.job_status)(job) };
+            if status >= JOB_OK {
+                return status;
+            }
+            self.pump();
+        }
+    }
+
+    // Wait for the next message and drop what has queued up. Nothing here reacts to messages; a
+    // caller rechecks whatever it was waiting for.
+    fn pump(&self) {
+        // SAFETY: the context is alive for as long as self
+        unsafe {
+            (self.api.message_wait)(self.context);
+            while !(self.api.message_peek)(self.context).is_null() {
+                (self.api.message_pop)(self.context);
+            }
+        }
+    }
+
+    fn page_count(&self) -> i32 {
+        self.pages.len() as i32
+    }
+
+    // Page size in pixels and its resolution.
+    fn page_info(&mut self, page_num: i32) -> Option<(i32, i32, i32)> {
+        let slot = usize::try_from(page_num).ok()?;
+        if let Some(info) = *self.pages.get(slot)? {
+            return Some(info);
+        }
+        let mut info = PageInfo::default();
+        let status = loop {
+            // SAFETY: `info` is as large as the size passed
+            let status = unsafe {
+                (self.api.document_get_pageinfo_imp)(
+                    self.document,
+                    page_num,
+                    &mut info,
+                    std::mem::size_of::<PageInfo>() as c_uint,
+                )
+            };
+            if status >= JOB_OK {
+                break status;
+            }
+            self.pump();
+        };
+        if status != JOB_OK || info.width <= 0 || info.height <= 0 {
+            return None;
+        }
+        let dpi = if info.dpi > 0 { info.dpi } else { DEFAULT_DPI };
+        self.pages[slot] = Some((info.width, info.height, dpi));
+        Some((info.width, info.height, dpi))
+    }
+
+    // Page size in points.
+    fn page_size(&mut self, page_num: i32) -> Option<(f64, f64)> {
+        let (width, height, dpi) = self.page_info(page_num)?;
+        Some((points(width, dpi), points(height, dpi)))
+    }
+
+    // `region` of the page drawn `full` pixels large, as cairo Rgb24. Left white where the page has
+    // nothing to draw (a page of text layer only) and past its edges. Pages are drawn as stored,
+    // ignoring INFO's initial rotation, so the text layer's boxes keep matching the pixels.
+    fn render(&self, page_num: i32, full: (i32, i32), region: PixelRect) -> Option<Vec<u8>> {
+        let (width, height) = (region.x1 - region.x0, region.y1 - region.y0);
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+        let stride = width as usize * 4;
+        let mut data = vec![0xffu8; stride * height as usize];
+
+        let (x0, y0) = (region.x0.max(0), region.y0.max(0));
+        let (x1, y1) = (region.x1.min(full.0), region.y1.min(full.1));
+        if x0 >= x1 || y0 >= y1 {
+            return Some(data);
+        }
+        let page_rect = Rect {
+            x: 0,
+            y: 0,
+            w: full.0 as c_uint,
+            h: full.1 as c_uint,
+        };
+        let render_rect = Rect {
+            x: x0,
+            y: y0,
+            w: (x1 - x0) as c_uint,
+            h: (y1 - y0) as c_uint,
+        };
+        let offset = (y0 - region.y0) as usize * stride + (x0 - region.x0) as usize * 4;
+
+        // SAFETY: the page and format are released here, the render writes render_rect's rows at
+        // `stride` from `offset`, all inside `data`
+        unsafe {
+            let page = (self.api.page_create_by_pageno)(self.document, page_num);
+            if page.is_null() {
+                return None;
+            }
+            let job = (self.api.page_job)(page);
+            if self.wait(job) != JOB_OK {
+                (self.api.job_release)(job);
+                return None;
+            }
+            (self.api.page_set_rotation)(page, ROTATE_0);
+            // native-endian 0x00RRGGBB words, which is what cairo Rgb24 is
+            let masks: [c_uint; 3] = [0xff0000, 0x00ff00, 0x0000ff];
+            let format = (self.api.format_create)(FORMAT_RGBMASK32, 3, masks.as_ptr());
+            if !format.is_null() {
+                (self.api.format_set_row_order)(format, 1);
+                (self.api.format_set_y_direction)(format, 1);
+                (self.api.page_render)(
+                    page,
+                    RENDER_COLOR,
+                    &page_rect,
+                    &render_rect,
+                    format,
+                    stride as c_ulong,
+                    data.as_mut_ptr().add(offset).cast(),
+                );
+                (self.api.format_release)(format);
+            }
+            (self.api.job_release)(job);
+            (!format.is_null()).then_some(data)
+        }
+    }
+
+    // The page's hidden text as a zone tree, None if it has none.
+    fn text(&self, page_num: i32) -> Option<Zone> {
+        // SAFETY: the expression is read before it's released
+        unsafe {
+            let exp = self.query(|| {
+                (self.api.document_get_pagetext)(self.document, page_num, std::ptr::null())
+            });
+            let zone = self.zone(exp);
+            (self.api.miniexp_release)(self.document, exp);
+            zone
+        }
+    }
+
+    fn outline(&self) -> Vec<Bookmark> {
+        // SAFETY: as for text
+        unsafe {
+            let exp = self.query(|| (self.api.document_get_outline)(self.document));
+            // (bookmarks entry...)
+            let bookmarks = list(exp)
+                .into_iter()
+                .skip(1)
+                .filter_map(|entry| self.bookmark(entry))
+                .collect();
+            (self.api.miniexp_release)(self.document, exp);
+            bookmarks
+        }
+    }
+
+    // Resolve an outline url to a 1-based page: "#12" is the page number, anything else after the
+    // '#' a page file's id, name or title.
+    fn resolve(&self, url: &str) -> Option<i32> {
+        let target = url.strip_prefix('#')?;
+        if let Ok(page) = target.parse::<i32>() {
+            return (1..=self.page_count()).contains(&page).then_some(page);
+        }
+        // SAFETY: fileinfo strings are owned by the document and read right away
+        unsafe {
+            let files = (self.api.document_get_filenum)(self.document);
+            (0..files).find_map(|file| {
+                let mut info = FileInfo {
+                    kind: 0,
+                    pageno: -1,
+                    size: 0,
+                    id: std::ptr::null(),
+                    name: std::ptr::null(),
+                    title: std::ptr::null(),
+                };
+                let status = (self.api.document_get_fileinfo_imp)(
+                    self.document,
+                    file,
+                    &mut info,
+                    std::mem::size_of::<FileInfo>() as c_uint,
+                );
+                let named = [info.id, info.name, info.title]
+                    .into_iter()
+                    .any(|s| !s.is_null() && CStr::from_ptr(s).to_bytes() == target.as_bytes());
+                (status == JOB_OK && info.pageno >= 0 && named).then_some(info.pageno + 1)
+            })
+        }
+    }
+
+    // Run a request that answers miniexp_dummy until its data has decoded.
+    fn query(&self, request: impl Fn() -> Exp) -> Exp {
+        loop {
+            let exp = request();
+            if exp != DUMMY {
+                return exp;
+            }
+            self.pump();
+        }
+    }
+
+    // (kind xmin ymin xmax ymax "text") or (kind xmin ymin xmax ymax zone...).
+    unsafe fn zone(&self, exp: Exp) -> Option<Zone> {
+        let items = list(exp);
+        let kind = self.symbol(*items.first()?)?;
+        let rect = [
+            number(*items.get(1)?)?,
+            number(*items.get(2)?)?,
+            number(*items.get(3)?)?,
+            number(*items.get(4)?)?,
+        ];
+        let rest = &items[5..];
+        let text = rest.first().and_then(|&item| self.string(item));
+        let children = match text {
+            Some(_) => Vec::new(),
+            None => rest.iter().filter_map(|&item| self.zone(item)).collect(),
+        };
+        Some(Zone {
+            kind,
+            rect,
+            text,
+            children,
+        })
+    }
+
+    // ("title" "url" entry...).
+    unsafe fn bookmark(&self, exp: Exp) -> Option<Bookmark> {
+        let items = list(exp);
+        Some(Bookmark {
+            title: self.string(*items.first()?)?,
+            url: items.get(1).and_then(|&url| self.string(url)),
+            children: items
+                .iter()
+                .skip(2)
+                .filter_map(|&child| self.bookmark(child))
+                .collect(),
+        })
+    }
+
+    unsafe fn symbol(&self, exp: Exp) -> Option<String> {
+        if (exp as usize & 3) != 2 {
+            return None;
+        }
+        let name = (self.api.miniexp_to_name)(exp);
+        (!name.is_null()).then(|| CStr::from_ptr(name).to_string_lossy().into_owned())
+    }
+
+    unsafe fn string(&self, exp: Exp) -> Option<String> {
+        if (self.api.miniexp_stringp)(exp) == 0 {
+            return None;
+        }
+        let s = (self.api.miniexp_to_str)(exp);
+        (!s.is_null()).then(|| CStr::from_ptr(s).to_string_lossy().into_owned())
+    }
+}
+
+impl Drop for Document {
+    fn drop(&mut self) {
+        // SAFETY: ddjvu_document_release is ddjvu_job_release of the document's job; the context
+        // goes after everything made in it
+        unsafe {
+            (self.api.job_release)((self.api.document_job)(self.document));
+            (self.api.context_release)(self.context);
+        }
+    }
+}
+
+// miniexp_t is a tagged word (miniexp.h's accessors are inline, so they're redone here): pairs are
+// untagged pointers to a (car, cdr) pair, numbers have tag 3 and their value above it.
+fn list(exp: Exp) -> Vec<Exp> {
+    let mut items = Vec::new();
+    let mut rest = exp;
+    while !rest.is_null() && (rest as usize & 3) == 0 {
+        // SAFETY: a pair's two words, per the tag
+        unsafe {
+            let pair = rest as *const Exp;
+            items.push(*pair);
+            rest = *pair.add(1);
+        }
+    }
+    items
+}
+
+fn number(exp: Exp) -> Option<i32> {
+    ((exp as usize & 3) == 3).then_some((exp as isize >> 2) as i32)
+}
+
+fn points(pixels: i32, dpi: i32) -> f64 {
+    f64::from(pixels) * 72.0 / f64::from(dpi)
+}
+
+// A hidden-text zone: its kind (page, column, region, para, line, word, char), its box in page
+// pixels from the bottom-left corner, and either its text or the zones inside it.
+#[derive(Debug, Clone, PartialEq)]
+struct Zone {
+    kind: String,
+    rect: [i32; 4],
+    text: Option<String>,
+    children: Vec<Zone>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Bookmark {
+    title: String,
+    url: Option<String>,
+    children: Vec<Bookmark>,
+}
+
+// Flatten a page's zones into glyphs in reading order. A zone's text is spread evenly across its
+// box, since the layer rarely goes below words; neighbouring words on a line get a space glyph
+// spanning the gap between them. Lines and paragraphs (or regions and columns, if that's as far as
+// the layer goes) number the glyphs' lines and blocks.
+fn zone_glyphs(page: &Zone, height_px: i32, dpi: i32) -> Vec<Glyph> {
+    let mut walk = Walk {
+        height_px,
+        dpi,
+        glyphs: Vec::new(),
+        line: 0,
+        block: 0,
+    };
+    walk.zone(page);
+    walk.glyphs
+}
+
+struct Walk {
+    height_px: i32,
+    dpi: i32,
+    glyphs: Vec<Glyph>,
+    line: usize,
+    block: usize,
+}
+
+impl Walk {
+    fn zone(&mut self, zone: &Zone) {
+        let before = self.glyphs.len();
+        let (left, top, right, bottom) = self.to_points(zone.rect);
+        if zone.kind == "word" {
+            self.space_before(left, top, bottom);
+        }
+        match &zone.text {
+            Some(text) => self.text(text, (left, top, right, bottom)),
+            None => zone.children.iter().for_each(|child| self.zone(child)),
+        }
+        let block = matches!(zone.kind.as_str(), "para" | "region" | "column");
+        if self.glyphs.len() == before || !(block || zone.kind == "line") {
+            return;
+        }
+        // a line ends with its zone, or with the paragraph (region, column) it's loose in
+        if self
+            .glyphs
+            .last()
+            .is_some_and(|glyph| glyph.line == self.line)
+        {
+            self.line += 1;
+        }
+        if block {
+            self.block += 1;
+        }
+    }
+
+    // The rect's (left, top, right, bottom) in top-left points.
+    fn to_points(&self, [xmin, ymin, xmax, ymax]: [i32; 4]) -> (f64, f64, f64, f64) {
+        (
+            points(xmin, self.dpi),
+            points(self.height_px - ymax, self.dpi),
+            points(xmax, self.dpi),
+            points(self.height_px - ymin, self.dpi),
+        )
+    }
+
+    fn space_before(&mut self, left: f64, top: f64, bottom: f64) {
+        let Some(prev) = self.glyphs.last() else {
+            return;
+        };
+        if prev.line != self.line || prev.ch.is_whitespace() {
+            return;
+        }
+        let x0 = prev.bbox.2;
+        self.push(' ', (x0, top, left.max(x0), bottom));
+    }
+
+    fn text(&mut self, text: &str, (left, top, right, bottom): (f64, f64, f64, f64)) {
+        let chars: Vec<char> = text.chars().filter(|ch| !ch.is_control()).collect();
+        let width = (right - left) / chars.len().max(1) as f64;
+        for (i, &ch) in chars.iter().enumerate() {
+            let x0 = left + width * i as f64;
+            self.push(ch, (x0, top, x0 + width, bottom));
+        }
+    }
+
+    fn push(&mut self, ch: char, bbox: (f64, f64, f64, f64)) {
+        self.glyphs.push(Glyph {
+            ch,
+            cx: (bbox.0 + bbox.2) / 2.0,
+            cy: (bbox.1 + bbox.3) / 2.0,
+            bbox,
+            baseline: bbox.3,
+            line: self.line,
+            block: self.block,
+        });
+    }
+}
+
+// Flatten bookmarks depth-first, like a PDF outline; `resolve` maps a url to a 1-based page.
+fn flatten(
+    bookmarks: &[Bookmark],
+    depth: u32,
+    resolve: &impl Fn(&str) -> Option<i32>,
+) -> Vec<OutlineEntry> {
+    let mut out = Vec::new();
+    for bookmark in bookmarks {
+        out.push(OutlineEntry {
+            title: bookmark.title.clone(),
+            depth,
+            page: bookmark.url.as_deref().and_then(resolve),
+        });
+        out.extend(flatten(&bookmark.children, depth + 1, resolve));
+    }
+    out
+}
+
+thread_local! {
+    // (uri, generation-at-open, Document). One per thread, each with its own context, like a MuPDF
+    // worker's: render threads decode pages side by side instead of queueing on one document.
+    static DOC: RefCell<Option<(String, u64, Document)>> = const { RefCell::new(None) };
+}
+
+// Close this thread's document, as mupdf_render::release_thread_resources does MuPDF's.
+pub(crate) fn release_thread_resources() {
+    DOC.with(|cell| cell.borrow_mut().take());
+}
+
+// The DjVu backend. Documents are per thread (see DOC); page sizes, which the main thread asks for
+// while laying out, are shared, so once any thread has read one no document is needed for it.
+#[derive(Default)]
+pub(crate) struct Djvu {
+    // (uri, generation) they belong to, and each page's size in points once read
+    sizes: RwLock<Option<(String, u64, Vec<Option<(f64, f64)>>)>>,
+}
+
+impl Djvu {
+    fn with_doc<T>(&self, uri: &str, f: impl FnOnce(&mut Document) -> Option<T>) -> Option<T> {
+        let generation = crate::mupdf_render::generation();
+        DOC.with(|cell| {
+            let mut open = cell.borrow_mut();
+            let fresh = open
+                .as_ref()
+                .is_some_and(|(u, g, _)| u == uri && *g == generation);
+            if !fresh {
+                // the old document goes first, so only one is held at a time
+                *open = None;
+                let path = crate::mupdf_render::local_path(uri)?;
+                *open = Some((uri.to_string(), generation, Document::open(&path)?));
+            }
+            f(&mut open.as_mut().unwrap().2)
+        })
+    }
+
+    fn known_size(&self, uri: &str, generation: u64, page_num: i32) -> Option<(f64, f64)> {
+        let sizes = self.sizes.read().unwrap();
+        let (u, g, sizes) = sizes.as_ref()?;
+        if u != uri || *g != generation {
+            return None;
+        }
+        *sizes.get(usize::try_from(page_num).ok()?)?
+    }
+
+    fn remember_size(
+        &self,
+        uri: &str,
+        generation: u64,
+        n_pages: i32,
+        page_num: i32,
+        size: (f64, f64),
+    ) {
+        let mut sizes = self.sizes.write().unwrap();
+        let fresh = sizes
+            .as_ref()
+            .is_some_and(|(u, g, _)| u == uri && *g == generation);
+        if !fresh {
+            let pages = vec![None; n_pages.max(0) as usize];
+            *sizes = Some((uri.to_string(), generation, pages));
+        }
+        let pages = &mut sizes.as_mut().unwrap().2;
+        if let Some(slot) = usize::try_from(page_num)
+            .ok()
+            .and_then(|i| pages.get_mut(i))
+        {
+            *slot = Some(size);
+        }
+    }
+
+    // `region` of the page at `scale`*`dsf`, grey gamma applied and recoloured if `dark`.
+    fn render(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        region: PixelRect,
+        dark: bool,
+    ) -> Option<PagePixels> {
+        let mut pixels = self.draw(uri, page_num, scale, dsf, region)?;
+        crate::mupdf_render::darken_rgb24(&mut pixels.data);
+        if dark {
+            crate::mupdf_render::recolor_rgb24(&mut pixels.data);
+        }
+        Some(pixels)
+    }
+
+    // `region` of the page at `scale`*`dsf`, as DjVuLibre draws it.
+    fn draw(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        region: PixelRect,
+    ) -> Option<PagePixels> {
+        self.with_doc(uri, |doc| {
+            let (w, h) = doc.page_size(page_num)?;
+            let full = ((w * scale * dsf) as i32, (h * scale * dsf) as i32);
+            let data = doc.render(page_num, full, region)?;
+            let width = region.x1 - region.x0;
+            Some(PagePixels {
+                data,
+                width,
+                height: region.y1 - region.y0,
+                stride: width * 4,
+            })
+        })
+    }
+}
+
+impl RenderBackend for Djvu {
+    fn probe(&self, path: &Path, pages: i32) -> Option<(i32, Option<f64>)> {
+        let mut doc = Document::open(path)?;
+        let n_pages = doc.page_count();
+        let tallest_page_height = (0..n_pages.min(pages))
+            .filter_map(|page_num| doc.page_size(page_num))
+            .map(|(_, height)| height)
+            .max_by(f64::total_cmp);
+        Some((n_pages, tallest_page_height))
+    }
+
+    fn page_count(&self, uri: &str) -> Option<i32> {
+        self.with_doc(uri, |doc| Some(doc.page_count()))
+    }
+
+    fn page_size(&self, uri: &str, page_num: i32) -> Option<(f64, f64)> {
+        let generation = crate::mupdf_render::generation();
+        if let Some(size) = self.known_size(uri, generation, page_num) {
+            return Some(size);
+        }
+        let (n_pages, size) = self.with_doc(uri, |doc| {
+            Some((doc.page_count(), doc.page_size(page_num)?))
+        })?;
+        self.remember_size(uri, generation, n_pages, page_num, size);
+        Some(size)
+    }
+
+    fn render_page(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        page_pt: Option<(f64, f64)>,
+        dark: bool,
+        cancel: Option<&Cancel>,
+    ) -> Option<PagePixels> {
+        let (w, h) = match page_pt {
+            Some(size) => size,
+            None => self.page_size(uri, page_num)?,
+        };
+        let width = ((w * scale * dsf) as i32).max(1);
+        let height = ((h * scale * dsf) as i32).max(1);
+        let region = PixelRect::new(0, 0, width, height);
+        self.render_tile(uri, page_num, scale, dsf, region, dark, cancel)
+    }
+
+    fn render_tile(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        region: PixelRect,
+        dark: bool,
+        cancel: Option<&Cancel>,
+    ) -> Option<PagePixels> {
+        if cancel.is_some_and(Cancel::is_cancelled) {
+            return None;
+        }
+        let pixels = self.render(uri, page_num, scale, dsf, region, dark)?;
+        (!cancel.is_some_and(Cancel::is_cancelled)).then_some(pixels)
+    }
+
+    fn render_regions(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        regions: &[PixelRect],
+        dark: bool,
+    ) -> Option<Vec<PagePixels>> {
+        regions
+            .iter()
+            .map(|&region| self.render(uri, page_num, scale, dsf, region, dark))
+            .collect()
+    }
+
+    fn text_page(&self, uri: &str, page_num: i32) -> Option<Vec<Glyph>> {
+        self.with_doc(uri, |doc| {
+            let (_, height, dpi) = doc.page_info(page_num)?;
+            Some(zone_glyphs(&doc.text(page_num)?, height, dpi))
+        })
+    }
+
+    // Map areas (DjVu's hyperlinks) aren't read.
+    fn links(&self, _uri: &str, _page_num: i32) -> Vec<(Rectangle, LinkTarget)> {
+        Vec::new()
+    }
+
+    fn outline(&self, uri: &str) -> Vec<OutlineEntry> {
+        self.with_doc(uri, |doc| {
+            let bookmarks = doc.outline();
+            Some(flatten(&bookmarks, 0, &|url| doc.resolve(url)))
+        })
+        .unwrap_or_default()
+    }
+
+    // Like MuPDF's: a small render scanned for non-white pixels.
+    fn content_bbox(&self, uri: &str, page_num: i32) -> Option<(f64, f64, f64, f64)> {
+        const SCALE: f64 = 0.2;
+        // drawn without the grey gamma, so the box doesn't move with that setting
+        let (w, h) = self.page_size(uri, page_num)?;
+        let region = PixelRect::new(
+            0,
+            0,
+            ((w * SCALE) as i32).max(1),
+            ((h * SCALE) as i32).max(1),
+        );
+        let px = self.draw(uri, page_num, SCALE, 1.0, region)?;
+        let (min_x, min_y, max_x, max_y) =
+            crate::mupdf_render::scan_bbox(&px.data, px.width, px.height, px.stride as usize)?;
+        Some((
+            min_x as f64 / SCALE,
+            min_y as f64 / SCALE,
+            (max_x + 1) as f64 / SCALE,
+            (max_y + 1) as f64 / SCALE,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One 600x300 px page at 300 dpi (144x72 pt), no image, and a text layer of one line:
+    // "Hello DjVu hello".
+    const TEXT_DJVU: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/text.djvu");
+
+    fn word(text: &str, xmin: i32, xmax: i32) -> Zone {
+        Zone {
+            kind: "word".into(),
+            rect: [xmin, 200, xmax, 250],
+            text: Some(text.into()),
+            children: Vec::new(),
+        }
+    }
+
+    fn zone(kind: &str, rect: [i32; 4], children: Vec<Zone>) -> Zone {
+        Zone {
+            kind: kind.into(),
+            rect,
+            text: None,
+            children,
+        }
+    }
+
+    fn text(glyphs: &[Glyph]) -> String {
+        glyphs.iter().map(|glyph| glyph.ch).collect()
+    }
+
+    #[test]
+    fn recognises_djvu_headers() {
+        assert!(is_djvu(b"AT&TFORM\0\0\0\x88DJVUINFO"));
+        assert!(is_djvu(b"AT&TFORM\0\0\x10\0DJVMDIRM"));
+        assert!(!is_djvu(b"AT&TFORM\0\0\0\x88DJVI"));
+        assert!(!is_djvu(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n"));
+        assert!(!is_djvu(b"AT&TFORM"));
+        assert!(is_djvu(&std::fs::read(TEXT_DJVU).unwrap()));
+    }
+
+    #[test]
+    fn zones_flatten_to_glyphs_in_points_from_the_top() {
+        let page = zone(
+            "page",
+            [0, 0, 600, 300],
+            vec![zone(
+                "line",
+                [50, 200, 550, 250],
+                vec![word("Hello", 50, 200), word("DjVu", 220, 350)],
+            )],
+        );
+        let glyphs = zone_glyphs(&page, 300, 144);
+
+        assert_eq!(text(&glyphs), "Hello DjVu");
+        // half a point a pixel, from the top; the word's box split evenly between its letters
+        let h = &glyphs[0];
+        assert_eq!(h.bbox, (25.0, 25.0, 40.0, 50.0));
+        assert_eq!(h.baseline, 50.0);
+        // the space fills the gap between the words
+        assert_eq!(glyphs[5].bbox, (100.0, 25.0, 110.0, 50.0));
+        assert!(glyphs
+            .iter()
+            .all(|glyph| glyph.line == 0 && glyph.block == 0));
+    }
+
+    #[test]
+    fn lines_and_paragraphs_number_the_glyphs() {
+        let para = |y: i32, lines: &[&str]| {
+            let lines = lines
+                .iter()
+                .enumerate()
+                .map(|(i, text)| {
+                    let y = y - 60 * i as i32;
+                    zone(
+                        "line",
+                        [0, y, 100, y + 50],
+                        vec![Zone {
+                            rect: [0, y, 100, y + 50],
+                            ..word(text, 0, 100)
+                        }],
+                    )
+                })
+                .collect();
+            zone("para", [0, 0, 100, 300], lines)
+        };
+        let page = zone(
+            "page",
+            [0, 0, 100, 300],
+            vec![para(240, &["one", "two"]), para(60, &["three"])],
+        );
+        let glyphs = zone_glyphs(&page, 300, 72);
+
+        // no spaces across lines
+        assert_eq!(text(&glyphs), "onetwothree");
+        let numbers: Vec<_> = glyphs
+            .iter()
+            .map(|glyph| (glyph.line, glyph.block))
+            .collect();
+        assert_eq!(&numbers[..4], &[(0, 0), (0, 0), (0, 0), (1, 0)]);
+        assert_eq!(numbers[6], (2, 1));
+        // 72 dpi: a pixel is a point, flipped to the top
+        assert_eq!(glyphs[6].bbox.1, 300.0 - 110.0);
+    }
+
+    #[test]
+    fn bookmarks_flatten_depth_first() {
+        let bookmark = |title: &str, url: &str, children| Bookmark {
+            title: title.into(),
+            url: Some(url.into()),
+            children,
+        };
+        let bookmarks = vec![
+            bookmark("One", "#1", Vec::new()),
+            bookmark(
+                "Two",
+                "#2",
+                vec![bookmark("Two.1", "#p0003.djvu", Vec::new())],
+            ),
+            bookmark("Web", "https://example.org", Vec::new()),
+        ];
+        let resolve = |url: &str| match url {
+            "#p0003.djvu" => Some(3),
+            _ => url.strip_prefix('#')?.parse().ok(),
+        };
+        let got: Vec<_> = flatten(&bookmarks, 0, &resolve)
+            .into_iter()
+            .map(|entry| (entry.title, entry.depth, entry.page))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("One".into(), 0, Some(1)),
+                ("Two".into(), 0, Some(2)),
+                ("Two.1".into(), 1, Some(3)),
+                ("Web".into(), 0, None),
+            ]
+        );
+    }
+
+    // Reads the fixture through djvulibre itself; skipped where it isn't installed.
+    #[test]
+    fn reads_pages_text_and_pixels_through_djvulibre() {
+        use gtk::prelude::FileExt;
+
+        if !available() {
+            return;
+        }
+        let djvu = Djvu::default();
+        let path = Path::new(TEXT_DJVU);
+        assert_eq!(djvu.probe(path, 1), Some((1, Some(72.0))));
+
+        let uri = gtk::gio::File::for_path(path).uri().to_string();
+        assert_eq!(djvu.page_count(&uri), Some(1));
+        assert_eq!(djvu.page_size(&uri, 0), Some((144.0, 72.0)));
+        assert_eq!(djvu.page_size(&uri, 1), None);
+        // once read, a size is known to every thread, documents of their own or not
+        let generation = crate::mupdf_render::generation();
+        let known = std::thread::scope(|scope| {
+            scope
+                .spawn(|| djvu.known_size(&uri, generation, 0))
+                .join()
+                .unwrap()
+        });
+        assert_eq!(known, Some((144.0, 72.0)));
+
+        let glyphs = djvu.text_page(&uri, 0).unwrap();
+        assert_eq!(text(&glyphs), "Hello DjVu hello");
+        assert_eq!(glyphs[0].bbox.0, 12.0);
+        assert_eq!(djvu.search(&uri, 0, "hello").len(), 2);
+        assert_eq!(djvu.search(&uri, 0, "hello djvu").len(), 1);
+
+        // no image layers: blank paper, sized like the page
+        let px = djvu
+            .render_page(&uri, 0, 2.0, 1.0, None, false, None)
+            .unwrap();
+        assert_eq!((px.width, px.height), (288, 144));
+        assert!(px.data.chunks(4).all(|pixel| pixel[..3] == [0xff; 3]));
+        assert_eq!(djvu.content_bbox(&uri, 0), None);
+        assert!(djvu.outline(&uri).is_empty());
+    }
+}
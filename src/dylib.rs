@@ -0,0 +1,19 @@
+// C libraries loaded at runtime instead of linked (djvulibre, libarchive), so the viewer builds and
+// runs without them; only the formats they read go missing.
+
+use libloading::Library;
+
+// The first of `names` that loads: the soname, its macOS spelling, then the unversioned development
+// link.
+pub(crate) fn open(names: &[&str]) -> Option<Library> {
+    // SAFETY: the libraries loaded here run no initialisers beyond their C and C++ runtimes'
+    names
+        .iter()
+        .find_map(|name| unsafe { Library::new(name) }.ok())
+}
+
+// The function `name` (NUL-terminated) from `library`, copied out of its Symbol. The caller gives
+// the function's prototype as `T` and keeps the library loaded for as long as the copy is used.
+pub(crate) unsafe fn symbol<T: Copy>(library: &Library, name: &[u8]) -> Option<T> {
+    library.get::<T>(name).ok().map(|symbol| *symbol)
+}
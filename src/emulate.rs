@@ -8,11 +8,19 @@
 //   SCROLEX_EMULATE_PREVIEW_MS=60  preview render time
 
 use std::env;
-use std::sync::OnceLock;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 
 use gtk::cairo::{Context, FontSlant, FontWeight, Format, ImageSurface};
 
+use crate::backend::RenderBackend;
+use crate::links::LinkTarget;
+use crate::mupdf_render::{Cancel, PagePixels, PixelRect};
+use crate::outline::OutlineEntry;
+use crate::page::Rectangle;
+use crate::selection::Glyph;
+
 pub const URI: &str = "emulate:///doc";
 
 pub struct Config {
@@ -28,6 +36,11 @@ pub fn config() -> Option<&'static Config> {
     CONFIG.get_or_init(parse).as_ref()
 }
 
+// The synthetic document's backend, when emulating.
+pub(crate) fn backend() -> Option<Arc<dyn RenderBackend>> {
+    config().map(|cfg| Arc::new(Emulated(cfg)) as Arc<dyn RenderBackend>)
+}
+
 fn parse() -> Option<Config> {
     match env::var("SCROLEX_EMULATE").ok().as_deref() {
         Some("1" | "true") => {}
@@ -57,46 +70,134 @@ fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> T {
         .unwrap_or(default)
 }
 
-pub fn pixels(
-    cfg: &Config,
-    page_num: i32,
-    scale: f64,
-    dsf: f64,
-    is_preview: bool,
-) -> (Vec<u8>, i32, i32, i32) {
-    let ms = if is_preview {
-        cfg.preview_ms
-    } else {
-        cfg.full_ms
-    };
+fn pixels(cfg: &Config, page_num: i32, scale: f64, dsf: f64, ms: u64) -> PagePixels {
     std::thread::sleep(Duration::from_millis(ms));
+    let (w, h) = page_px(cfg, scale, dsf);
+    dummy_pixels(page_num, w, h)
+}
+
+fn page_px(cfg: &Config, scale: f64, dsf: f64) -> (i32, i32) {
     let (w, h) = cfg.page_pt;
-    let width = ((w * scale * dsf) as i32).max(1);
-    let height = ((h * scale * dsf) as i32).max(1);
-    dummy_pixels(page_num, width, height)
+    (
+        ((w * scale * dsf) as i32).max(1),
+        ((h * scale * dsf) as i32).max(1),
+    )
 }
 
-pub fn full_surface(cfg: &Config, page_num: i32, scale: f64, dsf: f64) -> ImageSurface {
-    let (data, width, height, stride) = pixels(cfg, page_num, scale, dsf, false);
-    let surface = ImageSurface::create_for_data(data, Format::Rgb24, width, height, stride)
-        .expect("emulate surface");
-    surface.set_device_scale(dsf, dsf);
-    surface
+// Every page alike: the configured size, a grey sheet with its number, no text, links or outline.
+// Full renders and previews take the configured times; a tile takes its share of a full render.
+struct Emulated(&'static Config);
+
+impl RenderBackend for Emulated {
+    fn probe(&self, _path: &Path, _pages: i32) -> Option<(i32, Option<f64>)> {
+        Some((self.0.pages, Some(self.0.page_pt.1)))
+    }
+
+    fn fingerprint(&self, _path: &Path) -> Option<String> {
+        None
+    }
+
+    fn page_count(&self, _uri: &str) -> Option<i32> {
+        Some(self.0.pages)
+    }
+
+    fn page_size(&self, _uri: &str, _page_num: i32) -> Option<(f64, f64)> {
+        Some(self.0.page_pt)
+    }
+
+    fn render_page(
+        &self,
+        _uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        _page_pt: Option<(f64, f64)>,
+        _dark: bool,
+        _cancel: Option<&Cancel>,
+    ) -> Option<PagePixels> {
+        Some(pixels(self.0, page_num, scale, dsf, self.0.full_ms))
+    }
+
+    fn render_preview(
+        &self,
+        _uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        _page_pt: Option<(f64, f64)>,
+        _dark: bool,
+        _cancel: Option<&Cancel>,
+    ) -> Option<PagePixels> {
+        Some(pixels(self.0, page_num, scale, dsf, self.0.preview_ms))
+    }
+
+    fn render_tile(
+        &self,
+        _uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        region: PixelRect,
+        _dark: bool,
+        _cancel: Option<&Cancel>,
+    ) -> Option<PagePixels> {
+        let (page_width, page_height) = page_px(self.0, scale, dsf);
+        let share = f64::from((region.x1 - region.x0) * (region.y1 - region.y0))
+            / (f64::from(page_width) * f64::from(page_height));
+        std::thread::sleep(Duration::from_millis(
+            (self.0.full_ms as f64 * share) as u64,
+        ));
+        Some(region_pixels(page_num, page_width, page_height, region))
+    }
+
+    fn render_regions(
+        &self,
+        _uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        regions: &[PixelRect],
+        _dark: bool,
+    ) -> Option<Vec<PagePixels>> {
+        let (page_width, page_height) = page_px(self.0, scale, dsf);
+        Some(
+            regions
+                .iter()
+                .map(|&region| region_pixels(page_num, page_width, page_height, region))
+                .collect(),
+        )
+    }
+
+    fn text_page(&self, _uri: &str, _page_num: i32) -> Option<Vec<Glyph>> {
+        None
+    }
+
+    fn links(&self, _uri: &str, _page_num: i32) -> Vec<(Rectangle, LinkTarget)> {
+        Vec::new()
+    }
+
+    fn outline(&self, _uri: &str) -> Vec<OutlineEntry> {
+        Vec::new()
+    }
+
+    // the grey sheet is all content
+    fn content_bbox(&self, _uri: &str, _page_num: i32) -> Option<(f64, f64, f64, f64)> {
+        Some((0.0, 0.0, self.0.page_pt.0, self.0.page_pt.1))
+    }
 }
 
-fn dummy_pixels(page_num: i32, width: i32, height: i32) -> (Vec<u8>, i32, i32, i32) {
-    region_pixels(page_num, width, height, 0, 0, width, height)
+fn dummy_pixels(page_num: i32, width: i32, height: i32) -> PagePixels {
+    region_pixels(page_num, width, height, PixelRect::new(0, 0, width, height))
 }
 
-pub fn region_pixels(
+fn region_pixels(
     page_num: i32,
     page_width: i32,
     page_height: i32,
-    x: i32,
-    y: i32,
-    width: i32,
-    height: i32,
-) -> (Vec<u8>, i32, i32, i32) {
+    region: PixelRect,
+) -> PagePixels {
+    let (x, y) = (region.x0, region.y0);
+    let (width, height) = (region.x1 - region.x0, region.y1 - region.y0);
     let mut surface = ImageSurface::create(Format::Rgb24, width, height).expect("emulate surface");
     {
         let cr = Context::new(&surface).expect("emulate context");
@@ -118,5 +219,10 @@ pub fn region_pixels(
     surface.flush();
     let stride = surface.stride();
     let data = surface.data().expect("emulate data").to_vec();
-    (data, width, height, stride)
+    PagePixels {
+        data,
+        width,
+        height,
+        stride,
+    }
 }
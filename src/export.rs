@@ -0,0 +1,739 @@
+// Headless export for build scripts, run instead of the viewer when the command line asks for it:
+// `scrolex --export-images out/%03d.png --pages 1-10 --dpi 150 [--crop] [--theme dark] doc.pdf`
+// renders pages with MuPDF and writes them as PNG or JPEG, `scrolex --export-text out.md doc.pdf`
+// writes their text. Neither opens a window, so both run without a display. The text export is
+// also what the menu's "Export Text…" writes.
+
+use std::path::{Path, PathBuf};
+
+use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+
+use crate::mupdf_render::PagePixels;
+use crate::outline::OutlineEntry;
+
+const DEFAULT_DPI: f64 = 150.0;
+// a letter page at 2400 dpi is already a 550 megapixel buffer
+const MAX_DPI: f64 = 2400.0;
+const JPEG_QUALITY: &str = "90";
+
+// What the command line asks to export.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Export {
+    Images(ImageExport),
+    Text(TextExport),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageExport {
+    // output path; `%d` (or `%03d`, ...) is replaced by the 1-based page number
+    pub pattern: String,
+    pub format: ImageFormat,
+    // 1-based inclusive ranges, an open end running to the last page; empty means every page
+    pub pages: Vec<(i32, Option<i32>)>,
+    pub dpi: f64,
+    // export each page's crop view box (content plus margin) instead of the whole page
+    pub crop: bool,
+    pub dark: bool,
+    pub document: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextExport {
+    // output path, "-" for stdout
+    pub path: String,
+    // a .md output gets the outline's titles as headings
+    pub markdown: bool,
+    // as ImageExport's
+    pub pages: Vec<(i32, Option<i32>)>,
+    pub document: String,
+}
+
+impl Export {
+    // The export the command line asks for, or None if it doesn't ask for one and the viewer should
+    // start. `args` includes the program name.
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        let given = |flag: &str| args.iter().any(|arg| arg == flag);
+        let (images, text) = (given("--export-images"), given("--export-text"));
+        if !images && !text {
+            return Ok(None);
+        }
+        if images && text {
+            return Err("--export-images and --export-text can't be combined".to_string());
+        }
+        let mut output = None;
+        let mut document = None;
+        let mut pages = Vec::new();
+        let mut dpi = None;
+        let mut crop = false;
+        let mut dark = false;
+
+        let mut args = args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--export-images" | "--export-text" => output = Some(value()?.clone()),
+                "--pages" => pages = parse_pages(value()?)?,
+                "--dpi" => dpi = Some(parse_dpi(value()?)?),
+                "--crop" => crop = true,
+                "--theme" => {
+                    dark = match value()?.as_str() {
+                        "light" => false,
+                        "dark" => true,
+                        other => return Err(format!("unknown theme {other}: use light or dark")),
+                    }
+                }
+                "-v" | "--verbose" => {}
+                flag if flag.starts_with('-') => return Err(format!("unknown option {flag}")),
+                path if document.is_none() => document = Some(path.to_string()),
+                path => return Err(format!("more than one document given: {path}")),
+            }
+        }
+
+        let output = output.ok_or("no output path given")?;
+        let document = document.ok_or("no document given")?;
+        if text {
+            if dpi.is_some() || crop || dark {
+                return Err("--dpi, --crop and --theme only apply to --export-images".to_string());
+            }
+            return Ok(Some(Self::Text(TextExport {
+                markdown: is_markdown(&output),
+                path: output,
+                pages,
+                document,
+            })));
+        }
+        Ok(Some(Self::Images(ImageExport {
+            format: image_format(&output)?,
+            pattern: output,
+            pages,
+            dpi: dpi.unwrap_or(DEFAULT_DPI),
+            crop,
+            dark,
+            document,
+        })))
+    }
+
+    // The document as given on the command line, a path or a uri.
+    pub fn document(&self) -> &str {
+        match self {
+            Self::Images(export) => &export.document,
+            Self::Text(export) => &export.document,
+        }
+    }
+
+    // Export from the document at `uri` (`document` resolved). Err if the document can't be opened
+    // or there is nothing to export; otherwise the number of pages that failed, each reported on
+    // stderr.
+    pub fn run(&self, uri: &str) -> Result<usize, String> {
+        match self {
+            Self::Images(export) => export_images(export, uri),
+            Self::Text(export) => export_text(export, uri),
+        }
+    }
+}
+
+// The 1-based page numbers `ranges` (empty for all) pick out of `n_pages`, in order. Closed ranges
+// may run past the end of the document; those pages fail rather than being dropped silently.
+fn page_numbers(ranges: &[(i32, Option<i32>)], n_pages: i32) -> Vec<i32> {
+    if ranges.is_empty() {
+        return (1..=n_pages).collect();
+    }
+    ranges
+        .iter()
+        .flat_map(|&(first, last)| first..=last.unwrap_or(n_pages))
+        .collect()
+}
+
+// The page count of the document at `uri` and the pages `ranges` pick out of it; Err if it can't be
+// opened or they pick none.
+fn requested_pages(
+    document: &str,
+    uri: &str,
+    ranges: &[(i32, Option<i32>)],
+) -> Result<(i32, Vec<i32>), String> {
+    let n_pages =
+        crate::backend::page_count(uri).ok_or_else(|| format!("cannot open {document}"))?;
+    let pages = page_numbers(ranges, n_pages);
+    if pages.is_empty() {
+        return Err(format!("no pages to export, the document has {n_pages}"));
+    }
+    Ok((n_pages, pages))
+}
+
+fn create_parent_dir(path: &Path) -> Result<(), String> {
+    match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => {
+            std::fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))
+        }
+        None => Ok(()),
+    }
+}
+
+// "1-10,12,15-": comma-separated 1-based pages and inclusive ranges; an open end runs to the last
+// page, an open start from the first.
+fn parse_pages(spec: &str) -> Result<Vec<(i32, Option<i32>)>, String> {
+    spec.split(',')
+        .map(|part| {
+            let bad = || format!("invalid page range {part}");
+            let number = |s: &str| {
+                s.trim()
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|n| *n >= 1)
+                    .ok_or_else(bad)
+            };
+            match part.split_once('-') {
+                Some((first, last)) if last.trim().is_empty() => Ok((number(first)?, None)),
+                Some((first, last)) => {
+                    let first = if first.trim().is_empty() {
+                        1
+                    } else {
+                        number(first)?
+                    };
+                    let last = number(last)?;
+                    if last < first {
+                        return Err(bad());
+                    }
+                    Ok((first, Some(last)))
+                }
+                None => number(part).map(|n| (n, Some(n))),
+            }
+        })
+        .collect()
+}
+
+fn parse_dpi(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|dpi| *dpi > 0.0 && *dpi <= MAX_DPI)
+        .ok_or_else(|| format!("invalid dpi {value}: expected a number up to {MAX_DPI}"))
+}
+
+fn image_format(pattern: &str) -> Result<ImageFormat, String> {
+    let extension = Path::new(pattern)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("png") => Ok(ImageFormat::Png),
+        Some("jpg" | "jpeg") => Ok(ImageFormat::Jpeg),
+        _ => Err(format!(
+            "{pattern}: the output must end in .png, .jpg or .jpeg"
+        )),
+    }
+}
+
+pub fn is_markdown(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
+}
+
+// `pattern` with its printf-style page placeholder (`%d`, `%03d`, ...) filled in and `%%` turned
+// into a percent sign, or None if it has no placeholder.
+fn output_path(pattern: &str, page: i32) -> Option<PathBuf> {
+    let mut out = String::new();
+    let mut filled = false;
+    let mut rest = pattern;
+    while let Some(at) = rest.find('%') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+        if let Some(tail) = rest.strip_prefix('%') {
+            out.push('%');
+            rest = tail;
+            continue;
+        }
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if filled || !rest[digits..].starts_with('d') {
+            out.push('%');
+            continue;
+        }
+        let spec = &rest[..digits];
+        let width = spec.parse::<usize>().unwrap_or(0);
+        if spec.starts_with('0') {
+            out.push_str(&format!("{page:0width$}"));
+        } else {
+            out.push_str(&format!("{page:width$}"));
+        }
+        rest = &rest[digits + 1..];
+        filled = true;
+    }
+    out.push_str(rest);
+    filled.then(|| PathBuf::from(out))
+}
+
+// Render and write the requested pages of the document at `uri`; see Export::run.
+fn export_images(export: &ImageExport, uri: &str) -> Result<usize, String> {
+    let (n_pages, pages) = requested_pages(&export.document, uri, &export.pages)?;
+    if pages.len() > 1 && output_path(&export.pattern, 1).is_none() {
+        return Err(format!(
+            "{}: several pages need a %d for the page number",
+            export.pattern
+        ));
+    }
+
+    let mut failed = 0;
+    for page in pages {
+        let path =
+            output_path(&export.pattern, page).unwrap_or_else(|| PathBuf::from(&export.pattern));
+        if let Err(err) = export_page(export, uri, page, n_pages, &path) {
+            eprintln!("page {page}: {err}");
+            failed += 1;
+        }
+    }
+    Ok(failed)
+}
+
+fn export_page(
+    export: &ImageExport,
+    uri: &str,
+    page: i32,
+    n_pages: i32,
+    path: &Path,
+) -> Result<(), String> {
+    if page > n_pages {
+        return Err(format!("no such page, the document has {n_pages}"));
+    }
+    let area = if export.crop {
+        let b = crate::page::crop_box(uri, page - 1).ok_or("unreadable page")?;
+        Some((b.x1, b.y1, b.x2, b.y2))
+    } else {
+        None
+    };
+    let px = crate::backend::render_export(uri, page - 1, export.dpi, area, export.dark)
+        .ok_or("unreadable page")?;
+
+    create_parent_dir(path)?;
+    let saved = match export.format {
+        ImageFormat::Png => pixbuf(&px).savev(path, "png", &[]),
+        ImageFormat::Jpeg => pixbuf(&px).savev(path, "jpeg", &[("quality", JPEG_QUALITY)]),
+    };
+    saved.map_err(|err| format!("{}: {err}", path.display()))
+}
+
+// Cairo's Rgb24 (BGRx) pixels as an RGB pixbuf, which can be saved as either format.
+fn pixbuf(px: &PagePixels) -> Pixbuf {
+    let (width, height) = (px.width as usize, px.height as usize);
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for row in px.data.chunks_exact(px.stride as usize).take(height) {
+        for p in row[..width * 4].chunks_exact(4) {
+            rgb.extend_from_slice(&[p[2], p[1], p[0]]);
+        }
+    }
+    Pixbuf::from_mut_slice(
+        rgb,
+        Colorspace::Rgb,
+        false,
+        8,
+        px.width,
+        px.height,
+        px.width * 3,
+    )
+}
+
+// Write the text of the requested pages of the document at `uri`; see Export::run.
+fn export_text(export: &TextExport, uri: &str) -> Result<usize, String> {
+    let (_, pages) = requested_pages(&export.document, uri, &export.pages)?;
+    let (text, failed) = document_text(uri, &pages, export.markdown);
+    for page in &failed {
+        eprintln!("page {page}: cannot read its text");
+    }
+    if export.path == "-" {
+        print!("{text}");
+    } else {
+        let path = Path::new(&export.path);
+        create_parent_dir(path)?;
+        std::fs::write(path, text).map_err(|err| format!("{}: {err}", path.display()))?;
+    }
+    Ok(failed.len())
+}
+
+// The text of `pages` (1-based) of the document at `uri`, and the pages whose text couldn't be read.
+// Each page's lines are reflowed into paragraphs as a copied selection is, so the text reads as
+// prose rather than the page's hard-wrapped lines.
+pub fn document_text(uri: &str, pages: &[i32], markdown: bool) -> (String, Vec<i32>) {
+    let read: Vec<(i32, Option<Vec<String>>)> = pages
+        .iter()
+        .take_while(|_| !crate::bg_job::stopping())
+        .map(|&page| {
+            let paragraphs = crate::selection::page_lines(uri, page - 1).map(|lines| {
+                let text = crate::selection::reflow(&lines);
+                text.lines().map(String::from).collect()
+            });
+            (page, paragraphs)
+        })
+        .collect();
+    let failed = read
+        .iter()
+        .filter(|(_, paragraphs)| paragraphs.is_none())
+        .map(|(page, _)| *page)
+        .collect();
+    let headings = if markdown {
+        crate::outline::entries(uri)
+    } else {
+        Vec::new()
+    };
+    (format_text(&read, &headings, markdown), failed)
+}
+
+// Each page's paragraphs (None if unreadable) under a page-break marker, blank lines between. The
+// outline entries in `headings` become Markdown headings on the pages they point to, replacing the
+// paragraph that repeats the title if there is one, else placed at the top of the page.
+fn format_text(
+    pages: &[(i32, Option<Vec<String>>)],
+    headings: &[OutlineEntry],
+    markdown: bool,
+) -> String {
+    let mut blocks = Vec::new();
+    for (page, paragraphs) in pages {
+        blocks.push(if markdown {
+            format!("<!-- Page {page} -->")
+        } else {
+            format!("--- Page {page} ---")
+        });
+        let mut rest = paragraphs.as_deref().unwrap_or_default();
+        for heading in headings.iter().filter(|entry| entry.page == Some(*page)) {
+            if let Some(i) = rest.iter().position(|p| same_title(p, &heading.title)) {
+                blocks.extend(rest[..i].iter().cloned());
+                rest = &rest[i + 1..];
+            }
+            let level = (heading.depth as usize + 1).min(6);
+            blocks.push(format!("{} {}", "#".repeat(level), heading.title.trim()));
+        }
+        blocks.extend(rest.iter().cloned());
+    }
+    let mut out = blocks.join("\n\n");
+    out.push('\n');
+    out
+}
+
+// Whether a paragraph is an outline title, ignoring case and spacing.
+fn same_title(paragraph: &str, title: &str) -> bool {
+    let words =
+        |text: &str| -> Vec<String> { text.split_whitespace().map(str::to_lowercase).collect() };
+    words(paragraph) == words(title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two 100x80 pages, each with a black rect over PDF [20 20 60 50], i.e. top-left (20,30)-(60,60).
+    // Its edges sit on whole points, so renders at whole multiples of 72 dpi have no anti-aliased
+    // pixels and can be compared exactly.
+    const RECT_PDF: &[u8] = b"%PDF-1.4\n\
+1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+2 0 obj\n<< /Type /Pages /Kids [3 0 R 5 0 R] /Count 2 /MediaBox [0 0 100 80] >>\nendobj\n\
+3 0 obj\n<< /Type /Page /Parent 2 0 R /Contents 4 0 R >>\nendobj\n\
+4 0 obj\n<< /Length 20 >>\nstream\n0 g 20 20 40 30 re f\nendstream\nendobj\n\
+5 0 obj\n<< /Type /Page /Parent 2 0 R /Contents 4 0 R >>\nendobj\n\
+trailer\n<< /Root 1 0 R >>\n%%EOF";
+
+    const WHITE: [u8; 3] = [0xff; 3];
+    const BLACK: [u8; 3] = [0x00; 3];
+    // the dark theme's paper and ink (mupdf_render::DARK_MODE)
+    const DARK_PAPER: [u8; 3] = [0x1e; 3];
+    const DARK_INK: [u8; 3] = [0xea; 3];
+
+    fn args(line: &str) -> Vec<String> {
+        std::iter::once("scrolex")
+            .chain(line.split_whitespace())
+            .map(String::from)
+            .collect()
+    }
+
+    fn rect_pdf(dir: &Path) -> ImageExport {
+        let path = dir.join("rect.pdf");
+        std::fs::write(&path, RECT_PDF).unwrap();
+        ImageExport {
+            pattern: dir.join("out/%03d.png").display().to_string(),
+            format: ImageFormat::Png,
+            pages: Vec::new(),
+            dpi: 72.0,
+            crop: false,
+            dark: false,
+            document: path.display().to_string(),
+        }
+    }
+
+    fn uri(export: &ImageExport) -> String {
+        format!("file://{}", export.document)
+    }
+
+    // An image of `size` pixels filled with `paper`, with `ink` over the pixel rect `rect`.
+    fn golden(
+        size: (i32, i32),
+        rect: (i32, i32, i32, i32),
+        paper: [u8; 3],
+        ink: [u8; 3],
+    ) -> (i32, i32, Vec<[u8; 3]>) {
+        let pixels = (0..size.1)
+            .flat_map(|y| (0..size.0).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let inside = x >= rect.0 && x < rect.2 && y >= rect.1 && y < rect.3;
+                if inside {
+                    ink
+                } else {
+                    paper
+                }
+            })
+            .collect();
+        (size.0, size.1, pixels)
+    }
+
+    fn decoded(path: &Path) -> (i32, i32, Vec<[u8; 3]>) {
+        let image = Pixbuf::from_file(path).unwrap();
+        let (n, stride) = (image.n_channels() as usize, image.rowstride() as usize);
+        let bytes = image.read_pixel_bytes();
+        let pixels = (0..image.height() as usize)
+            .flat_map(|y| (0..image.width() as usize).map(move |x| y * stride + x * n))
+            .map(|at| [bytes[at], bytes[at + 1], bytes[at + 2]])
+            .collect();
+        (image.width(), image.height(), pixels)
+    }
+
+    #[test]
+    fn reads_the_export_from_the_command_line() {
+        assert_eq!(Export::from_args(&args("doc.pdf")), Ok(None));
+        let export = Export::from_args(&args(
+            "--export-images out/%03d.JPG --pages 1-10,12 --dpi 300 --crop --theme dark doc.pdf",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            export,
+            Export::Images(ImageExport {
+                pattern: "out/%03d.JPG".into(),
+                format: ImageFormat::Jpeg,
+                pages: vec![(1, Some(10)), (12, Some(12))],
+                dpi: 300.0,
+                crop: true,
+                dark: true,
+                document: "doc.pdf".into(),
+            })
+        );
+        let export = Export::from_args(&args("-v doc.pdf --export-text notes/Doc.MD --pages 3-"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            export,
+            Export::Text(TextExport {
+                path: "notes/Doc.MD".into(),
+                markdown: true,
+                pages: vec![(3, None)],
+                document: "doc.pdf".into(),
+            })
+        );
+        assert_eq!(export.document(), "doc.pdf");
+
+        for bad in [
+            "--export-images out.png",
+            "--export-images out.gif doc.pdf",
+            "--export-images out.png --dpi 0 doc.pdf",
+            "--export-images out.png --theme sepia doc.pdf",
+            "--export-images out.png --zoom 2 doc.pdf",
+            "--export-images out.png a.pdf b.pdf",
+            "doc.pdf --export-images",
+            "--export-text out.txt --dpi 300 doc.pdf",
+            "--export-text out.txt --export-images out.png doc.pdf",
+        ] {
+            assert!(Export::from_args(&args(bad)).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn page_ranges_run_to_the_ends_of_the_document() {
+        assert_eq!(
+            parse_pages("1-3,5,7-,-2"),
+            Ok(vec![(1, Some(3)), (5, Some(5)), (7, None), (1, Some(2))])
+        );
+        for bad in ["", "0", "3-1", "a-b", "1,,2"] {
+            assert!(parse_pages(bad).is_err(), "{bad}");
+        }
+
+        let ranges = [(2, Some(3)), (8, None)];
+        assert_eq!(page_numbers(&ranges, 9), [2, 3, 8, 9]);
+        // a closed range past the end keeps its pages, which then fail
+        assert_eq!(page_numbers(&ranges, 2), [2, 3]);
+        assert_eq!(page_numbers(&[], 3), [1, 2, 3]);
+    }
+
+    #[test]
+    fn output_paths_take_the_page_number() {
+        let path = |pattern, page| output_path(pattern, page).map(|p| p.display().to_string());
+        assert_eq!(path("out/%03d.png", 7).as_deref(), Some("out/007.png"));
+        assert_eq!(path("page-%d.png", 12).as_deref(), Some("page-12.png"));
+        assert_eq!(path("%%%d%%.png", 5).as_deref(), Some("%5%.png"));
+        assert_eq!(path("%2d.png", 5).as_deref(), Some(" 5.png"));
+        assert_eq!(path("cover.png", 1), None);
+        assert_eq!(path("50%.png", 1), None);
+    }
+
+    #[test]
+    fn exports_pixel_exact_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let export = rect_pdf(dir.path());
+        assert_eq!(export_images(&export, &uri(&export)), Ok(0));
+        for page in ["001.png", "002.png"] {
+            assert_eq!(
+                decoded(&dir.path().join("out").join(page)),
+                golden((100, 80), (20, 30, 60, 60), WHITE, BLACK)
+            );
+        }
+
+        let export = ImageExport {
+            pages: vec![(2, Some(2))],
+            dpi: 144.0,
+            dark: true,
+            ..export
+        };
+        assert_eq!(export_images(&export, &uri(&export)), Ok(0));
+        assert_eq!(
+            decoded(&dir.path().join("out/002.png")),
+            golden((200, 160), (40, 60, 120, 120), DARK_PAPER, DARK_INK)
+        );
+    }
+
+    #[test]
+    fn crop_exports_the_crop_view_box() {
+        let dir = tempfile::tempdir().unwrap();
+        let export = ImageExport {
+            pages: vec![(1, Some(1))],
+            crop: true,
+            ..rect_pdf(dir.path())
+        };
+        assert_eq!(export_images(&export, &uri(&export)), Ok(0));
+        // the content (20,30)-(60,60) plus the crop view's 5pt margin
+        assert_eq!(
+            decoded(&dir.path().join("out/001.png")),
+            golden((50, 40), (5, 5, 45, 35), WHITE, BLACK)
+        );
+    }
+
+    #[test]
+    fn writes_jpeg() {
+        let dir = tempfile::tempdir().unwrap();
+        let export = ImageExport {
+            pattern: dir.path().join("page.jpg").display().to_string(),
+            format: ImageFormat::Jpeg,
+            pages: vec![(1, Some(1))],
+            ..rect_pdf(dir.path())
+        };
+        assert_eq!(export_images(&export, &uri(&export)), Ok(0));
+        let (width, height, _) = decoded(&dir.path().join("page.jpg"));
+        assert_eq!((width, height), (100, 80));
+    }
+
+    #[test]
+    fn missing_pages_fail_and_the_rest_are_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let export = ImageExport {
+            pages: vec![(2, Some(4))],
+            ..rect_pdf(dir.path())
+        };
+        assert_eq!(export_images(&export, &uri(&export)), Ok(2));
+        assert!(dir.path().join("out/002.png").exists());
+        assert!(!dir.path().join("out/003.png").exists());
+
+        let export = ImageExport {
+            pattern: dir.path().join("single.png").display().to_string(),
+            ..export
+        };
+        assert!(export_images(&export, &uri(&export)).is_err());
+
+        let missing = ImageExport {
+            document: dir.path().join("missing.pdf").display().to_string(),
+            ..rect_pdf(dir.path())
+        };
+        assert!(export_images(&missing, &uri(&missing)).is_err());
+    }
+
+    fn section(title: &str, depth: u32, page: i32) -> OutlineEntry {
+        OutlineEntry {
+            title: title.into(),
+            depth,
+            page: Some(page),
+        }
+    }
+
+    fn paragraphs(texts: &[&str]) -> Option<Vec<String>> {
+        Some(texts.iter().map(|text| text.to_string()).collect())
+    }
+
+    #[test]
+    fn text_is_laid_out_under_page_markers() {
+        let pages = [
+            (1, paragraphs(&["Title page"])),
+            (2, None),
+            (3, paragraphs(&["One.", "Two."])),
+        ];
+        assert_eq!(
+            format_text(&pages, &[], false),
+            "--- Page 1 ---\n\nTitle page\n\n--- Page 2 ---\n\n--- Page 3 ---\n\nOne.\n\nTwo.\n"
+        );
+    }
+
+    #[test]
+    fn outline_titles_become_headings() {
+        let pages = [
+            (1, paragraphs(&["Preface text."])),
+            (2, paragraphs(&["Runs on.", "2.1  METHODS", "We measured."])),
+        ];
+        let outline = [
+            section("Chapter 2", 0, 2),
+            section("2.1 Methods", 1, 2),
+            section("Appendix", 0, 9),
+        ];
+        assert_eq!(
+            format_text(&pages, &outline, true),
+            "<!-- Page 1 -->\n\nPreface text.\n\n<!-- Page 2 -->\n\n\
+# Chapter 2\n\nRuns on.\n\n## 2.1 Methods\n\nWe measured.\n"
+        );
+    }
+
+    // A page with the title "Results" over a paragraph hyphenated across its lines, and an outline
+    // entry "Results" pointing at it.
+    const TEXT_PDF: &[u8] = b"%PDF-1.4\n\
+1 0 obj\n<< /Type /Catalog /Pages 2 0 R /Outlines 6 0 R >>\nendobj\n\
+2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 300 300] >>\nendobj\n\
+3 0 obj\n<< /Type /Page /Parent 2 0 R /Contents 4 0 R \
+/Resources << /Font << /F1 5 0 R >> >> >>\nendobj\n\
+4 0 obj\n<< /Length 92 >>\nstream\n\
+BT /F1 18 Tf 20 250 Td (Results) Tj ET BT /F1 12 Tf 20 180 Td 14 TL (An ef-) Tj (fect.) ' ET\n\
+endstream\nendobj\n\
+5 0 obj\n<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>\nendobj\n\
+6 0 obj\n<< /Type /Outlines /First 7 0 R /Last 7 0 R /Count 1 >>\nendobj\n\
+7 0 obj\n<< /Title (Results) /Parent 6 0 R /Dest [3 0 R /Fit] >>\nendobj\n\
+trailer\n<< /Root 1 0 R >>\n%%EOF";
+
+    #[test]
+    fn exports_reflowed_text_with_headings() {
+        let dir = tempfile::tempdir().unwrap();
+        let pdf = dir.path().join("text.pdf");
+        std::fs::write(&pdf, TEXT_PDF).unwrap();
+        let export = Export::Text(TextExport {
+            path: dir.path().join("text.md").display().to_string(),
+            markdown: true,
+            pages: vec![(1, Some(2))],
+            document: pdf.display().to_string(),
+        });
+        // page 2 doesn't exist
+        assert_eq!(export.run(&format!("file://{}", pdf.display())), Ok(1));
+
+        let text = std::fs::read_to_string(dir.path().join("text.md")).unwrap();
+        assert!(
+            text.starts_with("<!-- Page 1 -->\n\n# Results\n\n"),
+            "{text}"
+        );
+        assert!(text.contains("An effect."), "{text}");
+        assert!(text.ends_with("<!-- Page 2 -->\n"), "{text}");
+    }
+}
@@ -0,0 +1,263 @@
+// Interactive forms (AcroForm): the fillable fields on a page, read through MuPDF, and writing a new
+// value into one. Values go into a private working copy of the document (see
+// mupdf_render::set_working_copy) that renders in place of the original until it is saved. Rects
+// are page-local top-left points, matching MuPDF's coordinate space.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use mupdf::pdf::{FieldFlags, PdfDocument, PdfObject, PdfPage, PdfWidget, WidgetType};
+use mupdf::Colorspace;
+
+use crate::page::Rectangle;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldKind {
+    Text,
+    // `on` is the value that checks the box (its "on" appearance state, often "Yes")
+    Checkbox { on: String },
+    // one button of a group: `on` selects it
+    Radio { on: String },
+    // (export value, label) per option
+    Choice { options: Vec<(String, String)> },
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub xref: i32,
+    pub page: i32,
+    pub kind: FieldKind,
+    pub rect: Rectangle,
+    pub value: String,
+}
+
+impl Field {
+    pub fn is_checked(&self) -> bool {
+        match &self.kind {
+            FieldKind::Checkbox { on } | FieldKind::Radio { on } => self.value == *on,
+            _ => false,
+        }
+    }
+
+    // Whether the field is edited in an entry or dropdown rather than toggled by a click.
+    pub fn is_typed(&self) -> bool {
+        matches!(self.kind, FieldKind::Text | FieldKind::Choice { .. })
+    }
+
+    // The value a click sets: a checkbox toggles, a radio button selects itself.
+    pub fn clicked_value(&self) -> Option<String> {
+        match &self.kind {
+            FieldKind::Checkbox { .. } if self.is_checked() => Some("Off".to_string()),
+            FieldKind::Checkbox { on } | FieldKind::Radio { on } => Some(on.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Forms {
+    current_page: i32,
+    loaded: bool,
+    fields: Vec<Field>,
+}
+
+impl Forms {
+    pub(crate) fn clear(&mut self) {
+        self.fields.clear();
+        self.loaded = false;
+        self.current_page = -1;
+    }
+
+    // The page's editable fields in widget order (the order Tab walks), loaded on first use.
+    pub fn fields(&mut self, uri: &str, page_num: i32) -> &[Field] {
+        if !self.loaded || page_num != self.current_page {
+            self.fields = crate::mupdf_render::with_doc(uri, |doc| {
+                if !doc.is_pdf() {
+                    return None;
+                }
+                let page = PdfPage::try_from(doc.load_page(page_num).ok()?).ok()?;
+                Some(
+                    page.widgets()
+                        .filter_map(|widget| field(&widget, page_num))
+                        .collect(),
+                )
+            })
+            .unwrap_or_default();
+            self.current_page = page_num;
+            self.loaded = true;
+        }
+        &self.fields
+    }
+
+    pub fn field_at(&mut self, uri: &str, page_num: i32, x: f64, y: f64) -> Option<&Field> {
+        self.fields(uri, page_num)
+            .iter()
+            .find(|field| field.rect.contains(x, y))
+    }
+}
+
+fn field(widget: &PdfWidget, page_num: i32) -> Option<Field> {
+    let flags = widget.field_flags().ok()?;
+    if flags.contains(FieldFlags::READ_ONLY) {
+        return None;
+    }
+    let annot = widget.annotation();
+    let kind = match widget.r#type().ok()? {
+        WidgetType::Text => FieldKind::Text,
+        WidgetType::Checkbox => FieldKind::Checkbox {
+            on: on_state(&annot.object())?,
+        },
+        WidgetType::RadioButton => FieldKind::Radio {
+            on: on_state(&annot.object())?,
+        },
+        WidgetType::Combobox | WidgetType::Listbox => FieldKind::Choice {
+            options: choice_options(&annot.object()),
+        },
+        // push buttons run scripts, signatures need a certificate: neither is filled in
+        _ => return None,
+    };
+    let b = annot.bounds().ok()?;
+    Some(Field {
+        xref: widget.xref().ok()?,
+        page: page_num,
+        kind,
+        rect: Rectangle::new(b.x0 as f64, b.y0 as f64, b.x1 as f64, b.y1 as f64),
+        value: widget.value().ok().flatten().unwrap_or_default(),
+    })
+}
+
+// The name of a button's "on" appearance: whichever normal appearance state isn't "Off".
+fn on_state(obj: &PdfObject) -> Option<String> {
+    let normal = obj.get_dict("AP").ok()??.get_dict("N").ok()??;
+    normal.dict_iter().ok()?.find_map(|entry| {
+        let name = entry.ok()?.0.as_name().ok()?;
+        let name = String::from_utf8_lossy(&name).into_owned();
+        (name != "Off").then_some(name)
+    })
+}
+
+// A choice field's /Opt: each entry a label, or an [export value, label] pair. Inherited from the
+// parent field when the widget is one of several kids.
+fn choice_options(obj: &PdfObject) -> Vec<(String, String)> {
+    let Some(opt) = obj.get_dict_inheritable("Opt").ok().flatten() else {
+        return Vec::new();
+    };
+    let Ok(entries) = opt.array_iter() else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            if entry.is_array().ok()? {
+                let export = entry.get_array(0).ok()??.as_string().ok()?;
+                let label = entry.get_array(1).ok()??.as_string().ok()?;
+                Some((export, label))
+            } else {
+                let label = entry.as_string().ok()?;
+                Some((label.clone(), label))
+            }
+        })
+        .collect()
+}
+
+// Held while a field is written, so fields filled in quick succession (each on its own thread)
+// build on each other's working copy instead of racing from the same one.
+static WRITING: Mutex<()> = Mutex::new(());
+
+// Set the field `xref` on `page_num` to `value` in the document's working copy (made on the first
+// edit), so the next render of the page shows it.
+pub fn set_field(uri: &str, page_num: i32, xref: i32, value: &str) -> Result<(), String> {
+    let _writing = WRITING.lock().unwrap();
+    let path = match crate::mupdf_render::working_copy(uri) {
+        Some(path) => path,
+        None => new_working_copy(uri)?,
+    };
+    let path_str = path.to_str().ok_or("the working copy path is not UTF-8")?;
+
+    let out = {
+        let _ctx = Colorspace::device_bgr();
+        let mut doc = PdfDocument::open(path_str).map_err(|err| err.to_string())?;
+        let mut page = doc.load_pdf_page(page_num).map_err(|err| err.to_string())?;
+        let mut widget = page
+            .load_widget(xref)
+            .map_err(|err| err.to_string())?
+            .ok_or("the form field is gone")?;
+        widget
+            .set_value(&mut doc, value, false)
+            .map_err(|err| err.to_string())?;
+        widget.update().map_err(|err| err.to_string())?;
+        page.update().map_err(|err| err.to_string())?;
+        crate::markup::write_changes(&doc, &path)?
+    };
+    // a rewrite went to a fresh file; set_working_copy retires the old one
+    crate::mupdf_render::set_working_copy(uri, out);
+    Ok(())
+}
+
+fn new_working_copy(uri: &str) -> Result<PathBuf, String> {
+    let src = crate::mupdf_render::local_path(uri).ok_or("the document is not readable")?;
+    let tmp = tempfile::Builder::new()
+        .prefix("scrolex-form-")
+        .suffix(".pdf")
+        .tempfile()
+        .map_err(|err| err.to_string())?;
+    std::fs::copy(&src, tmp.path()).map_err(|err| err.to_string())?;
+    tmp.into_temp_path()
+        .keep()
+        .map_err(|err| err.error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 200x200 page with a text field "name" over PDF rect [20 150 180 170] and a checkbox "agree"
+    // over [20 100 40 120] whose on state is /Yes.
+    const FORM_PDF: &[u8] = b"%PDF-1.4\n\
+1 0 obj\n<< /Type /Catalog /Pages 2 0 R /AcroForm << /Fields [4 0 R 5 0 R] >> >>\nendobj\n\
+2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>\nendobj\n\
+3 0 obj\n<< /Type /Page /Parent 2 0 R /Annots [4 0 R 5 0 R] >>\nendobj\n\
+4 0 obj\n<< /Type /Annot /Subtype /Widget /FT /Tx /T (name) /V (Ada) /Rect [20 150 180 170] /P 3 0 R >>\nendobj\n\
+5 0 obj\n<< /Type /Annot /Subtype /Widget /FT /Btn /T (agree) /V /Off /AS /Off /Rect [20 100 40 120] /P 3 0 R \
+/AP << /N << /Yes 6 0 R /Off 6 0 R >> >> >>\nendobj\n\
+6 0 obj\n<< /Type /XObject /Subtype /Form /BBox [0 0 20 20] /Length 0 >>\nstream\n\nendstream\nendobj\n\
+trailer\n<< /Root 1 0 R >>\n%%EOF";
+
+    fn form_pdf_uri() -> String {
+        let dir = std::env::temp_dir().join("scrolex_forms_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("form.pdf");
+        std::fs::write(&path, FORM_PDF).unwrap();
+        format!("file://{}", path.display())
+    }
+
+    #[gtk::test]
+    fn reads_fields_and_fills_them_into_a_working_copy() {
+        let uri = form_pdf_uri();
+        let mut forms = Forms::default();
+        // PDF rect [20 150 180 170] flips to top-left y (30..50) on a 200-tall page
+        let name = forms.field_at(&uri, 0, 100.0, 40.0).unwrap().clone();
+        assert_eq!(name.kind, FieldKind::Text);
+        assert_eq!(name.value, "Ada");
+        let agree = forms.field_at(&uri, 0, 30.0, 90.0).unwrap().clone();
+        assert_eq!(agree.kind, FieldKind::Checkbox { on: "Yes".into() });
+        assert!(!agree.is_checked());
+        assert_eq!(agree.clicked_value().as_deref(), Some("Yes"));
+
+        set_field(&uri, 0, name.xref, "Grace").unwrap();
+        set_field(&uri, 0, agree.xref, "Yes").unwrap();
+        let working = crate::mupdf_render::working_copy(&uri).unwrap();
+
+        forms.clear();
+        assert_eq!(forms.field_at(&uri, 0, 100.0, 40.0).unwrap().value, "Grace");
+        assert!(forms.field_at(&uri, 0, 30.0, 90.0).unwrap().is_checked());
+        // the original file is untouched until the form is saved
+        assert_eq!(
+            std::fs::read(form_pdf_uri().trim_start_matches("file://")).unwrap(),
+            FORM_PDF
+        );
+
+        crate::mupdf_render::invalidate();
+        assert!(!working.exists());
+    }
+}
@@ -0,0 +1,513 @@
+// A folder of images, or a comic archive unpacked into one, as a document: each image a page, in
+// natural order of the file names (page2 before page10). Page sizes come from the image headers, so
+// listing a long comic doesn't decode it; MuPDF draws each page from its own file.
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use gtk::prelude::FileExt;
+
+use crate::archive;
+use crate::backend::RenderBackend;
+use crate::links::LinkTarget;
+use crate::mupdf_render::{Cancel, Mupdf, PagePixels, PixelRect};
+use crate::outline::OutlineEntry;
+use crate::page::Rectangle;
+use crate::selection::Glyph;
+
+// Images rarely say how large they were meant to print; sized at MuPDF's default of 96 dpi, a page
+// shows at about the size an image viewer would.
+const PIXELS_PER_INCH: f64 = 96.0;
+
+// One page: the image file and its size in points.
+struct Picture {
+    path: PathBuf,
+    // the file's own uri, which MuPDF opens it by
+    uri: String,
+    size: (f64, f64),
+}
+
+struct Listing {
+    pages: Vec<Picture>,
+    // where an archive was unpacked; removed with the listing
+    _unpacked: Option<tempfile::TempDir>,
+}
+
+impl Listing {
+    // The images in the folder at `path`, or in the archive there, unpacked to a temp dir.
+    fn read(path: &Path) -> Option<Self> {
+        if path.is_dir() {
+            let files = std::fs::read_dir(path)
+                .ok()?
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    (!name.starts_with('.')).then(|| (name, entry.path()))
+                })
+                .collect();
+            return Some(Self::of(files, None));
+        }
+        let mut header = Vec::new();
+        File::open(path)
+            .ok()?
+            .take(512)
+            .read_to_end(&mut header)
+            .ok()?;
+        let kind = archive::kind(&header)?;
+        let unpacked = tempfile::Builder::new()
+            .prefix("scrolex-unpacked-")
+            .tempdir()
+            .ok()?;
+        let files = archive::unpack(path, kind, unpacked.path())?;
+        Some(Self::of(files, Some(unpacked)))
+    }
+
+    // The files that are images whose size can be read, in natural order of their names.
+    fn of(mut files: Vec<(String, PathBuf)>, unpacked: Option<tempfile::TempDir>) -> Self {
+        files.sort_by(|(a, _), (b, _)| natural_cmp(a, b).then_with(|| a.cmp(b)));
+        let pages = files
+            .into_iter()
+            .filter_map(|(_, path)| {
+                let (width, height) = image_size(&mut File::open(&path).ok()?)?;
+                let pt = 72.0 / PIXELS_PER_INCH;
+                Some(Picture {
+                    uri: gtk::gio::File::for_path(&path).uri().to_string(),
+                    path,
+                    size: (f64::from(width) * pt, f64::from(height) * pt),
+                })
+            })
+            .collect();
+        Self {
+            pages,
+            _unpacked: unpacked,
+        }
+    }
+}
+
+// Compare names the way people number pages: runs of digits by their value, the rest letter by
+// letter regardless of case.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let (x, y) = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(&x), Some(&y)) => (x, y),
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let digits = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                let mut run = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    run.push(digit);
+                }
+                run.trim_start_matches('0').to_string()
+            };
+            let (m, n) = (digits(&mut a), digits(&mut b));
+            let order = m.len().cmp(&n.len()).then_with(|| m.cmp(&n));
+            if order != Ordering::Equal {
+                return order;
+            }
+            continue;
+        }
+        let order = x.to_lowercase().cmp(y.to_lowercase());
+        if order != Ordering::Equal {
+            return order;
+        }
+        a.next();
+        b.next();
+    }
+}
+
+// Pixel size of a PNG, JPEG, GIF, BMP, TIFF or PNM image, from its header alone. None for anything
+// else, which a gallery doesn't show.
+pub(crate) fn image_size(image: &mut (impl Read + Seek)) -> Option<(u32, u32)> {
+    let mut head = [0u8; 32];
+    let n = read_up_to(image, &mut head)?;
+    let head = &head[..n];
+    let le16 = |at: usize| Some(u16::from_le_bytes(head.get(at..at + 2)?.try_into().ok()?) as u32);
+    let be32 = |at: usize| Some(u32::from_be_bytes(head.get(at..at + 4)?.try_into().ok()?));
+    let le32 = |at: usize| Some(i32::from_le_bytes(head.get(at..at + 4)?.try_into().ok()?));
+
+    let size = if head.starts_with(b"\x89PNG\r\n\x1a\n") && head.get(12..16) == Some(b"IHDR") {
+        (be32(16)?, be32(20)?)
+    } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        (le16(6)?, le16(8)?)
+    } else if head.starts_with(b"BM") {
+        match le32(14)? {
+            12 => (le16(18)?, le16(20)?), // OS/2 BITMAPCOREHEADER
+            _ => (le32(18)?.unsigned_abs(), le32(22)?.unsigned_abs()),
+        }
+    } else if head.starts_with(b"\xff\xd8") {
+        jpeg_size(image)?
+    } else if head.starts_with(b"II*\0") || head.starts_with(b"MM\0*") {
+        tiff_size(image, head[0] == b'I')?
+    } else if head.len() > 2 && head[0] == b'P' && (b'1'..=b'7').contains(&head[1]) {
+        pnm_size(image)?
+    } else {
+        return None;
+    };
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> Option<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]).ok()? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Some(n)
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Option<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf).ok()?;
+    Some(buf)
+}
+
+// Walk the JPEG's marker segments to its start-of-frame, skipping metadata (which can be long, with
+// thumbnails) by its length.
+fn jpeg_size(image: &mut (impl Read + Seek)) -> Option<(u32, u32)> {
+    image.seek(SeekFrom::Start(2)).ok()?;
+    loop {
+        let [mut marker] = read_array::<1>(image)?;
+        if marker != 0xff {
+            return None;
+        }
+        while marker == 0xff {
+            [marker] = read_array::<1>(image)?; // fill bytes
+        }
+        // markers without a length: TEM, RSTn, SOI
+        if marker == 0x01 || (0xd0..=0xd8).contains(&marker) {
+            continue;
+        }
+        let length = u16::from_be_bytes(read_array(image)?);
+        // SOF0-SOF15, but for DHT, JPG and DAC that share the range
+        if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+            let [_precision, h1, h0, w1, w0] = read_array(image)?;
+            return Some((
+                u16::from_be_bytes([w1, w0]).into(),
+                u16::from_be_bytes([h1, h0]).into(),
+            ));
+        }
+        image
+            .seek(SeekFrom::Current(i64::from(length.checked_sub(2)?)))
+            .ok()?;
+    }
+}
+
+// ImageWidth and ImageLength from the TIFF's first directory.
+fn tiff_size(image: &mut (impl Read + Seek), little: bool) -> Option<(u32, u32)> {
+    let u16_of = |b: [u8; 2]| {
+        if little {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        }
+    };
+    let u32_of = |b: [u8; 4]| {
+        if little {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        }
+    };
+    image.seek(SeekFrom::Start(4)).ok()?;
+    let directory = u32_of(read_array(image)?);
+    image.seek(SeekFrom::Start(directory.into())).ok()?;
+    let entries = u16_of(read_array(image)?);
+    let (mut width, mut height) = (None, None);
+    for _ in 0..entries {
+        let entry: [u8; 12] = read_array(image)?;
+        let tag = u16_of([entry[0], entry[1]]);
+        // SHORT or LONG, held in the entry itself
+        let value = match u16_of([entry[2], entry[3]]) {
+            3 => u32::from(u16_of([entry[8], entry[9]])),
+            4 => u32_of([entry[8], entry[9], entry[10], entry[11]]),
+            _ => continue,
+        };
+        match tag {
+            256 => width = Some(value),
+            257 => height = Some(value),
+            _ => {}
+        }
+    }
+    Some((width?, height?))
+}
+
+// The width and height after a PBM/PGM/PPM's magic (comments allowed between), or a PAM's WIDTH
+// and HEIGHT lines.
+fn pnm_size(image: &mut (impl Read + Seek)) -> Option<(u32, u32)> {
+    image.seek(SeekFrom::Start(0)).ok()?;
+    let mut head = [0u8; 512];
+    let n = read_up_to(image, &mut head)?;
+    let text = String::from_utf8_lossy(&head[..n]);
+    let mut words = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(str::split_whitespace);
+    let magic = words.next()?;
+    if magic == "P7" {
+        let (mut width, mut height) = (None, None);
+        while let Some(word) = words.next() {
+            match word {
+                "WIDTH" => width = words.next()?.parse().ok(),
+                "HEIGHT" => height = words.next()?.parse().ok(),
+                "ENDHDR" => break,
+                _ => {}
+            }
+        }
+        return Some((width?, height?));
+    }
+    Some((words.next()?.parse().ok()?, words.next()?.parse().ok()?))
+}
+
+// The gallery backend. The listing is read on probe, or on first use for a document that didn't
+// load through one (an export from the command line).
+#[derive(Default)]
+pub(crate) struct Gallery {
+    listing: Mutex<Option<Arc<Listing>>>,
+}
+
+impl Gallery {
+    fn listing(&self, uri: &str) -> Option<Arc<Listing>> {
+        let mut listing = self.listing.lock().unwrap();
+        if listing.is_none() {
+            let path = crate::mupdf_render::local_path(uri)?;
+            *listing = Some(Arc::new(Listing::read(&path)?));
+        }
+        listing.clone()
+    }
+
+    // Page `page_num`'s image, and how many of MuPDF's points it has to one of the gallery's.
+    fn picture(&self, uri: &str, page_num: i32) -> Option<(String, (f64, f64), f64)> {
+        let listing = self.listing(uri)?;
+        let picture = listing.pages.get(usize::try_from(page_num).ok()?)?;
+        let (width, _) = Mupdf.page_size(&picture.uri, 0)?;
+        Some((picture.uri.clone(), picture.size, picture.size.0 / width))
+    }
+}
+
+impl RenderBackend for Gallery {
+    // Every size is already read from the image headers, so this reports the tallest of them all.
+    fn probe(&self, path: &Path, _pages: i32) -> Option<(i32, Option<f64>)> {
+        let listing = Listing::read(path)?;
+        let n_pages = listing.pages.len() as i32;
+        let tallest_page_height = listing
+            .pages
+            .iter()
+            .map(|picture| picture.size.1)
+            .max_by(f64::total_cmp);
+        *self.listing.lock().unwrap() = Some(Arc::new(listing));
+        Some((n_pages, tallest_page_height))
+    }
+
+    // A folder's names, sizes and change times of its images; an archive's bytes, as usual.
+    fn fingerprint(&self, path: &Path) -> Option<String> {
+        if !path.is_dir() {
+            return crate::preview_store::fingerprint(path);
+        }
+        let listing = self.listing.lock().unwrap().clone()?;
+        let mut checksum = glib::Checksum::new(glib::ChecksumType::Sha256)?;
+        for picture in &listing.pages {
+            let metadata = std::fs::metadata(&picture.path).ok()?;
+            let modified = metadata
+                .modified()
+                .ok()?
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()?;
+            checksum.update(picture.uri.as_bytes());
+            checksum.update(&metadata.len().to_le_bytes());
+            checksum.update(&modified.as_nanos().to_le_bytes());
+        }
+        checksum.string()
+    }
+
+    fn page_count(&self, uri: &str) -> Option<i32> {
+        Some(self.listing(uri)?.pages.len() as i32)
+    }
+
+    fn page_size(&self, uri: &str, page_num: i32) -> Option<(f64, f64)> {
+        let listing = self.listing(uri)?;
+        Some(listing.pages.get(usize::try_from(page_num).ok()?)?.size)
+    }
+
+    fn render_page(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        page_pt: Option<(f64, f64)>,
+        dark: bool,
+        cancel: Option<&Cancel>,
+    ) -> Option<PagePixels> {
+        let (image, size, k) = self.picture(uri, page_num)?;
+        let (w, h) = page_pt.unwrap_or(size);
+        Mupdf.render_page(
+            &image,
+            0,
+            scale * k,
+            dsf,
+            Some((w / k, h / k)),
+            dark,
+            cancel,
+        )
+    }
+
+    fn render_tile(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        region: PixelRect,
+        dark: bool,
+        cancel: Option<&Cancel>,
+    ) -> Option<PagePixels> {
+        let (image, _, k) = self.picture(uri, page_num)?;
+        Mupdf.render_tile(&image, 0, scale * k, dsf, region, dark, cancel)
+    }
+
+    fn render_regions(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        regions: &[PixelRect],
+        dark: bool,
+    ) -> Option<Vec<PagePixels>> {
+        let (image, _, k) = self.picture(uri, page_num)?;
+        Mupdf.render_regions(&image, 0, scale * k, dsf, regions, dark)
+    }
+
+    fn text_page(&self, _uri: &str, _page_num: i32) -> Option<Vec<Glyph>> {
+        None
+    }
+
+    fn links(&self, _uri: &str, _page_num: i32) -> Vec<(Rectangle, LinkTarget)> {
+        Vec::new()
+    }
+
+    fn outline(&self, _uri: &str) -> Vec<OutlineEntry> {
+        Vec::new()
+    }
+
+    fn content_bbox(&self, uri: &str, page_num: i32) -> Option<(f64, f64, f64, f64)> {
+        let (image, _, k) = self.picture(uri, page_num)?;
+        let (x0, y0, x1, y1) = Mupdf.content_bbox(&image, 0)?;
+        Some((x0 * k, y0 * k, x1 * k, y1 * k))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    // page2.png (40x20, red), page10.png (20x40, blue) and notes.txt, as a folder and as a CBT.
+    const GALLERY_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gallery");
+    const GALLERY_CBT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gallery.cbt");
+
+    fn size_of(bytes: &[u8]) -> Option<(u32, u32)> {
+        image_size(&mut Cursor::new(bytes))
+    }
+
+    #[test]
+    fn natural_order_counts_numbers() {
+        let mut names = vec![
+            "page10.png",
+            "Page2.png",
+            "page1.png",
+            "page02a.png",
+            "cover.png",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec![
+                "cover.png",
+                "page1.png",
+                "Page2.png",
+                "page02a.png",
+                "page10.png"
+            ]
+        );
+        assert_eq!(natural_cmp("ch2/10.jpg", "ch10/2.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("a007", "a7"), Ordering::Equal);
+    }
+
+    #[test]
+    fn sizes_come_from_image_headers() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\x01\x2c\0\0\0\xc8\x08\x02\0\0\0";
+        assert_eq!(size_of(png), Some((300, 200)));
+        assert_eq!(size_of(b"GIF89a\x40\x01\xf0\0\x80\0\0"), Some((320, 240)));
+        let bmp = b"BM\0\0\0\0\0\0\0\0\0\0\0\0\x28\0\0\0\x10\0\0\0\xf8\xff\xff\xff\x01\0";
+        assert_eq!(size_of(bmp), Some((16, 8)));
+        // an APP0 segment to skip, then the baseline frame: 2 rows of 3
+        let jpeg = b"\xff\xd8\xff\xe0\0\x10JFIF\0\x01\x01\0\0\x01\0\x01\0\0\
+                     \xff\xdb\0\x03\0\xff\xc0\0\x11\x08\0\x02\0\x03\x03";
+        assert_eq!(size_of(jpeg), Some((3, 2)));
+        let tiff = b"II*\0\x08\0\0\0\x02\0\
+                     \0\x01\x03\0\x01\0\0\0\x40\0\0\0\
+                     \x01\x01\x04\0\x01\0\0\0\x30\0\0\0";
+        assert_eq!(size_of(tiff), Some((64, 48)));
+        assert_eq!(size_of(b"P6\n# made by hand\n12 34\n255\n"), Some((12, 34)));
+        assert_eq!(
+            size_of(b"P7\nWIDTH 5\nHEIGHT 6\nDEPTH 3\nMAXVAL 255\nENDHDR\n"),
+            Some((5, 6))
+        );
+        assert_eq!(size_of(b"%PDF-1.7\n"), None);
+        assert_eq!(size_of(b"notes about the pages"), None);
+    }
+
+    fn listed(path: &str) -> Vec<(String, (f64, f64))> {
+        let gallery = Gallery::default();
+        let (n_pages, tallest) = gallery.probe(Path::new(path), 1).unwrap();
+        assert_eq!((n_pages, tallest), (2, Some(30.0)));
+        let listing = gallery.listing.lock().unwrap().clone().unwrap();
+        listing
+            .pages
+            .iter()
+            .map(|picture| {
+                let name = picture.path.extension().unwrap().to_string_lossy();
+                (name.into_owned(), picture.size)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn folders_and_archives_list_their_images_in_natural_order() {
+        // 96 dpi: three quarters of a point a pixel; notes.txt isn't a page
+        let pages = vec![
+            ("png".to_string(), (30.0, 15.0)),
+            ("png".to_string(), (15.0, 30.0)),
+        ];
+        assert_eq!(listed(GALLERY_DIR), pages);
+        assert_eq!(listed(GALLERY_CBT), pages);
+    }
+
+    #[test]
+    fn pages_render_from_their_images() {
+        let uri = gtk::gio::File::for_path(GALLERY_CBT).uri().to_string();
+        let gallery = Gallery::default();
+        assert_eq!(gallery.page_count(&uri), Some(2));
+        assert_eq!(gallery.page_size(&uri, 1), Some((15.0, 30.0)));
+
+        // the second page is blue, drawn at the gallery's size whatever MuPDF takes it for
+        let px = gallery
+            .render_page(&uri, 1, 2.0, 1.0, None, false, None)
+            .unwrap();
+        assert_eq!((px.width, px.height), (30, 60));
+        let centre = &px.data[(30 * px.stride + 15 * 4) as usize..][..3];
+        assert_eq!(centre, &[0xff, 0, 0]); // BGR
+        assert_eq!(gallery.content_bbox(&uri, 0), Some((0.0, 0.0, 30.0, 15.0)));
+    }
+}
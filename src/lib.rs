@@ -1,15 +1,30 @@
 pub mod about;
+pub mod annotations;
+pub mod archive;
+pub mod attachments;
+pub mod backend;
 pub mod bg_job;
 pub mod config;
+pub mod djvu;
+pub mod dylib;
 pub mod emulate;
+pub mod export;
+pub mod forms;
+pub mod gallery;
 pub mod jump_stack;
 pub mod links;
+pub mod markup;
 pub mod mupdf_render;
+pub mod notes;
 pub mod outline;
 pub mod page;
+pub mod preview_store;
+pub mod print;
+pub mod properties;
 pub mod render_cache;
 pub mod search;
 pub mod selection;
+pub mod snapshot;
 pub mod state;
 pub mod window;
 //pub use crate::links::Links;
@@ -1,6 +1,8 @@
-// Interactive links: hit-test the pointer against a page's link rects (from MuPDF) and resolve to
-// either a target page (internal goto) or a URI (external). Rects are page-local top-left points,
-// matching MuPDF's coordinate space.
+// Interactive links: hit-test the pointer against a page's link rects (from the document's backend)
+// and resolve to either a target page (internal goto) or a URI (external). Rects are page-local
+// top-left points, matching MuPDF's coordinate space.
+
+use mupdf::Document;
 
 use crate::page::Rectangle;
 
@@ -38,32 +40,33 @@ impl Links {
     }
 
     fn load(&mut self, uri: &str, page_num: i32) {
-        self.rects.clear();
-        self.targets.clear();
         self.current_page = page_num;
         self.loaded = true;
+        (self.rects, self.targets) = crate::backend::get(uri)
+            .links(uri, page_num)
+            .into_iter()
+            .unzip();
+    }
+}
 
-        crate::mupdf_render::with_doc(uri, |doc| {
-            let page = doc.load_page(page_num).ok()?;
-            for link in page.links().ok()? {
-                let target = match &link.dest {
-                    // internal goto: MuPDF resolves to a 0-based page; the handler wants 1-based.
-                    Some(dest) => LinkTarget::Page(dest.loc.page_number as i32 + 1),
-                    None if !link.uri.is_empty() => LinkTarget::Uri(link.uri.clone()),
-                    None => continue,
-                };
-                let b = link.bounds;
-                self.rects.push(Rectangle::new(
-                    b.x0 as f64,
-                    b.y0 as f64,
-                    b.x1 as f64,
-                    b.y1 as f64,
-                ));
-                self.targets.push(target);
-            }
-            Some(())
-        });
+// A MuPDF page's link rects and targets.
+pub(crate) fn page_links(doc: &Document, page_num: i32) -> Option<Vec<(Rectangle, LinkTarget)>> {
+    let page = doc.load_page(page_num).ok()?;
+    let mut links = Vec::new();
+    for link in page.links().ok()? {
+        let target = match &link.dest {
+            // internal goto: MuPDF resolves to a 0-based page; the handler wants 1-based.
+            Some(dest) => LinkTarget::Page(dest.loc.page_number as i32 + 1),
+            None if !link.uri.is_empty() => LinkTarget::Uri(link.uri.clone()),
+            None => continue,
+        };
+        let b = link.bounds;
+        links.push((
+            Rectangle::new(b.x0 as f64, b.y0 as f64, b.x1 as f64, b.y1 as f64),
+            target,
+        ));
     }
+    Some(links)
 }
 
 #[cfg(test)]
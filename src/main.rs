@@ -45,6 +45,15 @@ fn main() -> glib::ExitCode {
 
     init_logging();
 
+    match scrolex::export::Export::from_args(&std::env::args().collect::<Vec<_>>()) {
+        Ok(Some(export)) => return run_export(&export),
+        Ok(None) => {}
+        Err(err) => {
+            eprintln!("scrolex: {err}");
+            return glib::ExitCode::from(2);
+        }
+    }
+
     // register types for usage in templates
     page::PageNumber::static_type();
     page::Page::static_type();
@@ -68,6 +77,28 @@ fn main() -> glib::ExitCode {
     app.run_with_args(&std::env::args().collect::<Vec<_>>())
 }
 
+// `--export-images` / `--export-text`: straight to files, without a GTK application or a display.
+fn run_export(export: &scrolex::export::Export) -> glib::ExitCode {
+    let uri = match from_str_to_uri(&OsString::from(export.document())) {
+        Ok(uri) => uri,
+        Err(err) => {
+            eprintln!("scrolex: {err}");
+            return glib::ExitCode::FAILURE;
+        }
+    };
+    match export.run(&uri) {
+        Ok(0) => glib::ExitCode::SUCCESS,
+        Ok(failed) => {
+            eprintln!("scrolex: {failed} page(s) could not be exported");
+            glib::ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("scrolex: {err}");
+            glib::ExitCode::FAILURE
+        }
+    }
+}
+
 fn setup_dark_mode(app: &Application) {
     let enabled = config::load_config().dark_mode;
     scrolex::mupdf_render::set_dark_mode(enabled);
@@ -158,13 +189,17 @@ fn build_ui(app: &Application, args: &[OsString]) {
             if let Err(err) = state.save() {
                 eprintln!("Error saving state: {err}");
             }
-
-            // The background render threads (bg_job) are detached and may be mid MuPDF render at
-            // this point; a MuPDF render can't be interrupted. Terminating normally would let the
-            // C library destructors free MuPDF/cairo/pixman globals out from under a
-            // still-running render thread, which segfaults. State is saved above, so exit
-            // immediately without running those destructors and let the OS reclaim everything.
-            unsafe { libc_exit(0) };
+            scrolex::attachments::remove_opened_copies();
+
+            // Abort the background renders (bg_job), stop the threads searching, measuring or
+            // exporting the document, and wait for them all to exit: terminating normally runs
+            // the C library destructors, which free MuPDF/cairo/pixman globals and would segfault
+            // a render still running. Should one not stop in time, state is saved above, so exit
+            // immediately without running those destructors instead.
+            if !page::shutdown_renders() {
+                log::warn!("Document threads still busy at shutdown; exiting immediately");
+                unsafe { libc_exit(0) };
+            }
         }
     ));
 
@@ -252,7 +287,8 @@ fn from_str_to_uri(oss: &OsString) -> Result<String, std::io::Error> {
     }
 
     let path = PathBuf::from(&oss).canonicalize()?;
-    if path.is_file() {
+    // A folder opens as a gallery of its images.
+    if path.is_file() || path.is_dir() {
         return Ok(format!("file://{}", path.to_string_lossy()));
     }
 
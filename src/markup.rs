@@ -0,0 +1,483 @@
+// Annotation authoring: highlight/underline/strikeout over the text selection, sticky notes at a
+// point and freehand ink strokes. Edits are kept as a session undo stack (drawn as page overlays
+// until saved) and written as real PDF annotations on save, appended incrementally so the original
+// bytes stay intact. Rects and points are page-local top-left points, as MuPDF's annotation API
+// takes them.
+
+use std::path::{Path, PathBuf};
+
+use gtk::gio;
+use gtk::prelude::*;
+use mupdf::color::AnnotationColor;
+use mupdf::pdf::{PdfDocument, PdfWriteOptions};
+use mupdf::{Colorspace, Point, Quad, Rect};
+
+use crate::page::Rectangle;
+
+// Side of a sticky note's icon, in points.
+pub const NOTE_SIZE: f64 = 18.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkupKind {
+    Highlight,
+    Underline,
+    StrikeOut,
+}
+
+// A freehand pen stroke. Points are (x, y, pressure) with pressure in 0..=1 (1 for a mouse); it
+// shapes the stroke as drawn, while the saved ink annotation takes the mean width, since a PDF ink
+// annotation has a single line width.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stroke {
+    // 0xRRGGBB
+    pub color: u32,
+    pub width: f64,
+    pub points: Vec<(f64, f64, f64)>,
+}
+
+impl Stroke {
+    pub fn rgb(&self) -> (f32, f32, f32) {
+        let channel = |shift: u32| ((self.color >> shift) & 0xff) as f32 / 255.0;
+        (channel(16), channel(8), channel(0))
+    }
+
+    // Line width at a point of the given pressure, never vanishing under a light touch.
+    pub fn width_at(&self, pressure: f64) -> f64 {
+        self.width * pressure.clamp(0.25, 1.0)
+    }
+
+    pub fn mean_width(&self) -> f64 {
+        if self.points.is_empty() {
+            return self.width;
+        }
+        let total: f64 = self.points.iter().map(|&(_, _, p)| self.width_at(p)).sum();
+        total / self.points.len() as f64
+    }
+
+    // Whether the stroke passes within `reach` of (x, y), allowing for its own width.
+    pub fn passes_near(&self, x: f64, y: f64, reach: f64) -> bool {
+        let reach = reach + self.width / 2.0;
+        let near = |(ax, ay): (f64, f64), (bx, by): (f64, f64)| {
+            let (dx, dy) = (bx - ax, by - ay);
+            let len2 = dx * dx + dy * dy;
+            let t = if len2 == 0.0 {
+                0.0
+            } else {
+                (((x - ax) * dx + (y - ay) * dy) / len2).clamp(0.0, 1.0)
+            };
+            let (px, py) = (ax + t * dx - x, ay + t * dy - y);
+            px * px + py * py <= reach * reach
+        };
+        let mut points = self.points.iter().map(|&(x, y, _)| (x, y));
+        let Some(first) = points.next() else {
+            return false;
+        };
+        // a dot is a zero-length segment
+        let mut prev = first;
+        near(prev, prev)
+            || points.any(|point| {
+                let hit = near(prev, point);
+                prev = point;
+                hit
+            })
+    }
+}
+
+// What an eraser drag rubbed out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Erased {
+    // an unsaved stroke, by its id
+    Stroke(u64),
+    // a saved ink annotation, by its object number; `rect` is its bounds, marked until the save
+    // deletes it
+    Saved { xref: i32, rect: Rectangle },
+}
+
+#[derive(Debug, Clone)]
+pub enum Edit {
+    Markup {
+        page: i32,
+        kind: MarkupKind,
+        rects: Vec<Rectangle>,
+    },
+    Note {
+        page: i32,
+        x: f64,
+        y: f64,
+        text: String,
+    },
+    // `id` names the stroke for the eraser
+    Ink {
+        page: i32,
+        id: u64,
+        stroke: Stroke,
+    },
+    // rubs out a stroke; undoing it brings the stroke back
+    Erase {
+        page: i32,
+        target: Erased,
+    },
+}
+
+impl Edit {
+    pub fn page(&self) -> i32 {
+        match self {
+            Edit::Markup { page, .. }
+            | Edit::Note { page, .. }
+            | Edit::Ink { page, .. }
+            | Edit::Erase { page, .. } => *page,
+        }
+    }
+}
+
+// Unsaved edits in the order they were made, and the ones undone since (for redo). One user action
+// is one step: marking up a selection across pages adds an edit per page, undone together.
+#[derive(Default, Debug)]
+pub struct Edits {
+    done: Vec<Vec<Edit>>,
+    undone: Vec<Vec<Edit>>,
+    last_id: u64,
+}
+
+impl Edits {
+    pub(crate) fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+
+    // A new step forks history: whatever was undone can no longer be redone.
+    pub fn push(&mut self, step: Vec<Edit>) {
+        if step.is_empty() {
+            return;
+        }
+        self.done.push(step);
+        self.undone.clear();
+    }
+
+    // Undo the last step; returns the pages it touched.
+    pub fn undo(&mut self) -> Option<Vec<i32>> {
+        let step = self.done.pop()?;
+        let pages = step.iter().map(Edit::page).collect();
+        self.undone.push(step);
+        Some(pages)
+    }
+
+    // Redo the last undone step; returns the pages it touched.
+    pub fn redo(&mut self) -> Option<Vec<i32>> {
+        let step = self.undone.pop()?;
+        let pages = step.iter().map(Edit::page).collect();
+        self.done.push(step);
+        Some(pages)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.done.is_empty()
+    }
+
+    // An id for a new ink stroke.
+    pub fn new_id(&mut self) -> u64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    // The edits still in effect: erased unsaved strokes and their erasures drop out, while the
+    // erasure of a saved annotation stays for the save to delete it.
+    pub fn pending(&self) -> impl Iterator<Item = &Edit> {
+        let erased: Vec<u64> = self
+            .done
+            .iter()
+            .flatten()
+            .filter_map(|edit| match edit {
+                Edit::Erase {
+                    target: Erased::Stroke(id),
+                    ..
+                } => Some(*id),
+                _ => None,
+            })
+            .collect();
+        self.done.iter().flatten().filter(move |edit| match edit {
+            Edit::Ink { id, .. } => !erased.contains(id),
+            Edit::Erase { target, .. } => matches!(target, Erased::Saved { .. }),
+            _ => true,
+        })
+    }
+
+    pub fn on_page(&self, page: i32) -> impl Iterator<Item = &Edit> {
+        self.pending().filter(move |edit| edit.page() == page)
+    }
+}
+
+// Write `edits` into the document at `uri` and store the result at `dest` (the document itself, or a
+// "save as" target). Blocking: call off the main thread. The edits are appended as an incremental
+// update to a temp copy of the bytes MuPDF renders - the staged copy for a non-local GFile - and the
+// copy then replaces `dest` through GIO, so remote and local destinations take the same path.
+pub fn save(uri: &str, edits: &[Edit], dest: &gio::File) -> Result<(), String> {
+    let src = crate::mupdf_render::local_path(uri).ok_or("the document is not readable")?;
+    let tmp = tempfile::Builder::new()
+        .prefix("scrolex-save-")
+        .suffix(".pdf")
+        .tempfile()
+        .map_err(|err| err.to_string())?;
+    std::fs::copy(&src, tmp.path()).map_err(|err| err.to_string())?;
+    let tmp_path = tmp.path().to_str().ok_or("the temp path is not UTF-8")?;
+
+    let out = {
+        let _ctx = Colorspace::device_bgr();
+        let doc = PdfDocument::open(tmp_path).map_err(|err| err.to_string())?;
+        apply(&doc, edits).map_err(|err| err.to_string())?;
+        write_changes(&doc, tmp.path())?
+    };
+
+    let bytes = std::fs::read(&out).map_err(|err| err.to_string())?;
+    if out != tmp.path() {
+        let _ = std::fs::remove_file(&out);
+    }
+    dest.replace_contents(
+        &bytes,
+        None,
+        false,
+        gio::FileCreateFlags::NONE,
+        gio::Cancellable::NONE,
+    )
+    .map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+// Write `doc`'s changes: appended to `opened_from` as an incremental update when the file can take
+// one, else the whole document rewritten to a fresh temp file, since MuPDF can't rewrite the file it
+// is reading from (a repaired file with a broken xref can't be appended to). Returns the file that
+// holds the result; a fresh one is the caller's to remove.
+pub(crate) fn write_changes(doc: &PdfDocument, opened_from: &Path) -> Result<PathBuf, String> {
+    let mut options = PdfWriteOptions::default();
+    let out = if doc.can_be_saved_incrementally() {
+        options.set_incremental(true);
+        opened_from.to_path_buf()
+    } else {
+        tempfile::Builder::new()
+            .prefix("scrolex-save-")
+            .suffix(".pdf")
+            .tempfile()
+            .and_then(|tmp| tmp.into_temp_path().keep().map_err(|err| err.error))
+            .map_err(|err| err.to_string())?
+    };
+    let out_str = out.to_str().ok_or("the temp path is not UTF-8")?;
+    doc.save_with_options(out_str, options)
+        .map_err(|err| err.to_string())?;
+    Ok(out)
+}
+
+fn apply(doc: &PdfDocument, edits: &[Edit]) -> Result<(), mupdf::Error> {
+    let author = gtk::glib::real_name().to_string_lossy().into_owned();
+    for edit in edits {
+        let mut page = doc.load_pdf_page(edit.page())?;
+        let mut annot = match edit {
+            Edit::Markup { kind, rects, .. } => {
+                let quads: Vec<Quad> = rects.iter().map(|r| Quad::from(to_rect(r))).collect();
+                match kind {
+                    MarkupKind::Highlight => page.add_highlight_annotation(quads)?,
+                    MarkupKind::Underline => page.add_underline_annotation(quads)?,
+                    MarkupKind::StrikeOut => page.add_strikeout_annotation(quads)?,
+                }
+            }
+            Edit::Note { x, y, text, .. } => page.add_text_annotation(
+                to_rect(&Rectangle::new(*x, *y, x + NOTE_SIZE, y + NOTE_SIZE)),
+                text,
+            )?,
+            Edit::Ink { stroke, .. } => {
+                let points = stroke.points.iter().map(|&(x, y, _)| Point {
+                    x: x as f32,
+                    y: y as f32,
+                });
+                let mut annot = page.add_ink_annotation([points])?;
+                let (red, green, blue) = stroke.rgb();
+                annot.set_color(AnnotationColor::Rgb { red, green, blue })?;
+                annot.set_border_width(stroke.mean_width() as f32)?;
+                annot
+            }
+            Edit::Erase {
+                target: Erased::Saved { xref, .. },
+                ..
+            } => {
+                let saved = page
+                    .annotations()
+                    .find(|annot| annot.xref().is_ok_and(|own| own == *xref));
+                if let Some(saved) = saved {
+                    page.delete_annotation(saved)?;
+                    page.update()?;
+                }
+                continue;
+            }
+            Edit::Erase { .. } => continue,
+        };
+        annot.set_author(&author)?;
+        annot.update()?;
+        page.update()?;
+    }
+    Ok(())
+}
+
+fn to_rect(r: &Rectangle) -> Rect {
+    Rect {
+        x0: r.x1 as f32,
+        y0: r.y1 as f32,
+        x1: r.x2 as f32,
+        y1: r.y2 as f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A blank 200x200 page, with a real xref table so MuPDF can append to it rather than repair it.
+    const BLANK_PDF: &[u8] = b"%PDF-1.4\n\
+1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>\nendobj\n\
+3 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+xref\n0 4\n\
+0000000000 65535 f \n\
+0000000009 00000 n \n\
+0000000058 00000 n \n\
+0000000139 00000 n \n\
+trailer\n<< /Size 4 /Root 1 0 R >>\nstartxref\n186\n%%EOF\n";
+
+    fn highlight(page: i32) -> Edit {
+        Edit::Markup {
+            page,
+            kind: MarkupKind::Highlight,
+            rects: vec![Rectangle::new(20.0, 30.0, 120.0, 42.0)],
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_history() {
+        let mut edits = Edits::default();
+        edits.push(vec![highlight(0)]);
+        // a selection across two pages is one step
+        edits.push(vec![highlight(2), highlight(3)]);
+        assert_eq!(edits.undo(), Some(vec![2, 3]));
+        assert_eq!(edits.pending().count(), 1);
+        assert_eq!(edits.redo(), Some(vec![2, 3]));
+        assert_eq!(edits.redo(), None);
+
+        // a new edit after an undo drops the redo branch
+        edits.undo();
+        edits.push(vec![highlight(1)]);
+        assert_eq!(edits.redo(), None);
+        let pages: Vec<_> = edits.pending().map(Edit::page).collect();
+        assert_eq!(pages, vec![0, 1]);
+    }
+
+    fn ink(page: i32, id: u64) -> Edit {
+        Edit::Ink {
+            page,
+            id,
+            stroke: Stroke {
+                color: 0xff0000,
+                width: 2.0,
+                points: vec![(10.0, 10.0, 1.0), (50.0, 10.0, 0.5), (50.0, 60.0, 1.0)],
+            },
+        }
+    }
+
+    #[test]
+    fn erasing_a_stroke_is_undoable() {
+        let mut edits = Edits::default();
+        let id = edits.new_id();
+        edits.push(vec![ink(0, id)]);
+        edits.push(vec![Edit::Erase {
+            page: 0,
+            target: Erased::Stroke(id),
+        }]);
+        assert_eq!(edits.pending().count(), 0);
+
+        assert_eq!(edits.undo(), Some(vec![0]));
+        assert!(matches!(edits.pending().next(), Some(Edit::Ink { .. })));
+    }
+
+    #[test]
+    fn strokes_are_hit_along_their_segments() {
+        let Edit::Ink { stroke, .. } = ink(0, 1) else {
+            unreachable!()
+        };
+        // midway along the first segment, and near the corner
+        assert!(stroke.passes_near(30.0, 12.0, 2.0));
+        assert!(stroke.passes_near(52.0, 30.0, 2.0));
+        // inside the bend, away from both segments
+        assert!(!stroke.passes_near(30.0, 40.0, 2.0));
+        assert_eq!(stroke.rgb(), (1.0, 0.0, 0.0));
+        assert!((stroke.mean_width() - 5.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[gtk::test]
+    fn save_appends_annotations_to_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blank.pdf");
+        std::fs::write(&path, BLANK_PDF).unwrap();
+        let file = gio::File::for_path(&path);
+
+        let edits = vec![
+            highlight(0),
+            Edit::Note {
+                page: 0,
+                x: 150.0,
+                y: 10.0,
+                text: "Needs a source".into(),
+            },
+            ink(0, 1),
+        ];
+        save(&file.uri(), &edits, &file).unwrap();
+
+        // incremental: the original bytes are a prefix of the saved file
+        let saved = std::fs::read(&path).unwrap();
+        assert!(saved.starts_with(BLANK_PDF));
+
+        let doc = PdfDocument::open(path.as_path()).unwrap();
+        let page = doc.load_pdf_page(0).unwrap();
+        let contents: Vec<_> = page
+            .annotations()
+            .map(|annot| annot.contents().unwrap().unwrap_or_default().to_owned())
+            .collect();
+        assert_eq!(contents.len(), 3);
+        assert!(contents.contains(&"Needs a source".to_string()));
+    }
+
+    #[gtk::test]
+    fn erasing_saved_ink_deletes_it_on_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blank.pdf");
+        std::fs::write(&path, BLANK_PDF).unwrap();
+        let file = gio::File::for_path(&path);
+        save(&file.uri(), &[highlight(0), ink(0, 1)], &file).unwrap();
+
+        let xref = {
+            let doc = PdfDocument::open(path.as_path()).unwrap();
+            let page = doc.load_pdf_page(0).unwrap();
+            let ink = page
+                .annotations()
+                .find(|annot| annot.r#type().ok() == Some(mupdf::pdf::PdfAnnotationType::Ink))
+                .unwrap();
+            ink.xref().unwrap()
+        };
+        let mut edits = Edits::default();
+        edits.push(vec![Edit::Erase {
+            page: 0,
+            target: Erased::Saved {
+                xref,
+                rect: Rectangle::new(10.0, 10.0, 50.0, 60.0),
+            },
+        }]);
+        // the erasure is what there is to save
+        assert_eq!(edits.pending().count(), 1);
+        let pending: Vec<_> = edits.pending().cloned().collect();
+        save(&file.uri(), &pending, &file).unwrap();
+
+        let doc = PdfDocument::open(path.as_path()).unwrap();
+        let page = doc.load_pdf_page(0).unwrap();
+        let kinds: Vec<_> = page
+            .annotations()
+            .map(|annot| annot.r#type().unwrap())
+            .collect();
+        assert_eq!(kinds, vec![mupdf::pdf::PdfAnnotationType::Highlight]);
+    }
+}
@@ -1,18 +1,22 @@
 // Rasterize PDF pages with MuPDF, which downscale-decodes embedded images (JPEG/JPEG2000) to the
 // requested resolution - scanned pages render at fit-to-page cost, not poppler's full-res decode.
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::{hash_map::Entry, HashMap};
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 use gtk::cairo::{Format, ImageSurface};
 use gtk::gio::prelude::InputStreamExtManual;
 use gtk::prelude::FileExt;
-use mupdf::{Colorspace, Device, Document, IRect, Matrix, Pixmap, Rect};
+use mupdf::{
+    Colorspace, Context, Device, DisplayList, Document, IRect, Matrix, Page, Pixmap, Rect,
+};
 use once_cell::sync::Lazy;
 
+use crate::backend::RenderBackend;
+
 #[derive(Clone, Copy)]
 struct DarkMode {
     paper: [u8; 3],
@@ -30,20 +34,60 @@ static GREY_LUT: Lazy<[[u8; 3]; 256]> = Lazy::new(|| {
     std::array::from_fn(|value| recolor(value as u8, value as u8, value as u8, DARK_MODE))
 });
 
+// How pages are rasterized: MuPDF's anti-aliasing for line art and for text, in bits (0 is off, 8
+// the smoothest), and a gamma for grey pixels - the anti-aliased edges thin black fonts are mostly
+// made of, though it darkens greyscale pictures as well. Coloured pixels are left alone; 1.0 leaves
+// pixels as drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderQuality {
+    pub graphics_aa: i32,
+    pub text_aa: i32,
+    pub grey_gamma: f64,
+}
+
+impl Default for RenderQuality {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl RenderQuality {
+    const DEFAULT: Self = Self {
+        graphics_aa: 8,
+        text_aa: 8,
+        grey_gamma: 1.0,
+    };
+}
+
+static QUALITY: Mutex<RenderQuality> = Mutex::new(RenderQuality::DEFAULT);
+// Bumped per quality change, so each render thread updates its fz_context once.
+static QUALITY_GENERATION: AtomicU64 = AtomicU64::new(1);
+
 // Bumped on document load so every thread's cached Document is reopened - otherwise reloading the
 // same path after the file changed on disk would keep serving the stale document.
 static GENERATION: AtomicU64 = AtomicU64::new(0);
 
 // Non-local GFiles (smb://, sftp://, GVfs mounts) have no local path, and MuPDF opens by path only.
 // Stage their bytes to a temp file once, keyed by uri; cleared on invalidate() so a changed remote
-// file re-stages. Shutdown skips destructors (main.rs _exit), so the last session's staged file
-// lingers in the temp dir - harmless, and left for the OS temp cleaner.
+// file re-stages. Statics are never dropped, so the last session's staged file lingers in the temp
+// dir - harmless, and left for the OS temp cleaner.
 static STAGED: Lazy<Mutex<HashMap<String, PathBuf>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
+// Filled-in form fields are written to a private working copy of the document, which stands in for
+// the original (local or staged) until the next load; keyed by uri, cleared on invalidate().
+static WORKING: Lazy<Mutex<HashMap<String, PathBuf>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
 thread_local! {
     // (uri, generation-at-open, Document). One Document per thread: it's bound to the thread's
     // fz_context, so it can't cross threads. Reopened when the uri or the generation changes.
     static DOC: RefCell<Option<(String, u64, Document)>> = const { RefCell::new(None) };
+
+    // QUALITY_GENERATION this thread's fz_context was last brought up to.
+    static APPLIED_QUALITY: Cell<u64> = const { Cell::new(0) };
+
+    // (uri, generation, page, display list) of the page this thread last drew tiles of. A page's
+    // tiles are queued together, so a worker mostly draws several in a row from one recording.
+    static TILE_LIST: RefCell<Option<(String, u64, i32, DisplayList)>> = const { RefCell::new(None) };
 }
 
 // Current document generation, bumped by invalidate(). Callers that cache derived data (e.g. the
@@ -52,6 +96,32 @@ pub(crate) fn generation() -> u64 {
     GENERATION.load(Ordering::Relaxed)
 }
 
+// Takes effect on each render thread at its next render; rendered pages need dropping to show it.
+pub fn set_render_quality(quality: RenderQuality) {
+    let mut current = QUALITY.lock().unwrap();
+    if *current != quality {
+        *current = quality;
+        QUALITY_GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn render_quality() -> RenderQuality {
+    *QUALITY.lock().unwrap()
+}
+
+// Bring this thread's fz_context up to the current anti-aliasing levels.
+fn apply_render_quality() {
+    let generation = QUALITY_GENERATION.load(Ordering::Relaxed);
+    if APPLIED_QUALITY.get() == generation {
+        return;
+    }
+    let quality = render_quality();
+    let mut ctx = Context::get();
+    ctx.set_graphics_aa_level(quality.graphics_aa);
+    ctx.set_text_aa_level(quality.text_aa);
+    APPLIED_QUALITY.set(generation);
+}
+
 pub fn set_dark_mode(enabled: bool) {
     DARK_MODE_ENABLED.store(enabled, Ordering::Relaxed);
 }
@@ -76,11 +146,32 @@ pub(crate) fn loading_text_rgb() -> [u8; 3] {
 // thread reopens against the current bytes, and any staged remote copies are re-fetched.
 pub fn invalidate() {
     GENERATION.fetch_add(1, Ordering::Relaxed);
-    let mut staged = STAGED.lock().unwrap();
-    for path in staged.values() {
-        let _ = std::fs::remove_file(path);
+    for copies in [&STAGED, &WORKING] {
+        let mut copies = copies.lock().unwrap();
+        for path in copies.values() {
+            let _ = std::fs::remove_file(path);
+        }
+        copies.clear();
+    }
+}
+
+pub(crate) fn working_copy(uri: &str) -> Option<PathBuf> {
+    WORKING.lock().unwrap().get(uri).cloned()
+}
+
+// Render `uri` from `path` from now on. Every thread reopens (the bytes changed), but nothing else
+// is invalidated: the caller knows which pages the change touched.
+pub(crate) fn set_working_copy(uri: &str, path: PathBuf) {
+    if let Some(old) = WORKING
+        .lock()
+        .unwrap()
+        .insert(uri.to_string(), path.clone())
+    {
+        if old != path {
+            let _ = std::fs::remove_file(old);
+        }
     }
-    staged.clear();
+    GENERATION.fetch_add(1, Ordering::Relaxed);
 }
 
 // Stream a non-local GFile into a secure temp copy (O_EXCL, mode 600): peak memory is one buffer,
@@ -103,6 +194,10 @@ fn fetch_to_temp(file: &gtk::gio::File) -> Option<tempfile::TempPath> {
     Some(tmp.into_temp_path())
 }
 
+// Pages whose height is read before the document shows. Enough for the pages in view at any
+// zoom the load restores; a long scan would otherwise load every page before the first paint.
+pub(crate) const PROBED_PAGES: i32 = 16;
+
 // A document staged for load: `path` is validated then commit()ted, so validation and render see
 // identical bytes (no TOCTOU gap). Dropped uncommitted, an owned temp copy is removed.
 pub(crate) struct Candidate {
@@ -110,22 +205,26 @@ pub(crate) struct Candidate {
     path: PathBuf,
     // Some for a temp copy we own: kept on commit, else deleted on drop.
     temp: Option<tempfile::TempPath>,
+    // what reads these bytes; serves the uri once committed
+    backend: Arc<dyn RenderBackend>,
 }
 
 // Stage `uri`: own path if local, else a temp copy of the bytes.
 pub(crate) fn stage_candidate(uri: &str) -> Option<Candidate> {
     // Emulate mode has no real file to stage; hand back a placeholder candidate.
-    if crate::emulate::config().is_some() {
+    if let Some(backend) = crate::emulate::backend() {
         return Some(Candidate {
             uri: uri.to_string(),
             path: PathBuf::new(),
             temp: None,
+            backend,
         });
     }
     let file = gtk::gio::File::for_uri(uri);
     if let Some(path) = file.path() {
         return Some(Candidate {
             uri: uri.to_string(),
+            backend: crate::backend::select(&path),
             path,
             temp: None,
         });
@@ -134,28 +233,26 @@ pub(crate) fn stage_candidate(uri: &str) -> Option<Candidate> {
     let path = temp.to_path_buf();
     Some(Candidate {
         uri: uri.to_string(),
+        backend: crate::backend::select(&path),
         path,
         temp: Some(temp),
     })
 }
 
 impl Candidate {
-    // Read the page count and the tallest paper height from one document open.
+    // Read the page count and the tallest paper height of the first pages from one document open;
+    // State::measure_pages reads the rest once the document shows.
     pub(crate) fn probe(&self) -> Option<(i32, Option<f64>)> {
-        if let Some(cfg) = crate::emulate::config() {
-            return Some((cfg.pages, Some(cfg.page_pt.1)));
-        }
-        let _ctx = Colorspace::device_bgr();
-        let doc = Document::open(self.path.as_path()).ok()?;
-        let n_pages = doc.page_count().ok()?;
-        let tallest_page_height = (0..n_pages)
-            .filter_map(|index| doc.load_page(index).ok()?.bounds().ok())
-            .map(|bounds| f64::from(bounds.y1 - bounds.y0))
-            .max_by(f64::total_cmp);
-        Some((n_pages, tallest_page_height))
+        self.backend.probe(&self.path, PROBED_PAGES)
+    }
+
+    // Content fingerprint of the staged bytes, naming the document's previews on disk.
+    pub(crate) fn fingerprint(&self) -> Option<String> {
+        self.backend.fingerprint(&self.path)
     }
 
-    // Publish the validated temp so workers render these exact bytes. Call after invalidate().
+    // Publish the validated temp so workers render these exact bytes, and its backend for the uri.
+    // Call after invalidate().
     pub(crate) fn commit(mut self) {
         if let Some(temp) = self.temp.take() {
             if let Ok(path) = temp.keep() {
@@ -164,11 +261,28 @@ impl Candidate {
                 }
             }
         }
+        crate::backend::install(&self.uri, self.backend.clone());
     }
 }
 
-// Local path for `uri`: own path if local, else the staged temp copy (miss → fetch as fallback).
+// Page count and the tallest height of the first `pages` pages, from one open.
+fn probe_path(path: &Path, pages: i32) -> Option<(i32, Option<f64>)> {
+    let _ctx = Colorspace::device_bgr();
+    let doc = Document::open(path).ok()?;
+    let n_pages = doc.page_count().ok()?;
+    let tallest_page_height = (0..n_pages.min(pages))
+        .filter_map(|index| doc.load_page(index).ok()?.bounds().ok())
+        .map(|bounds| f64::from(bounds.y1 - bounds.y0))
+        .max_by(f64::total_cmp);
+    Some((n_pages, tallest_page_height))
+}
+
+// Local path for `uri`: its form working copy if fields were filled in, else its own path if local,
+// else the staged temp copy (miss → fetch as fallback).
 pub(crate) fn local_path(uri: &str) -> Option<PathBuf> {
+    if let Some(path) = working_copy(uri) {
+        return Some(path);
+    }
     let file = gtk::gio::File::for_uri(uri);
     if let Some(path) = file.path() {
         return Some(path);
@@ -198,6 +312,7 @@ pub(crate) fn local_path(uri: &str) -> Option<PathBuf> {
 // ("thread local panicked on drop") when a pool worker exits.
 pub fn with_doc<T>(uri: &str, f: impl FnOnce(&Document) -> Option<T>) -> Option<T> {
     let _ctx = Colorspace::device_bgr();
+    apply_render_quality();
     let generation = GENERATION.load(Ordering::Relaxed);
     DOC.with(|cell| {
         let mut slot = cell.borrow_mut();
@@ -213,6 +328,88 @@ pub fn with_doc<T>(uri: &str, f: impl FnOnce(&Document) -> Option<T>) -> Option<
     })
 }
 
+// A render's cancellation: a flag for its job to check and a MuPDF cookie for the render to poll.
+// Cancelling a queued job makes it a no-op when popped; a running render stops at MuPDF's next
+// check, and whatever it drew is discarded.
+#[derive(Clone)]
+pub struct Cancel(Arc<CancelInner>);
+
+struct CancelInner {
+    cancelled: AtomicBool,
+    // None if MuPDF couldn't allocate one; the job then only sees the flag
+    cookie: Option<SharedCookie>,
+}
+
+// fz_cookie is made to be written by one thread while another renders with it: MuPDF polls its
+// abort field without locking. Cancelling stores into that field atomically through a pointer of our
+// own, never through the Cookie (whose abort() takes &mut), so renders only ever share `&Cookie`.
+struct SharedCookie {
+    cookie: mupdf::Cookie,
+    // the fz_cookie's first field, `int abort`
+    abort: *mut i32,
+}
+
+// mupdf::Cookie is its fz_cookie pointer and nothing else: being that size leaves room for no other
+// field, so the pointer is at its start.
+const _: () = assert!(std::mem::size_of::<mupdf::Cookie>() == std::mem::size_of::<*mut i32>());
+
+unsafe impl Send for SharedCookie {}
+unsafe impl Sync for SharedCookie {}
+
+impl SharedCookie {
+    fn new() -> Option<Self> {
+        let cookie = mupdf::Cookie::new().ok()?;
+        // SAFETY: see the size assertion; this copies the pointer out, `cookie` still owns it
+        let abort = unsafe { std::mem::transmute_copy::<mupdf::Cookie, *mut i32>(&cookie) };
+        Some(Self { cookie, abort })
+    }
+
+    fn abort(&self) {
+        // SAFETY: `abort` is the aligned int at the start of the fz_cookie `self.cookie` keeps
+        // alive; MuPDF only reads it
+        unsafe { AtomicI32::from_ptr(self.abort) }.store(1, Ordering::Relaxed);
+    }
+}
+
+impl Cancel {
+    pub(crate) fn new() -> Self {
+        let cookie = SharedCookie::new();
+        Self(Arc::new(CancelInner {
+            cancelled: AtomicBool::new(false),
+            cookie,
+        }))
+    }
+
+    pub fn cancel(&self) {
+        if self.0.cancelled.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        if let Some(cookie) = &self.0.cookie {
+            cookie.abort();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Relaxed)
+    }
+
+    // The cookie to hand MuPDF's render calls.
+    fn cookie(&self) -> Option<&mupdf::Cookie> {
+        self.0.cookie.as_ref().map(|cookie| &cookie.cookie)
+    }
+
+    pub(crate) fn same(&self, other: &Cancel) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// Drop this thread's Document and display list while its fz_context is still alive; a worker
+// thread calls this on its way out, since thread-locals are torn down in no particular order.
+pub(crate) fn release_thread_resources() {
+    TILE_LIST.with(|cell| cell.borrow_mut().take());
+    DOC.with(|cell| cell.borrow_mut().take());
+}
+
 // One raster buffer's raw pixels (cairo Rgb24/BGRx), transferable between render and UI threads.
 pub struct PagePixels {
     pub data: Vec<u8>,
@@ -223,17 +420,28 @@ pub struct PagePixels {
 
 // Page pixels at `scale`*`dsf`, or None if unrenderable. `page_pt` sizes the buffer to match the
 // render cache's check - MuPDF's pixmap rounding differs ~1px, which would look endlessly stale.
-// None → size from MuPDF bounds (bench only).
+// None → size from MuPDF bounds (bench only). A cancelled render stops early and returns None.
 pub fn render_page_pixels(
     uri: &str,
     page_num: i32,
     scale: f64,
     dsf: f64,
     page_pt: Option<(f64, f64)>,
+    cancel: Option<&Cancel>,
 ) -> Option<PagePixels> {
-    render_page_pixels_with_mode(uri, page_num, scale, dsf, page_pt, dark_mode())
+    render_page_pixels_with_mode(
+        uri,
+        page_num,
+        scale,
+        dsf,
+        page_pt,
+        dark_mode(),
+        true,
+        cancel,
+    )
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_page_pixels_with_mode(
     uri: &str,
     page_num: i32,
@@ -241,25 +449,31 @@ fn render_page_pixels_with_mode(
     dsf: f64,
     page_pt: Option<(f64, f64)>,
     dark_mode: Option<DarkMode>,
+    darken: bool,
+    cancel: Option<&Cancel>,
 ) -> Option<PagePixels> {
     with_doc(uri, |doc| {
         // device_bgr + no alpha yields B,G,R samples, matching cairo Rgb24's byte order.
         let colorspace = Colorspace::device_bgr();
         let page = doc.load_page(page_num).ok()?;
         let ctm = Matrix::new_scale((scale * dsf) as f32, (scale * dsf) as f32);
-        // show_extras: render annotations/widgets too, matching a full page render.
-        let pixmap = page.to_pixmap(&ctm, &colorspace, false, true).ok()?;
-
-        let (pw, ph) = match page_pt {
-            Some(size) => size,
-            None => {
-                let b = page.bounds().ok()?;
-                ((b.x1 - b.x0) as f64, (b.y1 - b.y0) as f64)
-            }
-        };
+        // What Page::to_pixmap does - a white pixmap over the transformed bounds - but run through
+        // a device, which is what takes a cookie.
+        let b = page.bounds().ok()?;
+        let mut pixmap =
+            Pixmap::new_with_rect(&colorspace, b.transform(&ctm).round(), false).ok()?;
+        pixmap.clear_with(255).ok()?;
+        let device = Device::from_pixmap(&pixmap).ok()?;
+        run_page(&page, &device, &ctm, cancel).ok()?;
+        drop(device);
+        if cancel.is_some_and(Cancel::is_cancelled) {
+            return None;
+        }
+
+        let (pw, ph) = page_pt.unwrap_or(((b.x1 - b.x0) as f64, (b.y1 - b.y0) as f64));
         let width = ((pw * scale * dsf) as i32).max(1);
         let height = ((ph * scale * dsf) as i32).max(1);
-        let (data, stride) = pack_pixmap(&pixmap, width, height, dark_mode)?;
+        let (data, stride) = pack_pixmap(&pixmap, width, height, dark_mode, darken)?;
         Some(PagePixels {
             data,
             width,
@@ -291,51 +505,139 @@ pub fn render_page_regions(
     scale: f64,
     dsf: f64,
     regions: &[PixelRect],
+) -> Option<Vec<PagePixels>> {
+    render_page_regions_with_mode(uri, page_num, scale, dsf, regions, dark_mode())
+}
+
+fn render_page_regions_with_mode(
+    uri: &str,
+    page_num: i32,
+    scale: f64,
+    dsf: f64,
+    regions: &[PixelRect],
+    dark_mode: Option<DarkMode>,
 ) -> Option<Vec<PagePixels>> {
     with_doc(uri, |doc| {
-        let colorspace = Colorspace::device_bgr();
-        let page = doc.load_page(page_num).ok()?;
-        let list = page.to_display_list(true).ok()?;
+        let list = doc.load_page(page_num).ok()?.to_display_list(true).ok()?;
         let ctm = Matrix::new_scale((scale * dsf) as f32, (scale * dsf) as f32);
-        let mut rendered = Vec::with_capacity(regions.len());
-        let dark_mode = dark_mode();
+        regions
+            .iter()
+            .map(|&region| rasterize_region(&list, &ctm, region, dark_mode, None))
+            .collect()
+    })
+}
 
-        for region in regions {
-            debug_assert!(region.x0 < region.x1 && region.y0 < region.y1);
-            if region.x0 >= region.x1 || region.y0 >= region.y1 {
+// One tile of a page for the viewport, rasterized like render_page_regions. The page's display list
+// is recorded once per thread and reused for its next tiles. A cancelled tile returns None, and a
+// recording cut short is never kept.
+pub fn render_page_tile(
+    uri: &str,
+    page_num: i32,
+    scale: f64,
+    dsf: f64,
+    region: PixelRect,
+    cancel: Option<&Cancel>,
+) -> Option<PagePixels> {
+    render_page_tile_with_mode(uri, page_num, scale, dsf, region, dark_mode(), cancel)
+}
+
+fn render_page_tile_with_mode(
+    uri: &str,
+    page_num: i32,
+    scale: f64,
+    dsf: f64,
+    region: PixelRect,
+    dark_mode: Option<DarkMode>,
+    cancel: Option<&Cancel>,
+) -> Option<PagePixels> {
+    // the context first, so it outlives TILE_LIST as in with_doc
+    let _ctx = Colorspace::device_bgr();
+    // a recorded page skips with_doc
+    apply_render_quality();
+    let generation = GENERATION.load(Ordering::Relaxed);
+    TILE_LIST.with(|cell| {
+        let mut slot = cell.borrow_mut();
+        let fresh = slot
+            .as_ref()
+            .is_some_and(|(u, g, p, _)| u == uri && *g == generation && *p == page_num);
+        if !fresh {
+            *slot = None;
+            let list = with_doc(uri, |doc| {
+                let page = doc.load_page(page_num).ok()?;
+                // Page::to_display_list(true), recorded by hand so the cookie reaches it
+                let mut list = DisplayList::new(page.bounds().ok()?).ok()?;
+                {
+                    let device = Device::from_display_list(&mut list).ok()?;
+                    run_page(&page, &device, &Matrix::IDENTITY, cancel).ok()?;
+                }
+                Some(list)
+            })?;
+            if cancel.is_some_and(Cancel::is_cancelled) {
                 return None;
             }
-            let rect = IRect::new(region.x0, region.y0, region.x1, region.y1);
-            let mut pixmap = Pixmap::new_with_rect(&colorspace, rect, false).ok()?;
-            pixmap.clear_with(255).ok()?;
-            let device = Device::from_pixmap(&pixmap).ok()?;
-            list.run(
-                &device,
-                &ctm,
-                Rect::new(
-                    region.x0 as f32,
-                    region.y0 as f32,
-                    region.x1 as f32,
-                    region.y1 as f32,
-                ),
-            )
-            .ok()?;
-            drop(device);
-
-            let width = region.x1 - region.x0;
-            let height = region.y1 - region.y0;
-            let (data, stride) = pack_pixmap(&pixmap, width, height, dark_mode)?;
-            rendered.push(PagePixels {
-                data,
-                width,
-                height,
-                stride,
-            });
+            *slot = Some((uri.to_string(), generation, page_num, list));
         }
-        Some(rendered)
+        let (.., list) = slot.as_ref()?;
+        let ctm = Matrix::new_scale((scale * dsf) as f32, (scale * dsf) as f32);
+        let pixels = rasterize_region(list, &ctm, region, dark_mode, cancel)?;
+        (!cancel.is_some_and(Cancel::is_cancelled)).then_some(pixels)
     })
 }
 
+// Draw `region` (device pixels) of a recorded page onto white.
+fn rasterize_region(
+    list: &DisplayList,
+    ctm: &Matrix,
+    region: PixelRect,
+    dark_mode: Option<DarkMode>,
+    cancel: Option<&Cancel>,
+) -> Option<PagePixels> {
+    debug_assert!(region.x0 < region.x1 && region.y0 < region.y1);
+    if region.x0 >= region.x1 || region.y0 >= region.y1 {
+        return None;
+    }
+    let colorspace = Colorspace::device_bgr();
+    let rect = IRect::new(region.x0, region.y0, region.x1, region.y1);
+    let mut pixmap = Pixmap::new_with_rect(&colorspace, rect, false).ok()?;
+    pixmap.clear_with(255).ok()?;
+    let device = Device::from_pixmap(&pixmap).ok()?;
+    let area = Rect::new(
+        region.x0 as f32,
+        region.y0 as f32,
+        region.x1 as f32,
+        region.y1 as f32,
+    );
+    match cancel.and_then(Cancel::cookie) {
+        Some(cookie) => list.run_with_cookie(&device, ctm, area, cookie),
+        None => list.run(&device, ctm, area),
+    }
+    .ok()?;
+    drop(device);
+
+    let width = region.x1 - region.x0;
+    let height = region.y1 - region.y0;
+    let (data, stride) = pack_pixmap(&pixmap, width, height, dark_mode, true)?;
+    Some(PagePixels {
+        data,
+        width,
+        height,
+        stride,
+    })
+}
+
+// Page contents, annotations and widgets, as a full page render shows them.
+fn run_page(
+    page: &Page,
+    device: &Device,
+    ctm: &Matrix,
+    cancel: Option<&Cancel>,
+) -> Result<(), mupdf::Error> {
+    match cancel.and_then(Cancel::cookie) {
+        Some(cookie) => page.run_with_cookie(device, ctm, cookie),
+        None => page.run(device, ctm),
+    }
+}
+
 // `render_page_pixels` as an ImageSurface for benchmarks and tests.
 pub fn render_page_surface(
     uri: &str,
@@ -344,7 +646,7 @@ pub fn render_page_surface(
     dsf: f64,
     page_pt: Option<(f64, f64)>,
 ) -> Option<ImageSurface> {
-    render_page_surface_with_mode(uri, page_num, scale, dsf, page_pt, dark_mode())
+    render_page_surface_with_mode(uri, page_num, scale, dsf, page_pt, dark_mode(), true)
 }
 
 fn render_page_surface_with_mode(
@@ -354,11 +656,10 @@ fn render_page_surface_with_mode(
     dsf: f64,
     page_pt: Option<(f64, f64)>,
     dark_mode: Option<DarkMode>,
+    darken: bool,
 ) -> Option<ImageSurface> {
-    if let Some(cfg) = crate::emulate::config() {
-        return Some(crate::emulate::full_surface(cfg, page_num, scale, dsf));
-    }
-    let px = render_page_pixels_with_mode(uri, page_num, scale, dsf, page_pt, dark_mode)?;
+    let px =
+        render_page_pixels_with_mode(uri, page_num, scale, dsf, page_pt, dark_mode, darken, None)?;
     let surface =
         ImageSurface::create_for_data(px.data, Format::Rgb24, px.width, px.height, px.stride)
             .ok()?;
@@ -368,23 +669,110 @@ fn render_page_surface_with_mode(
 
 // Page size in points (width, height), or None.
 pub fn page_size(uri: &str, page_num: i32) -> Option<(f64, f64)> {
-    if let Some(cfg) = crate::emulate::config() {
-        let _ = page_num;
-        return Some(cfg.page_pt);
-    }
     with_doc(uri, |doc| {
         let b = doc.load_page(page_num).ok()?.bounds().ok()?;
         Some(((b.x1 - b.x0) as f64, (b.y1 - b.y0) as f64))
     })
 }
 
+// PDF, EPUB, XPS and whatever else MuPDF opens.
+pub(crate) struct Mupdf;
+
+impl RenderBackend for Mupdf {
+    fn probe(&self, path: &Path, pages: i32) -> Option<(i32, Option<f64>)> {
+        probe_path(path, pages)
+    }
+
+    fn page_count(&self, uri: &str) -> Option<i32> {
+        with_doc(uri, |doc| doc.page_count().ok())
+    }
+
+    fn page_size(&self, uri: &str, page_num: i32) -> Option<(f64, f64)> {
+        page_size(uri, page_num)
+    }
+
+    fn render_page(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        page_pt: Option<(f64, f64)>,
+        dark: bool,
+        cancel: Option<&Cancel>,
+    ) -> Option<PagePixels> {
+        let dark_mode = dark.then_some(DARK_MODE);
+        render_page_pixels_with_mode(uri, page_num, scale, dsf, page_pt, dark_mode, true, cancel)
+    }
+
+    fn render_tile(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        region: PixelRect,
+        dark: bool,
+        cancel: Option<&Cancel>,
+    ) -> Option<PagePixels> {
+        let dark_mode = dark.then_some(DARK_MODE);
+        render_page_tile_with_mode(uri, page_num, scale, dsf, region, dark_mode, cancel)
+    }
+
+    fn render_regions(
+        &self,
+        uri: &str,
+        page_num: i32,
+        scale: f64,
+        dsf: f64,
+        regions: &[PixelRect],
+        dark: bool,
+    ) -> Option<Vec<PagePixels>> {
+        let dark_mode = dark.then_some(DARK_MODE);
+        render_page_regions_with_mode(uri, page_num, scale, dsf, regions, dark_mode)
+    }
+
+    fn text_page(&self, uri: &str, page_num: i32) -> Option<Vec<crate::selection::Glyph>> {
+        with_doc(uri, |doc| crate::selection::page_glyphs(doc, page_num))
+    }
+
+    fn search(&self, uri: &str, page_num: i32, query: &str) -> Vec<crate::search::Match> {
+        with_doc(uri, |doc| {
+            Some(crate::search::search_page(doc, page_num, query))
+        })
+        .unwrap_or_default()
+    }
+
+    fn links(
+        &self,
+        uri: &str,
+        page_num: i32,
+    ) -> Vec<(crate::page::Rectangle, crate::links::LinkTarget)> {
+        with_doc(uri, |doc| crate::links::page_links(doc, page_num)).unwrap_or_default()
+    }
+
+    fn outline(&self, uri: &str) -> Vec<crate::outline::OutlineEntry> {
+        with_doc(uri, |doc| Some(crate::outline::from_doc(doc))).unwrap_or_default()
+    }
+
+    fn content_bbox(&self, uri: &str, page_num: i32) -> Option<(f64, f64, f64, f64)> {
+        content_bbox(uri, page_num)
+    }
+
+    // EPUB, XPS and the rest open read-only; only a PDF takes annotations.
+    fn annotatable(&self, uri: &str) -> bool {
+        with_doc(uri, |doc| Some(doc.is_pdf())).unwrap_or(false)
+    }
+}
+
 // Bounding box of the page's non-white content in page-local top-left points, or None for a blank
 // page. Used for crop-to-content. MuPDF exposes no ink-bbox device via the Rust binding (and a
 // display list's bounds are just its mediabox), so this renders the page small and scans for the
-// tightest non-white rect - robust across text, vector and image content.
+// tightest non-white rect - robust across text, vector and image content. Drawn without the grey
+// gamma, so the box doesn't move with that setting.
 pub fn content_bbox(uri: &str, page_num: i32) -> Option<(f64, f64, f64, f64)> {
     const SCALE: f64 = 0.2; // 1 sampled pixel = 5pt; crop adds a 5pt margin anyway
-    let surface = render_page_surface_with_mode(uri, page_num, SCALE, 1.0, None, None)?;
+    let surface = render_page_surface_with_mode(uri, page_num, SCALE, 1.0, None, None, false)?;
     let (w, h, stride) = (surface.width(), surface.height(), surface.stride() as usize);
 
     let mut pixels = None;
@@ -402,7 +790,12 @@ pub fn content_bbox(uri: &str, page_num: i32) -> Option<(f64, f64, f64, f64)> {
 
 // Tightest pixel bounding box (min_x, min_y, max_x, max_y, inclusive) of non-white content in a
 // Rgb24 (BGRx) buffer, or None if every pixel is near-white.
-fn scan_bbox(data: &[u8], w: i32, h: i32, stride: usize) -> Option<(i32, i32, i32, i32)> {
+pub(crate) fn scan_bbox(
+    data: &[u8],
+    w: i32,
+    h: i32,
+    stride: usize,
+) -> Option<(i32, i32, i32, i32)> {
     let (mut min_x, mut min_y, mut max_x, mut max_y) = (w, h, -1, -1);
     for y in 0..h {
         let row = &data[y as usize * stride..];
@@ -422,11 +815,13 @@ fn scan_bbox(data: &[u8], w: i32, h: i32, stride: usize) -> Option<(i32, i32, i3
 
 // Pack a MuPDF BGR pixmap into a Rgb24 (BGRx) buffer of exactly (target_w, target_h) plus its stride.
 // The pixmap is within ~1px; copy the overlap and fill any padding with the page background.
+// `darken` applies the quality's grey gamma.
 fn pack_pixmap(
     pix: &mupdf::Pixmap,
     target_w: i32,
     target_h: i32,
     dark_mode: Option<DarkMode>,
+    darken: bool,
 ) -> Option<(Vec<u8>, i32)> {
     let n = pix.n() as usize; // 3 for device_bgr without alpha
     let src = pix.samples();
@@ -436,11 +831,18 @@ fn pack_pixmap(
     let mut data = vec![0xffu8; dst_stride * target_h as usize];
     let rows = (pix.height() as usize).min(target_h as usize);
     let cols = (pix.width() as usize).min(target_w as usize);
+    let gamma = darken
+        .then(|| gamma_lut(render_quality().grey_gamma))
+        .flatten();
     for y in 0..rows {
         let srow = &src[y * src_stride..];
         let drow = &mut data[y * dst_stride..];
         for x in 0..cols {
             let s = &srow[x * n..];
+            let s = match &gamma {
+                Some(lut) if s[0] == s[1] && s[1] == s[2] => [lut[s[0] as usize]; 3],
+                _ => [s[0], s[1], s[2]],
+            };
             let rgb = match dark_mode {
                 Some(_) if s[0] == s[1] && s[1] == s[2] => GREY_LUT[s[0] as usize],
                 Some(mode) => recolor(s[2], s[1], s[0], mode),
@@ -465,6 +867,43 @@ fn pack_pixmap(
     Some((data, dst_stride as i32))
 }
 
+// Darken the grey Rgb24 (BGRx) pixels in place by the quality's grey gamma, as pack_pixmap does
+// MuPDF's; for backends that draw their own. Before any dark-mode recolouring.
+pub(crate) fn darken_rgb24(data: &mut [u8]) {
+    if let Some(lut) = gamma_lut(render_quality().grey_gamma) {
+        darken_greys(data, &lut);
+    }
+}
+
+fn darken_greys(data: &mut [u8], lut: &[u8; 256]) {
+    for pixel in data.chunks_exact_mut(4) {
+        if pixel[0] == pixel[1] && pixel[1] == pixel[2] {
+            let grey = lut[pixel[0] as usize];
+            pixel[..3].fill(grey);
+        }
+    }
+}
+
+// Each 8-bit level raised to `gamma`, or None when that changes nothing.
+fn gamma_lut(gamma: f64) -> Option<[u8; 256]> {
+    (gamma != 1.0).then(|| {
+        std::array::from_fn(|value| ((value as f64 / 255.0).powf(gamma) * 255.0).round() as u8)
+    })
+}
+
+// Recolour Rgb24 (BGRx) pixels in place for dark mode, as pack_pixmap does MuPDF's; for backends
+// that draw their own.
+pub(crate) fn recolor_rgb24(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        let rgb = if pixel[0] == pixel[1] && pixel[1] == pixel[2] {
+            GREY_LUT[pixel[0] as usize]
+        } else {
+            recolor(pixel[2], pixel[1], pixel[0], DARK_MODE)
+        };
+        pixel[..3].copy_from_slice(&[rgb[2], rgb[1], rgb[0]]);
+    }
+}
+
 fn dark_mode() -> Option<DarkMode> {
     dark_mode_enabled().then_some(DARK_MODE)
 }
@@ -539,6 +978,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn grey_gamma_darkens_the_greys_between_paper_and_ink() {
+        assert_eq!(gamma_lut(1.0), None);
+
+        let lut = gamma_lut(1.8).unwrap();
+        assert_eq!((lut[0], lut[255]), (0, 255));
+        assert!(lut[128] < 128 && lut[200] < 200, "mid greys darken");
+        assert!(lut.windows(2).all(|pair| pair[0] <= pair[1]), "order kept");
+
+        // a grey, then a red, as BGRx: only the grey darkens
+        let mut data = vec![128, 128, 128, 0, 0, 0, 200, 0];
+        darken_greys(&mut data, &lut);
+        assert!(data[0] < 128 && data[0] == data[1] && data[1] == data[2]);
+        assert_eq!(&data[4..], &[0, 0, 200, 0]);
+    }
+
+    #[test]
+    fn recolor_rgb24_turns_paper_and_ink_in_place() {
+        // white, then black, as BGRx
+        let mut data = vec![0xff, 0xff, 0xff, 0, 0, 0, 0, 0];
+        recolor_rgb24(&mut data);
+        let [r, g, b] = DARK_MODE.paper;
+        assert_eq!(&data[..3], &[b, g, r]);
+        let [r, g, b] = DARK_MODE.ink;
+        assert_eq!(&data[4..7], &[b, g, r]);
+    }
+
     // Cold (open+repair) vs warm (render) cost, plus a PPM dump to eyeball correctness. Needs a file:
     //   PDF_PATH=/abs/scan.pdf SCALE=0.25 cargo test --release \
     //     mupdf_render::tests::bench -- --ignored --nocapture
@@ -591,7 +1057,7 @@ trailer\n<< /Root 1 0 R >>\n%%EOF";
     #[test]
     fn page_regions_match_the_same_pixels_in_a_full_render() {
         let uri = margin_pdf_uri();
-        let full = render_page_pixels(&uri, 0, 1.0, 1.0, Some((200.0, 200.0))).unwrap();
+        let full = render_page_pixels(&uri, 0, 1.0, 1.0, Some((200.0, 200.0)), None).unwrap();
         let regions = [
             PixelRect::new(0, 0, 100, 100),
             PixelRect::new(100, 0, 200, 100),
@@ -618,6 +1084,45 @@ trailer\n<< /Root 1 0 R >>\n%%EOF";
         }
     }
 
+    #[test]
+    fn tiles_match_page_regions_and_a_cancelled_tile_is_dropped() {
+        let uri = margin_pdf_uri();
+        let regions = [
+            PixelRect::new(50, 40, 150, 120),
+            PixelRect::new(0, 0, 60, 60),
+        ];
+        let expected = render_page_regions(&uri, 0, 1.0, 1.0, &regions).unwrap();
+        // the second tile replays the recording the first one left behind
+        for (region, expected) in regions.into_iter().zip(expected) {
+            let tile = render_page_tile(&uri, 0, 1.0, 1.0, region, None).unwrap();
+            assert_eq!((tile.width, tile.height), (expected.width, expected.height));
+            assert_eq!(tile.data, expected.data);
+        }
+
+        let cancel = Cancel::new();
+        cancel.cancel();
+        assert!(render_page_tile(&uri, 0, 1.0, 1.0, regions[0], Some(&cancel)).is_none());
+    }
+
+    #[test]
+    fn snapshot_renders_the_region_at_the_requested_dpi() {
+        let uri = margin_pdf_uri();
+        // the mark, (60,50)-(140,150) in top-left points, at twice the page's 72 dpi
+        let shot =
+            crate::backend::render_snapshot(&uri, 0, (60.0, 50.0, 140.0, 150.0), 144.0, false)
+                .unwrap();
+        assert_eq!((shot.width, shot.height), (160, 200));
+        let centre = (100 * shot.stride + 80 * 4) as usize;
+        assert_eq!(&shot.data[centre..centre + 3], &[0, 0, 0]);
+
+        // recoloured only when asked, with the dark-mode ink
+        let dark =
+            crate::backend::render_snapshot(&uri, 0, (60.0, 50.0, 140.0, 150.0), 144.0, true)
+                .unwrap();
+        let ink = DARK_MODE.ink;
+        assert_eq!(&dark.data[centre..centre + 3], &[ink[2], ink[1], ink[0]]);
+    }
+
     #[test]
     fn page_count_and_size_read_the_document() {
         let uri = margin_pdf_uri();
@@ -678,17 +1183,14 @@ trailer\n<< /Root 1 0 R >>\n%%EOF";
 trailer\n<< /Root 1 0 R >>\n%%EOF";
 
     #[test]
-    fn probe_reads_every_page_not_a_sample() {
+    fn probe_reads_only_the_first_pages() {
         let dir = std::env::temp_dir().join("scrolex_tall_last_page_test");
         std::fs::create_dir_all(&dir).unwrap();
         let path = dir.join("tall_last.pdf");
         std::fs::write(&path, TALL_LAST_PAGE_PDF).unwrap();
-        let uri = format!("file://{}", path.display());
 
-        assert_eq!(
-            stage_candidate(&uri).unwrap().probe(),
-            Some((12, Some(900.0)))
-        );
+        assert_eq!(Mupdf.probe(&path, 8), Some((12, Some(200.0))));
+        assert_eq!(Mupdf.probe(&path, PROBED_PAGES), Some((12, Some(900.0))));
     }
 
     // A 300x200 page with /Rotate 90 (displayed 200x300) and the word "Hello" near the top-left of
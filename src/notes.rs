@@ -0,0 +1,188 @@
+// Reading notes: the document's highlights and comments as Markdown, grouped under the outline
+// section each falls in. Highlighted text is read back from under the markup's quads with the same
+// glyph extraction the text selection uses.
+
+use crate::annotations::Annotation;
+use crate::markup::Edit;
+use crate::outline::OutlineEntry;
+
+// One exported note: the marked-up text (if any) and what was written about it.
+struct Note {
+    // 1-based
+    page: i32,
+    quote: String,
+    annotation: Annotation,
+}
+
+// The notes of the document at `uri`, unsaved `pending` edits included, as a Markdown document
+// headed `title`. Reads every page: call off the main thread.
+pub fn markdown(uri: &str, title: &str, pending: &[Edit]) -> String {
+    let notes: Vec<Note> = crate::annotations::with_pending(crate::annotations::all(uri), pending)
+        .into_iter()
+        .filter_map(|annotation| {
+            let quote = if annotation.quads.is_empty() {
+                String::new()
+            } else {
+                crate::selection::text_in_rects(uri, annotation.page, &annotation.quads)
+            };
+            // bare shapes and stamps say nothing worth exporting
+            if quote.is_empty() && !annotation.has_comment() {
+                return None;
+            }
+            Some(Note {
+                page: annotation.page + 1,
+                quote,
+                annotation,
+            })
+        })
+        .collect();
+    render(title, &crate::outline::entries(uri), &notes)
+}
+
+fn render(title: &str, sections: &[OutlineEntry], notes: &[Note]) -> String {
+    let mut out = format!("# {title}\n");
+    // sections whose heading is already out, so a section's notes and its subsections share it
+    let mut written = vec![false; sections.len()];
+    let mut current = None;
+    for note in notes {
+        let section = section_of(sections, note.page);
+        if section != current {
+            if let Some(section) = section {
+                for i in heading_chain(sections, section) {
+                    if !written[i] {
+                        written[i] = true;
+                        let level = (sections[i].depth as usize + 2).min(6);
+                        out.push_str(&format!("\n{} {}\n", "#".repeat(level), sections[i].title));
+                    }
+                }
+            }
+            current = section;
+        }
+        out.push('\n');
+        write_note(&mut out, note);
+    }
+    out
+}
+
+fn write_note(out: &mut String, note: &Note) {
+    let annotation = &note.annotation;
+    if !note.quote.is_empty() {
+        out.push_str(&format!("> {} (p. {})\n", note.quote, note.page));
+        let comment = annotation.contents.trim();
+        if !comment.is_empty() {
+            out.push('\n');
+            out.push_str(&quote_lines(comment, ""));
+        }
+    } else {
+        out.push_str(&format!("**{}, p. {}:** ", annotation.kind, note.page));
+        out.push_str(&quote_lines(annotation.contents.trim(), ""));
+    }
+    for reply in &annotation.replies {
+        let who = reply.author.as_deref().unwrap_or("Reply");
+        out.push_str(&format!("\n- **{who}:** "));
+        out.push_str(&quote_lines(reply.contents.trim(), "  "));
+    }
+}
+
+// `text` with every line after the first indented by `indent`, newline-terminated.
+fn quote_lines(text: &str, indent: &str) -> String {
+    let mut out = text.replace('\n', &format!("\n{indent}"));
+    out.push('\n');
+    out
+}
+
+// The last outline entry starting on or before `page`: the section the page's notes belong to.
+fn section_of(sections: &[OutlineEntry], page: i32) -> Option<usize> {
+    sections
+        .iter()
+        .rposition(|entry| entry.page.is_some_and(|start| start <= page))
+}
+
+// `section` and the entries it nests under, outermost first.
+fn heading_chain(sections: &[OutlineEntry], section: usize) -> Vec<usize> {
+    let mut chain = vec![section];
+    let mut depth = sections[section].depth;
+    for i in (0..section).rev() {
+        if sections[i].depth < depth {
+            depth = sections[i].depth;
+            chain.push(i);
+        }
+    }
+    chain.reverse();
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::Rectangle;
+
+    fn section(title: &str, depth: u32, page: i32) -> OutlineEntry {
+        OutlineEntry {
+            title: title.into(),
+            depth,
+            page: Some(page),
+        }
+    }
+
+    fn note(page: i32, quote: &str, contents: &str) -> Note {
+        Note {
+            page,
+            quote: quote.into(),
+            annotation: Annotation {
+                page: page - 1,
+                kind: if quote.is_empty() {
+                    "Note"
+                } else {
+                    "Highlight"
+                },
+                author: None,
+                contents: contents.into(),
+                modified: None,
+                rect: Rectangle::default(),
+                quads: Vec::new(),
+                replies: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn notes_fall_under_their_sections() {
+        let sections = [
+            section("Intro", 0, 1),
+            section("Methods", 0, 3),
+            section("Sampling", 1, 4),
+        ];
+        let mut reply = note(5, "", "Agreed");
+        reply.annotation.author = Some("Bob".into());
+        let mut commented = note(5, "", "Check the sample size");
+        commented.annotation.replies.push(reply.annotation);
+        let notes = [
+            note(2, "A key claim", ""),
+            note(4, "Random draws", "Which seed?"),
+            commented,
+        ];
+        assert_eq!(
+            render("Paper", &sections, &notes),
+            "# Paper\n\
+             \n## Intro\n\
+             \n> A key claim (p. 2)\n\
+             \n## Methods\n\
+             \n### Sampling\n\
+             \n> Random draws (p. 4)\n\
+             \nWhich seed?\n\
+             \n**Note, p. 5:** Check the sample size\n\
+             \n- **Bob:** Agreed\n"
+        );
+    }
+
+    #[test]
+    fn notes_before_the_first_section_have_no_heading() {
+        let sections = [section("Chapter 1", 0, 3)];
+        let notes = [note(1, "Preface remark", "")];
+        assert_eq!(
+            render("Book", &sections, &notes),
+            "# Book\n\n> Preface remark (p. 1)\n"
+        );
+    }
+}
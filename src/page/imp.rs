@@ -6,7 +6,8 @@ use std::collections::hash_map::Entry;
 use std::rc::Rc;
 use std::sync::OnceLock;
 
-use futures::channel::oneshot;
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt;
 use gtk::cairo::{FontSlant, FontWeight};
 use gtk::gdk::prelude::*;
 use gtk::gdk::{MemoryFormat, MemoryTexture, BUTTON_PRIMARY, RGBA};
@@ -20,8 +21,11 @@ use once_cell::sync::Lazy;
 
 use super::Rectangle;
 use crate::bg_job::{RenderPool, RenderPriority};
+use crate::forms::{Field, FieldKind};
 use crate::links::LinkTarget;
-use crate::selection::PageSelection;
+use crate::markup::{Edit, Erased, MarkupKind, Stroke};
+use crate::mupdf_render::Cancel;
+use crate::selection::{TextSelection, Unit};
 
 // Max bytes in one page buffer. A whole page is rendered at once, so the buffer grows with the
 // scale squared. render_scale keeps it under this.
@@ -30,9 +34,12 @@ pub(crate) const MAX_PAGE_BYTES: f64 = 128.0 * 1024.0 * 1024.0;
 // can pass this while still under MAX_PAGE_BYTES.
 const MAX_TEXTURE_DIM: f64 = 16384.0;
 // Fixed device-pixel grid for viewport rendering. A 1024-square BGRx texture is 4 MiB, which keeps
-// the usual viewport to a small serial batch while bounding every allocation independently.
+// the usual viewport to a handful of tiles while bounding every allocation independently.
 const TILE_SIZE: i32 = 1024;
 const TILE_GUTTER: i32 = 1;
+// Queued visible renders. Each viewport tile is its own job, so this holds a couple of screens'
+// worth of tiles on a large display; older ones fall off first.
+const MAX_QUEUED_VISIBLE: usize = 32;
 
 // Low-resolution previews rendered ahead of the visible page and shown (upscaled) while the full
 // render is pending, so aggressive scrolling shows blurry pages rather than blank ones. The render
@@ -55,6 +62,17 @@ const PREVIEW_SLOW_MS: u128 = 250;
 // Consecutive slow-at-min-scale previews before giving up on the document; shrugs off one-off outliers.
 const PREVIEW_SLOW_STREAK_LIMIT: u32 = 5;
 
+// Width (px) of the bands at the list's left and right edges where a selection drag auto-scrolls,
+// the scroll per tick a band's depth adds, and the cap once the pointer is far past the edge.
+const SELECT_SCROLL_EDGE: f64 = 32.0;
+const SELECT_SCROLL_STEP: f64 = 12.0;
+const SELECT_SCROLL_MAX_STEP: f64 = 60.0;
+const SELECT_SCROLL_TICK_MS: u64 = 16;
+
+// How long shutdown waits for aborted renders to return. MuPDF polls the cookie between content
+// operators, so this is normally a few milliseconds; it's only reached inside one huge image decode.
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(2);
+
 thread_local!(
     // Pool caps: visible-preview, visible, preview, prefetch. Fast-scroll flooding is bounded by the
     // wanted-range filter (out-of-view full renders dropped on pop), so caps can be generous.
@@ -62,7 +80,7 @@ thread_local!(
         RenderPool::new(
             crate::config::DEFAULT_RENDER_THREADS,
             8,
-            8,
+            MAX_QUEUED_VISIBLE,
             MAX_INFLIGHT_PREVIEWS,
             8,
         )
@@ -79,16 +97,95 @@ pub(crate) fn set_wanted_pages(client: u64, range: Option<(i32, i32)>) {
     RENDER_QUEUE.with(|queue| queue.set_wanted(client, range));
 }
 
+// The preview scale a document starts at: the one its stored previews were rendered at, if any.
+pub(crate) fn stored_preview_scale(fingerprint: &str) -> f64 {
+    crate::preview_store::scale(fingerprint)
+        .filter(|scale| (PREVIEW_MIN_SCALE..=PREVIEW_MAX_SCALE).contains(scale))
+        .unwrap_or(PREVIEW_INITIAL_SCALE)
+}
+
+// Work out every page's crop box in the background, nearest the current page first, so turning
+// crop on never waits on a render and page widths don't change as boxes resolve. Pages whose box is
+// already known (saved with the document's state, or resolved on screen) are skipped. The jobs run
+// only when the pool has nothing else to do; a reload drops the rest.
+pub(crate) fn sweep_crop_boxes(state: &crate::state::State) {
+    let uri = state.uri();
+    let client = state.render_client_id();
+    let doc_epoch = state.doc_epoch();
+    let pages: Vec<i32> = {
+        let known = state.bbox_cache();
+        let known = known.borrow();
+        crate::search::search_order(state.n_pages(), state.page() as i32)
+            .into_iter()
+            .filter(|page| !known.contains_key(page))
+            .collect()
+    };
+    if pages.is_empty() {
+        return;
+    }
+
+    let (tx, mut rx) = mpsc::unbounded::<(i32, Rectangle)>();
+    RENDER_QUEUE.with(|queue| {
+        // farthest first: the newest job runs first
+        for &page_num in pages.iter().rev() {
+            let uri_job = uri.clone();
+            let tx = tx.clone();
+            queue.submit(
+                &uri,
+                client,
+                page_num,
+                RenderPriority::Background,
+                Box::new(move |cancel| {
+                    if cancel.is_cancelled() {
+                        return;
+                    }
+                    if let Some(bbox) = crop_box(&uri_job, page_num) {
+                        let _ = tx.unbounded_send((page_num, bbox));
+                    }
+                }),
+            );
+        }
+    });
+    drop(tx);
+
+    glib::spawn_future_local(clone!(
+        #[weak]
+        state,
+        async move {
+            while let Some((page_num, bbox)) = rx.next().await {
+                if state.doc_epoch() != doc_epoch {
+                    return;
+                }
+                // one resolved on screen meanwhile is the same box; keep it
+                state
+                    .bbox_cache()
+                    .borrow_mut()
+                    .entry(page_num)
+                    .or_insert(bbox);
+            }
+        }
+    ));
+}
+
 // Drop a window's queued full renders (zoom invalidates their scale; previews survive).
 pub(crate) fn clear_full_renders(client: u64) {
     RENDER_QUEUE.with(|queue| queue.clear_full(client));
 }
 
-// Drop all of a window's queued renders, previews included (document switch / window close).
+// Drop all of a window's queued renders, previews included, and abort its running ones (document
+// switch / window close).
 pub(crate) fn clear_all_renders(client: u64) {
     RENDER_QUEUE.with(|queue| queue.clear_all(client));
 }
 
+// Abort every render and stop the pool's workers and the document threads beside it, for application
+// shutdown. True once they have all exited; false if one is still inside MuPDF after the grace
+// period.
+pub fn shutdown_renders() -> bool {
+    let pool = RENDER_QUEUE.with(|queue| queue.shutdown(SHUTDOWN_GRACE));
+    pool && crate::bg_job::stop_document_threads(SHUTDOWN_GRACE)
+}
+
 // How many pages to prefetch ahead: the threads not busy on visible pages, but never more full
 // pages than the cache can hold beyond the visible ones - else completed prefetches evict the
 // visible pages and thrash. At deep zoom `page_bytes` alone fills the budget, which yields 0: pages
@@ -235,6 +332,26 @@ pub struct Page {
 
     // Whether this widget currently needs viewport regions instead of a whole-page texture.
     tiled: Cell<bool>,
+
+    // area of a snapshot drag in progress (page points), outlined until the drag ends
+    snapshot_region: RefCell<Option<Rectangle>>,
+
+    // comment popover, created on first hover over an annotation
+    annotation_popover: RefCell<Option<gtk::Popover>>,
+    // (page index, rect) of the annotation the popover shows, so hovering within it doesn't rebuild it
+    shown_annotation: Cell<Option<(i32, f64, f64, f64, f64)>>,
+
+    // in-place editor (entry, dropdown or check button) of the form field being filled, laid over
+    // the field
+    field_editor: RefCell<Option<(gtk::Widget, Field)>>,
+
+    // pen stroke being drawn (page points), shown live until the drag ends
+    ink_stroke: RefCell<Option<Stroke>>,
+    // during an eraser drag, the strokes rubbed out so far: hidden (or, saved ones, marked) now,
+    // erased on release
+    erasing: RefCell<Option<Vec<Erased>>>,
+    // the page's saved ink annotations, read when an eraser drag starts
+    saved_ink: RefCell<Vec<crate::annotations::SavedInk>>,
 }
 
 // What a snapshot drew, from best-looking to worst.
@@ -278,12 +395,25 @@ impl ObjectImpl for Page {
         self.setup_state_listeners();
         self.setup_text_selection();
         self.setup_link_handling();
+        self.setup_annotation_handling();
+        self.setup_form_handling();
+        self.setup_ink_drawing();
 
         self.obj().connect_unmap(|page| page.imp().unpin_render());
+        // a recycled widget showing another page drops the old page's editor
+        self.obj()
+            .connect_notify_local(Some("index"), |page, _| page.imp().close_field_editor());
 
         self.obj().set_size_request(600, 800);
     }
 
+    fn dispose(&self) {
+        if let Some(popover) = self.annotation_popover.take() {
+            popover.unparent();
+        }
+        self.close_field_editor();
+    }
+
     fn signals() -> &'static [Signal] {
         static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
         SIGNALS.get_or_init(|| {
@@ -295,6 +425,23 @@ impl ObjectImpl for Page {
 }
 
 impl WidgetImpl for Page {
+    fn size_allocate(&self, width: i32, height: i32, baseline: i32) {
+        self.parent_size_allocate(width, height, baseline);
+        // a popover child has to be re-presented whenever its parent moves or resizes
+        if let Some(popover) = self.annotation_popover.borrow().as_ref() {
+            popover.present();
+        }
+        if let Some((editor, field)) = self.field_editor.borrow().as_ref() {
+            let mut rect = apply_zoom_and_crop(&self.obj(), &field.rect);
+            // at low zoom the field can be smaller than the widget can draw itself
+            let (min_width, ..) = editor.measure(gtk::Orientation::Horizontal, -1);
+            let (min_height, ..) = editor.measure(gtk::Orientation::Vertical, -1);
+            rect.set_width(rect.width().max(min_width));
+            rect.set_height(rect.height().max(min_height));
+            editor.size_allocate(&rect, -1);
+        }
+    }
+
     fn snapshot(&self, snapshot: &gtk::Snapshot) {
         let Some(page) = self.page_info() else {
             return;
@@ -319,8 +466,13 @@ impl WidgetImpl for Page {
             self.render_snapshot(snapshot, &page);
         }
 
+        self.snapshot_edits_overlay(snapshot, &page);
         self.snapshot_selection_overlay(snapshot, &page);
         self.snapshot_search_overlay(snapshot, &page);
+        self.snapshot_region_overlay(snapshot, &page);
+        if let Some((editor, _)) = self.field_editor.borrow().as_ref() {
+            self.obj().snapshot_child(editor, snapshot);
+        }
     }
 }
 
@@ -425,7 +577,7 @@ impl Page {
     // the page can't be read.
     fn page_info(&self) -> Option<PageInfo> {
         let index = self.obj().index();
-        let (width, height) = crate::mupdf_render::page_size(&self.obj().uri(), index)?;
+        let (width, height) = crate::backend::page_size(&self.obj().uri(), index)?;
         Some(PageInfo {
             index,
             width,
@@ -435,7 +587,7 @@ impl Page {
 
     fn setup_text_selection(&self) {
         let obj = self.obj();
-        let mouse_coords = Rc::new(RefCell::new(None));
+        let drag = Rc::new(SelectionDrag::default());
         let gc = gtk::GestureClick::builder().button(BUTTON_PRIMARY).build();
 
         // indicates that we have "borrowed" global page cursor
@@ -443,16 +595,75 @@ impl Page {
 
         gc.connect_pressed(clone!(
             #[strong]
-            mouse_coords,
+            drag,
             #[strong(rename_to = page)]
             obj,
             #[weak(rename_to = imp)]
             self,
             #[strong]
             cursor,
-            move |_gc, _n_press, x, y| {
-                page.state().clear_selection();
-                mouse_coords.replace(Some((x, y)));
+            move |gc, n_press, x, y| {
+                let Point { x: px, y: py } = undo_zoom_and_crop(&page, x, y);
+                let here = (page.index(), (px, py));
+                let modifiers = gc.current_event_state();
+                let extend = modifiers.contains(gtk::gdk::ModifierType::SHIFT_MASK);
+
+                // In snapshot mode a drag copies the area as an image (Shift: saves it instead)
+                drag.snapshot.set(page.state().snapshot_mode().then(|| {
+                    if extend {
+                        crate::snapshot::Target::File
+                    } else {
+                        crate::snapshot::Target::Clipboard
+                    }
+                }));
+                if drag.snapshot.get().is_some() {
+                    drag.anchor.set(Some(here));
+                    if !imp.cursor_guard.get() {
+                        page.set_cursor_from_name(Some("crosshair"));
+                        imp.cursor_guard.set(true);
+                        cursor.set(true);
+                    }
+                    return;
+                }
+
+                // Alt+drag selects a rectangle instead of a run of text
+                drag.region
+                    .set(modifiers.contains(gtk::gdk::ModifierType::ALT_MASK));
+                if drag.region.get() {
+                    drag.anchor.set(Some(here));
+                    page.state().clear_selection();
+                    if !imp.cursor_guard.get() {
+                        page.set_cursor_from_name(Some("crosshair"));
+                        imp.cursor_guard.set(true);
+                        cursor.set(true);
+                    }
+                    return;
+                }
+
+                // Shift+click keeps the existing selection's start, on whichever page, and moves its
+                // end here
+                let start = extend
+                    .then(|| {
+                        page.state()
+                            .selection()
+                            .borrow()
+                            .as_ref()
+                            .map(|selection| selection.anchor)
+                    })
+                    .flatten();
+
+                let unit = Unit::from_n_press(n_press);
+                drag.unit.set(unit);
+                drag.anchor.set(Some(start.unwrap_or(here)));
+                drag.pointer.set(None);
+                if let Some(start) = start {
+                    select_text(&page, start, here, unit);
+                } else if unit != Unit::Char {
+                    select_text(&page, here, here, unit);
+                } else {
+                    page.state().clear_selection();
+                }
+
                 if !imp.cursor_guard.get() {
                     page.set_cursor_from_name(Some("text"));
                     imp.cursor_guard.set(true);
@@ -464,35 +675,80 @@ impl Page {
         let obj = self.obj().clone();
         gc.connect_update(clone!(
             #[strong]
-            mouse_coords,
+            drag,
             move |gc, seq| {
-                let Some((start_x, start_y)) = *mouse_coords.borrow() else {
+                let Some((x, y)) = gc.point(seq) else {
                     return;
                 };
-                let Some((end_x, end_y)) = gc.point(seq) else {
+                if drag.snapshot.get().is_some() {
+                    let Some((_, start)) = drag.anchor.get() else {
+                        return;
+                    };
+                    let Point { x, y } = undo_zoom_and_crop(&obj, x, y);
+                    obj.imp().snapshot_region.replace(Some(Rectangle::new(
+                        start.0.min(x),
+                        start.1.min(y),
+                        start.0.max(x),
+                        start.1.max(y),
+                    )));
+                    obj.queue_draw();
+                    return;
+                }
+                if drag.region.get() {
+                    // a region stays on the page it was started on, clipped by nothing but the page
+                    let Some((page, start)) = drag.anchor.get() else {
+                        return;
+                    };
+                    let Point { x, y } = undo_zoom_and_crop(&obj, x, y);
+                    match crate::selection::region_selection(&obj.uri(), page, start, (x, y)) {
+                        Some(selection) => obj.state().set_selection(Some(selection)),
+                        None => obj.state().clear_selection(),
+                    }
+                    return;
+                }
+                let Some(list) = obj
+                    .ancestor(gtk::ListView::static_type())
+                    .and_downcast::<gtk::ListView>()
+                else {
+                    return;
+                };
+                // The pointer may be over another page by now; track it in list coordinates and
+                // let the list say which
+                let Some(p) = obj.compute_point(&list, &graphene::Point::new(x as f32, y as f32))
+                else {
                     return;
                 };
+                drag.pointer.set(Some((f64::from(p.x()), f64::from(p.y()))));
+                drag_select(&obj, &list, &drag);
 
-                let Point { x: x1, y: y1 } = undo_zoom_and_crop(&obj, start_x, start_y);
-                let Point { x: x2, y: y2 } = undo_zoom_and_crop(&obj, end_x, end_y);
-
-                let selection =
-                    crate::selection::selection(&obj.uri(), obj.index(), (x1, y1), (x2, y2));
-                match selection {
-                    Some(sel) if !sel.rects.is_empty() => {
-                        obj.state().set_selection(Some(PageSelection {
-                            page: obj.index(),
-                            rects: sel.rects.into_iter().map(Rectangle::from).collect(),
-                            text: sel.text,
-                        }));
-                    }
-                    _ => obj.state().clear_selection(),
+                if drag.scroll.borrow().is_none()
+                    && autoscroll_step(f64::from(p.x()), f64::from(list.width())) != 0.0
+                {
+                    start_drag_autoscroll(&obj, &list, &drag);
                 }
             }
         ));
 
         let obj = self.obj().clone();
         gc.connect_end(move |_, _| {
+            if let Some(source) = drag.scroll.take() {
+                source.remove();
+            }
+            if let Some(target) = drag.snapshot.take() {
+                if let Some(region) = obj.imp().snapshot_region.take() {
+                    obj.queue_draw();
+                    crate::snapshot::take(&obj, region, target);
+                }
+            }
+            // the region outline is only a drag-time guide; the selected rows stay highlighted
+            if drag.region.get() {
+                let state = obj.state();
+                let selection = state.selection().borrow_mut().take();
+                state.set_selection(selection.map(|selection| TextSelection {
+                    region: None,
+                    ..selection
+                }));
+            }
             // Primary selection only (middle-click paste); the clipboard needs an explicit copy.
             if let Some(text) = obj.state().selected_text() {
                 obj.primary_clipboard().set_text(&text);
@@ -581,6 +837,486 @@ impl Page {
         obj.add_controller(gc);
     }
 
+    // Hovering an annotation pops up its comment thread; clicking it keeps the popover open (and its
+    // text selectable) until a click elsewhere. Ctrl+click starts a new sticky note there.
+    fn setup_annotation_handling(&self) {
+        let motion_controller = gtk::EventControllerMotion::new();
+        motion_controller.connect_motion(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, x, y| {
+                let pinned = imp
+                    .annotation_popover
+                    .borrow()
+                    .as_ref()
+                    .is_some_and(|popover| popover.autohides() && popover.is_visible());
+                if !pinned {
+                    imp.show_annotation_at(x, y, false);
+                }
+            }
+        ));
+        motion_controller.connect_leave(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| {
+                if let Some(popover) = imp.annotation_popover.borrow().as_ref() {
+                    if !popover.autohides() {
+                        popover.popdown();
+                    }
+                }
+            }
+        ));
+        self.obj().add_controller(motion_controller);
+
+        let gc = gtk::GestureClick::builder().button(BUTTON_PRIMARY).build();
+        gc.connect_pressed(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |gc, _n_press, x, y| {
+                if gc
+                    .current_event_state()
+                    .contains(gtk::gdk::ModifierType::CONTROL_MASK)
+                    && imp.obj().state().annotatable()
+                {
+                    imp.edit_note_at(x, y);
+                } else {
+                    imp.show_annotation_at(x, y, true);
+                }
+            }
+        ));
+        self.obj().add_controller(gc);
+    }
+
+    // Show the comment thread of the annotation under (x, y) widget coordinates, or hide the popover
+    // when there is none. A pinned popover autohides: it grabs input and closes on an outside click.
+    fn show_annotation_at(&self, x: f64, y: f64, pin: bool) {
+        let obj = self.obj();
+        let Point { x: px, y: py } = undo_zoom_and_crop(&obj, x, y);
+        let state = obj.state();
+        let mut annotations = state.imp().annotations.borrow_mut();
+        let Some(annot) = annotations.get_annotation(&obj.uri(), obj.index(), px, py) else {
+            if let Some(popover) = self.annotation_popover.borrow().as_ref() {
+                popover.popdown();
+            }
+            return;
+        };
+
+        let popover = self.annotation_popover();
+        let r = annot.rect;
+        let key = Some((obj.index(), r.x1, r.y1, r.x2, r.y2));
+        if popover.is_visible() && self.shown_annotation.get() == key && popover.autohides() == pin
+        {
+            return;
+        }
+        popover.popdown();
+        popover.set_autohide(pin);
+        popover.set_child(Some(&crate::annotations::comment_widget(annot)));
+        drop(annotations);
+        popover.set_pointing_to(Some(&apply_zoom_and_crop(&obj, &r)));
+        self.shown_annotation.set(key);
+        popover.popup();
+    }
+
+    // Ask for a note's text at (x, y) widget coordinates; Enter adds it as an unsaved annotation.
+    fn edit_note_at(&self, x: f64, y: f64) {
+        let obj = self.obj();
+        let Point { x: px, y: py } = undo_zoom_and_crop(&obj, x, y);
+        let entry = gtk::Entry::builder()
+            .placeholder_text("Note")
+            .width_chars(32)
+            .build();
+
+        let popover = self.annotation_popover();
+        popover.popdown();
+        popover.set_autohide(true);
+        popover.set_child(Some(&entry));
+        popover.set_pointing_to(Some(&gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+        entry.connect_activate(clone!(
+            #[weak]
+            obj,
+            #[weak]
+            popover,
+            move |entry| {
+                let text = entry.text().trim().to_string();
+                if !text.is_empty() {
+                    obj.state().add_edits(vec![Edit::Note {
+                        page: obj.index(),
+                        x: px,
+                        y: py,
+                        text,
+                    }]);
+                }
+                popover.popdown();
+            }
+        ));
+        self.shown_annotation.set(None);
+        popover.popup();
+        entry.grab_focus();
+    }
+
+    fn annotation_popover(&self) -> gtk::Popover {
+        if let Some(popover) = self.annotation_popover.borrow().as_ref() {
+            return popover.clone();
+        }
+        let popover = gtk::Popover::new();
+        popover.set_position(gtk::PositionType::Top);
+        popover.set_autohide(false);
+        popover.set_parent(&*self.obj());
+        popover.connect_closed(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_| imp.shown_annotation.set(None)
+        ));
+        self.annotation_popover.replace(Some(popover.clone()));
+        popover
+    }
+
+    // Clicking a form field fills it: checkboxes and radio buttons toggle, text and choice fields
+    // open an editor over the field. Runs in the capture phase and claims the click, so it doesn't
+    // also start a text selection.
+    fn setup_form_handling(&self) {
+        let gc = gtk::GestureClick::builder().button(BUTTON_PRIMARY).build();
+        gc.set_propagation_phase(gtk::PropagationPhase::Capture);
+        gc.connect_pressed(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |gc, _n_press, x, y| {
+                let obj = imp.obj();
+                if obj.state().pen_mode() {
+                    return;
+                }
+                // clicks inside an open editor are the editor's
+                if obj
+                    .pick(x, y, gtk::PickFlags::DEFAULT)
+                    .is_some_and(|picked| &picked != obj.upcast_ref::<gtk::Widget>())
+                {
+                    return;
+                }
+                let Point { x: px, y: py } = undo_zoom_and_crop(&obj, x, y);
+                let state = obj.state();
+                let field = state
+                    .forms()
+                    .borrow_mut()
+                    .field_at(&obj.uri(), obj.index(), px, py)
+                    .cloned();
+                let Some(field) = field else {
+                    imp.close_field_editor();
+                    return;
+                };
+                gc.set_state(gtk::EventSequenceState::Claimed);
+                if field.is_typed() {
+                    imp.open_field_editor(field);
+                } else {
+                    imp.close_field_editor();
+                    if let Some(value) = field.clicked_value() {
+                        imp.fill_field(&field, &value);
+                    }
+                }
+            }
+        ));
+        self.obj().add_controller(gc);
+    }
+
+    // Lay an editor for `field` over it and focus it. Enter (or picking an option, or toggling)
+    // writes the value, Escape drops it, Tab and Shift+Tab write it and move to the next or previous
+    // field on the page.
+    fn open_field_editor(&self, field: Field) {
+        self.close_field_editor();
+        let obj = self.obj();
+
+        let editor: gtk::Widget = match &field.kind {
+            FieldKind::Text => {
+                let entry = gtk::Entry::builder().text(&field.value).build();
+                entry.connect_activate(clone!(
+                    #[weak(rename_to = imp)]
+                    self,
+                    move |entry| imp.commit_field_editor(entry.text().as_str())
+                ));
+                // leaving the entry keeps what was typed, as in any form
+                let focus = gtk::EventControllerFocus::new();
+                focus.connect_leave(clone!(
+                    #[weak(rename_to = imp)]
+                    self,
+                    #[weak]
+                    entry,
+                    move |_| imp.commit_field_editor(entry.text().as_str())
+                ));
+                entry.add_controller(focus);
+                entry.upcast()
+            }
+            FieldKind::Choice { options } => {
+                let labels: Vec<&str> = options.iter().map(|(_, label)| label.as_str()).collect();
+                let dropdown = gtk::DropDown::from_strings(&labels);
+                if let Some(i) = options
+                    .iter()
+                    .position(|(export, _)| *export == field.value)
+                {
+                    dropdown.set_selected(i as u32);
+                }
+                let exports: Vec<String> =
+                    options.iter().map(|(export, _)| export.clone()).collect();
+                dropdown.connect_selected_notify(clone!(
+                    #[weak(rename_to = imp)]
+                    self,
+                    move |dropdown| {
+                        if let Some(value) = exports.get(dropdown.selected() as usize) {
+                            imp.commit_field_editor(value);
+                        }
+                    }
+                ));
+                dropdown.upcast()
+            }
+            FieldKind::Checkbox { .. } | FieldKind::Radio { .. } => {
+                // reached by Tab; Space toggles it
+                let check = gtk::CheckButton::builder()
+                    .active(field.is_checked())
+                    .build();
+                check.connect_toggled(clone!(
+                    #[weak(rename_to = imp)]
+                    self,
+                    move |_| {
+                        let value = imp
+                            .field_editor
+                            .borrow()
+                            .as_ref()
+                            .and_then(|(_, field)| field.clicked_value());
+                        if let Some(value) = value {
+                            imp.commit_field_editor(&value);
+                        }
+                    }
+                ));
+                check.upcast()
+            }
+        };
+        editor.add_css_class("form-field");
+
+        let keys = gtk::EventControllerKey::new();
+        keys.set_propagation_phase(gtk::PropagationPhase::Capture);
+        keys.connect_key_pressed(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            #[upgrade_or]
+            glib::Propagation::Proceed,
+            move |_, key, _, _| match key {
+                gtk::gdk::Key::Escape => {
+                    imp.close_field_editor();
+                    glib::Propagation::Stop
+                }
+                gtk::gdk::Key::Tab | gtk::gdk::Key::ISO_Left_Tab => {
+                    imp.move_to_field(key == gtk::gdk::Key::Tab);
+                    glib::Propagation::Stop
+                }
+                _ => glib::Propagation::Proceed,
+            }
+        ));
+        editor.add_controller(keys);
+
+        editor.set_parent(&*obj);
+        self.field_editor.replace(Some((editor.clone(), field)));
+        obj.queue_allocate();
+        editor.grab_focus();
+    }
+
+    // In pen mode a primary drag draws a stroke, following the pen's pressure where the device
+    // reports one, or with the eraser on rubs out the strokes it crosses, saved or not. Capture
+    // phase, so the drag doesn't also select text.
+    fn setup_ink_drawing(&self) {
+        let drag = gtk::GestureDrag::builder().button(BUTTON_PRIMARY).build();
+        drag.set_propagation_phase(gtk::PropagationPhase::Capture);
+        drag.connect_drag_begin(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |drag, x, y| {
+                let obj = imp.obj();
+                let state = obj.state();
+                if !state.pen_mode() {
+                    drag.set_state(gtk::EventSequenceState::Denied);
+                    return;
+                }
+                drag.set_state(gtk::EventSequenceState::Claimed);
+                let Point { x, y } = undo_zoom_and_crop(&obj, x, y);
+                if state.eraser() {
+                    imp.erasing.replace(Some(Vec::new()));
+                    imp.saved_ink
+                        .replace(crate::annotations::saved_ink(&obj.uri(), obj.index()));
+                    imp.erase_at(x, y);
+                } else {
+                    imp.ink_stroke.replace(Some(Stroke {
+                        color: state.pen_color(),
+                        width: state.pen_width(),
+                        points: vec![(x, y, pen_pressure(drag))],
+                    }));
+                    obj.queue_draw();
+                }
+            }
+        ));
+        drag.connect_drag_update(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |drag, dx, dy| {
+                let Some((x, y)) = drag.start_point() else {
+                    return;
+                };
+                let obj = imp.obj();
+                let Point { x, y } = undo_zoom_and_crop(&obj, x + dx, y + dy);
+                if imp.erasing.borrow().is_some() {
+                    imp.erase_at(x, y);
+                    return;
+                }
+                if let Some(stroke) = imp.ink_stroke.borrow_mut().as_mut() {
+                    // motion finer than a device pixel adds nothing but points
+                    let min_step = 1.0 / obj.zoom();
+                    let &(last_x, last_y, _) = stroke.points.last().unwrap();
+                    if (x - last_x).hypot(y - last_y) >= min_step {
+                        stroke.points.push((x, y, pen_pressure(drag)));
+                        obj.queue_draw();
+                    }
+                }
+            }
+        ));
+        drag.connect_drag_end(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |_, _, _| imp.finish_ink()
+        ));
+        self.obj().add_controller(drag);
+    }
+
+    // Rub out the strokes on this page passing under (x, y) page points: unsaved ones, and saved
+    // ink annotations not already erased.
+    fn erase_at(&self, x: f64, y: f64) {
+        let obj = self.obj();
+        // a few pixels of slack, whatever the zoom
+        let reach = 4.0 / obj.zoom();
+        let edits = obj.state().edits();
+        let edits = edits.borrow();
+        let mut erasing = self.erasing.borrow_mut();
+        let Some(erasing) = erasing.as_mut() else {
+            return;
+        };
+        let before = erasing.len();
+        let mut erased_saved = Vec::new();
+        for edit in edits.on_page(obj.index()) {
+            match edit {
+                Edit::Ink { id, stroke, .. } => {
+                    let target = Erased::Stroke(*id);
+                    if !erasing.contains(&target) && stroke.passes_near(x, y, reach) {
+                        erasing.push(target);
+                    }
+                }
+                Edit::Erase {
+                    target: Erased::Saved { xref, .. },
+                    ..
+                } => erased_saved.push(*xref),
+                _ => {}
+            }
+        }
+        for ink in self.saved_ink.borrow().iter() {
+            let target = Erased::Saved {
+                xref: ink.xref,
+                rect: ink.rect,
+            };
+            if !erased_saved.contains(&ink.xref)
+                && !erasing.contains(&target)
+                && ink.strokes.iter().any(|s| s.passes_near(x, y, reach))
+            {
+                erasing.push(target);
+            }
+        }
+        if erasing.len() != before {
+            obj.queue_draw();
+        }
+    }
+
+    // Commit the drag's stroke or erasure as one undoable step.
+    fn finish_ink(&self) {
+        let obj = self.obj();
+        if let Some(stroke) = self.ink_stroke.take() {
+            obj.state().add_ink(obj.index(), stroke);
+        }
+        if let Some(erased) = self.erasing.take() {
+            if !erased.is_empty() {
+                obj.state().erase_ink(obj.index(), &erased);
+            }
+        }
+        self.saved_ink.borrow_mut().clear();
+    }
+
+    // Write the open editor's value (if changed) and close it.
+    fn commit_field_editor(&self, value: &str) {
+        let Some((editor, field)) = self.field_editor.take() else {
+            return;
+        };
+        editor.unparent();
+        if value != field.value {
+            self.fill_field(&field, value);
+        }
+    }
+
+    // Write the open editor's value and open the next field's (previous with `forward` false),
+    // wrapping around the page.
+    fn move_to_field(&self, forward: bool) {
+        let Some((editor, field)) = self.field_editor.borrow().clone() else {
+            return;
+        };
+        let value = if let Some(entry) = editor.downcast_ref::<gtk::Entry>() {
+            entry.text().to_string()
+        } else {
+            // dropdowns and check buttons write on change
+            field.value.clone()
+        };
+        self.commit_field_editor(&value);
+
+        let obj = self.obj();
+        let next = {
+            let forms = obj.state().forms();
+            let mut forms = forms.borrow_mut();
+            let fields = forms.fields(&obj.uri(), obj.index());
+            let Some(i) = fields.iter().position(|f| f.xref == field.xref) else {
+                return;
+            };
+            let n = fields.len();
+            let next = if forward {
+                (i + 1) % n
+            } else {
+                (i + n - 1) % n
+            };
+            fields[next].clone()
+        };
+        self.open_field_editor(next);
+    }
+
+    fn close_field_editor(&self) {
+        if let Some((editor, _)) = self.field_editor.take() {
+            editor.unparent();
+        }
+    }
+
+    fn fill_field(&self, field: &Field, value: &str) {
+        let state = self.obj().state();
+        let page = field.page;
+        state.set_form_field(
+            page,
+            field.xref,
+            value,
+            clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |result| {
+                    let Err(err) = result else {
+                        return;
+                    };
+                    if let Some(window) = imp.obj().root().and_downcast::<crate::window::Window>() {
+                        window.show_error_dialog(&format!(
+                            "Error filling in the form field on page {}: {err}",
+                            page + 1
+                        ));
+                    }
+                }
+            ),
+        );
+    }
+
     fn get_bbox(&self, page: &PageInfo, crop: bool) -> Rectangle {
         if let Some(bbox) = self.lookup_bbox(page, crop) {
             return bbox;
@@ -603,7 +1339,8 @@ impl Page {
         Rectangle::new(0.0, 0.0, page.width, page.height)
     }
 
-    // Resolve the page's bounding box and hand it to `cb`. Computed inline on the main thread and
+    // Resolve the page's bounding box and hand it to `cb`. Usually the background sweep
+    // (sweep_crop_boxes) has it already; otherwise it's computed inline on the main thread and
     // cached per page: crop resolves it from a low-res render (cheaper than a full render, and it
     // sizes the widget at once). A pooled job would lag behind the renders during a fast scroll,
     // leaving the page stuck at its stale size until the box arrived.
@@ -755,8 +1492,9 @@ impl Page {
         ));
         snapshot.save();
         snapshot.translate(&graphene::Point::new(ox as f32, oy as f32));
-        snapshot.append_texture(
+        snapshot.append_scaled_texture(
             texture,
+            self.scaling_filter(),
             &graphene::Rect::new(0.0, 0.0, fw as f32, fh as f32),
         );
         snapshot.restore();
@@ -787,21 +1525,31 @@ impl Page {
             (page.width * scale) as f32,
             (page.height * scale) as f32,
         );
-        snapshot.append_texture(texture, &full);
+        snapshot.append_scaled_texture(texture, self.scaling_filter(), &full);
         snapshot.restore();
         snapshot.pop();
     }
 
+    // How a texture is resampled where it doesn't land 1:1 on device pixels.
+    fn scaling_filter(&self) -> gtk::gsk::ScalingFilter {
+        if self.obj().state().smooth_images() {
+            gtk::gsk::ScalingFilter::Linear
+        } else {
+            gtk::gsk::ScalingFilter::Nearest
+        }
+    }
+
     // Fill this page's selection rects, using the same zoom/crop transform as the page render so they
     // land on the words.
     fn snapshot_selection_overlay(&self, snapshot: &gtk::Snapshot, page: &PageInfo) {
         let obj = self.obj();
         let selection = obj.state().selection();
         let selection = selection.borrow();
-        let Some(selection) = selection
-            .as_ref()
-            .filter(|selection| selection.page == obj.index())
-        else {
+        let Some((selection, page_selection)) = selection.as_ref().and_then(|selection| {
+            selection
+                .on_page(obj.index())
+                .map(|page_selection| (selection, page_selection))
+        }) else {
             return;
         };
 
@@ -811,13 +1559,107 @@ impl Page {
         snapshot.save();
         overlay_transform(snapshot, &bbox, scale);
         let color = RGBA::new(0.5, 0.8, 0.9, 0.5);
-        for rect in &selection.rects {
+        for rect in &page_selection.rects {
             let (w, h) = rect.size();
             snapshot.append_color(
                 &color,
                 &graphene::Rect::new(rect.x1 as f32, rect.y1 as f32, w as f32, h as f32),
             );
         }
+        // the rectangle of an Alt+drag in progress
+        if let Some(region) = selection
+            .region
+            .filter(|_| selection.anchor.0 == obj.index())
+        {
+            append_outline(snapshot, &region, scale);
+        }
+        snapshot.restore();
+    }
+
+    // Paint this page's unsaved annotations roughly as MuPDF will once they are saved into the file.
+    fn snapshot_edits_overlay(&self, snapshot: &gtk::Snapshot, page: &PageInfo) {
+        let obj = self.obj();
+        let edits = obj.state().edits();
+        let edits = edits.borrow();
+        let erasing = self.erasing.borrow();
+        let erasing = erasing.as_deref().unwrap_or_default();
+        let live = self.ink_stroke.borrow();
+        let mut on_page = edits
+            .on_page(obj.index())
+            .filter(|edit| match edit {
+                Edit::Ink { id, .. } => !erasing.contains(&Erased::Stroke(*id)),
+                _ => true,
+            })
+            .peekable();
+        if on_page.peek().is_none() && live.is_none() && erasing.is_empty() {
+            return;
+        }
+
+        let bbox = self.get_bbox(page, obj.crop());
+        let scale = obj.zoom();
+
+        snapshot.save();
+        overlay_transform(snapshot, &bbox, scale);
+        for edit in on_page {
+            match edit {
+                Edit::Markup { kind, rects, .. } => {
+                    for rect in rects {
+                        let (w, h) = rect.size();
+                        // at least a device pixel thick, however far out we zoom
+                        let line = (h * 0.08).max(1.0 / scale);
+                        let (color, y, h) = match kind {
+                            MarkupKind::Highlight => (RGBA::new(1.0, 0.9, 0.0, 0.4), rect.y1, h),
+                            MarkupKind::Underline => {
+                                (RGBA::new(0.0, 0.45, 0.9, 0.9), rect.y2 - line, line)
+                            }
+                            MarkupKind::StrikeOut => (
+                                RGBA::new(0.85, 0.1, 0.1, 0.9),
+                                rect.y1 + (h - line) / 2.0,
+                                line,
+                            ),
+                        };
+                        snapshot.append_color(
+                            &color,
+                            &graphene::Rect::new(rect.x1 as f32, y as f32, w as f32, h as f32),
+                        );
+                    }
+                }
+                Edit::Note { x, y, .. } => {
+                    let size = crate::markup::NOTE_SIZE as f32;
+                    let note = graphene::Rect::new(*x as f32, *y as f32, size, size);
+                    snapshot.append_color(&RGBA::new(1.0, 0.85, 0.2, 1.0), &note);
+                    let width = (1.0 / scale) as f32;
+                    snapshot.append_border(
+                        &gtk::gsk::RoundedRect::from_rect(note, 0.0),
+                        &[width; 4],
+                        &[RGBA::new(0.45, 0.35, 0.0, 1.0); 4],
+                    );
+                }
+                Edit::Ink { stroke, .. } => append_ink(snapshot, stroke),
+                Edit::Erase { target, .. } => append_erased(snapshot, target, scale),
+            }
+        }
+        for target in erasing {
+            append_erased(snapshot, target, scale);
+        }
+        if let Some(stroke) = live.as_ref() {
+            append_ink(snapshot, stroke);
+        }
+        snapshot.restore();
+    }
+
+    // Outline the area of a snapshot drag in progress.
+    fn snapshot_region_overlay(&self, snapshot: &gtk::Snapshot, page: &PageInfo) {
+        let Some(region) = *self.snapshot_region.borrow() else {
+            return;
+        };
+        let obj = self.obj();
+        let bbox = self.get_bbox(page, obj.crop());
+        let scale = obj.zoom();
+
+        snapshot.save();
+        overlay_transform(snapshot, &bbox, scale);
+        append_outline(snapshot, &region, scale);
         snapshot.restore();
     }
 
@@ -866,11 +1708,13 @@ impl Page {
         bbox: &Rectangle,
         scale: f64,
         dsf: f64,
-    ) -> Vec<crate::mupdf_render::PixelRect> {
+    ) -> (Vec<crate::mupdf_render::PixelRect>, (f64, f64)) {
         let obj = self.obj();
         let (bw, bh) = bbox.size();
         let mut visible = Rectangle::new(0.0, 0.0, bw * scale, bh * scale);
         let mut found_viewport = false;
+        // centre of the innermost scroller, which may lie off this page
+        let mut centre = None;
         let mut ancestor = obj.parent();
         while let Some(widget) = ancestor {
             ancestor = widget.parent();
@@ -881,6 +1725,10 @@ impl Page {
                 continue;
             };
             found_viewport = true;
+            centre.get_or_insert((
+                f64::from(scroller.width()) / 2.0 - f64::from(origin.x()),
+                f64::from(scroller.height()) / 2.0 - f64::from(origin.y()),
+            ));
             visible.x1 = visible.x1.max(-f64::from(origin.x()));
             visible.y1 = visible.y1.max(-f64::from(origin.y()));
             visible.x2 = visible
@@ -898,7 +1746,11 @@ impl Page {
         }
 
         let (ox, oy) = page_offset(bbox, scale, dsf);
-        tile_regions(
+        let (cx, cy) = centre.unwrap_or((
+            (visible.x1 + visible.x2) / 2.0,
+            (visible.y1 + visible.y2) / 2.0,
+        ));
+        let regions = tile_regions(
             render_dimensions((page.width, page.height), scale, dsf),
             (
                 (visible.x1 - ox) * dsf,
@@ -906,7 +1758,8 @@ impl Page {
                 (visible.x2 - ox) * dsf,
                 (visible.y2 - oy) * dsf,
             ),
-        )
+        );
+        (regions, ((cx - ox) * dsf, (cy - oy) * dsf))
     }
 
     fn append_tile_texture(
@@ -927,8 +1780,9 @@ impl Page {
             ((region.x1 - region.x0) as f64 / dsf) as f32,
             ((region.y1 - region.y0) as f64 / dsf) as f32,
         ));
-        snapshot.append_texture(
+        snapshot.append_scaled_texture(
             texture,
+            self.scaling_filter(),
             &graphene::Rect::new(
                 (ox + pixels.x0 as f64 / dsf) as f32,
                 (oy + pixels.y0 as f64 / dsf) as f32,
@@ -951,7 +1805,7 @@ impl Page {
         let page_num = page.index;
         let pixel_scale = scale * dsf;
         let page_px = render_dimensions((page.width, page.height), scale, dsf);
-        let regions = self.visible_tile_regions(page, bbox, scale, dsf);
+        let (regions, centre) = self.visible_tile_regions(page, bbox, scale, dsf);
         let mut ready = Vec::new();
         let mut missing = Vec::new();
         let visible_ids: Vec<_> = regions
@@ -1013,7 +1867,7 @@ impl Page {
             self.prefetch_previews(page_num);
             return;
         }
-        self.schedule_tile_render(page_num, scale, dsf, page_px, missing);
+        self.schedule_tile_renders(page_num, scale, dsf, page_px, centre, missing);
         obj.state()
             .render_waiters()
             .borrow_mut()
@@ -1186,7 +2040,7 @@ impl Page {
         let uri = obj.uri();
         // Page size (points) from the main-thread doc, so the worker sizes its pixel buffer to
         // exactly what the render cache expects (see mupdf_render::render_page_pixels).
-        let page_pt = crate::mupdf_render::page_size(&uri, page_num);
+        let page_pt = crate::backend::page_size(&uri, page_num);
 
         // Capped and skipped before the marker goes in. A page marked in flight with no render to
         // release it would stay wedged.
@@ -1240,7 +2094,7 @@ impl Page {
                 client,
                 page_num,
                 priority,
-                Box::new(move || {
+                Box::new(move |cancel| {
                     request_render(
                         &uri_job,
                         scale,
@@ -1248,86 +2102,86 @@ impl Page {
                         page_num,
                         priority,
                         page_pt,
+                        None,
                         resp_sender,
+                        cancel,
                     );
                 }),
             );
         });
     }
 
-    // Queue one viewport batch. One worker records and replays the page serially, then publishes the
-    // complete batch; region identities remain independent in the cache and painter.
-    fn schedule_tile_render(
+    // Queue each missing viewport tile as its own job, so the pool's workers draw them in parallel
+    // and each lands in the cache (and on screen) as soon as it's done. Tiles nearest the viewport
+    // centre are queued last, which the LIFO visible stack runs first.
+    fn schedule_tile_renders(
         &self,
         page_num: i32,
         scale: f64,
         scale_factor: f64,
         page_px: (i32, i32),
-        regions: Vec<crate::mupdf_render::PixelRect>,
+        centre: (f64, f64),
+        mut regions: Vec<crate::mupdf_render::PixelRect>,
     ) {
-        if regions.is_empty() {
-            return;
-        }
         let obj = self.obj();
         let epoch = obj.state().render_epoch();
-        match obj.state().render_inflight().borrow_mut().entry(page_num) {
-            Entry::Occupied(_) => return,
-            Entry::Vacant(slot) => {
-                slot.insert(epoch);
-            }
-        }
-
-        let uri = obj.uri();
         let client = obj.state().render_client_id();
         let doc_epoch = obj.state().doc_epoch();
         let pixel_scale = scale * scale_factor;
-        let (resp_sender, resp_receiver) = oneshot::channel::<Vec<RenderedRegion>>();
-        let obj_clone = obj.clone();
-        glib::spawn_future_local(async move {
-            let result = resp_receiver.await;
-            let state = obj_clone.state();
-            let Some(rendered) = accept_render(&state, page_num, epoch, doc_epoch, result) else {
-                return;
+        let uri = obj.uri();
+        nearest_first(&mut regions, centre);
+
+        for region in regions.into_iter().rev() {
+            let tile = crate::render_cache::TileId {
+                page: page_num,
+                x: region.x0,
+                y: region.y0,
             };
+            match obj.state().tile_inflight().borrow_mut().entry(tile) {
+                Entry::Occupied(_) => continue,
+                Entry::Vacant(slot) => {
+                    slot.insert(epoch);
+                }
+            }
 
-            let textures = rendered
-                .into_iter()
-                .map(|region| {
-                    let id = crate::render_cache::TileId {
-                        page: page_num,
-                        x: region.x,
-                        y: region.y,
-                    };
-                    (id, region.pixels.into_texture().upcast())
-                })
-                .collect();
-            state
-                .render_cache()
-                .borrow_mut()
-                .insert_tile_batch(textures, pixel_scale);
-            finish_render(&state, page_num);
-        });
+            let (resp_sender, resp_receiver) = oneshot::channel::<RenderedPixels>();
+            let obj_clone = obj.clone();
+            glib::spawn_future_local(async move {
+                let result = resp_receiver.await;
+                let state = obj_clone.state();
+                let Some(rendered) = accept_tile(&state, tile, epoch, doc_epoch, result) else {
+                    return;
+                };
 
-        let uri_job = uri.clone();
-        RENDER_QUEUE.with(move |queue| {
-            queue.submit(
-                &uri,
-                client,
-                page_num,
-                RenderPriority::Visible,
-                Box::new(move || {
-                    request_region_render(
-                        &uri_job,
-                        scale,
-                        scale_factor,
-                        page_num,
-                        page_px,
-                        regions,
-                        resp_sender,
-                    );
-                }),
-            );
-        });
+                state.render_cache().borrow_mut().insert_tile(
+                    tile,
+                    rendered.into_texture().upcast(),
+                    pixel_scale,
+                );
+                finish_tile(&state, page_num);
+            });
+
+            let uri_job = uri.clone();
+            RENDER_QUEUE.with(move |queue| {
+                queue.submit(
+                    &uri,
+                    client,
+                    page_num,
+                    RenderPriority::Visible,
+                    Box::new(move |cancel| {
+                        request_tile_render(
+                            &uri_job,
+                            (scale, scale_factor),
+                            page_num,
+                            page_px,
+                            region,
+                            resp_sender,
+                            cancel,
+                        );
+                    }),
+                );
+            });
+        }
     }
 
     // Prefetch low-res previews over a symmetric window (they're cheap and tiny), so scrolling
@@ -1380,7 +2234,17 @@ impl Page {
         let uri = obj.uri();
         let client = obj.state().render_client_id();
         let scale = obj.state().preview_scale();
-        let page_pt = crate::mupdf_render::page_size(&uri, page_num);
+        let page_pt = crate::backend::page_size(&uri, page_num);
+        let fingerprint = obj.state().preview_fingerprint();
+        let store = fingerprint
+            .clone()
+            .map(|fingerprint| crate::preview_store::Key {
+                fingerprint,
+                page: page_num,
+                scale,
+                dark: crate::mupdf_render::dark_mode_enabled(),
+                quality: crate::mupdf_render::render_quality(),
+            });
 
         let (resp_sender, resp_receiver) = oneshot::channel::<RenderedPixels>();
         let obj_clone = obj.clone();
@@ -1400,6 +2264,15 @@ impl Page {
             let Ok(rendered) = result else {
                 return;
             };
+            if rendered.from_disk {
+                state.preview_cache().borrow_mut().insert(
+                    page_num,
+                    rendered.into_texture().upcast(),
+                    scale,
+                );
+                repaint_waiter(&state, page_num);
+                return;
+            }
 
             // decode-bound documents (e.g. scanned images) don't get cheaper as the scale shrinks:
             // once several previews in a row are slow at the floor they never will pay off - stop
@@ -1433,6 +2306,9 @@ impl Page {
                     bytes / 1024
                 );
                 state.set_preview_scale(new_scale);
+                if let Some(fingerprint) = &fingerprint {
+                    crate::preview_store::store_scale(fingerprint, new_scale);
+                }
             }
 
             let texture = rendered.into_texture();
@@ -1443,16 +2319,7 @@ impl Page {
 
             // repaint the waiting widget, but leave the waiter registered so the
             // full render still repaints it when it lands
-            if let Some(widget) = state
-                .render_waiters()
-                .borrow()
-                .get(&page_num)
-                .and_then(glib::WeakRef::upgrade)
-            {
-                if widget.index() == page_num {
-                    widget.queue_draw();
-                }
-            }
+            repaint_waiter(&state, page_num);
         });
 
         let uri_job = uri.clone();
@@ -1462,7 +2329,7 @@ impl Page {
                 client,
                 page_num,
                 priority,
-                Box::new(move || {
+                Box::new(move |cancel| {
                     request_render(
                         &uri_job,
                         scale,
@@ -1470,7 +2337,9 @@ impl Page {
                         page_num,
                         priority,
                         page_pt,
+                        store,
                         resp_sender,
+                        cancel,
                     );
                 }),
             );
@@ -1527,6 +2396,58 @@ fn overlay_transform(snapshot: &gtk::Snapshot, bbox: &Rectangle, scale: f64) {
     snapshot.scale(scale as f32, scale as f32);
 }
 
+// A pen stroke in page points (under overlay_transform). Runs of equal width go out as one path; the
+// width changes with pressure.
+fn append_ink(snapshot: &gtk::Snapshot, stroke: &Stroke) {
+    let (r, g, b) = stroke.rgb();
+    let color = RGBA::new(r, g, b, 1.0);
+    let Some(&(x, y, pressure)) = stroke.points.first() else {
+        return;
+    };
+    if stroke.points.len() == 1 {
+        let dot = gtk::gsk::PathBuilder::new();
+        dot.add_circle(
+            &graphene::Point::new(x as f32, y as f32),
+            (stroke.width_at(pressure) / 2.0) as f32,
+        );
+        snapshot.append_fill(&dot.to_path(), gtk::gsk::FillRule::Winding, &color);
+        return;
+    }
+
+    let flush = |path: &gtk::gsk::PathBuilder, width: f64| {
+        let line = gtk::gsk::Stroke::new(width as f32);
+        line.set_line_cap(gtk::gsk::LineCap::Round);
+        line.set_line_join(gtk::gsk::LineJoin::Round);
+        snapshot.append_stroke(&path.to_path(), &line, &color);
+    };
+    let mut path = gtk::gsk::PathBuilder::new();
+    path.move_to(x as f32, y as f32);
+    let mut width = None;
+    let mut last = (x, y);
+    for &(x, y, pressure) in &stroke.points[1..] {
+        let w = stroke.width_at(pressure);
+        if let Some(prev) = width.filter(|&prev| prev != w) {
+            flush(&path, prev);
+            path = gtk::gsk::PathBuilder::new();
+            path.move_to(last.0 as f32, last.1 as f32);
+        }
+        path.line_to(x as f32, y as f32);
+        width = Some(w);
+        last = (x, y);
+    }
+    if let Some(width) = width {
+        flush(&path, width);
+    }
+}
+
+// The pressure of the pen driving `drag`, in 0..=1; 1 for devices that don't report one.
+fn pen_pressure(drag: &gtk::GestureDrag) -> f64 {
+    drag.last_event(drag.current_sequence().as_ref())
+        .and_then(|event| event.axis(gtk::gdk::AxisUse::Pressure))
+        .filter(|&pressure| pressure > 0.0)
+        .unwrap_or(1.0)
+}
+
 // Synchronous main-thread render, used before the background pipeline engages.
 fn render_page_texture(
     uri: &str,
@@ -1535,17 +2456,19 @@ fn render_page_texture(
     dsf: f64,
     page_pt: Option<(f64, f64)>,
 ) -> Option<MemoryTexture> {
-    if let Some(cfg) = crate::emulate::config() {
-        let (data, width, height, stride) =
-            crate::emulate::pixels(cfg, page_num, scale, dsf, false);
-        return Some(texture_from_raw(data, width, height, stride));
-    }
-    let px = crate::mupdf_render::render_page_pixels(uri, page_num, scale, dsf, page_pt)?;
+    let dark = crate::mupdf_render::dark_mode_enabled();
+    let px =
+        crate::backend::get(uri).render_page(uri, page_num, scale, dsf, page_pt, dark, None)?;
     Some(texture_from_raw(px.data, px.width, px.height, px.stride))
 }
 
 // Pixels are cairo Rgb24 (BGRx); the x8 format ignores the padding byte.
-fn texture_from_raw(data: Vec<u8>, width: i32, height: i32, stride: i32) -> MemoryTexture {
+pub(crate) fn texture_from_raw(
+    data: Vec<u8>,
+    width: i32,
+    height: i32,
+    stride: i32,
+) -> MemoryTexture {
     let bytes = glib::Bytes::from_owned(data);
     MemoryTexture::new(
         width,
@@ -1608,12 +2531,8 @@ struct RenderedPixels {
     height: i32,
     stride: i32,
     render_ms: u128,
-}
-
-struct RenderedRegion {
-    x: i32,
-    y: i32,
-    pixels: RenderedPixels,
+    // loaded from the on-disk preview store, so render_ms says nothing about the render's cost
+    from_disk: bool,
 }
 
 impl RenderedPixels {
@@ -1622,6 +2541,7 @@ impl RenderedPixels {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn request_render(
     uri: &str,
     scale: f64,
@@ -1629,34 +2549,57 @@ fn request_render(
     page_num: i32,
     priority: RenderPriority,
     page_pt: Option<(f64, f64)>,
+    store: Option<crate::preview_store::Key>,
     resp_sender: oneshot::Sender<RenderedPixels>,
+    cancel: &Cancel,
 ) {
+    // A cancelled render sends nothing: the dropped sender reads as "reschedule on next draw", like
+    // a job dropped from the queue.
+    if cancel.is_cancelled() {
+        return;
+    }
+    // a preview kept on disk by an earlier session; a fresh one is stored once rendered
+    if let Some(px) = store.as_ref().and_then(crate::preview_store::load) {
+        log::debug!("Loaded page {page_num} [{}] from disk", priority.label());
+        let _ = resp_sender.send(RenderedPixels {
+            data: px.data.into_boxed_slice(),
+            width: px.width,
+            height: px.height,
+            stride: px.stride,
+            render_ms: 0,
+            from_disk: true,
+        });
+        return;
+    }
     let start = std::time::Instant::now();
-    if let Some(cfg) = crate::emulate::config() {
-        let (data, width, height, stride) = crate::emulate::pixels(
-            cfg,
+    let backend = crate::backend::get(uri);
+    let dark = crate::mupdf_render::dark_mode_enabled();
+    let pixels = if priority.is_preview() {
+        backend.render_preview(
+            uri,
             page_num,
             scale,
             device_scale_factor,
-            priority.is_preview(),
-        );
-        let render_ms = start.elapsed().as_millis();
-        log::debug!(
-            "Rendered page {page_num} [{}] on background thread in {render_ms}ms (scale_factor={device_scale_factor})",
-            priority.label()
-        );
-        let _ = resp_sender.send(RenderedPixels {
-            data: data.into_boxed_slice(),
-            width,
-            height,
-            stride,
-            render_ms,
-        });
+            page_pt,
+            dark,
+            Some(cancel),
+        )
+    } else {
+        backend.render_page(
+            uri,
+            page_num,
+            scale,
+            device_scale_factor,
+            page_pt,
+            dark,
+            Some(cancel),
+        )
+    };
+    let render_ms = start.elapsed().as_millis();
+    if cancel.is_cancelled() {
+        log::debug!("Render of page {page_num} aborted after {render_ms}ms");
         return;
     }
-    let pixels =
-        crate::mupdf_render::render_page_pixels(uri, page_num, scale, device_scale_factor, page_pt);
-    let render_ms = start.elapsed().as_millis();
     log::debug!(
         "Rendered page {page_num} [{}] on background thread in {render_ms}ms (scale_factor={device_scale_factor})",
         priority.label()
@@ -1664,13 +2607,19 @@ fn request_render(
 
     // Send the raw buffer; the texture is built from it on the main thread.
     let rendered = match pixels {
-        Some(px) => RenderedPixels {
-            data: px.data.into_boxed_slice(),
-            width: px.width,
-            height: px.height,
-            stride: px.stride,
-            render_ms,
-        },
+        Some(px) => {
+            if let Some(key) = &store {
+                crate::preview_store::store(key, &px);
+            }
+            RenderedPixels {
+                data: px.data.into_boxed_slice(),
+                width: px.width,
+                height: px.height,
+                stride: px.stride,
+                render_ms,
+                from_disk: false,
+            }
+        }
         None => {
             log::warn!("mupdf render failed for page {page_num}; showing blank");
             blank_rendered_page(page_pt, scale, device_scale_factor, render_ms)
@@ -1681,90 +2630,60 @@ fn request_render(
     let _ = resp_sender.send(rendered);
 }
 
-fn request_region_render(
+// Render one viewport tile, gutter included (see raster_region).
+fn request_tile_render(
     uri: &str,
-    scale: f64,
-    device_scale_factor: f64,
+    density: (f64, f64),
     page_num: i32,
     page_px: (i32, i32),
-    regions: Vec<crate::mupdf_render::PixelRect>,
-    resp_sender: oneshot::Sender<Vec<RenderedRegion>>,
+    region: crate::mupdf_render::PixelRect,
+    resp_sender: oneshot::Sender<RenderedPixels>,
+    cancel: &Cancel,
 ) {
+    // cancelled: send nothing, as in request_render
+    if cancel.is_cancelled() {
+        return;
+    }
+    let (scale, device_scale_factor) = density;
     let start = std::time::Instant::now();
-    let raster_regions: Vec<_> = regions
-        .iter()
-        .copied()
-        .map(|region| raster_region(region, page_px))
-        .collect();
-    let pixels = if let Some(cfg) = crate::emulate::config() {
-        std::thread::sleep(std::time::Duration::from_millis(cfg.full_ms));
-        let page_width = ((cfg.page_pt.0 * scale * device_scale_factor) as i32).max(1);
-        let page_height = ((cfg.page_pt.1 * scale * device_scale_factor) as i32).max(1);
-        Some(
-            raster_regions
-                .iter()
-                .map(|region| {
-                    let (data, width, height, stride) = crate::emulate::region_pixels(
-                        page_num,
-                        page_width,
-                        page_height,
-                        region.x0,
-                        region.y0,
-                        region.x1 - region.x0,
-                        region.y1 - region.y0,
-                    );
-                    crate::mupdf_render::PagePixels {
-                        data,
-                        width,
-                        height,
-                        stride,
-                    }
-                })
-                .collect(),
-        )
-    } else {
-        crate::mupdf_render::render_page_regions(
-            uri,
-            page_num,
-            scale,
-            device_scale_factor,
-            &raster_regions,
-        )
-    };
+    let pixels = raster_region(region, page_px);
+    let rendered = crate::backend::get(uri).render_tile(
+        uri,
+        page_num,
+        scale,
+        device_scale_factor,
+        pixels,
+        crate::mupdf_render::dark_mode_enabled(),
+        Some(cancel),
+    );
     let render_ms = start.elapsed().as_millis();
+    if cancel.is_cancelled() {
+        log::debug!(
+            "Tile ({}, {}) of page {page_num} aborted after {render_ms}ms",
+            region.x0,
+            region.y0
+        );
+        return;
+    }
     log::debug!(
-        "Rendered {} regions of page {page_num} [{}] serially on background thread in {render_ms}ms (scale_factor={device_scale_factor})",
-        regions.len(),
+        "Rendered tile ({}, {}) of page {page_num} [{}] on background thread in {render_ms}ms (scale_factor={device_scale_factor})",
+        region.x0,
+        region.y0,
         RenderPriority::Visible.label(),
     );
 
-    let rendered = match pixels {
-        Some(pixels) => regions
-            .into_iter()
-            .zip(pixels)
-            .map(|(region, px)| RenderedRegion {
-                x: region.x0,
-                y: region.y0,
-                pixels: RenderedPixels {
-                    data: px.data.into_boxed_slice(),
-                    width: px.width,
-                    height: px.height,
-                    stride: px.stride,
-                    render_ms,
-                },
-            })
-            .collect(),
+    let rendered = match rendered {
+        Some(px) => RenderedPixels {
+            data: px.data.into_boxed_slice(),
+            width: px.width,
+            height: px.height,
+            stride: px.stride,
+            render_ms,
+            from_disk: false,
+        },
         None => {
-            log::warn!("mupdf region render failed for page {page_num}; showing blank");
-            regions
-                .into_iter()
-                .zip(raster_regions)
-                .map(|(region, pixels)| RenderedRegion {
-                    x: region.x0,
-                    y: region.y0,
-                    pixels: blank_rendered_region(pixels, render_ms),
-                })
-                .collect()
+            log::warn!("mupdf tile render failed for page {page_num}; showing blank");
+            blank_rendered_region(pixels, render_ms)
         }
     };
     let _ = resp_sender.send(rendered);
@@ -1785,6 +2704,7 @@ fn blank_rendered_region(
         height,
         stride,
         render_ms,
+        from_disk: false,
     }
 }
 
@@ -1809,6 +2729,7 @@ fn blank_rendered_page(
         height,
         stride,
         render_ms,
+        from_disk: false,
     }
 }
 
@@ -1845,21 +2766,78 @@ fn accept_render<T, E>(
     match result {
         Ok(rendered) if state.render_epoch() == epoch => Some(rendered),
         _ => {
-            if let Some(widget) = state
-                .render_waiters()
-                .borrow()
-                .get(&page_num)
-                .and_then(glib::WeakRef::upgrade)
-            {
-                if widget.index() == page_num {
-                    widget.queue_draw();
-                }
-            }
+            repaint_waiter(state, page_num);
+            None
+        }
+    }
+}
+
+// accept_render for one viewport tile, whose slot is keyed by the tile rather than the page.
+fn accept_tile<T, E>(
+    state: &crate::state::State,
+    tile: crate::render_cache::TileId,
+    epoch: u64,
+    doc_epoch: u64,
+    result: Result<T, E>,
+) -> Option<T> {
+    if state.doc_epoch() != doc_epoch {
+        return None;
+    }
+    {
+        let inflight = state.tile_inflight();
+        let mut inflight = inflight.borrow_mut();
+        if inflight.get(&tile) == Some(&epoch) {
+            inflight.remove(&tile);
+        }
+    }
+
+    match result {
+        Ok(rendered) if state.render_epoch() == epoch => Some(rendered),
+        _ => {
+            repaint_waiter(state, tile.page);
             None
         }
     }
 }
 
+// Show a landed tile. The waiter stays registered while the page's other tiles are still drawing,
+// so each of them repaints it too; the last one finishes the page like a whole-page render.
+fn finish_tile(state: &crate::state::State, page_num: i32) {
+    let pending = state
+        .tile_inflight()
+        .borrow()
+        .keys()
+        .any(|tile| tile.page == page_num);
+    if pending {
+        repaint_waiter(state, page_num);
+    } else {
+        finish_render(state, page_num);
+    }
+}
+
+fn repaint_waiter(state: &crate::state::State, page_num: i32) {
+    if let Some(widget) = state
+        .render_waiters()
+        .borrow()
+        .get(&page_num)
+        .and_then(glib::WeakRef::upgrade)
+    {
+        if widget.index() == page_num {
+            widget.queue_draw();
+        }
+    }
+}
+
+// Order tiles by the distance of their centres from `centre` (device pixels), nearest first.
+fn nearest_first(regions: &mut [crate::mupdf_render::PixelRect], centre: (f64, f64)) {
+    let distance = |region: &crate::mupdf_render::PixelRect| {
+        let dx = f64::from(region.x0 + region.x1) / 2.0 - centre.0;
+        let dy = f64::from(region.y0 + region.y1) / 2.0 - centre.1;
+        dx * dx + dy * dy
+    };
+    regions.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+}
+
 // Log cache state and repaint whichever widget currently waits for this page, which may differ from
 // the widget that submitted the render after list-item recycling.
 fn finish_render(state: &crate::state::State, page_num: i32) {
@@ -1882,9 +2860,9 @@ fn finish_render(state: &crate::state::State, page_num: i32) {
     }
 }
 
-// Resident set size in MB, read from /proc (Linux). Used only for diagnostic logging, so a read
-// failure just reports 0.
-fn current_rss_mb() -> f64 {
+// Resident set size in MB, read from /proc (Linux). A read failure reports 0: for logging that's
+// harmless, and the optional RSS limit then never trips.
+pub(crate) fn current_rss_mb() -> f64 {
     let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
         return 0.0;
     };
@@ -1903,6 +2881,140 @@ struct Point {
     y: f64,
 }
 
+// Outline `region` (page points, under overlay_transform at `scale`), one pixel wide at any zoom.
+// Mark a saved ink annotation the eraser took: MuPDF still paints it until the save deletes it.
+fn append_erased(snapshot: &gtk::Snapshot, target: &Erased, scale: f64) {
+    let Erased::Saved { rect, .. } = target else {
+        return;
+    };
+    let (w, h) = rect.size();
+    let area = graphene::Rect::new(rect.x1 as f32, rect.y1 as f32, w as f32, h as f32);
+    snapshot.append_color(&RGBA::new(0.5, 0.5, 0.5, 0.35), &area);
+    let width = (1.0 / scale) as f32;
+    snapshot.append_border(
+        &gtk::gsk::RoundedRect::from_rect(area, 0.0),
+        &[width; 4],
+        &[RGBA::new(0.85, 0.1, 0.1, 0.9); 4],
+    );
+}
+
+fn append_outline(snapshot: &gtk::Snapshot, region: &Rectangle, scale: f64) {
+    let (w, h) = region.size();
+    let width = (1.0 / scale) as f32;
+    snapshot.append_border(
+        &gtk::gsk::RoundedRect::from_rect(
+            graphene::Rect::new(region.x1 as f32, region.y1 as f32, w as f32, h as f32),
+            0.0,
+        ),
+        &[width; 4],
+        &[RGBA::new(0.2, 0.5, 0.8, 0.9); 4],
+    );
+}
+
+// A text-selection drag in progress. The pointer is kept in list coordinates: the page the drag
+// started on scrolls away under auto-scroll, the list's viewport doesn't.
+#[derive(Default)]
+struct SelectionDrag {
+    anchor: Cell<Option<(i32, (f64, f64))>>,
+    // a snapshot drag, and where its image goes
+    snapshot: Cell<Option<crate::snapshot::Target>>,
+    // an Alt+drag rectangle on the anchor page rather than a run of text
+    region: Cell<bool>,
+    unit: Cell<Unit>,
+    pointer: Cell<Option<(f64, f64)>>,
+    scroll: RefCell<Option<glib::SourceId>>,
+}
+
+// Select from `start` to `end` (each a page and a point on it, in page points), widened to whole
+// `unit`s, as the document's selection.
+fn select_text(page: &super::Page, start: (i32, (f64, f64)), end: (i32, (f64, f64)), unit: Unit) {
+    match crate::selection::selection(&page.uri(), start, end, unit) {
+        Some(selection) => page.state().set_selection(Some(selection)),
+        None => page.state().clear_selection(),
+    }
+}
+
+// Reselect from the drag's anchor to whatever page point is now under its pointer.
+fn drag_select(page: &super::Page, list: &gtk::ListView, drag: &SelectionDrag) {
+    let (Some(anchor), Some((x, y))) = (drag.anchor.get(), drag.pointer.get()) else {
+        return;
+    };
+    if let Some(end) = page_point_in_list(list, x, y) {
+        select_text(page, anchor, end, drag.unit.get());
+    }
+}
+
+// Scroll the list while the drag's pointer sits in (or past) one of its edge bands, reselecting as
+// new pages slide under it. Stops once the pointer is back inside; the drag's end stops it too.
+fn start_drag_autoscroll(page: &super::Page, list: &gtk::ListView, drag: &Rc<SelectionDrag>) {
+    let source = glib::timeout_add_local(
+        std::time::Duration::from_millis(SELECT_SCROLL_TICK_MS),
+        clone!(
+            #[weak]
+            page,
+            #[weak]
+            list,
+            #[strong]
+            drag,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || {
+                let step = drag
+                    .pointer
+                    .get()
+                    .map_or(0.0, |(x, _)| autoscroll_step(x, f64::from(list.width())));
+                let Some(hadj) = list.hadjustment().filter(|_| step != 0.0) else {
+                    drag.scroll.replace(None);
+                    return glib::ControlFlow::Break;
+                };
+                hadj.set_value(hadj.value() + step);
+                drag_select(&page, &list, &drag);
+                glib::ControlFlow::Continue
+            }
+        ),
+    );
+    drag.scroll.replace(Some(source));
+}
+
+// Scroll per auto-scroll tick for a drag pointer at list x: none inside the edge bands, growing with
+// how deep into (or past) a band the pointer is.
+fn autoscroll_step(x: f64, width: f64) -> f64 {
+    let depth = if x < SELECT_SCROLL_EDGE {
+        x - SELECT_SCROLL_EDGE
+    } else if x > width - SELECT_SCROLL_EDGE {
+        x - (width - SELECT_SCROLL_EDGE)
+    } else {
+        return 0.0;
+    };
+    (depth / SELECT_SCROLL_EDGE * SELECT_SCROLL_STEP)
+        .clamp(-SELECT_SCROLL_MAX_STEP, SELECT_SCROLL_MAX_STEP)
+}
+
+// The page under list point (x, y), and that point on it in page points. Between pages or past the
+// ends it's the horizontally nearest page, so a drag over a gap or off-screen keeps selecting.
+fn page_point_in_list(list: &gtk::ListView, x: f64, y: f64) -> Option<(i32, (f64, f64))> {
+    let mut nearest: Option<(f64, super::Page)> = None;
+    let mut child = list.first_child();
+    while let Some(c) = child {
+        if let Some(page) = super::descendant_page(&c).filter(|page| page.is_mapped()) {
+            if let Some(bounds) = page.compute_bounds(list) {
+                let left = f64::from(bounds.x());
+                let right = left + f64::from(bounds.width());
+                let distance = (left - x).max(x - right).max(0.0);
+                if nearest.as_ref().is_none_or(|(d, _)| distance < *d) {
+                    nearest = Some((distance, page));
+                }
+            }
+        }
+        child = c.next_sibling();
+    }
+
+    let (_, page) = nearest?;
+    let local = list.compute_point(&page, &graphene::Point::new(x as f32, y as f32))?;
+    let Point { x, y } = undo_zoom_and_crop(&page, f64::from(local.x()), f64::from(local.y()));
+    Some((page.index(), (x, y)))
+}
+
 fn undo_zoom_and_crop(page: &super::Page, x: f64, y: f64) -> Point {
     let mut x = x / page.zoom();
     let mut y = y / page.zoom();
@@ -1915,6 +3027,22 @@ fn undo_zoom_and_crop(page: &super::Page, x: f64, y: f64) -> Point {
     Point { x, y }
 }
 
+// Widget-space rectangle of a page-local rect; the inverse of undo_zoom_and_crop.
+fn apply_zoom_and_crop(page: &super::Page, rect: &Rectangle) -> gtk::gdk::Rectangle {
+    let (mut x1, mut y1) = (rect.x1, rect.y1);
+    if page.crop() {
+        x1 -= page.imp().bbox.borrow().x1;
+        y1 -= page.imp().bbox.borrow().y1;
+    }
+    let zoom = page.zoom();
+    gtk::gdk::Rectangle::new(
+        (x1 * zoom) as i32,
+        (y1 * zoom) as i32,
+        ((rect.x2 - rect.x1) * zoom).ceil().max(1.0) as i32,
+        ((rect.y2 - rect.y1) * zoom).ceil().max(1.0) as i32,
+    )
+}
+
 // A page's index and size in points - the page facts the widget needs, sourced from MuPDF instead
 // of holding a live page object.
 struct PageInfo {
@@ -1923,13 +3051,24 @@ struct PageInfo {
     height: f64,
 }
 
+// The box the crop view shows of a page: its content plus margin.
+pub(crate) fn crop_box(uri: &str, page_num: i32) -> Option<Rectangle> {
+    let (width, height) = crate::backend::page_size(uri, page_num)?;
+    let page = PageInfo {
+        index: page_num,
+        width,
+        height,
+    };
+    Some(get_bbox(uri, &page, true))
+}
+
 fn get_bbox(uri: &str, page: &PageInfo, crop: bool) -> Rectangle {
     if !crop {
         return Rectangle::new(0.0, 0.0, page.width, page.height);
     }
     // MuPDF's content bbox is page-local top-left points, same convention as our Rectangle. Fall
     // back to the full page if it can't be resolved.
-    match crate::mupdf_render::content_bbox(uri, page.index) {
+    match crate::backend::content_bbox(uri, page.index) {
         Some((x1, y1, x2, y2)) => {
             apply_crop(Rectangle::new(x1, y1, x2, y2), page.width, page.height)
         }
@@ -1973,6 +3112,19 @@ mod tests {
         assert_eq!(prefetch_depth(11, 1, 100 * mb, 64 * mb), 0);
     }
 
+    #[test]
+    fn drag_autoscroll_only_in_the_edge_bands() {
+        let width = 800.0;
+        assert_eq!(autoscroll_step(400.0, width), 0.0);
+        assert_eq!(autoscroll_step(SELECT_SCROLL_EDGE, width), 0.0);
+        assert!(autoscroll_step(10.0, width) < 0.0, "left band scrolls back");
+        assert!(autoscroll_step(790.0, width) > 0.0, "right band scrolls on");
+        // deeper goes faster, up to the cap once well off-screen
+        assert!(autoscroll_step(0.0, width) < autoscroll_step(10.0, width));
+        assert_eq!(autoscroll_step(5000.0, width), SELECT_SCROLL_MAX_STEP);
+        assert_eq!(autoscroll_step(-5000.0, width), -SELECT_SCROLL_MAX_STEP);
+    }
+
     #[test]
     fn page_buffer_bytes_stays_measurable_at_extreme_sizes() {
         // A4 at 100%, device scale 2
@@ -2058,6 +3210,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tiles_nearest_the_viewport_centre_come_first() {
+        let mut regions = tile_regions((3000, 2048), (0.0, 0.0, 3000.0, 2048.0));
+        // centre inside the middle bottom tile
+        nearest_first(&mut regions, (1500.0, 1400.0));
+        let order: Vec<_> = regions.iter().map(|r| (r.x0, r.y0)).collect();
+        assert_eq!(order[0], (1024, 1024));
+        assert_eq!(order[1], (1024, 0));
+        assert_eq!(order[5], (2048, 0));
+
+        // a centre off the page (another page in view) still orders by distance
+        nearest_first(&mut regions, (-500.0, 1024.0));
+        assert_eq!((regions[0].x0, regions[1].x0), (0, 0));
+        assert_eq!(regions[5].x0, 2048);
+    }
+
     #[test]
     fn fallback_uses_the_higher_resolution_texture() {
         assert_eq!(
@@ -4,15 +4,22 @@ mod page_number_imp;
 
 pub(crate) use imp::clear_all_renders;
 pub(crate) use imp::clear_full_renders;
+pub(crate) use imp::crop_box;
+pub(crate) use imp::current_rss_mb;
 pub(crate) use imp::set_render_threads;
 pub(crate) use imp::set_wanted_pages;
+pub use imp::shutdown_renders;
+pub(crate) use imp::stored_preview_scale;
+pub(crate) use imp::sweep_crop_boxes;
+pub(crate) use imp::texture_from_raw;
 pub(crate) use imp::PREVIEW_INITIAL_SCALE;
 
 use gtk::gio::prelude::*;
 use gtk::glib;
+use gtk::prelude::WidgetExt;
 use gtk::subclass::prelude::ObjectSubclassIsExt;
 
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
 pub struct Rectangle {
     pub x1: f64,
     pub y1: f64,
@@ -40,6 +47,21 @@ impl From<(f64, f64, f64, f64)> for Rectangle {
     }
 }
 
+// Find the Page widget within a list item's widget subtree.
+pub(crate) fn descendant_page(widget: &gtk::Widget) -> Option<Page> {
+    if let Some(page) = widget.downcast_ref::<Page>() {
+        return Some(page.clone());
+    }
+    let mut child = widget.first_child();
+    while let Some(c) = child {
+        if let Some(page) = descendant_page(&c) {
+            return Some(page);
+        }
+        child = c.next_sibling();
+    }
+    None
+}
+
 glib::wrapper! {
     pub struct PageNumber(ObjectSubclass<page_number_imp::PageNumber>);
 }
@@ -0,0 +1,387 @@
+// Low-res page previews kept on disk across sessions, under $XDG_CACHE_HOME/scrolex/previews, so
+// reopening a book shows its previews at once instead of rendering each again (a preview of a
+// scanned page can take a quarter second). An entry is zlib-compressed BGRx pixels, named for the
+// document's fingerprint, the page, the render scale, the theme and the render quality. The
+// directory is trimmed to a size cap, least recently used first; a hit refreshes the entry's mtime.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use gtk::gio;
+use gtk::gio::prelude::*;
+use gtk::glib;
+
+use crate::mupdf_render::{PagePixels, RenderQuality};
+
+const MAX_BYTES: u64 = 256 * 1024 * 1024;
+// A trim brings the directory this far under the cap, so the next one is a while off.
+const TRIM_TO_BYTES: u64 = MAX_BYTES / 8 * 7;
+// Bytes written between trims. Starts due, so each run trims once on its first write.
+static WRITTEN_SINCE_TRIM: AtomicU64 = AtomicU64::new(MAX_BYTES / 8);
+
+// The bytes hashed for a fingerprint: the file's head and tail, along with its size, change time and
+// inode. An incremental save appends to the tail; an edit in the middle that keeps the size still
+// moves the change time, and a replacement file has an inode of its own, so an edited document
+// never meets its old previews.
+const FINGERPRINT_SPAN: u64 = 64 * 1024;
+
+const MAGIC: &[u8; 4] = b"SCP1";
+const HEADER_LEN: usize = MAGIC.len() + 12;
+
+// Where one preview is stored.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Key {
+    pub fingerprint: String,
+    pub page: i32,
+    pub scale: f64,
+    pub dark: bool,
+    pub quality: RenderQuality,
+}
+
+impl Key {
+    fn file_name(&self) -> String {
+        let theme = if self.dark { "dark" } else { "light" };
+        let quality = &self.quality;
+        // the exact scale, so a hit has exactly the size a fresh render would
+        format!(
+            "{}-{}-{:016x}-{theme}-{}{}-{:016x}",
+            self.fingerprint,
+            self.page,
+            self.scale.to_bits(),
+            quality.graphics_aa,
+            quality.text_aa,
+            quality.grey_gamma.to_bits()
+        )
+    }
+}
+
+// A content fingerprint for the document at `path`, or None if it can't be read.
+pub(crate) fn fingerprint(path: &Path) -> Option<String> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let metadata = file.metadata().ok()?;
+    let size = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?;
+    let mut checksum = glib::Checksum::new(glib::ChecksumType::Sha256)?;
+    checksum.update(&size.to_le_bytes());
+    checksum.update(&modified.as_nanos().to_le_bytes());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        checksum.update(&metadata.dev().to_le_bytes());
+        checksum.update(&metadata.ino().to_le_bytes());
+    }
+
+    let mut head = Vec::new();
+    (&mut file)
+        .take(FINGERPRINT_SPAN)
+        .read_to_end(&mut head)
+        .ok()?;
+    checksum.update(&head);
+    if size > FINGERPRINT_SPAN {
+        // the tail, without going back over the head
+        let start = (size - FINGERPRINT_SPAN).max(FINGERPRINT_SPAN);
+        file.seek(SeekFrom::Start(start)).ok()?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).ok()?;
+        checksum.update(&tail);
+    }
+    checksum.string()
+}
+
+pub(crate) fn load(key: &Key) -> Option<PagePixels> {
+    load_in(&cache_dir()?, key)
+}
+
+// Store a preview, then trim the directory if enough has been written since the last trim. Errors
+// are only logged: the cache is an optimisation.
+pub(crate) fn store(key: &Key, pixels: &PagePixels) {
+    let Some(dir) = cache_dir() else {
+        return;
+    };
+    match store_in(&dir, key, pixels) {
+        Ok(written) => {
+            let since = WRITTEN_SINCE_TRIM.fetch_add(written, Ordering::Relaxed) + written;
+            if since >= MAX_BYTES / 8 {
+                WRITTEN_SINCE_TRIM.store(0, Ordering::Relaxed);
+                trim_in(&dir, MAX_BYTES, TRIM_TO_BYTES);
+            }
+        }
+        Err(err) => log::debug!("could not store preview of page {}: {err}", key.page),
+    }
+}
+
+// The preview scale a document's previews were last rendered at. Previews adapt their scale per
+// document, so a reopen starts from this one instead of the initial scale, and its previews hit.
+pub(crate) fn scale(fingerprint: &str) -> Option<f64> {
+    let text = std::fs::read_to_string(cache_dir()?.join(scale_file_name(fingerprint))).ok()?;
+    text.trim().parse().ok()
+}
+
+pub(crate) fn store_scale(fingerprint: &str, scale: f64) {
+    let Some(dir) = cache_dir() else {
+        return;
+    };
+    let written = std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(dir.join(scale_file_name(fingerprint)), scale.to_string()));
+    if let Err(err) = written {
+        log::debug!("could not store the preview scale: {err}");
+    }
+}
+
+fn scale_file_name(fingerprint: &str) -> String {
+    format!("{fingerprint}-scale")
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    // tests render previews; keep them out of the reader's cache
+    if cfg!(test) {
+        return None;
+    }
+    let mut path = std::env::var("XDG_CACHE_HOME")
+        .or_else(|_| std::env::var("HOME").map(|home| format!("{home}/.cache")))
+        .map(PathBuf::from)
+        .ok()?;
+    path.push("scrolex");
+    path.push("previews");
+    Some(path)
+}
+
+fn load_in(dir: &Path, key: &Key) -> Option<PagePixels> {
+    let path = dir.join(key.file_name());
+    let bytes = std::fs::read(&path).ok()?;
+    let pixels = decode(&bytes);
+    match &pixels {
+        // a hit counts as a use for eviction
+        Some(_) => {
+            if let Ok(file) = std::fs::File::options().write(true).open(&path) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+        }
+        None => {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+    pixels
+}
+
+// Write through a temp file and rename, so a concurrent reader never sees half an entry. Returns
+// the bytes written.
+fn store_in(dir: &Path, key: &Key, pixels: &PagePixels) -> Result<u64, String> {
+    let bytes = encode(pixels)?;
+    std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    let mut tmp = tempfile::NamedTempFile::new_in(dir).map_err(|err| err.to_string())?;
+    std::io::Write::write_all(&mut tmp, &bytes).map_err(|err| err.to_string())?;
+    tmp.persist(dir.join(key.file_name()))
+        .map_err(|err| err.to_string())?;
+    Ok(bytes.len() as u64)
+}
+
+// Once the directory holds more than `max` bytes, delete the least recently used entries until it
+// holds `target`.
+fn trim_in(dir: &Path, max: u64, target: u64) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            meta.is_file()
+                .then(|| (meta.modified().ok()?, meta.len(), entry.path()))
+        })
+        .collect();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    if total <= max {
+        return;
+    }
+    files.sort_by_key(|(modified, ..)| *modified);
+    for (_, len, path) in files {
+        if total <= target {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+}
+
+fn encode(pixels: &PagePixels) -> Result<Vec<u8>, String> {
+    let mem = gio::MemoryOutputStream::new_resizable();
+    let compressor = gio::ZlibCompressor::new(gio::ZlibCompressorFormat::Zlib, 1);
+    let stream = gio::ConverterOutputStream::new(&mem, &compressor);
+    stream
+        .write_all(&pixels.data, gio::Cancellable::NONE)
+        .and_then(|_| stream.close(gio::Cancellable::NONE))
+        .map_err(|err| err.to_string())?;
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + mem.data_size());
+    bytes.extend_from_slice(MAGIC);
+    for value in [pixels.width, pixels.height, pixels.stride] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.extend_from_slice(&mem.steal_as_bytes());
+    Ok(bytes)
+}
+
+// None for a truncated, foreign or corrupt entry.
+fn decode(bytes: &[u8]) -> Option<PagePixels> {
+    let (header, compressed) = bytes.split_at_checked(HEADER_LEN)?;
+    let (magic, dims) = header.split_at(MAGIC.len());
+    if magic != MAGIC {
+        return None;
+    }
+    let dim = |i: usize| i32::from_le_bytes(dims[i * 4..i * 4 + 4].try_into().unwrap());
+    let (width, height, stride) = (dim(0), dim(1), dim(2));
+    if width <= 0 || height <= 0 || i64::from(stride) < i64::from(width) * 4 {
+        return None;
+    }
+    let len = usize::try_from(stride).ok()? * usize::try_from(height).ok()?;
+
+    let input = gio::MemoryInputStream::from_bytes(&glib::Bytes::from(compressed));
+    let decompressor = gio::ZlibDecompressor::new(gio::ZlibCompressorFormat::Zlib);
+    let mut data = Vec::with_capacity(len);
+    gio::ConverterInputStream::new(&input, &decompressor)
+        .into_read()
+        // one byte more than expected tells an overlong entry from an exact one
+        .take(len as u64 + 1)
+        .read_to_end(&mut data)
+        .ok()?;
+    (data.len() == len).then_some(PagePixels {
+        data,
+        width,
+        height,
+        stride,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(page: i32) -> Key {
+        Key {
+            fingerprint: "abc".to_string(),
+            page,
+            scale: 0.25,
+            dark: false,
+            quality: RenderQuality::default(),
+        }
+    }
+
+    fn pixels(seed: u8) -> PagePixels {
+        PagePixels {
+            data: (0..8 * 3).map(|i| seed.wrapping_add(i as u8)).collect(),
+            width: 2,
+            height: 3,
+            stride: 8,
+        }
+    }
+
+    #[test]
+    fn stored_previews_load_back_under_their_key_only() {
+        let dir = tempfile::tempdir().unwrap();
+        store_in(dir.path(), &key(3), &pixels(7)).unwrap();
+
+        let loaded = load_in(dir.path(), &key(3)).unwrap();
+        assert_eq!((loaded.width, loaded.height, loaded.stride), (2, 3, 8));
+        assert_eq!(loaded.data, pixels(7).data);
+
+        assert!(load_in(dir.path(), &key(4)).is_none());
+        assert!(load_in(
+            dir.path(),
+            &Key {
+                dark: true,
+                ..key(3)
+            }
+        )
+        .is_none());
+        assert!(load_in(
+            dir.path(),
+            &Key {
+                scale: 0.2,
+                ..key(3)
+            }
+        )
+        .is_none());
+        assert!(load_in(
+            dir.path(),
+            &Key {
+                quality: RenderQuality {
+                    grey_gamma: 1.6,
+                    ..RenderQuality::default()
+                },
+                ..key(3)
+            }
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn a_corrupt_entry_misses_and_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        store_in(dir.path(), &key(0), &pixels(1)).unwrap();
+        let path = dir.path().join(key(0).file_name());
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+
+        assert!(load_in(dir.path(), &key(0)).is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn trimming_drops_the_least_recently_used_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sizes = Vec::new();
+        for page in 0..4 {
+            sizes.push(store_in(dir.path(), &key(page), &pixels(page as u8)).unwrap());
+            let age = SystemTime::now() - std::time::Duration::from_secs(100 - page as u64);
+            let path = dir.path().join(key(page).file_name());
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(age)
+                .unwrap();
+        }
+        // page 0 is the oldest write but was just used
+        assert!(load_in(dir.path(), &key(0)).is_some());
+
+        let total: u64 = sizes.iter().sum();
+        trim_in(dir.path(), total - 1, sizes[0] + sizes[3]);
+
+        let kept: Vec<_> = (0..4)
+            .filter(|&page| dir.path().join(key(page).file_name()).exists())
+            .collect();
+        assert_eq!(kept, vec![0, 3]);
+    }
+
+    #[test]
+    fn fingerprints_follow_the_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doc.pdf");
+        std::fs::write(&path, vec![1u8; 200 * 1024]).unwrap();
+        let first = fingerprint(&path).unwrap();
+        assert_eq!(fingerprint(&path).unwrap(), first);
+
+        // an appended update changes the tail
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.extend_from_slice(b"%%EOF");
+        std::fs::write(&path, &bytes).unwrap();
+        let appended = fingerprint(&path).unwrap();
+        assert_ne!(appended, first);
+
+        // a same-size edit between the head and the tail moves the change time
+        bytes[100 * 1024] = 2;
+        std::fs::write(&path, &bytes).unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(5);
+        file.set_modified(later).unwrap();
+        assert_ne!(fingerprint(&path).unwrap(), appended);
+    }
+}
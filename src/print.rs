@@ -0,0 +1,328 @@
+// Printing through GtkPrintOperation. Each page is rendered afresh by MuPDF at the printer's
+// resolution - never recoloured for dark mode - and painted into the print context, fitted to the
+// paper or at actual size, optionally cropped to its content like the crop view. The dialog's
+// "Selection" page range prints the pages that carry highlights, saved or not.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use gtk::cairo;
+use gtk::glib::clone;
+use gtk::prelude::*;
+
+use crate::markup::Edit;
+use crate::page::Rectangle;
+
+// The export backends (PDF/PS file) report 72 dpi, which prints blurry, and a 1200 dpi printer
+// would want a half-gigabyte buffer per page.
+const MIN_PRINT_DPI: f64 = 150.0;
+const MAX_PRINT_DPI: f64 = 600.0;
+
+// Choices on the dialog's layout tab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    // scale each page to the paper; otherwise print at actual size
+    pub fit: bool,
+    // print the crop view's box (content plus margin) instead of the whole page
+    pub crop: bool,
+}
+
+// A print operation for the document at `uri`: `current` (0-based) is the dialog's "current page",
+// `layout` the layout tab's initial choices and `pending` the unsaved edits, whose highlights count
+// for "Selection".
+pub fn operation(
+    uri: &str,
+    title: &str,
+    current: i32,
+    layout: Layout,
+    pending: Vec<Edit>,
+) -> gtk::PrintOperation {
+    let n_pages = crate::backend::page_count(uri).unwrap_or(0);
+
+    let op = gtk::PrintOperation::new();
+    op.set_job_name(title);
+    op.set_n_pages(n_pages);
+    op.set_current_page(current);
+    op.set_unit(gtk::Unit::Points);
+    op.set_embed_page_setup(true);
+    op.set_support_selection(true);
+    // whether any page is highlighted takes reading them all, so it's only found out if chosen
+    op.set_has_selection(true);
+    op.set_custom_tab_label(Some("Layout"));
+
+    let layout = Rc::new(Cell::new(layout));
+    // document pages in print order; "Selection" narrows them to the highlighted ones
+    let pages = Rc::new(RefCell::new(Vec::new()));
+
+    op.connect_create_custom_widget(clone!(
+        #[strong]
+        layout,
+        move |_| Some(layout_tab(&layout).upcast())
+    ));
+
+    let uri_begin = uri.to_string();
+    op.connect_begin_print(clone!(
+        #[strong]
+        pages,
+        move |op, _| {
+            let selection = op
+                .print_settings()
+                .is_some_and(|settings| settings.print_pages() == gtk::PrintPages::Selection);
+            let chosen: Vec<i32> = if selection {
+                highlighted_pages(&uri_begin, &pending)
+            } else {
+                (0..n_pages).collect()
+            };
+            if chosen.is_empty() {
+                gtk::AlertDialog::builder()
+                    .message("No page has highlights to print")
+                    .build()
+                    .show(gtk::Window::NONE);
+                op.cancel();
+                return;
+            }
+            op.set_n_pages(chosen.len() as i32);
+            pages.replace(chosen);
+        }
+    ));
+
+    // landscape pages go on landscape paper
+    let uri_setup = uri.to_string();
+    op.connect_request_page_setup(clone!(
+        #[strong]
+        pages,
+        move |_, _, n, setup| {
+            let Some(&page_num) = pages.borrow().get(n as usize) else {
+                return;
+            };
+            if let Some((w, h)) = crate::backend::page_size(&uri_setup, page_num) {
+                setup.set_orientation(if w > h {
+                    gtk::PageOrientation::Landscape
+                } else {
+                    gtk::PageOrientation::Portrait
+                });
+            }
+        }
+    ));
+
+    let uri = uri.to_string();
+    op.connect_draw_page(move |_, ctx, n| {
+        let Some(&page_num) = pages.borrow().get(n as usize) else {
+            return;
+        };
+        if let Err(err) = draw_page(&uri, page_num, ctx, layout.get()) {
+            log::warn!("Failed to print page {}: {err}", page_num + 1);
+        }
+    });
+    op
+}
+
+fn layout_tab(layout: &Rc<Cell<Layout>>) -> gtk::Box {
+    let tab = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(6)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+    let current = layout.get();
+    let fit = gtk::CheckButton::builder()
+        .label("Fit to paper")
+        .active(current.fit)
+        .build();
+    let actual = gtk::CheckButton::builder()
+        .label("Actual size")
+        .group(&fit)
+        .active(!current.fit)
+        .build();
+    let crop = gtk::CheckButton::builder()
+        .label("Crop margins")
+        .tooltip_text("Print each page's content with a small margin, as the crop view shows it")
+        .active(current.crop)
+        .build();
+    let hint = gtk::Label::builder()
+        .label("Page range \u{201c}Selection\u{201d} prints the pages with highlights.")
+        .halign(gtk::Align::Start)
+        .wrap(true)
+        .css_classes(["dim-label"])
+        .build();
+
+    fit.connect_toggled(clone!(
+        #[strong]
+        layout,
+        move |fit| layout.set(Layout {
+            fit: fit.is_active(),
+            ..layout.get()
+        })
+    ));
+    crop.connect_toggled(clone!(
+        #[strong]
+        layout,
+        move |crop| layout.set(Layout {
+            crop: crop.is_active(),
+            ..layout.get()
+        })
+    ));
+
+    tab.append(&fit);
+    tab.append(&actual);
+    tab.append(&crop);
+    tab.append(&hint);
+    tab
+}
+
+fn draw_page(
+    uri: &str,
+    page_num: i32,
+    ctx: &gtk::PrintContext,
+    layout: Layout,
+) -> Result<(), String> {
+    let (w, h) = crate::backend::page_size(uri, page_num).ok_or("unreadable page")?;
+    let area = if layout.crop {
+        crate::page::crop_box(uri, page_num).unwrap_or(Rectangle::new(0.0, 0.0, w, h))
+    } else {
+        Rectangle::new(0.0, 0.0, w, h)
+    };
+    let size = (area.x2 - area.x1, area.y2 - area.y1);
+    let (scale, dx, dy) = placement(size, (ctx.width(), ctx.height()), layout.fit);
+
+    // rendered at the resolution the area lands on the paper at, so one pixel is one printer dot
+    let dpi = ctx.dpi_x().clamp(MIN_PRINT_DPI, MAX_PRINT_DPI);
+    let px = crate::backend::render_snapshot(
+        uri,
+        page_num,
+        (area.x1, area.y1, area.x2, area.y2),
+        dpi * scale,
+        false,
+    )
+    .ok_or("render failed")?;
+    let surface = cairo::ImageSurface::create_for_data(
+        px.data,
+        cairo::Format::Rgb24,
+        px.width,
+        px.height,
+        px.stride,
+    )
+    .map_err(|err| err.to_string())?;
+
+    let cr = ctx.cairo_context();
+    cr.save().map_err(|err| err.to_string())?;
+    cr.translate(dx, dy);
+    cr.scale(72.0 / dpi, 72.0 / dpi);
+    cr.set_source_surface(&surface, 0.0, 0.0)
+        .map_err(|err| err.to_string())?;
+    cr.paint().map_err(|err| err.to_string())?;
+    cr.restore().map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+// Scale and offset (paper points) that put an area of `size` points on `paper`: scaled to fit, up or
+// down, or at actual size; centred either way.
+fn placement(size: (f64, f64), paper: (f64, f64), fit: bool) -> (f64, f64, f64) {
+    let scale = if fit {
+        (paper.0 / size.0).min(paper.1 / size.1)
+    } else {
+        1.0
+    };
+    (
+        scale,
+        (paper.0 - size.0 * scale) / 2.0,
+        (paper.1 - size.1 * scale) / 2.0,
+    )
+}
+
+// Pages with text markup (highlights, underlines, ...), saved or `pending`, ascending.
+fn highlighted_pages(uri: &str, pending: &[Edit]) -> Vec<i32> {
+    let annotations = crate::annotations::with_pending(crate::annotations::all(uri), pending);
+    let mut pages: Vec<i32> = annotations
+        .iter()
+        .filter(|annotation| !annotation.quads.is_empty())
+        .map(|annotation| annotation.page)
+        .collect();
+    pages.dedup();
+    pages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fitting_scales_to_the_tighter_side_and_centres() {
+        // a letter page on A4: width-bound, centred vertically
+        let (scale, dx, dy) = placement((612.0, 792.0), (595.0, 842.0), true);
+        assert!((scale - 595.0 / 612.0).abs() < 1e-9);
+        assert!(dx.abs() < 1e-9);
+        assert!((dy - (842.0 - 792.0 * scale) / 2.0).abs() < 1e-9);
+
+        // a small crop box is scaled up
+        let (scale, ..) = placement((300.0, 400.0), (595.0, 842.0), true);
+        assert!((scale - 595.0 / 300.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn actual_size_keeps_the_scale_and_centres() {
+        assert_eq!(
+            placement((300.0, 400.0), (600.0, 800.0), false),
+            (1.0, 150.0, 200.0)
+        );
+    }
+
+    #[gtk::test]
+    fn prints_every_page_to_a_pdf_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("two.pdf");
+        std::fs::write(&src, TWO_PAGE_PDF).unwrap();
+        let uri = gtk::gio::File::for_path(&src).uri();
+        let out = dir.path().join("printed.pdf");
+
+        let op = operation(
+            &uri,
+            "two",
+            0,
+            Layout {
+                fit: true,
+                crop: false,
+            },
+            Vec::new(),
+        );
+        op.set_export_filename(&out);
+        let result = op.run(gtk::PrintOperationAction::Export, gtk::Window::NONE);
+        assert_eq!(result.unwrap(), gtk::PrintOperationResult::Apply);
+
+        let printed = mupdf::Document::open(out.to_str().unwrap()).unwrap();
+        assert_eq!(printed.page_count().unwrap(), 2);
+    }
+
+    #[gtk::test]
+    fn unsaved_highlights_select_their_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("two.pdf");
+        std::fs::write(&src, TWO_PAGE_PDF).unwrap();
+        let uri = gtk::gio::File::for_path(&src).uri();
+        assert!(highlighted_pages(&uri, &[]).is_empty());
+
+        let pending = [
+            Edit::Note {
+                page: 0,
+                x: 10.0,
+                y: 10.0,
+                text: "Not a highlight".into(),
+            },
+            Edit::Markup {
+                page: 1,
+                kind: crate::markup::MarkupKind::Highlight,
+                rects: vec![Rectangle::new(10.0, 10.0, 90.0, 20.0)],
+            },
+        ];
+        assert_eq!(highlighted_pages(&uri, &pending), vec![1]);
+    }
+
+    const TWO_PAGE_PDF: &[u8] = b"%PDF-1.4\n\
+1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+2 0 obj\n<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 /MediaBox [0 0 200 200] >>\nendobj\n\
+3 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+4 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 300 200] >>\nendobj\n\
+trailer\n<< /Root 1 0 R >>\n%%EOF";
+}
@@ -0,0 +1,474 @@
+// Document properties: the metadata MuPDF reports for the open document, read through this
+// thread's Document, plus its page sizes and fonts, which take a pass over every page and so are
+// scanned on a background thread. Documents MuPDF doesn't read (DjVu, folders and archives of
+// images) get just their page count and sizes, from their backend. Shown in a dialog whose values
+// are selectable, so they can be copied.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use futures::channel::oneshot;
+use gtk::glib;
+use gtk::prelude::*;
+use mupdf::pdf::{PdfObject, PdfPage};
+use mupdf::MetadataName;
+
+// Nested form XObjects followed when looking for fonts; real documents rarely go past two.
+const MAX_RESOURCE_DEPTH: usize = 8;
+
+// Paper sizes (points, portrait) recognised in the page size list, within a point.
+const PAPER_SIZES: &[(&str, f64, f64)] = &[
+    ("A3", 842.0, 1191.0),
+    ("A4", 595.0, 842.0),
+    ("A5", 420.0, 595.0),
+    ("Letter", 612.0, 792.0),
+    ("Legal", 612.0, 1008.0),
+    ("Tabloid", 792.0, 1224.0),
+];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub title: String,
+    pub author: String,
+    pub subject: String,
+    pub keywords: String,
+    pub creator: String,
+    pub producer: String,
+    pub created: String,
+    pub modified: String,
+    // e.g. "PDF 1.7"
+    pub format: String,
+    pub encryption: String,
+    pub n_pages: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Font {
+    // without a subset's "ABCDEF+" tag
+    pub name: String,
+    // the font dictionary's /Subtype: Type1, TrueType, Type0, ...
+    pub kind: String,
+    pub embedded: bool,
+    pub subset: bool,
+}
+
+// What the background scan finds: page sizes grouped as the dialog lists them, and the distinct fonts
+// in name order.
+#[derive(Debug, Default)]
+pub struct Scan {
+    pub page_sizes: Vec<String>,
+    pub fonts: Vec<Font>,
+}
+
+pub fn metadata(uri: &str) -> Option<Metadata> {
+    mupdf_metadata(uri).or_else(|| {
+        Some(Metadata {
+            n_pages: crate::backend::page_count(uri)?,
+            ..Metadata::default()
+        })
+    })
+}
+
+fn mupdf_metadata(uri: &str) -> Option<Metadata> {
+    crate::mupdf_render::with_doc(uri, |doc| {
+        let get = |name| doc.metadata(name).unwrap_or_default().trim().to_string();
+        Some(Metadata {
+            title: get(MetadataName::Title),
+            author: get(MetadataName::Author),
+            subject: get(MetadataName::Subject),
+            keywords: get(MetadataName::Keywords),
+            creator: get(MetadataName::Creator),
+            producer: get(MetadataName::Producer),
+            created: crate::annotations::format_pdf_date(&get(MetadataName::CreationDate)),
+            modified: crate::annotations::format_pdf_date(&get(MetadataName::ModDate)),
+            format: get(MetadataName::Format),
+            encryption: get(MetadataName::Encryption),
+            n_pages: doc.page_count().ok()?,
+        })
+    })
+}
+
+// The title the document gives itself, if any.
+pub fn title(uri: &str) -> Option<String> {
+    mupdf_metadata(uri)
+        .map(|metadata| metadata.title)
+        .filter(|title| !title.is_empty())
+}
+
+pub fn scan(uri: &str) -> Scan {
+    crate::mupdf_render::with_doc(uri, |doc| {
+        let n_pages = doc.page_count().ok()?;
+        let mut sizes = Vec::new();
+        let mut fonts = BTreeSet::new();
+        let mut seen = HashSet::new();
+        for page_num in 0..n_pages {
+            if crate::bg_job::stopping() {
+                break;
+            }
+            let Ok(page) = doc.load_page(page_num) else {
+                continue;
+            };
+            if let Ok(b) = page.bounds() {
+                sizes.push((f64::from(b.x1 - b.x0), f64::from(b.y1 - b.y0)));
+            }
+            if !doc.is_pdf() {
+                continue;
+            }
+            let Ok(page) = PdfPage::try_from(page) else {
+                continue;
+            };
+            if let Ok(Some(resources)) = page.object().get_dict_inheritable("Resources") {
+                collect_fonts(&resources, 0, &mut seen, &mut fonts);
+            }
+        }
+        Some(Scan {
+            page_sizes: size_summary(&sizes),
+            fonts: fonts.into_iter().collect(),
+        })
+    })
+    .unwrap_or_else(|| {
+        let backend = crate::backend::get(uri);
+        let n_pages = backend.page_count(uri).unwrap_or(0);
+        let sizes: Vec<_> = (0..n_pages)
+            .take_while(|_| !crate::bg_job::stopping())
+            .filter_map(|page_num| backend.page_size(uri, page_num))
+            .collect();
+        Scan {
+            page_sizes: size_summary(&sizes),
+            fonts: Vec::new(),
+        }
+    })
+}
+
+// The fonts of a resource dictionary and of the form XObjects it uses. `seen` holds the object
+// numbers already visited, since pages mostly share their fonts and forms.
+fn collect_fonts(
+    resources: &PdfObject,
+    depth: usize,
+    seen: &mut HashSet<i32>,
+    fonts: &mut BTreeSet<Font>,
+) {
+    let first_visit = |obj: &PdfObject, seen: &mut HashSet<i32>| {
+        !obj.is_indirect().unwrap_or(false) || obj.as_indirect().is_ok_and(|num| seen.insert(num))
+    };
+    if let Ok(Some(dict)) = resources.get_dict("Font") {
+        for (_, font) in dict.dict_iter().into_iter().flatten().flatten() {
+            if first_visit(&font, seen) {
+                fonts.extend(font_info(&font));
+            }
+        }
+    }
+    if depth >= MAX_RESOURCE_DEPTH {
+        return;
+    }
+    if let Ok(Some(dict)) = resources.get_dict("XObject") {
+        for (_, xobject) in dict.dict_iter().into_iter().flatten().flatten() {
+            let is_form = name(&xobject, "Subtype").is_some_and(|subtype| subtype == "Form");
+            if !is_form || !first_visit(&xobject, seen) {
+                continue;
+            }
+            if let Ok(Some(inner)) = xobject.get_dict("Resources") {
+                collect_fonts(&inner, depth + 1, seen, fonts);
+            }
+        }
+    }
+}
+
+fn font_info(font: &PdfObject) -> Option<Font> {
+    let kind = name(font, "Subtype")?;
+    let base = name(font, "BaseFont").unwrap_or_else(|| "(unnamed)".to_string());
+    // a composite font's glyphs, and so its font file, live in its descendant
+    let descriptor = if kind == "Type0" {
+        font.get_dict("DescendantFonts")
+            .ok()??
+            .get_array(0)
+            .ok()??
+            .get_dict("FontDescriptor")
+    } else {
+        font.get_dict("FontDescriptor")
+    };
+    let embedded = kind == "Type3"
+        || descriptor.ok().flatten().is_some_and(|descriptor| {
+            ["FontFile", "FontFile2", "FontFile3"]
+                .iter()
+                .any(|key| descriptor.get_dict(*key).ok().flatten().is_some())
+        });
+    let (name, subset) = strip_subset_tag(&base);
+    Some(Font {
+        name: name.to_string(),
+        kind,
+        embedded,
+        subset,
+    })
+}
+
+fn name(obj: &PdfObject, key: &str) -> Option<String> {
+    let value = obj.get_dict(key).ok()??.as_name().ok()?;
+    Some(String::from_utf8_lossy(&value).into_owned())
+}
+
+// A subset font's name starts with six capitals and a plus: "ABCDEF+Helvetica".
+fn strip_subset_tag(name: &str) -> (&str, bool) {
+    match name.split_once('+') {
+        Some((tag, rest)) if tag.len() == 6 && tag.bytes().all(|b| b.is_ascii_uppercase()) => {
+            (rest, true)
+        }
+        _ => (name, false),
+    }
+}
+
+// Page sizes as listed in the dialog: one line per distinct size (rounded to the point), most common
+// first, naming the paper size it matches.
+fn size_summary(sizes: &[(f64, f64)]) -> Vec<String> {
+    let mut counts: BTreeMap<(i64, i64), usize> = BTreeMap::new();
+    for &(w, h) in sizes {
+        *counts
+            .entry((w.round() as i64, h.round() as i64))
+            .or_default() += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1));
+    counts
+        .into_iter()
+        .map(|((w, h), count)| {
+            let mut line = format!("{w} × {h} pt");
+            if let Some(paper) = paper_name(w as f64, h as f64) {
+                line.push_str(&format!(" ({paper})"));
+            }
+            let pages = if count == 1 { "page" } else { "pages" };
+            line.push_str(&format!(", {count} {pages}"));
+            line
+        })
+        .collect()
+}
+
+fn paper_name(w: f64, h: f64) -> Option<String> {
+    PAPER_SIZES.iter().find_map(|&(name, pw, ph)| {
+        let near = |a: f64, b: f64| (a - b).abs() <= 1.0;
+        if near(w, pw) && near(h, ph) {
+            Some(name.to_string())
+        } else if near(w, ph) && near(h, pw) {
+            Some(format!("{name} landscape"))
+        } else {
+            None
+        }
+    })
+}
+
+fn font_line(font: &Font) -> String {
+    let mut notes = vec![font.kind.as_str()];
+    notes.push(if font.embedded {
+        "embedded"
+    } else {
+        "not embedded"
+    });
+    if font.subset {
+        notes.push("subset");
+    }
+    format!("{} ({})", font.name, notes.join(", "))
+}
+
+pub fn present(parent: &gtk::Window, uri: &str) {
+    let Some(metadata) = metadata(uri) else {
+        return;
+    };
+    let grid = gtk::Grid::builder()
+        .row_spacing(6)
+        .column_spacing(12)
+        .margin_top(18)
+        .margin_bottom(18)
+        .margin_start(18)
+        .margin_end(18)
+        .build();
+    let file = gtk::gio::File::for_uri(uri);
+    let location = file
+        .path()
+        .map_or_else(|| uri.to_string(), |path| path.display().to_string());
+    let rows = [
+        ("Title", metadata.title),
+        ("Author", metadata.author),
+        ("Subject", metadata.subject),
+        ("Keywords", metadata.keywords),
+        ("Creator", metadata.creator),
+        ("Producer", metadata.producer),
+        ("Created", metadata.created),
+        ("Modified", metadata.modified),
+        ("Format", metadata.format),
+        ("Encryption", metadata.encryption),
+        ("Pages", metadata.n_pages.to_string()),
+        ("Location", location),
+    ];
+    let mut row = 0;
+    for (key, value) in rows {
+        if !value.is_empty() {
+            add_row(&grid, row, key, &value);
+            row += 1;
+        }
+    }
+    let sizes = add_row(&grid, row, "Page sizes", "Scanning…");
+    let fonts = add_row(&grid, row + 1, "Fonts", "Scanning…");
+
+    let scrolled = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(600)
+        .child(&grid)
+        .build();
+    let dialog = gtk::Window::builder()
+        .title("Document Properties")
+        .modal(true)
+        .default_width(520)
+        .transient_for(parent)
+        .child(&scrolled)
+        .build();
+    let close = gtk::ShortcutController::new();
+    close.set_scope(gtk::ShortcutScope::Managed);
+    close.add_shortcut(gtk::Shortcut::new(
+        gtk::ShortcutTrigger::parse_string("Escape"),
+        Some(gtk::NamedAction::new("window.close")),
+    ));
+    dialog.add_controller(close);
+    dialog.present();
+
+    let (tx, rx) = oneshot::channel();
+    let uri = uri.to_string();
+    crate::bg_job::spawn_document_thread(move || {
+        let _ = tx.send(scan(&uri));
+    });
+    glib::spawn_future_local(async move {
+        let Ok(scan) = rx.await else {
+            return;
+        };
+        sizes.set_label(&scan.page_sizes.join("\n"));
+        if scan.fonts.is_empty() {
+            fonts.set_label("None");
+        } else {
+            let lines: Vec<String> = scan.fonts.iter().map(font_line).collect();
+            fonts.set_label(&lines.join("\n"));
+        }
+    });
+}
+
+// A key and a selectable value on `row` of the grid; the value label, to fill in later.
+fn add_row(grid: &gtk::Grid, row: i32, key: &str, value: &str) -> gtk::Label {
+    let key = gtk::Label::builder()
+        .label(key)
+        .xalign(1.0)
+        .yalign(0.0)
+        .css_classes(["dim-label"])
+        .build();
+    let value = gtk::Label::builder()
+        .label(value)
+        .xalign(0.0)
+        .yalign(0.0)
+        .hexpand(true)
+        .wrap(true)
+        .wrap_mode(gtk::pango::WrapMode::WordChar)
+        .selectable(true)
+        .build();
+    grid.attach(&key, 0, row, 1, 1);
+    grid.attach(&value, 1, row, 1, 1);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One page using Helvetica directly and a subset TrueType font, embedded, through a form
+    // XObject; the Info dictionary gives a title, author and creation date.
+    const PROPERTIES_PDF: &[u8] = b"%PDF-1.6\n\
+1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 595 842] >>\nendobj\n\
+3 0 obj\n<< /Type /Page /Parent 2 0 R /Resources << /Font << /F1 4 0 R >> /XObject << /X1 5 0 R >> >> >>\nendobj\n\
+4 0 obj\n<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>\nendobj\n\
+5 0 obj\n<< /Type /XObject /Subtype /Form /BBox [0 0 10 10] /Resources << /Font << /F2 6 0 R >> >> /Length 0 >>\nstream\n\nendstream\nendobj\n\
+6 0 obj\n<< /Type /Font /Subtype /TrueType /BaseFont /ABCDEF+Custom /FontDescriptor 7 0 R >>\nendobj\n\
+7 0 obj\n<< /Type /FontDescriptor /FontName /ABCDEF+Custom /FontFile2 8 0 R >>\nendobj\n\
+8 0 obj\n<< /Length 0 >>\nstream\n\nendstream\nendobj\n\
+9 0 obj\n<< /Title (Quarterly Report) /Author (Ada) /CreationDate (D:20230415093000+02'00') >>\nendobj\n\
+trailer\n<< /Root 1 0 R /Info 9 0 R >>\n%%EOF";
+
+    #[test]
+    fn page_sizes_are_grouped_and_named() {
+        let sizes = [
+            (595.3, 841.9),
+            (612.0, 792.0),
+            (595.0, 842.0),
+            (842.0, 595.0),
+            (500.0, 500.0),
+            (595.0, 842.0),
+        ];
+        assert_eq!(
+            size_summary(&sizes),
+            [
+                "595 × 842 pt (A4), 3 pages",
+                "500 × 500 pt, 1 page",
+                "612 × 792 pt (Letter), 1 page",
+                "842 × 595 pt (A4 landscape), 1 page",
+            ]
+        );
+    }
+
+    #[test]
+    fn subset_tags_are_stripped() {
+        assert_eq!(strip_subset_tag("ABCDEF+Times"), ("Times", true));
+        assert_eq!(strip_subset_tag("Times+Bold"), ("Times+Bold", false));
+        assert_eq!(strip_subset_tag("Helvetica"), ("Helvetica", false));
+    }
+
+    #[gtk::test]
+    fn reads_metadata_and_scans_fonts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("properties.pdf");
+        std::fs::write(&path, PROPERTIES_PDF).unwrap();
+        let uri = format!("file://{}", path.display());
+
+        let metadata = metadata(&uri).unwrap();
+        assert_eq!(metadata.title, "Quarterly Report");
+        assert_eq!(metadata.author, "Ada");
+        assert_eq!(metadata.created, "2023-04-15 09:30:00 +02:00");
+        assert_eq!(metadata.format, "PDF 1.6");
+        assert_eq!(metadata.n_pages, 1);
+        assert_eq!(title(&uri).as_deref(), Some("Quarterly Report"));
+
+        let scan = scan(&uri);
+        assert_eq!(scan.page_sizes, ["595 × 842 pt (A4), 1 page"]);
+        assert_eq!(
+            scan.fonts,
+            [
+                Font {
+                    name: "Custom".into(),
+                    kind: "TrueType".into(),
+                    embedded: true,
+                    subset: true,
+                },
+                Font {
+                    name: "Helvetica".into(),
+                    kind: "Type1".into(),
+                    embedded: false,
+                    subset: false,
+                },
+            ]
+        );
+        assert_eq!(
+            font_line(&scan.fonts[0]),
+            "Custom (TrueType, embedded, subset)"
+        );
+    }
+
+    #[gtk::test]
+    fn image_folders_show_their_page_count_and_sizes() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gallery");
+        let uri = gtk::gio::File::for_path(dir).uri().to_string();
+        let metadata = metadata(&uri).expect("the gallery's page count");
+        assert_eq!(metadata.n_pages, 2);
+        assert!(metadata.title.is_empty());
+
+        let scan = scan(&uri);
+        assert_eq!(
+            scan.page_sizes,
+            vec!["15 × 30 pt, 1 page", "30 × 15 pt, 1 page"]
+        );
+        assert!(scan.fonts.is_empty());
+    }
+}
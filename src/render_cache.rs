@@ -31,6 +31,9 @@ struct Entry {
 
 pub struct RenderCache {
     budget_bytes: usize,
+    // halvings of the budget while the system is short of memory; the configured budget stays in
+    // budget_bytes so lifting the pressure restores it
+    pressure: u32,
     total_bytes: usize,
     entries: HashMap<CacheKey, Entry>,
     // texture identities ordered least- to most-recently used
@@ -52,6 +55,7 @@ impl std::fmt::Debug for RenderCache {
             .field("textures", &self.entries.len())
             .field("total_bytes", &self.total_bytes)
             .field("budget_bytes", &self.budget_bytes)
+            .field("pressure", &self.pressure)
             .finish()
     }
 }
@@ -60,6 +64,7 @@ impl RenderCache {
     pub fn new(budget_bytes: usize) -> Self {
         Self {
             budget_bytes,
+            pressure: 0,
             total_bytes: 0,
             entries: HashMap::new(),
             order: Vec::new(),
@@ -72,8 +77,28 @@ impl RenderCache {
         self.evict();
     }
 
+    // The budget in effect: the configured one, halved per pressure level.
     pub fn budget_bytes(&self) -> usize {
-        self.budget_bytes
+        self.budget_bytes >> self.pressure
+    }
+
+    pub fn set_pressure(&mut self, pressure: u32) {
+        self.pressure = pressure.min(usize::BITS - 1);
+        self.evict();
+    }
+
+    // Drop every tile no mapped page presents. Tiles are the largest textures and the cheapest to
+    // lose: a page without them shows its whole-page texture or preview until they come back.
+    pub fn drop_tiles(&mut self) {
+        let keys: Vec<CacheKey> = self
+            .entries
+            .keys()
+            .copied()
+            .filter(|key| matches!(key, CacheKey::Tile(_)) && !self.is_pinned(key))
+            .collect();
+        for key in keys {
+            self.remove_key(key);
+        }
     }
 
     pub fn get(&mut self, page: i32) -> Option<gdk::Texture> {
@@ -115,25 +140,17 @@ impl RenderCache {
             return 0;
         }
         let avg = self.total_bytes / self.entries.len();
-        self.budget_bytes.checked_div(avg).unwrap_or(0)
+        self.budget_bytes().checked_div(avg).unwrap_or(0)
     }
 
     pub fn insert(&mut self, page: i32, texture: gdk::Texture, pixel_scale: f64) {
         self.insert_key(CacheKey::Page(page), texture, pixel_scale);
     }
 
-    #[cfg(test)]
-    fn insert_tile(&mut self, tile: TileId, texture: gdk::Texture, pixel_scale: f64) {
+    pub fn insert_tile(&mut self, tile: TileId, texture: gdk::Texture, pixel_scale: f64) {
         self.insert_key(CacheKey::Tile(tile), texture, pixel_scale);
     }
 
-    pub fn insert_tile_batch(&mut self, tiles: Vec<(TileId, gdk::Texture)>, pixel_scale: f64) {
-        for (tile, texture) in tiles {
-            self.insert_key_unbounded(CacheKey::Tile(tile), texture, pixel_scale);
-        }
-        self.evict();
-    }
-
     pub fn pin_page(&mut self, page: i32) {
         if self
             .pinned_by_page
@@ -182,11 +199,6 @@ impl RenderCache {
     }
 
     fn insert_key(&mut self, key: CacheKey, texture: gdk::Texture, pixel_scale: f64) {
-        self.insert_key_unbounded(key, texture, pixel_scale);
-        self.evict();
-    }
-
-    fn insert_key_unbounded(&mut self, key: CacheKey, texture: gdk::Texture, pixel_scale: f64) {
         // 4 bytes/pixel (BGRx) - close enough to the resident buffer for the budget.
         let bytes = (texture.width() as usize) * (texture.height() as usize) * 4;
         self.remove_key(key);
@@ -200,6 +212,7 @@ impl RenderCache {
         );
         self.order.push(key);
         self.total_bytes += bytes;
+        self.evict();
     }
 
     fn remove_key(&mut self, key: CacheKey) {
@@ -209,6 +222,23 @@ impl RenderCache {
         }
     }
 
+    // Drop a page's whole-page texture and tiles (after an edit changed its content), keeping the
+    // rest of the cache and the page's pins.
+    pub fn remove_page(&mut self, page: i32) {
+        let keys: Vec<CacheKey> = self
+            .entries
+            .keys()
+            .copied()
+            .filter(|key| match key {
+                CacheKey::Page(p) => *p == page,
+                CacheKey::Tile(tile) => tile.page == page,
+            })
+            .collect();
+        for key in keys {
+            self.remove_key(key);
+        }
+    }
+
     pub fn clear(&mut self) {
         self.entries.clear();
         self.order.clear();
@@ -227,7 +257,7 @@ impl RenderCache {
     // oversized: visible pages pin it before rendering, while retaining one completed prefetch
     // avoids immediately scheduling the same render again.
     fn evict(&mut self) {
-        while self.total_bytes > self.budget_bytes {
+        while self.total_bytes > self.budget_bytes() {
             let mru = self.order.last().copied();
             let Some(pos) = self
                 .order
@@ -343,7 +373,7 @@ mod tests {
     }
 
     #[gtk::test]
-    fn serial_tile_batch_keeps_the_complete_viewport_over_budget() {
+    fn tiles_landing_one_by_one_keep_the_complete_viewport_over_budget() {
         let mut cache = RenderCache::new(60);
         let stale = TileId {
             page: 1,
@@ -362,7 +392,8 @@ mod tests {
             y: 0,
         };
         cache.pin_tiles(2, &[left, right]);
-        cache.insert_tile_batch(vec![(left, texture(40)), (right, texture(40))], 2.0);
+        cache.insert_tile(left, texture(40), 2.0);
+        cache.insert_tile(right, texture(40), 2.0);
 
         assert!(cache.get_tile(stale, 1.0).is_none());
         assert!(cache.get_tile(left, 2.0).is_some());
@@ -383,7 +414,8 @@ mod tests {
             y: 0,
         };
         cache.pin_tiles(2, &[left, right]);
-        cache.insert_tile_batch(vec![(left, texture(40)), (right, texture(40))], 2.0);
+        cache.insert_tile(left, texture(40), 2.0);
+        cache.insert_tile(right, texture(40), 2.0);
 
         cache.insert(9, texture(40), 1.0);
 
@@ -405,6 +437,26 @@ mod tests {
         assert!(cache.get(10).is_some());
     }
 
+    #[gtk::test]
+    fn remove_page_drops_only_that_pages_textures() {
+        let mut cache = RenderCache::new(200);
+        let tile = TileId {
+            page: 2,
+            x: 0,
+            y: 0,
+        };
+        cache.insert(2, texture(40), 1.0);
+        cache.insert_tile(tile, texture(40), 2.0);
+        cache.insert(3, texture(40), 1.0);
+
+        cache.remove_page(2);
+
+        assert!(cache.get(2).is_none());
+        assert!(cache.get_tile(tile, 2.0).is_none());
+        assert!(cache.get(3).is_some());
+        assert_eq!(cache.total_bytes, 40);
+    }
+
     #[gtk::test]
     fn mapped_whole_page_and_tiles_can_jointly_exceed_the_budget() {
         let mut cache = RenderCache::new(60);
@@ -414,11 +466,86 @@ mod tests {
             y: 0,
         };
         cache.pin_tiles(2, &[tile]);
-        cache.insert_tile_batch(vec![(tile, texture(40))], 2.0);
+        cache.insert_tile(tile, texture(40), 2.0);
         cache.pin_page(9);
         cache.insert(9, texture(40), 1.0);
 
         assert!(cache.get_tile(tile, 2.0).is_some());
         assert!(cache.get(9).is_some());
     }
+
+    #[gtk::test]
+    fn pressure_halves_the_budget_per_level_and_lifting_it_restores_the_configured_one() {
+        let mut cache = RenderCache::new(160);
+        for page in 0..4 {
+            cache.insert(page, texture(40), 1.0);
+        }
+
+        cache.set_pressure(1);
+        assert_eq!(cache.budget_bytes(), 80);
+        assert!(cache.get(0).is_none());
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(2).is_some());
+        assert!(cache.get(3).is_some());
+
+        // a settings change under pressure moves the configured budget, still halved
+        cache.set_budget(320);
+        assert_eq!(cache.budget_bytes(), 160);
+
+        cache.set_pressure(0);
+        assert_eq!(cache.budget_bytes(), 320);
+        cache.insert(4, texture(40), 1.0);
+        cache.insert(5, texture(40), 1.0);
+        assert!(cache.get(2).is_some());
+        assert!(cache.get(5).is_some());
+    }
+
+    #[gtk::test]
+    fn pressure_never_evicts_what_mapped_pages_present() {
+        let mut cache = RenderCache::new(120);
+        cache.pin_page(1);
+        cache.insert(1, texture(40), 1.0);
+        cache.insert(2, texture(40), 1.0);
+        cache.insert(3, texture(40), 1.0);
+
+        cache.set_pressure(3);
+
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(2).is_none());
+        // the most recent completion survives, as at any budget
+        assert!(cache.get(3).is_some());
+    }
+
+    #[gtk::test]
+    fn drop_tiles_keeps_whole_pages_and_presented_tiles() {
+        let mut cache = RenderCache::new(1000);
+        let shown = TileId {
+            page: 2,
+            x: 0,
+            y: 0,
+        };
+        let offscreen = TileId {
+            page: 2,
+            x: 1,
+            y: 0,
+        };
+        let other_page = TileId {
+            page: 5,
+            x: 0,
+            y: 0,
+        };
+        cache.pin_tiles(2, &[shown]);
+        cache.insert_tile(shown, texture(40), 2.0);
+        cache.insert_tile(offscreen, texture(40), 2.0);
+        cache.insert_tile(other_page, texture(40), 2.0);
+        cache.insert(3, texture(40), 1.0);
+
+        cache.drop_tiles();
+
+        assert!(cache.get_tile(shown, 2.0).is_some());
+        assert!(cache.get_tile(offscreen, 2.0).is_none());
+        assert!(cache.get_tile(other_page, 2.0).is_none());
+        assert!(cache.get(3).is_some());
+        assert_eq!(cache.total_bytes, 80);
+    }
 }
@@ -1,7 +1,8 @@
 // Full-document text search. A background thread walks pages outward from the current page, runs
-// MuPDF's per-page search, and streams matches back. An epoch counter cancels a superseded sweep.
-// MuPDF search is case-insensitive. A match is a single logical hit and carries one rect per line it
-// spans, so a phrase wrapping across lines still counts as one match (and highlights every line).
+// the document backend's per-page search, and streams matches back. An epoch counter cancels a
+// superseded sweep. Search is case-insensitive. A match is a single logical hit and carries one
+// rect per line it spans, so a phrase wrapping across lines still counts as one match (and
+// highlights every line).
 
 use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -12,6 +13,7 @@ use mupdf::text_page::SearchHitResponse;
 use mupdf::TextPageFlags;
 
 use crate::page::Rectangle;
+use crate::selection::Glyph;
 
 // One match's highlight rects (one per line the hit spans), in page coords (top-left origin).
 pub type Match = Vec<Rectangle>;
@@ -123,21 +125,13 @@ pub fn spawn_search(
 ) -> MatchReceiver {
     let (tx, rx) = mpsc::unbounded();
 
-    std::thread::spawn(move || {
-        // Own MuPDF Document on this short-lived thread (dropped at scope end, before the thread's
-        // context TLS teardown, so no drop-order issue). local_path stages non-local files.
-        let Some(path) = crate::mupdf_render::local_path(&uri) else {
-            return;
-        };
-        let Ok(doc) = mupdf::Document::open(path.as_path()) else {
-            return;
-        };
-
+    crate::bg_job::spawn_document_thread(move || {
+        let backend = crate::backend::get(&uri);
         for page_num in search_order(n_pages, start_page) {
-            if shared_epoch.load(Ordering::Relaxed) != epoch {
-                return; // superseded by a newer query
+            if shared_epoch.load(Ordering::Relaxed) != epoch || crate::bg_job::stopping() {
+                break; // superseded by a newer query, or quitting
             }
-            let matches = search_page(&doc, page_num, &query);
+            let matches = backend.search(&uri, page_num, &query);
             if !matches.is_empty()
                 && tx
                     .unbounded_send(PageMatches {
@@ -147,7 +141,7 @@ pub fn spawn_search(
                     })
                     .is_err()
             {
-                return; // main loop dropped the receiver
+                break; // main loop dropped the receiver
             }
         }
     });
@@ -158,7 +152,7 @@ pub fn spawn_search(
 // All matches of `query` on one page. MuPDF's callback fires once per logical hit with that hit's
 // quads (one per line it spans), so a match becomes one rect per line and streams with no fixed cap.
 // Quads are already page-local top-left, so no origin flip.
-fn search_page(doc: &mupdf::Document, page_num: i32, query: &str) -> Vec<Match> {
+pub(crate) fn search_page(doc: &mupdf::Document, page_num: i32, query: &str) -> Vec<Match> {
     let mut matches: Vec<Match> = Vec::new();
     let Ok(page) = doc.load_page(page_num) else {
         return matches;
@@ -173,6 +167,71 @@ fn search_page(doc: &mupdf::Document, page_num: i32, query: &str) -> Vec<Match>
     matches
 }
 
+// All matches of `query` in a page's glyphs, for backends without a search of their own. Letters
+// compare case-insensitively and any run of whitespace, or a line break, matches any other; hits
+// don't overlap. A hit gets one rect per line, around its glyphs there.
+pub(crate) fn search_glyphs(glyphs: &[Glyph], query: &str) -> Vec<Match> {
+    let needle = fold(query.trim().chars());
+    if needle.is_empty() {
+        return Vec::new();
+    }
+    // the page's folded text, each char with the glyph it came from (None for a line break)
+    let mut text: Vec<(char, Option<usize>)> = Vec::with_capacity(glyphs.len());
+    for (i, glyph) in glyphs.iter().enumerate() {
+        let new_line = i > 0 && glyph.line != glyphs[i - 1].line;
+        if new_line && text.last().is_some_and(|&(ch, _)| ch != ' ') {
+            text.push((' ', None));
+        }
+        let ch = fold([glyph.ch])[0];
+        if ch != ' ' || text.last().is_some_and(|&(last, _)| last != ' ') {
+            text.push((ch, Some(i)));
+        }
+    }
+
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start + needle.len() <= text.len() {
+        let hit = &text[start..start + needle.len()];
+        if !hit.iter().map(|&(ch, _)| ch).eq(needle.iter().copied()) {
+            start += 1;
+            continue;
+        }
+        let mut rects: Vec<(usize, Rectangle)> = Vec::new();
+        for glyph in hit.iter().filter_map(|&(_, i)| i).map(|i| &glyphs[i]) {
+            let (x1, y1, x2, y2) = glyph.bbox;
+            match rects.last_mut() {
+                Some((line, rect)) if *line == glyph.line => {
+                    *rect = Rectangle::new(
+                        rect.x1.min(x1),
+                        rect.y1.min(y1),
+                        rect.x2.max(x2),
+                        rect.y2.max(y2),
+                    );
+                }
+                _ => rects.push((glyph.line, Rectangle::new(x1, y1, x2, y2))),
+            }
+        }
+        matches.push(rects.into_iter().map(|(_, rect)| rect).collect());
+        start += needle.len();
+    }
+    matches
+}
+
+// Chars as search compares them: lowercase, with any whitespace run a single space.
+fn fold(chars: impl IntoIterator<Item = char>) -> Vec<char> {
+    let mut folded: Vec<char> = Vec::new();
+    for ch in chars {
+        if ch.is_whitespace() {
+            if folded.last() != Some(&' ') {
+                folded.push(' ');
+            }
+        } else {
+            folded.push(ch.to_lowercase().next().unwrap_or(ch));
+        }
+    }
+    folded
+}
+
 // Axis-aligned bounding rect of a MuPDF quad (its four corners), in page-local top-left points.
 fn quad_rect(q: &mupdf::Quad) -> Rectangle {
     let xs = [q.ul.x, q.ur.x, q.ll.x, q.lr.x];
@@ -186,7 +245,7 @@ fn quad_rect(q: &mupdf::Quad) -> Rectangle {
 
 // Page order for a sweep: start page, then outward (start±1, start±2, …), clamped. Nearest matches
 // stream first, so the initial jump lands close by.
-fn search_order(n_pages: i32, start_page: i32) -> Vec<i32> {
+pub(crate) fn search_order(n_pages: i32, start_page: i32) -> Vec<i32> {
     if n_pages <= 0 {
         return Vec::new();
     }
@@ -257,6 +316,51 @@ trailer\n<< /Root 1 0 R >>\n%%EOF";
         assert!(search_page(&doc, 0, "zzz").is_empty());
     }
 
+    // "Hello world" on line 0 and "hello" on line 1, a glyph every 10 points.
+    fn two_lines() -> Vec<Glyph> {
+        let line = |text: &str, line: usize| {
+            let y = 20.0 * line as f64;
+            text.chars()
+                .enumerate()
+                .map(move |(i, ch)| {
+                    let x = 10.0 * i as f64;
+                    Glyph {
+                        ch,
+                        cx: x + 5.0,
+                        cy: y + 5.0,
+                        bbox: (x, y, x + 10.0, y + 10.0),
+                        baseline: y + 8.0,
+                        line,
+                        block: 0,
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        [line("Hello world", 0), line("hello", 1)].concat()
+    }
+
+    #[test]
+    fn search_glyphs_ignores_case_and_joins_lines() {
+        let glyphs = two_lines();
+        let hits = search_glyphs(&glyphs, "HELLO");
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0], vec![Rectangle::new(0.0, 0.0, 50.0, 10.0)]);
+        assert_eq!(hits[1], vec![Rectangle::new(0.0, 20.0, 50.0, 30.0)]);
+
+        // a phrase across the line break: one hit, a rect on each line
+        let hits = search_glyphs(&glyphs, "world  hello");
+        assert_eq!(
+            hits,
+            vec![vec![
+                Rectangle::new(60.0, 0.0, 110.0, 10.0),
+                Rectangle::new(0.0, 20.0, 50.0, 30.0),
+            ]]
+        );
+
+        assert!(search_glyphs(&glyphs, "worldhello").is_empty());
+        assert!(search_glyphs(&glyphs, "  ").is_empty());
+    }
+
     fn search_with(pages: &[(i32, usize)]) -> Search {
         let mut s = Search::default();
         for &(page, n) in pages {
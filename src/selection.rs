@@ -1,125 +1,308 @@
 // Text selection. Given the drag's start and end points (page-local top-left points), find the run
 // of characters between them in reading order via MuPDF's structured text, and return per-line
-// highlight rects plus the selected text. The page's glyph list is cached (a drag fires per pointer
-// move, so rebuilding the whole text page each time would be a real per-motion cost).
+// highlight rects plus the selected lines. The page's glyph list is cached (a drag fires per pointer
+// move, so rebuilding the whole text page each time would be a real per-motion cost). Copying
+// reflows the lines into paragraphs by default (see reflow); the raw lines stay available.
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use mupdf::TextPageFlags;
+use mupdf::{Document, TextPageFlags};
 
-use crate::mupdf_render::{self, with_doc};
+use crate::mupdf_render;
 use crate::page::Rectangle;
 
 pub struct Selection {
     // highlight rects, one per selected line, in page-local top-left points
     pub rects: Vec<(f64, f64, f64, f64)>,
+    pub lines: Vec<Line>,
+}
+
+// One selected line of text, and whether a paragraph ends with it (the last line of its text block).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
     pub text: String,
+    pub block_end: bool,
 }
 
-// The document's text selection: page, per-line highlight rects (page-local top-left points) and
-// text. Held by State, not by the page widget, since list recycling reassigns widgets to pages.
+// The part of the selection on one page: per-line highlight rects (page-local top-left points) and
+// lines.
 #[derive(Debug)]
 pub struct PageSelection {
     pub page: i32,
     pub rects: Vec<Rectangle>,
-    pub text: String,
+    pub lines: Vec<Line>,
+}
+
+// The document's text selection: its part on every page it touches, in page order, and where it
+// started (page and page-local point) so Shift+click can extend it. A rectangular (Alt+drag)
+// selection also carries the dragged region on the anchor page, outlined while dragging. Held by
+// State, not by the page widgets, since list recycling reassigns widgets to pages.
+#[derive(Debug)]
+pub struct TextSelection {
+    pub pages: Vec<PageSelection>,
+    pub anchor: (i32, (f64, f64)),
+    pub region: Option<Rectangle>,
+}
+
+impl TextSelection {
+    pub fn on_page(&self, page: i32) -> Option<&PageSelection> {
+        self.pages.iter().find(|selection| selection.page == page)
+    }
+
+    // The selected text in reading order, a line per line as on the page. A page break joins like
+    // any other line break.
+    pub fn raw_text(&self) -> String {
+        raw_text(&self.lines())
+    }
+
+    // The selected text reflowed into paragraphs (see reflow). A paragraph running onto the next
+    // page copies without a seam.
+    pub fn text(&self) -> String {
+        reflow(&self.lines())
+    }
+
+    // Every page's lines in reading order. A page's last line ends a block only as far as the
+    // page's layout goes, so across a page break only a line finishing a sentence ends a paragraph.
+    fn lines(&self) -> Vec<Line> {
+        let mut lines: Vec<Line> = Vec::new();
+        for selection in &self.pages {
+            if let Some(last) = lines.last_mut() {
+                last.block_end = ends_sentence(&last.text);
+            }
+            lines.extend(selection.lines.iter().cloned());
+        }
+        lines
+    }
+}
+
+// What a press selects: single characters (a drag), or the whole word, line or text block under the
+// pointer (double, triple and quadruple click). A drag after a multi-click extends by that unit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    #[default]
+    Char,
+    Word,
+    Line,
+    Block,
+}
+
+impl Unit {
+    pub fn from_n_press(n_press: i32) -> Self {
+        match n_press {
+            ..=1 => Unit::Char,
+            2 => Unit::Word,
+            3 => Unit::Line,
+            _ => Unit::Block,
+        }
+    }
 }
 
-// One selectable glyph: character, quad centre and bbox, and the line it belongs to.
-struct Glyph {
-    ch: char,
-    cx: f64,
-    cy: f64,
-    bbox: (f64, f64, f64, f64),
-    line: usize,
+// One selectable glyph: character, quad centre and bbox, baseline, and the line and text block it
+// belongs to.
+pub(crate) struct Glyph {
+    pub(crate) ch: char,
+    pub(crate) cx: f64,
+    pub(crate) cy: f64,
+    pub(crate) bbox: (f64, f64, f64, f64),
+    pub(crate) baseline: f64,
+    pub(crate) line: usize,
+    pub(crate) block: usize,
 }
 
-// (uri, page, doc-generation) -> reading-order glyphs.
-type GlyphCache = Option<(String, i32, u64, Rc<Vec<Glyph>>)>;
+// Gaps between neighbouring glyphs in a region row, in glyph heights: past WORD_GAP is a space,
+// past COLUMN_GAP a column break (about an em, wider than any justified space).
+const WORD_GAP: f64 = 0.1;
+const COLUMN_GAP: f64 = 0.8;
+
+// Pages of glyphs kept. A drag that crosses pages reselects every page between its ends on each
+// motion, so this covers a long drag without rebuilding text pages per event.
+const GLYPH_CACHE_PAGES: usize = 64;
+
+// (uri, page, doc-generation) -> reading-order glyphs, most recently used last.
+type GlyphCache = Vec<(String, i32, u64, Rc<Vec<Glyph>>)>;
 
 thread_local! {
     // Reused across the many motion events of a single drag; keyed on the generation so a reload
     // rebuilds.
-    static GLYPHS: RefCell<GlyphCache> = const { RefCell::new(None) };
+    static GLYPHS: RefCell<GlyphCache> = const { RefCell::new(Vec::new()) };
 }
 
-pub fn selection(uri: &str, page_num: i32, a: (f64, f64), b: (f64, f64)) -> Option<Selection> {
+// Select between `a` and `b`, each a page and a point on it. The ends may be on different pages and
+// in either order; the pages between them are selected whole. None if nothing selectable is in
+// between.
+pub fn selection(
+    uri: &str,
+    a: (i32, (f64, f64)),
+    b: (i32, (f64, f64)),
+    unit: Unit,
+) -> Option<TextSelection> {
+    let (start, end) = if b.0 < a.0 { (b, a) } else { (a, b) };
+    let mut pages = Vec::new();
+    for page in start.0..=end.0 {
+        let Some(glyphs) = cached_glyphs(uri, page) else {
+            continue;
+        };
+        let from = (page == start.0).then_some(start.1);
+        let to = (page == end.0).then_some(end.1);
+        let Some(sel) = select_span(&glyphs, from, to, unit) else {
+            continue;
+        };
+        pages.push(PageSelection {
+            page,
+            rects: sel.rects.into_iter().map(Rectangle::from).collect(),
+            lines: sel.lines,
+        });
+    }
+    if pages.is_empty() {
+        return None;
+    }
+    Some(TextSelection {
+        pages,
+        anchor: a,
+        region: None,
+    })
+}
+
+// Select the glyphs inside the rectangle with corners `a` and `b` on one page, as rows and
+// tab-separated columns. Kept even when the region holds no text, so its outline still shows.
+pub fn region_selection(
+    uri: &str,
+    page_num: i32,
+    a: (f64, f64),
+    b: (f64, f64),
+) -> Option<TextSelection> {
     let glyphs = cached_glyphs(uri, page_num)?;
-    select_between(&glyphs, a, b)
+    let region = Rectangle::new(a.0.min(b.0), a.1.min(b.1), a.0.max(b.0), a.1.max(b.1));
+    let sel = select_region(&glyphs, &region);
+    Some(TextSelection {
+        pages: vec![PageSelection {
+            page: page_num,
+            rects: sel.rects.into_iter().map(Rectangle::from).collect(),
+            lines: sel.lines,
+        }],
+        anchor: (page_num, a),
+        region: Some(region),
+    })
+}
+
+// Text under `rects` on a page (e.g. a highlight's quads): the glyphs whose centre falls inside any
+// of them, in reading order, reflowed into one run like a copy.
+pub fn text_in_rects(uri: &str, page_num: i32, rects: &[Rectangle]) -> String {
+    cached_glyphs(uri, page_num).map_or_else(String::new, |glyphs| {
+        reflow(&lines_in_rects(&glyphs, rects))
+    })
+}
+
+// A whole page's lines in reading order, for exporting its text; None if the page can't be read.
+// Built afresh rather than through the glyph cache, which a whole-document export would only churn.
+pub fn page_lines(uri: &str, page_num: i32) -> Option<Vec<Line>> {
+    let glyphs = build_glyphs(uri, page_num)?;
+    Some(select_span(&glyphs, None, None, Unit::Char).map_or_else(Vec::new, |sel| sel.lines))
 }
 
 fn cached_glyphs(uri: &str, page_num: i32) -> Option<Rc<Vec<Glyph>>> {
     let generation = mupdf_render::generation();
     GLYPHS.with(|cell| {
-        if let Some((u, p, g, glyphs)) = cell.borrow().as_ref() {
-            if u == uri && *p == page_num && *g == generation {
-                return Some(glyphs.clone());
-            }
+        let mut cache = cell.borrow_mut();
+        cache.retain(|(_, _, g, _)| *g == generation);
+        if let Some(i) = cache
+            .iter()
+            .position(|(u, p, _, _)| u == uri && *p == page_num)
+        {
+            let entry = cache.remove(i);
+            let glyphs = entry.3.clone();
+            cache.push(entry);
+            return Some(glyphs);
         }
         let glyphs = Rc::new(build_glyphs(uri, page_num)?);
-        *cell.borrow_mut() = Some((uri.to_string(), page_num, generation, glyphs.clone()));
+        if cache.len() >= GLYPH_CACHE_PAGES {
+            cache.remove(0);
+        }
+        cache.push((uri.to_string(), page_num, generation, glyphs.clone()));
         Some(glyphs)
     })
 }
 
-// Flatten the page's glyphs in reading order, numbering lines so a selection can be broken back into
-// per-line highlight rects.
+// The page's glyphs, from whichever backend reads the document.
 fn build_glyphs(uri: &str, page_num: i32) -> Option<Vec<Glyph>> {
-    with_doc(uri, |doc| {
-        let page = doc.load_page(page_num).ok()?;
-        let text_page = page.to_text_page(TextPageFlags::PRESERVE_WHITESPACE).ok()?;
-
-        let mut glyphs: Vec<Glyph> = Vec::new();
-        let mut line_id = 0usize;
-        for block in text_page.blocks() {
-            for line in block.lines() {
-                let before = glyphs.len();
-                for tc in line.chars() {
-                    let Some(ch) = tc.char() else { continue };
-                    let bbox = quad_bbox(&tc.quad());
-                    glyphs.push(Glyph {
-                        ch,
-                        cx: (bbox.0 + bbox.2) / 2.0,
-                        cy: (bbox.1 + bbox.3) / 2.0,
-                        bbox,
-                        line: line_id,
-                    });
-                }
-                if glyphs.len() > before {
-                    line_id += 1;
-                }
+    crate::backend::get(uri).text_page(uri, page_num)
+}
+
+// Flatten a MuPDF page's glyphs in reading order, numbering lines so a selection can be broken back
+// into per-line highlight rects, and blocks so a quadruple click can take a whole paragraph.
+pub(crate) fn page_glyphs(doc: &Document, page_num: i32) -> Option<Vec<Glyph>> {
+    let page = doc.load_page(page_num).ok()?;
+    let text_page = page.to_text_page(TextPageFlags::PRESERVE_WHITESPACE).ok()?;
+
+    let mut glyphs: Vec<Glyph> = Vec::new();
+    let mut line_id = 0usize;
+    let mut block_id = 0usize;
+    for block in text_page.blocks() {
+        let block_start = glyphs.len();
+        for line in block.lines() {
+            let before = glyphs.len();
+            for tc in line.chars() {
+                let Some(ch) = tc.char() else { continue };
+                let bbox = quad_bbox(&tc.quad());
+                glyphs.push(Glyph {
+                    ch,
+                    cx: (bbox.0 + bbox.2) / 2.0,
+                    cy: (bbox.1 + bbox.3) / 2.0,
+                    bbox,
+                    baseline: f64::from(tc.origin().y),
+                    line: line_id,
+                    block: block_id,
+                });
+            }
+            if glyphs.len() > before {
+                line_id += 1;
             }
         }
-        Some(glyphs)
-    })
+        if glyphs.len() > block_start {
+            block_id += 1;
+        }
+    }
+    Some(glyphs)
 }
 
-fn select_between(glyphs: &[Glyph], a: (f64, f64), b: (f64, f64)) -> Option<Selection> {
+// Select the run between points `a` and `b`. A missing end runs to the page's first (`a`) or last
+// (`b`) glyph, for the pages a multi-page selection starts or ends on.
+fn select_span(
+    glyphs: &[Glyph],
+    a: Option<(f64, f64)>,
+    b: Option<(f64, f64)>,
+    unit: Unit,
+) -> Option<Selection> {
     if glyphs.is_empty() {
         return None;
     }
     let (lo, hi) = {
-        let ia = nearest(glyphs, a);
-        let ib = nearest(glyphs, b);
+        let ia = a.map_or(0, |a| nearest(glyphs, a));
+        let ib = b.map_or(glyphs.len() - 1, |b| nearest(glyphs, b));
         (ia.min(ib), ia.max(ib))
     };
+    let (lo, hi) = (unit_start(glyphs, lo, unit), unit_end(glyphs, hi, unit));
 
     let mut rects = Vec::new();
-    let mut text = String::new();
+    let mut lines: Vec<Line> = Vec::new();
     let mut cur_line: Option<usize> = None;
     let mut acc: Option<(f64, f64, f64, f64)> = None;
-    for g in &glyphs[lo..=hi] {
+    for (i, g) in glyphs.iter().enumerate().take(hi + 1).skip(lo) {
         if cur_line != Some(g.line) {
             if let Some(r) = acc.take() {
                 rects.push(r);
             }
-            if cur_line.is_some() {
-                text.push('\n');
-            }
+            lines.push(Line {
+                text: String::new(),
+                block_end: false,
+            });
             cur_line = Some(g.line);
         }
-        text.push(g.ch);
+        if let Some(line) = lines.last_mut() {
+            line.text.push(g.ch);
+            line.block_end = glyphs.get(i + 1).is_none_or(|next| next.block != g.block);
+        }
         acc = Some(match acc {
             Some(r) => union(r, g.bbox),
             None => g.bbox,
@@ -129,7 +312,204 @@ fn select_between(glyphs: &[Glyph], a: (f64, f64), b: (f64, f64)) -> Option<Sele
         rects.push(r);
     }
 
-    Some(Selection { rects, text })
+    Some(Selection { rects, lines })
+}
+
+// The glyphs whose centre lies in `region`, regrouped into rows by baseline rather than by MuPDF's
+// lines: table cells are often separate lines (even blocks), yet a row of them should copy as one
+// line. A glyph joins a row whose baseline is within half its height (so sub- and superscripts stay
+// put); each row reads left to right, with spaces and tabs from the gaps between glyphs.
+fn select_region(glyphs: &[Glyph], region: &Rectangle) -> Selection {
+    let mut inside: Vec<&Glyph> = glyphs
+        .iter()
+        .filter(|g| !g.ch.is_whitespace() && region.contains(g.cx, g.cy))
+        .collect();
+    inside.sort_by(|a, b| a.baseline.total_cmp(&b.baseline));
+
+    let mut rows: Vec<Vec<&Glyph>> = Vec::new();
+    for g in inside {
+        match rows.last_mut() {
+            Some(row) if (g.baseline - row[0].baseline).abs() <= height(g) / 2.0 => row.push(g),
+            _ => rows.push(vec![g]),
+        }
+    }
+
+    let mut rects = Vec::new();
+    let mut lines = Vec::new();
+    for mut row in rows {
+        row.sort_by(|a, b| a.bbox.0.total_cmp(&b.bbox.0));
+        let h = row.iter().map(|g| height(g)).fold(0.0, f64::max);
+        let mut line = String::new();
+        let mut rect = row[0].bbox;
+        let mut prev: Option<&Glyph> = None;
+        for g in row {
+            if let Some(prev) = prev {
+                let gap = g.bbox.0 - prev.bbox.2;
+                if gap > COLUMN_GAP * h {
+                    line.push('\t');
+                } else if gap > WORD_GAP * h {
+                    line.push(' ');
+                }
+            }
+            line.push(g.ch);
+            rect = union(rect, g.bbox);
+            prev = Some(g);
+        }
+        rects.push(rect);
+        // every row stands alone: reflowing a table would run its rows together
+        lines.push(Line {
+            text: line,
+            block_end: true,
+        });
+    }
+
+    Selection { rects, lines }
+}
+
+// Lines of the glyphs centred in any of `rects`. A markup annotation covers one excerpt, so no line
+// ends a block: reflow joins them into a single paragraph.
+fn lines_in_rects(glyphs: &[Glyph], rects: &[Rectangle]) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
+    let mut cur_line = None;
+    for g in glyphs {
+        if !rects.iter().any(|r| r.contains(g.cx, g.cy)) {
+            continue;
+        }
+        if cur_line != Some(g.line) {
+            lines.push(Line {
+                text: String::new(),
+                block_end: false,
+            });
+            cur_line = Some(g.line);
+        }
+        if let Some(line) = lines.last_mut() {
+            line.text.push(g.ch);
+        }
+    }
+    lines
+}
+
+fn raw_text(lines: &[Line]) -> String {
+    let lines: Vec<&str> = lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .filter(|text| !text.is_empty())
+        .collect();
+    lines.join("\n")
+}
+
+// Copy-ready text: lines within a block join into one paragraph, a word hyphenated across a line
+// break is rejoined, ligatures are spelt out and whitespace runs collapse to a single space (tabs,
+// which a region selection uses between columns, are kept). Paragraphs are a line each.
+pub fn reflow(lines: &[Line]) -> String {
+    let mut out = String::new();
+    let mut in_paragraph = false;
+    for line in lines {
+        let text = normalise_whitespace(&expand_ligatures(&line.text));
+        if text.is_empty() {
+            continue;
+        }
+        if in_paragraph {
+            // a rejoined word continues straight on
+            if !dehyphenate(&mut out, &text) {
+                out.push(' ');
+            }
+        } else if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&text);
+        in_paragraph = !line.block_end;
+    }
+    out
+}
+
+// Drop a line-end hyphen from `out` if it splits a word that `next` finishes: a letter before it
+// and a lowercase letter after. "well-\nKnown" or "1990-\n2000" keep theirs.
+fn dehyphenate(out: &mut String, next: &str) -> bool {
+    let mut tail = out.chars().rev();
+    let (Some(hyphen), Some(before)) = (tail.next(), tail.next()) else {
+        return false;
+    };
+    let splits = matches!(hyphen, '-' | '\u{00ad}' | '\u{2010}')
+        && before.is_alphabetic()
+        && next.chars().next().is_some_and(char::is_lowercase);
+    if splits {
+        out.pop();
+    }
+    splits
+}
+
+fn expand_ligatures(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\u{fb00}' => out.push_str("ff"),
+            '\u{fb01}' => out.push_str("fi"),
+            '\u{fb02}' => out.push_str("fl"),
+            '\u{fb03}' => out.push_str("ffi"),
+            '\u{fb04}' => out.push_str("ffl"),
+            '\u{fb05}' | '\u{fb06}' => out.push_str("st"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+// Collapse each run of whitespace (no-break and thin spaces included) to one space and trim the
+// ends. A tab survives, absorbing the spaces around it.
+fn normalise_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut pending: Option<char> = None;
+    for ch in text.trim().chars() {
+        if ch == '\t' {
+            pending = Some('\t');
+        } else if ch.is_whitespace() {
+            pending = pending.or(Some(' '));
+        } else {
+            if let Some(space) = pending.take() {
+                out.push(space);
+            }
+            out.push(ch);
+        }
+    }
+    out
+}
+
+// Whether a line reads as the end of a sentence (and so, at a page break, of its paragraph).
+fn ends_sentence(text: &str) -> bool {
+    text.trim_end()
+        .ends_with(['.', '!', '?', ':', '\u{201d}', '"'])
+}
+
+fn height(g: &Glyph) -> f64 {
+    g.bbox.3 - g.bbox.1
+}
+
+// Whether glyphs `i` and `j` (adjacent in reading order) fall in the same `unit`.
+fn same_unit(glyphs: &[Glyph], i: usize, j: usize, unit: Unit) -> bool {
+    let (a, b) = (&glyphs[i], &glyphs[j]);
+    match unit {
+        Unit::Char => false,
+        Unit::Word => a.line == b.line && !a.ch.is_whitespace() && !b.ch.is_whitespace(),
+        Unit::Line => a.line == b.line,
+        Unit::Block => a.block == b.block,
+    }
+}
+
+// Walk back from glyph `i` to the first glyph of its unit.
+fn unit_start(glyphs: &[Glyph], mut i: usize, unit: Unit) -> usize {
+    while i > 0 && same_unit(glyphs, i - 1, i, unit) {
+        i -= 1;
+    }
+    i
+}
+
+// Walk forward from glyph `i` to the last glyph of its unit.
+fn unit_end(glyphs: &[Glyph], mut i: usize, unit: Unit) -> usize {
+    while i + 1 < glyphs.len() && same_unit(glyphs, i, i + 1, unit) {
+        i += 1;
+    }
+    i
 }
 
 fn quad_bbox(q: &mupdf::Quad) -> (f64, f64, f64, f64) {
@@ -165,19 +545,46 @@ fn nearest(glyphs: &[Glyph], p: (f64, f64)) -> usize {
 mod tests {
     use super::*;
 
-    // A glyph 10 wide, 10 tall, centred at (x, y) on `line`.
+    impl Selection {
+        fn text(&self) -> String {
+            raw_text(&self.lines)
+        }
+    }
+
+    // A glyph 10 wide, 10 tall, centred at (x, y) on `line` (in block 0).
     fn glyph(ch: char, x: f64, y: f64, line: usize) -> Glyph {
         Glyph {
             ch,
             cx: x,
             cy: y,
             bbox: (x - 5.0, y - 5.0, x + 5.0, y + 5.0),
+            baseline: y + 3.0,
             line,
+            block: 0,
         }
     }
 
+    // Glyphs for `text` laid out on `line` of `block`, one every 10 points from x = 10.
+    fn run(text: &str, y: f64, line: usize, block: usize) -> Vec<Glyph> {
+        text.chars()
+            .enumerate()
+            .map(|(i, ch)| Glyph {
+                block,
+                ..glyph(ch, 10.0 + 10.0 * i as f64, y, line)
+            })
+            .collect()
+    }
+
+    // Two blocks: "ab cd" / "ef gh" in block 0, "ij" in block 1.
+    fn paragraphs() -> Vec<Glyph> {
+        let mut glyphs = run("ab cd", 5.0, 0, 0);
+        glyphs.extend(run("ef gh", 25.0, 1, 0));
+        glyphs.extend(run("ij", 55.0, 2, 1));
+        glyphs
+    }
+
     #[test]
-    fn select_between_picks_reading_order_run() {
+    fn select_span_picks_reading_order_run() {
         // "abc" on one line at x = 10, 20, 30
         let glyphs = [
             glyph('a', 10.0, 5.0, 0),
@@ -185,13 +592,13 @@ mod tests {
             glyph('c', 30.0, 5.0, 0),
         ];
         // drag from near 'a' to near 'c' selects the whole run; endpoints may be given either way
-        let sel = select_between(&glyphs, (32.0, 5.0), (8.0, 5.0)).unwrap();
-        assert_eq!(sel.text, "abc");
+        let sel = select_span(&glyphs, Some((32.0, 5.0)), Some((8.0, 5.0)), Unit::Char).unwrap();
+        assert_eq!(sel.text(), "abc");
         assert_eq!(sel.rects.len(), 1, "single line => one rect");
     }
 
     #[test]
-    fn select_between_spans_lines_with_a_rect_each() {
+    fn select_span_spans_lines_with_a_rect_each() {
         // "ab" on line 0, "cd" on line 1
         let glyphs = [
             glyph('a', 10.0, 5.0, 0),
@@ -199,13 +606,218 @@ mod tests {
             glyph('c', 10.0, 25.0, 1),
             glyph('d', 20.0, 25.0, 1),
         ];
-        let sel = select_between(&glyphs, (8.0, 5.0), (22.0, 25.0)).unwrap();
-        assert_eq!(sel.text, "ab\ncd");
+        let sel = select_span(&glyphs, Some((8.0, 5.0)), Some((22.0, 25.0)), Unit::Char).unwrap();
+        assert_eq!(sel.text(), "ab\ncd");
         assert_eq!(sel.rects.len(), 2, "two lines => a rect each");
     }
 
     #[test]
-    fn select_between_empty_is_none() {
-        assert!(select_between(&[], (0.0, 0.0), (1.0, 1.0)).is_none());
+    fn text_under_rects_joins_the_covered_glyphs() {
+        let glyphs = paragraphs();
+        // "cd" at the end of line 0 and "ef" at the start of line 1, as a highlight's two quads
+        let quads = [
+            Rectangle::new(40.0, 0.0, 60.0, 10.0),
+            Rectangle::new(0.0, 20.0, 28.0, 30.0),
+        ];
+        assert_eq!(reflow(&lines_in_rects(&glyphs, &quads)), "cd ef");
+        assert!(lines_in_rects(&glyphs, &[Rectangle::new(0.0, 70.0, 99.0, 80.0)]).is_empty());
+    }
+
+    #[test]
+    fn select_span_empty_is_none() {
+        assert!(select_span(&[], Some((0.0, 0.0)), Some((1.0, 1.0)), Unit::Char).is_none());
+    }
+
+    #[test]
+    fn multi_click_selects_word_line_and_block() {
+        let glyphs = paragraphs();
+        // a click on 'd' (x = 50, line 0)
+        let at = (50.0, 5.0);
+        let text = |unit| {
+            select_span(&glyphs, Some(at), Some(at), unit)
+                .unwrap()
+                .text()
+        };
+        assert_eq!(text(Unit::Char), "d");
+        assert_eq!(text(Unit::Word), "cd");
+        assert_eq!(text(Unit::Line), "ab cd");
+        assert_eq!(text(Unit::Block), "ab cd\nef gh");
+    }
+
+    #[test]
+    fn word_drag_snaps_both_ends_to_word_bounds() {
+        let glyphs = paragraphs();
+        // from 'b' on line 0 to 'g' on line 1, by words
+        let sel = select_span(&glyphs, Some((20.0, 5.0)), Some((40.0, 25.0)), Unit::Word).unwrap();
+        assert_eq!(sel.text(), "ab cd\nef gh");
+        assert_eq!(sel.rects.len(), 2);
+    }
+
+    #[test]
+    fn a_click_on_a_space_selects_only_the_space() {
+        let glyphs = paragraphs();
+        let sel = select_span(&glyphs, Some((30.0, 5.0)), Some((30.0, 5.0)), Unit::Word).unwrap();
+        assert_eq!(sel.text(), " ");
+    }
+
+    #[test]
+    fn open_ends_run_to_the_page_edges() {
+        let glyphs = paragraphs();
+        // the page a selection starts on: from 'g' to the end
+        let sel = select_span(&glyphs, Some((40.0, 25.0)), None, Unit::Char).unwrap();
+        assert_eq!(sel.text(), "gh\nij");
+        // the page it ends on: from the start to 'b'
+        let sel = select_span(&glyphs, None, Some((20.0, 5.0)), Unit::Char).unwrap();
+        assert_eq!(sel.text(), "ab");
+        // a page in between: everything
+        let sel = select_span(&glyphs, None, None, Unit::Char).unwrap();
+        assert_eq!(sel.text(), "ab cd\nef gh\nij");
+        assert_eq!(sel.rects.len(), 3);
+    }
+
+    #[test]
+    fn page_parts_join_in_reading_order() {
+        let part = |page, text: &str| PageSelection {
+            page,
+            rects: Vec::new(),
+            lines: vec![Line {
+                text: text.to_string(),
+                block_end: true,
+            }],
+        };
+        let selection = TextSelection {
+            pages: vec![part(2, "runs onto"), part(3, ""), part(4, "the next page")],
+            anchor: (4, (0.0, 0.0)),
+            region: None,
+        };
+        assert_eq!(selection.raw_text(), "runs onto\nthe next page");
+        // mid-sentence at the page break: one paragraph when reflowed
+        assert_eq!(selection.text(), "runs onto the next page");
+        assert!(selection.on_page(3).is_some());
+        assert!(selection.on_page(5).is_none());
+    }
+
+    // Reflowed text of a whole fixture page.
+    fn reflowed(glyphs: &[Glyph]) -> String {
+        reflow(&select_span(glyphs, None, None, Unit::Char).unwrap().lines)
+    }
+
+    #[test]
+    fn reflow_joins_a_block_into_a_paragraph() {
+        // "one two" / "three" in block 0, "four" in block 1
+        let mut glyphs = run("one two", 5.0, 0, 0);
+        glyphs.extend(run("three", 25.0, 1, 0));
+        glyphs.extend(run("four", 55.0, 2, 1));
+        assert_eq!(reflowed(&glyphs), "one two three\nfour");
+        // the raw text keeps the page's line breaks
+        let sel = select_span(&glyphs, None, None, Unit::Char).unwrap();
+        assert_eq!(sel.text(), "one two\nthree\nfour");
+    }
+
+    #[test]
+    fn reflow_rejoins_hyphenated_words() {
+        let mut glyphs = run("an ef-", 5.0, 0, 0);
+        glyphs.extend(run("fect", 25.0, 1, 0));
+        assert_eq!(reflowed(&glyphs), "an effect");
+    }
+
+    #[test]
+    fn reflow_keeps_hyphens_that_belong_to_the_text() {
+        // a capitalised continuation or a number range is not a split word
+        let mut glyphs = run("well-", 5.0, 0, 0);
+        glyphs.extend(run("Known 1990-", 25.0, 1, 0));
+        glyphs.extend(run("2000", 45.0, 2, 0));
+        assert_eq!(reflowed(&glyphs), "well- Known 1990- 2000");
+    }
+
+    #[test]
+    fn reflow_leaves_a_paragraph_ending_in_a_hyphen_alone() {
+        // a hyphen at a block's end can't be a split word
+        let mut glyphs = run("pre-", 5.0, 0, 0);
+        glyphs.extend(run("post", 35.0, 1, 1));
+        assert_eq!(reflowed(&glyphs), "pre-\npost");
+    }
+
+    #[test]
+    fn reflow_expands_ligatures() {
+        let glyphs = run("\u{fb01}nd the e\u{fb00}ect", 5.0, 0, 0);
+        assert_eq!(reflowed(&glyphs), "find the effect");
+    }
+
+    #[test]
+    fn reflow_normalises_whitespace() {
+        let glyphs = run("  a \u{a0}\u{2009} b  ", 5.0, 0, 0);
+        assert_eq!(reflowed(&glyphs), "a b");
+        // tabs between region columns survive
+        assert_eq!(normalise_whitespace("a \t b"), "a\tb");
+    }
+
+    #[test]
+    fn reflow_keeps_region_rows_apart() {
+        let mut glyphs = cell("ab", 10.0, 5.0, 0);
+        glyphs.extend(cell("cd", 10.0, 25.0, 1));
+        glyphs.extend(cell("ef", 60.0, 5.0, 2));
+        let sel = select_region(&glyphs, &Rectangle::new(0.0, 0.0, 100.0, 40.0));
+        assert_eq!(reflow(&sel.lines), "ab\tef\ncd");
+    }
+
+    // Glyphs for `text` on `line` from x, one every 10 points, in a block of their own.
+    fn cell(text: &str, x: f64, y: f64, line: usize) -> Vec<Glyph> {
+        text.chars()
+            .enumerate()
+            .map(|(i, ch)| Glyph {
+                block: line,
+                ..glyph(ch, x + 10.0 * i as f64, y, line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn region_copies_a_table_as_tab_separated_rows() {
+        // two rows of two cells, each cell its own MuPDF line, the second row's reading order
+        // interleaved with the first's
+        let mut glyphs = cell("ab", 10.0, 5.0, 0);
+        glyphs.extend(cell("cd", 10.0, 25.0, 1));
+        glyphs.extend(cell("ef", 60.0, 5.0, 2));
+        glyphs.extend(cell("gh", 60.0, 25.0, 3));
+        let sel = select_region(&glyphs, &Rectangle::new(0.0, 0.0, 100.0, 40.0));
+        assert_eq!(sel.text(), "ab\tef\ncd\tgh");
+        assert_eq!(sel.rects.len(), 2, "a rect per row");
+    }
+
+    #[test]
+    fn region_takes_one_column_of_a_two_column_page() {
+        // left column "ab"/"cd", right column "xy"/"zw", read column by column
+        let mut glyphs = cell("ab", 10.0, 5.0, 0);
+        glyphs.extend(cell("cd", 10.0, 25.0, 1));
+        glyphs.extend(cell("xy", 110.0, 5.0, 2));
+        glyphs.extend(cell("zw", 110.0, 25.0, 3));
+        let sel = select_region(&glyphs, &Rectangle::new(100.0, 0.0, 140.0, 40.0));
+        assert_eq!(sel.text(), "xy\nzw");
+    }
+
+    #[test]
+    fn region_keeps_spaces_and_raised_glyphs_on_their_row() {
+        // "a b" with a narrow gap for the space, and a superscript '2' 2pt above the baseline
+        let mut glyphs = vec![glyph('a', 10.0, 5.0, 0), glyph('b', 22.0, 5.0, 0)];
+        glyphs.push(glyph('2', 32.0, 3.0, 0));
+        let sel = select_region(&glyphs, &Rectangle::new(0.0, 0.0, 50.0, 20.0));
+        assert_eq!(sel.text(), "a b2");
+    }
+
+    #[test]
+    fn an_empty_region_selects_nothing() {
+        let glyphs = paragraphs();
+        let sel = select_region(&glyphs, &Rectangle::new(200.0, 200.0, 300.0, 300.0));
+        assert!(sel.text().is_empty() && sel.rects.is_empty());
+    }
+
+    #[test]
+    fn presses_beyond_four_stay_on_blocks() {
+        assert_eq!(Unit::from_n_press(1), Unit::Char);
+        assert_eq!(Unit::from_n_press(2), Unit::Word);
+        assert_eq!(Unit::from_n_press(3), Unit::Line);
+        assert_eq!(Unit::from_n_press(4), Unit::Block);
+        assert_eq!(Unit::from_n_press(7), Unit::Block);
     }
 }
@@ -0,0 +1,83 @@
+// Region snapshots: copy a dragged page area to the clipboard as an image, or save it as a PNG. The
+// area is rendered afresh at the snapshot resolution on its own thread (like a search sweep) rather
+// than cut from the on-screen texture, which is only as sharp as the current zoom.
+
+use futures::channel::oneshot;
+use gtk::glib;
+use gtk::prelude::*;
+
+use crate::page::{Page, Rectangle};
+
+// Smallest area (points per side) worth a snapshot; a click or a twitch of the mouse is not one.
+const MIN_SNAPSHOT_PT: f64 = 2.0;
+
+// Where a finished snapshot goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Clipboard,
+    File,
+}
+
+// Render `region` (page points) of `page`'s page at the snapshot resolution and send it to `target`.
+pub(crate) fn take(page: &Page, region: Rectangle, target: Target) {
+    if region.x2 - region.x1 < MIN_SNAPSHOT_PT || region.y2 - region.y1 < MIN_SNAPSHOT_PT {
+        return;
+    }
+    let state = page.state();
+    let (uri, page_num) = (state.uri(), page.index());
+    let (dpi, dark) = (f64::from(state.snapshot_dpi()), state.snapshot_dark());
+
+    let (tx, rx) = oneshot::channel();
+    crate::bg_job::spawn_document_thread(move || {
+        let rect = (region.x1, region.y1, region.x2, region.y2);
+        let _ = tx.send(crate::backend::render_snapshot(
+            &uri, page_num, rect, dpi, dark,
+        ));
+    });
+
+    let page = page.clone();
+    glib::spawn_future_local(async move {
+        let Ok(Some(px)) = rx.await else {
+            log::warn!("Snapshot of page {} failed", page_num + 1);
+            return;
+        };
+        let texture = crate::page::texture_from_raw(px.data, px.width, px.height, px.stride);
+        match target {
+            Target::Clipboard => page.clipboard().set_texture(&texture),
+            Target::File => save(&page, texture, page_num),
+        }
+    });
+}
+
+// Ask where to save the snapshot, then write it as a PNG.
+fn save(page: &Page, texture: gtk::gdk::MemoryTexture, page_num: i32) {
+    let window = page.root().and_downcast::<crate::window::Window>();
+    let png = gtk::FileFilter::new();
+    png.set_name(Some("PNG image"));
+    png.add_suffix("png");
+    let filters = gtk::gio::ListStore::new::<gtk::FileFilter>();
+    filters.append(&png);
+
+    let dialog = gtk::FileDialog::builder()
+        .title("Save Snapshot")
+        .modal(true)
+        .filters(&filters)
+        .initial_name(format!("page-{}-snapshot.png", page_num + 1))
+        .build();
+
+    dialog.save(
+        window.clone().as_ref(),
+        gtk::gio::Cancellable::NONE,
+        move |file| {
+            // dismissing the dialog is not an error
+            let Some(path) = file.ok().and_then(|file| file.path()) else {
+                return;
+            };
+            if let Err(err) = texture.save_to_png(&path) {
+                if let Some(window) = window {
+                    window.show_error_dialog(&format!("Error saving snapshot: {err}"));
+                }
+            }
+        },
+    );
+}
@@ -8,7 +8,7 @@ use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 
 use crate::jump_stack;
 
@@ -27,12 +27,47 @@ pub struct State {
     // Zoom that the reader selected.
     pub(crate) manual_zoom: Cell<f64>,
 
-    // Tallest paper height in points.
-    pub(crate) tallest_page_height: Cell<f64>,
+    // Tallest paper height in points, of the pages measured so far.
+    #[property(get, set)]
+    tallest_page_height: Cell<f64>,
 
     #[property(get, set)]
     animate_scroll: Cell<bool>,
 
+    // Stretched renders (stand-ins, capped renders) are filtered smoothly; off shows their pixels.
+    #[property(get, set)]
+    smooth_images: Cell<bool>,
+
+    // A primary drag copies the dragged region as an image instead of selecting text.
+    #[property(get, set)]
+    snapshot_mode: Cell<bool>,
+
+    #[property(get, set)]
+    snapshot_dpi: Cell<u32>,
+
+    #[property(get, set)]
+    snapshot_dark: Cell<bool>,
+
+    // A primary drag draws freehand ink (or, with `eraser`, rubs out strokes) instead of selecting
+    // text.
+    #[property(get, set)]
+    pen_mode: Cell<bool>,
+
+    #[property(get, set)]
+    eraser: Cell<bool>,
+
+    // The document can take annotations, ink and form fields and save them (a PDF).
+    #[property(get, set)]
+    annotatable: Cell<bool>,
+
+    // 0xRRGGBB
+    #[property(get, set)]
+    pen_color: Cell<u32>,
+
+    // points
+    #[property(get, set)]
+    pen_width: Cell<f64>,
+
     #[property(get, set)]
     n_pages: Cell<i32>,
 
@@ -58,8 +93,12 @@ pub struct State {
     pub(super) forward_jump_stack: Rc<RefCell<jump_stack::JumpStack>>,
     pub(crate) bbox_cache: Rc<RefCell<HashMap<i32, crate::page::Rectangle>>>,
     pub(crate) links: Rc<RefCell<crate::links::Links>>,
+    pub(crate) annotations: Rc<RefCell<crate::annotations::Annotations>>,
+    pub(crate) forms: Rc<RefCell<crate::forms::Forms>>,
     pub(crate) search: Rc<RefCell<crate::search::Search>>,
-    pub(crate) selection: Rc<RefCell<Option<crate::selection::PageSelection>>>,
+    pub(crate) selection: Rc<RefCell<Option<crate::selection::TextSelection>>>,
+    // annotations made this session and not yet saved into the file
+    pub(crate) edits: Rc<RefCell<crate::markup::Edits>>,
 
     // Whole-page and viewport-region textures, kept so scrolling back reuses rendered pixels
     // instead of re-rendering (and flashing white).
@@ -68,6 +107,9 @@ pub struct State {
     // page at a time: a zoom leaves the entry in place, and the stale render's completion releases it
     // (see Page::schedule_render), so zooming can't stack up buffers for the same page.
     pub(crate) render_inflight: Rc<RefCell<HashMap<i32, u64>>>,
+    // viewport tiles with a render in flight, mapped to the render_epoch they were scheduled at, as
+    // for render_inflight
+    pub(crate) tile_inflight: Rc<RefCell<HashMap<crate::render_cache::TileId, u64>>>,
     // widget currently waiting to display each page, so a finished render repaints the right widget
     // even if list recycling moved the requester
     pub(crate) render_waiters: Rc<RefCell<HashMap<i32, glib::WeakRef<crate::page::Page>>>>,
@@ -86,6 +128,9 @@ pub struct State {
     // render scale for previews, adapted per document toward the time and memory budgets. Defaults
     // to 0.0 (Cell); set to the initial scale in constructed and on load.
     pub(crate) preview_scale: Cell<f64>,
+    // content fingerprint of the document, naming its previews in the on-disk preview store and
+    // vouching for its saved crop boxes; None when the bytes couldn't be read (or in emulate mode)
+    pub(crate) fingerprint: RefCell<Option<String>>,
     // direction of travel, used to prefetch the pages being read toward: true = forward (higher page
     // numbers), the default; flipped when the user scrolls back.
     pub(crate) scroll_forward: Cell<bool>,
@@ -99,11 +144,16 @@ pub struct State {
     // drops out on completion if it changed. Per-State so one window never invalidates another's
     // in-flight renders.
     pub(crate) doc_epoch: Cell<u64>,
+    // bumped per load; the page-height sweep's thread stops once it no longer matches
+    pub(crate) measure_epoch: Arc<AtomicU64>,
 
     // global render-thread count (user setting) and how many pages fully fit across the viewport;
     // together they set prefetch depth. Set in constructed / by the window.
     pub(crate) render_threads: Cell<usize>,
     pub(crate) visible_page_count: Cell<i32>,
+    // how hard the system is pressing for memory: each level halves both cache budgets and the
+    // render threads. 0 normally; set by the window's memory monitor.
+    pub(crate) memory_pressure: Cell<u32>,
 
     // bumped on each load; the async open's completion drops out if it changed, so a load started
     // while an earlier one is still opening supersedes it.
@@ -125,6 +175,23 @@ impl ObjectImpl for State {
         // animated scrolling is on by default; the builder-created instance doesn't run State::new,
         // so set it here
         self.obj().set_animate_scroll(true);
+        self.snapshot_dpi.set(crate::config::DEFAULT_SNAPSHOT_DPI);
+        self.pen_color.set(crate::config::DEFAULT_PEN_COLOR);
+        self.pen_width.set(crate::config::DEFAULT_PEN_WIDTH);
+
+        // a drag can either snapshot or draw, so turning one mode on turns the other off
+        self.obj()
+            .connect_notify_local(Some("pen-mode"), |state, _| {
+                if state.pen_mode() {
+                    state.set_snapshot_mode(false);
+                }
+            });
+        self.obj()
+            .connect_notify_local(Some("snapshot-mode"), |state, _| {
+                if state.snapshot_mode() {
+                    state.set_pen_mode(false);
+                }
+            });
 
         // Previews are tiny; give their cache its own small budget rather than the default
         // (full-render) one. Sized for the default resident-preview count; the window resizes it
@@ -168,6 +235,10 @@ impl ObjectImpl for State {
                 Signal::builder("selection-changed")
                     .param_types([i32::static_type()])
                     .build(),
+                // a page whose unsaved annotations changed and needs repainting
+                Signal::builder("edits-changed")
+                    .param_types([i32::static_type()])
+                    .build(),
             ]
         })
     }
@@ -1,6 +1,7 @@
 // Public state API for document loading, persistence, and rendering coordination.
 mod imp;
-use futures::channel::oneshot;
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt;
 use gtk::gio::prelude::*;
 use gtk::glib;
 use gtk::glib::clone;
@@ -12,6 +13,7 @@ use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 use std::{env, fs};
 
@@ -23,6 +25,9 @@ use crate::page;
 pub(crate) const PREVIEW_TARGET_BYTES: usize = 20 * 1024 * 1024 / 65;
 const MAX_MAIN_THREAD_RENDER_TIME: Duration = Duration::from_millis(100);
 
+// Deepest memory pressure: an eighth of the configured cache budgets.
+pub(crate) const MAX_MEMORY_PRESSURE: u32 = 3;
+
 // Zoom bounds. The same for every document: huge pages are the ones that need deep zoom most.
 // Render buffers are bounded by scale instead (see page::render_scale).
 const MAX_ZOOM: f64 = 10.0;
@@ -43,6 +48,15 @@ pub(crate) fn preview_cache_budget(pages: usize) -> usize {
 
 type TallestPageHeight = Option<f64>;
 
+// A document opened off the main thread, ready to commit: its page count, tallest page and
+// fingerprint.
+type Probed = (
+    crate::mupdf_render::Candidate,
+    i32,
+    TallestPageHeight,
+    Option<String>,
+);
+
 fn document_size_bytes(f: &gtk::gio::File) -> i64 {
     f.query_info(
         "standard::size",
@@ -65,6 +79,7 @@ impl State {
             .property("zoom", 1.0)
             .property("crop", false)
             .property("animate_scroll", true)
+            .property("smooth_images", true)
             .property("page", 0_u32)
             .build()
     }
@@ -103,10 +118,6 @@ impl State {
         }
     }
 
-    pub(crate) fn tallest_page_height(&self) -> f64 {
-        self.imp().tallest_page_height.get()
-    }
-
     pub(crate) fn jump_list_add(&self, page: u32) {
         self.set_prev_page(page);
         self.imp().jump_stack.borrow_mut().push(page);
@@ -167,13 +178,15 @@ impl State {
         // A failed open leaves the current document (and its in-flight render markers) intact,
         // since nothing below the commit runs until the open succeeds. Staging fetches a remote
         // file exactly once; those bytes are the ones committed for rendering - no re-fetch.
-        let (tx, rx) =
-            oneshot::channel::<Option<(crate::mupdf_render::Candidate, i32, TallestPageHeight)>>();
+        let (tx, rx) = oneshot::channel::<Option<Probed>>();
         let uri_probe = uri.clone();
-        std::thread::spawn(move || {
+        crate::bg_job::spawn_document_thread(move || {
             let probed = crate::mupdf_render::stage_candidate(&uri_probe).and_then(|candidate| {
                 match candidate.probe() {
-                    Some((n_pages, tallest)) if n_pages > 0 => Some((candidate, n_pages, tallest)),
+                    Some((n_pages, tallest)) if n_pages > 0 => {
+                        let fingerprint = candidate.fingerprint();
+                        Some((candidate, n_pages, tallest, fingerprint))
+                    }
                     _ => None,
                 }
             });
@@ -188,14 +201,14 @@ impl State {
                 if state.imp().load_seq.get() != seq {
                     return; // a newer load superseded this one
                 }
-                let Some((candidate, n_pages, tallest)) = probed else {
+                let Some((candidate, n_pages, tallest, fingerprint)) = probed else {
                     state.emit_by_name::<()>(
                         "load-failed",
                         &[&"could not open document".to_string()],
                     );
                     return;
                 };
-                state.commit_load(&uri, candidate, n_pages, tallest, size_bytes);
+                state.commit_load(&uri, candidate, n_pages, tallest, fingerprint, size_bytes);
             }
         ));
     }
@@ -206,6 +219,7 @@ impl State {
         candidate: crate::mupdf_render::Candidate,
         n_pages: i32,
         tallest_page_height: TallestPageHeight,
+        fingerprint: Option<String>,
         size_bytes: i64,
     ) {
         // Committed to the new document: force every thread to reopen (the same path may have
@@ -213,6 +227,8 @@ impl State {
         // per-document state.
         // Reloads are process-wide for a URI. Multiple windows displaying the same URI are not
         // version-isolated if that file changes on disk.
+        // The outgoing document's page-height sweep stops first, so it can't reopen the old file.
+        self.imp().measure_epoch.fetch_add(1, Ordering::Relaxed);
         crate::mupdf_render::invalidate();
         candidate.commit();
         // Drop this window's queued renders and wanted-range entry for the outgoing document so they
@@ -226,18 +242,28 @@ impl State {
             .set(self.imp().doc_epoch.get().wrapping_add(1));
         self.imp().bbox_cache.borrow_mut().clear();
         self.imp().links.borrow_mut().clear();
+        self.imp().annotations.borrow_mut().clear();
+        self.imp().forms.borrow_mut().clear();
         self.imp().search.borrow_mut().clear();
         self.imp().selection.replace(None);
+        self.imp().edits.borrow_mut().clear();
         self.imp().render_cache.borrow_mut().clear();
         self.imp().render_inflight.borrow_mut().clear();
+        self.imp().tile_inflight.borrow_mut().clear();
         self.imp().render_waiters.borrow_mut().clear();
         self.imp().preview_cache.borrow_mut().clear();
         self.imp().preview_inflight.borrow_mut().clear();
         self.imp().preview_enabled.set(true);
         self.imp().preview_slow_streak.set(0);
-        self.imp()
-            .preview_scale
-            .set(crate::page::PREVIEW_INITIAL_SCALE);
+        self.imp().preview_scale.set(fingerprint.as_deref().map_or(
+            crate::page::PREVIEW_INITIAL_SCALE,
+            crate::page::stored_preview_scale,
+        ));
+        self.imp().fingerprint.replace(fingerprint);
+        self.set_annotatable(crate::backend::annotatable(uri));
+        if !self.annotatable() {
+            self.set_pen_mode(false);
+        }
 
         self.emit_by_name::<()>("before-load", &[]);
 
@@ -249,9 +275,7 @@ impl State {
         self.set_next_page(0);
         self.set_uri(uri);
         self.set_n_pages(n_pages);
-        self.imp()
-            .tallest_page_height
-            .set(tallest_page_height.unwrap_or(0.0));
+        self.set_tallest_page_height(tallest_page_height.unwrap_or(0.0));
         self.zoom_to(1.0);
         self.set_crop(false);
         self.set_page(0);
@@ -259,7 +283,8 @@ impl State {
         self.set_multithread_rendering(false);
 
         if state_path.exists() {
-            for line in fs::read_to_string(&state_path).unwrap().lines() {
+            let saved = fs::read_to_string(&state_path).unwrap();
+            for line in saved.lines() {
                 match line.split_once('=') {
                     Some(("zoom", value)) => {
                         let zoom = value.parse().unwrap_or(1.0);
@@ -278,7 +303,13 @@ impl State {
                     _ => {}
                 }
             }
+            let fingerprint = self.imp().fingerprint.borrow().clone();
+            if let Some(fingerprint) = fingerprint {
+                *self.imp().bbox_cache.borrow_mut() = saved_crop_boxes(&saved, &fingerprint);
+            }
         }
+        page::sweep_crop_boxes(self);
+        self.measure_pages(crate::mupdf_render::PROBED_PAGES);
 
         log::info!(
             "Loaded document: {n_pages} pages, {size_bytes} bytes, tallest page {tallest_page_height:?} pt, \
@@ -291,6 +322,56 @@ impl State {
         self.emit_by_name::<()>("loaded", &[]);
     }
 
+    // Read the heights of the pages from `first` on, which the probe skipped, off the main thread.
+    // The document is already showing: a page widget sizes itself from the backend when it's bound,
+    // so only the tallest height, and with it the fit, changes as they arrive. A reload drops the
+    // rest.
+    fn measure_pages(&self, first: i32) {
+        let n_pages = self.n_pages();
+        if first >= n_pages {
+            return;
+        }
+        let uri = self.uri();
+        let doc_epoch = self.doc_epoch();
+        let shared_epoch = self.imp().measure_epoch.clone();
+        let epoch = shared_epoch.load(Ordering::Relaxed);
+
+        let (tx, mut rx) = mpsc::unbounded::<f64>();
+        crate::bg_job::spawn_document_thread(move || {
+            let backend = crate::backend::get(&uri);
+            let mut tallest = 0.0;
+            for page_num in first..n_pages {
+                if shared_epoch.load(Ordering::Relaxed) != epoch || crate::bg_job::stopping() {
+                    break; // another document loaded, or quitting
+                }
+                let Some((_, height)) = backend.page_size(&uri, page_num) else {
+                    continue;
+                };
+                if height > tallest {
+                    tallest = height;
+                    if tx.unbounded_send(height).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = state)]
+            self,
+            async move {
+                while let Some(height) = rx.next().await {
+                    if state.doc_epoch() != doc_epoch {
+                        return;
+                    }
+                    if height > state.tallest_page_height() {
+                        state.set_tallest_page_height(height);
+                    }
+                }
+            }
+        ));
+    }
+
     pub fn save(&self) -> io::Result<()> {
         let state_path = get_state_file_path(&self.uri()).unwrap();
         let state_dir = state_path.parent().unwrap();
@@ -308,6 +389,17 @@ impl State {
         writeln!(file, "zoom={}", self.imp().manual_zoom.get())?;
         writeln!(file, "page={}", self.page())?;
         writeln!(file, "crop={}", self.crop())?;
+        // crop boxes hold only for the bytes they were worked out from
+        if let Some(fingerprint) = self.imp().fingerprint.borrow().as_deref() {
+            writeln!(file, "fingerprint={fingerprint}")?;
+            let boxes = self.imp().bbox_cache.borrow();
+            let mut pages: Vec<_> = boxes.keys().copied().collect();
+            pages.sort_unstable();
+            for page in pages {
+                let b = boxes[&page];
+                writeln!(file, "bbox={page}:{},{},{},{}", b.x1, b.y1, b.x2, b.y2)?;
+            }
+        }
 
         file.flush()
     }
@@ -320,19 +412,145 @@ impl State {
         self.imp().search.clone()
     }
 
-    pub(crate) fn selection(&self) -> Rc<RefCell<Option<crate::selection::PageSelection>>> {
+    pub(crate) fn selection(&self) -> Rc<RefCell<Option<crate::selection::TextSelection>>> {
         self.imp().selection.clone()
     }
 
-    // Emits selection-changed for the page losing the highlight and the one gaining it.
-    pub(crate) fn set_selection(&self, selection: Option<crate::selection::PageSelection>) {
-        let page = selection.as_ref().map(|s| s.page);
-        let prev_page = self.imp().selection.replace(selection).map(|s| s.page);
+    pub(crate) fn edits(&self) -> Rc<RefCell<crate::markup::Edits>> {
+        self.imp().edits.clone()
+    }
+
+    // Record one undoable step of new annotations.
+    pub(crate) fn add_edits(&self, step: Vec<crate::markup::Edit>) {
+        let pages: Vec<i32> = step.iter().map(crate::markup::Edit::page).collect();
+        self.imp().edits.borrow_mut().push(step);
+        self.emit_edits_changed(pages);
+    }
+
+    // Record a finished pen stroke as one undoable step.
+    pub(crate) fn add_ink(&self, page: i32, stroke: crate::markup::Stroke) {
+        let id = self.imp().edits.borrow_mut().new_id();
+        self.add_edits(vec![crate::markup::Edit::Ink { page, id, stroke }]);
+    }
+
+    // Rub out the strokes `erased` on `page`, as one undoable step. Saved ink is deleted on save.
+    pub(crate) fn erase_ink(&self, page: i32, erased: &[crate::markup::Erased]) {
+        let step = erased
+            .iter()
+            .map(|&target| crate::markup::Edit::Erase { page, target })
+            .collect();
+        self.add_edits(step);
+    }
+
+    // Mark up the selected text, one annotation per page it spans, and drop the selection so the
+    // markup shows.
+    pub(crate) fn markup_selection(&self, kind: crate::markup::MarkupKind) {
+        let step = self
+            .imp()
+            .selection
+            .borrow()
+            .iter()
+            .flat_map(|selection| &selection.pages)
+            .filter(|page| !page.rects.is_empty())
+            .map(|page| crate::markup::Edit::Markup {
+                page: page.page,
+                kind,
+                rects: page.rects.clone(),
+            })
+            .collect();
+        self.add_edits(step);
+        self.clear_selection();
+    }
+
+    pub(crate) fn undo_edit(&self) {
+        let pages = self.imp().edits.borrow_mut().undo();
+        self.emit_edits_changed(pages.unwrap_or_default());
+    }
+
+    pub(crate) fn redo_edit(&self) {
+        let pages = self.imp().edits.borrow_mut().redo();
+        self.emit_edits_changed(pages.unwrap_or_default());
+    }
+
+    // Whether there is anything for a save to write: unsaved annotations or filled-in form fields.
+    pub(crate) fn has_unsaved_changes(&self) -> bool {
+        !self.imp().edits.borrow().is_empty()
+            || crate::mupdf_render::working_copy(&self.uri()).is_some()
+    }
+
+    pub(crate) fn forms(&self) -> Rc<RefCell<crate::forms::Forms>> {
+        self.imp().forms.clone()
+    }
+
+    // Set a form field on a document thread (the working copy is rewritten, which takes a while on
+    // a big file), then re-render just its page: the other pages' textures stay cached. `done`
+    // gets the outcome, unless another document was loaded meanwhile.
+    pub(crate) fn set_form_field(
+        &self,
+        page: i32,
+        xref: i32,
+        value: &str,
+        done: impl FnOnce(Result<(), String>) + 'static,
+    ) {
+        let uri = self.uri();
+        let value = value.to_string();
+        let (tx, rx) = oneshot::channel();
+        let uri_write = uri.clone();
+        crate::bg_job::spawn_document_thread(move || {
+            let _ = tx.send(crate::forms::set_field(&uri_write, page, xref, &value));
+        });
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = state)]
+            self,
+            async move {
+                let Ok(result) = rx.await else {
+                    return;
+                };
+                if state.uri() != uri {
+                    return;
+                }
+                if result.is_ok() {
+                    state.imp().forms.borrow_mut().clear();
+                    state.invalidate_page(page);
+                }
+                done(result);
+            }
+        ));
+    }
+
+    // Drop a page's rendered textures after its content changed. Bumping render_epoch makes an
+    // in-flight render of the old content drop out instead of caching it.
+    pub(crate) fn invalidate_page(&self, page: i32) {
+        let imp = self.imp();
+        imp.render_cache.borrow_mut().remove_page(page);
+        imp.preview_cache.borrow_mut().remove_page(page);
+        imp.render_epoch.set(imp.render_epoch.get().wrapping_add(1));
+        self.emit_by_name::<()>("edits-changed", &[&page]);
+    }
 
-        if let Some(prev_page) = prev_page.filter(|prev| Some(*prev) != page) {
-            self.emit_by_name::<()>("selection-changed", &[&prev_page]);
+    fn emit_edits_changed(&self, mut pages: Vec<i32>) {
+        pages.sort_unstable();
+        pages.dedup();
+        for page in pages {
+            self.emit_by_name::<()>("edits-changed", &[&page]);
         }
-        if let Some(page) = page {
+    }
+
+    // Emits selection-changed for every page losing, gaining or changing its highlight.
+    pub(crate) fn set_selection(&self, selection: Option<crate::selection::TextSelection>) {
+        let pages_of = |selection: &Option<crate::selection::TextSelection>| -> Vec<i32> {
+            selection
+                .iter()
+                .flat_map(|s| s.pages.iter().map(|p| p.page))
+                .collect()
+        };
+        let mut pages = pages_of(&selection);
+        pages.extend(pages_of(&self.imp().selection.replace(selection)));
+        pages.sort_unstable();
+        pages.dedup();
+
+        for page in pages {
             self.emit_by_name::<()>("selection-changed", &[&page]);
         }
     }
@@ -345,13 +563,24 @@ impl State {
         self.imp().selection.borrow().is_some()
     }
 
-    // Empty text (a drag over no glyphs) reads as nothing selected.
+    // The selection reflowed into paragraphs, as copied by default. Empty text (a drag over no
+    // glyphs) reads as nothing selected.
     pub(crate) fn selected_text(&self) -> Option<String> {
         self.imp()
             .selection
             .borrow()
             .as_ref()
-            .map(|selection| selection.text.clone())
+            .map(crate::selection::TextSelection::text)
+            .filter(|text| !text.is_empty())
+    }
+
+    // The selection with the page's own line breaks, for a raw copy.
+    pub(crate) fn selected_raw_text(&self) -> Option<String> {
+        self.imp()
+            .selection
+            .borrow()
+            .as_ref()
+            .map(crate::selection::TextSelection::raw_text)
             .filter(|text| !text.is_empty())
     }
 
@@ -363,6 +592,10 @@ impl State {
         self.imp().render_inflight.clone()
     }
 
+    pub(crate) fn tile_inflight(&self) -> Rc<RefCell<HashMap<crate::render_cache::TileId, u64>>> {
+        self.imp().tile_inflight.clone()
+    }
+
     pub(crate) fn render_waiters(&self) -> Rc<RefCell<HashMap<i32, glib::WeakRef<page::Page>>>> {
         self.imp().render_waiters.clone()
     }
@@ -397,8 +630,13 @@ impl State {
             .set(self.imp().doc_epoch.get().wrapping_add(1));
         self.imp().render_cache.borrow_mut().clear();
         self.imp().render_inflight.borrow_mut().clear();
+        self.imp().tile_inflight.borrow_mut().clear();
         self.imp().preview_cache.borrow_mut().clear();
         self.imp().preview_inflight.borrow_mut().clear();
+        // clear_all dropped the crop-box sweep too; carry on with the pages it hadn't reached
+        if self.n_pages() > 0 {
+            page::sweep_crop_boxes(self);
+        }
     }
 
     pub(crate) fn set_render_cache_mb(&self, mb: usize) {
@@ -428,6 +666,15 @@ impl State {
         self.imp().preview_slow_streak.set(streak);
     }
 
+    // The document's fingerprint in the on-disk preview store, unless form fields were filled in:
+    // the working copy's pages aren't the document's.
+    pub(crate) fn preview_fingerprint(&self) -> Option<String> {
+        if crate::mupdf_render::working_copy(&self.uri()).is_some() {
+            return None;
+        }
+        self.imp().fingerprint.borrow().clone()
+    }
+
     pub(crate) fn preview_scale(&self) -> f64 {
         self.imp().preview_scale.get()
     }
@@ -444,14 +691,32 @@ impl State {
         self.imp().scroll_forward.set(forward);
     }
 
+    // Render threads in use: the setting, halved per memory-pressure level.
     pub(crate) fn render_threads(&self) -> usize {
-        self.imp().render_threads.get()
+        (self.imp().render_threads.get() >> self.memory_pressure()).max(1)
     }
 
     pub(crate) fn set_render_threads(&self, n: usize) {
         self.imp().render_threads.set(n);
     }
 
+    pub(crate) fn memory_pressure(&self) -> u32 {
+        self.imp().memory_pressure.get()
+    }
+
+    // Shrink the caches and render pool to `pressure` (0 lifts it).
+    pub(crate) fn set_memory_pressure(&self, pressure: u32) {
+        let pressure = pressure.min(MAX_MEMORY_PRESSURE);
+        let previous = self.imp().memory_pressure.replace(pressure);
+        if pressure == previous {
+            return;
+        }
+        log::info!("Memory pressure: {previous} -> {pressure}");
+        self.imp().render_cache.borrow_mut().set_pressure(pressure);
+        self.imp().preview_cache.borrow_mut().set_pressure(pressure);
+        page::set_render_threads(self.render_threads());
+    }
+
     pub(crate) fn visible_page_count(&self) -> i32 {
         self.imp().visible_page_count.get()
     }
@@ -467,6 +732,31 @@ impl Default for State {
     }
 }
 
+// Crop boxes from a saved state file, if they were saved for the document with `fingerprint`.
+fn saved_crop_boxes(saved: &str, fingerprint: &str) -> HashMap<i32, page::Rectangle> {
+    let same_document = saved
+        .lines()
+        .any(|line| line.strip_prefix("fingerprint=") == Some(fingerprint));
+    if !same_document {
+        return HashMap::new();
+    }
+    saved
+        .lines()
+        .filter_map(|line| {
+            let (page, rect) = line.strip_prefix("bbox=")?.split_once(':')?;
+            let coords: Vec<f64> = rect
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .ok()?;
+            let [x1, y1, x2, y2] = coords[..] else {
+                return None;
+            };
+            Some((page.parse().ok()?, page::Rectangle::new(x1, y1, x2, y2)))
+        })
+        .collect()
+}
+
 // Tests open documents, and an open writes the reading position. Redirect the directory per test:
 // a zoom left by one test must not come back in another, nor in the reader's own files.
 #[cfg(test)]
@@ -601,6 +891,35 @@ mod tests {
         assert_eq!(state.zoom(), MIN_ZOOM);
     }
 
+    #[gtk::test]
+    fn crop_boxes_come_back_only_for_the_same_document() {
+        use_scratch_state_dir();
+        let state = State::new();
+        state.set_uri("crop-boxes-test.pdf");
+        state.imp().fingerprint.replace(Some("f00d".to_string()));
+        let boxes = [
+            (0, page::Rectangle::new(5.0, 10.5, 200.0, 300.25)),
+            (7, page::Rectangle::new(0.0, 0.0, 612.0, 792.0)),
+        ];
+        state.bbox_cache().borrow_mut().extend(boxes);
+
+        state.save().unwrap();
+
+        let path = get_state_file_path(&state.uri()).unwrap();
+        let saved = fs::read_to_string(path).unwrap();
+        let restored = saved_crop_boxes(&saved, "f00d");
+        assert_eq!(restored.len(), 2);
+        for (page, bbox) in boxes {
+            let got = restored[&page];
+            assert_eq!(
+                (got.x1, got.y1, got.x2, got.y2),
+                (bbox.x1, bbox.y1, bbox.x2, bbox.y2)
+            );
+        }
+        // the file changed since: its boxes may not fit any more
+        assert!(saved_crop_boxes(&saved, "beef").is_empty());
+    }
+
     #[gtk::test]
     fn fit_zoom_does_not_replace_the_saved_manual_zoom() {
         use_scratch_state_dir();
@@ -632,19 +951,37 @@ mod tests {
         assert!(state.render_cache().borrow().contains_at_scale(3, 1.0));
     }
 
-    fn selection_on(page: i32, text: &str) -> crate::selection::PageSelection {
-        crate::selection::PageSelection {
-            page,
-            rects: vec![page::Rectangle::new(0.0, 0.0, 10.0, 10.0)],
-            text: text.to_string(),
+    fn selection_on(page: i32, text: &str) -> crate::selection::TextSelection {
+        selection_across(&[(page, text)])
+    }
+
+    fn selection_across(parts: &[(i32, &str)]) -> crate::selection::TextSelection {
+        crate::selection::TextSelection {
+            pages: parts
+                .iter()
+                .map(|&(page, text)| crate::selection::PageSelection {
+                    page,
+                    rects: vec![page::Rectangle::new(0.0, 0.0, 10.0, 10.0)],
+                    lines: vec![crate::selection::Line {
+                        text: text.to_string(),
+                        block_end: true,
+                    }],
+                })
+                .collect(),
+            anchor: (parts[0].0, (0.0, 0.0)),
+            region: None,
         }
     }
 
     // Pages announced for repaint, in order.
     fn watch_repaints(state: &State) -> Rc<RefCell<Vec<i32>>> {
+        watch_pages(state, "selection-changed")
+    }
+
+    fn watch_pages(state: &State, signal: &str) -> Rc<RefCell<Vec<i32>>> {
         let repainted = Rc::new(RefCell::new(Vec::new()));
         state.connect_closure(
-            "selection-changed",
+            signal,
             false,
             glib::closure_local!(
                 #[strong]
@@ -667,7 +1004,11 @@ mod tests {
 
         // page 3 loses the highlight, page 7 gains it
         state.set_selection(Some(selection_on(7, "second")));
-        assert_eq!(state.selection().borrow().as_ref().map(|s| s.page), Some(7));
+        assert!(state
+            .selection()
+            .borrow()
+            .as_ref()
+            .is_some_and(|s| s.on_page(7).is_some() && s.on_page(3).is_none()));
         assert_eq!(state.selected_text().as_deref(), Some("second"));
         assert_eq!(*repainted.borrow(), vec![3, 3, 7]);
 
@@ -677,6 +1018,31 @@ mod tests {
         assert_eq!(*repainted.borrow(), vec![7]);
     }
 
+    #[gtk::test]
+    fn marking_up_a_selection_is_one_undoable_step() {
+        let state = State::new();
+        state.set_selection(Some(selection_across(&[(2, "ends a"), (3, "sentence")])));
+        let changed = watch_pages(&state, "edits-changed");
+
+        state.markup_selection(crate::markup::MarkupKind::Highlight);
+        assert!(!state.has_selection());
+        assert_eq!(state.edits().borrow().pending().count(), 2);
+        assert_eq!(*changed.borrow(), vec![2, 3]);
+
+        changed.borrow_mut().clear();
+        state.undo_edit();
+        assert!(state.edits().borrow().is_empty());
+        assert_eq!(*changed.borrow(), vec![2, 3]);
+
+        // nothing left to undo: silent
+        changed.borrow_mut().clear();
+        state.undo_edit();
+        assert!(changed.borrow().is_empty());
+
+        state.redo_edit();
+        assert_eq!(state.edits().borrow().pending().count(), 2);
+    }
+
     #[gtk::test]
     fn clearing_repaints_the_page_that_held_the_selection() {
         let state = State::new();
@@ -694,6 +1060,29 @@ mod tests {
         assert!(repainted.borrow().is_empty());
     }
 
+    #[gtk::test]
+    fn a_selection_across_pages_repaints_each_and_copies_as_one() {
+        let state = State::new();
+        let repainted = watch_repaints(&state);
+
+        state.set_selection(Some(selection_across(&[(2, "ends a"), (3, "sentence")])));
+        assert_eq!(state.selected_text().as_deref(), Some("ends a sentence"));
+        assert_eq!(
+            state.selected_raw_text().as_deref(),
+            Some("ends a\nsentence")
+        );
+        assert_eq!(*repainted.borrow(), vec![2, 3]);
+
+        // the drag moves on to page 4: every page involved before or after repaints once
+        repainted.borrow_mut().clear();
+        state.set_selection(Some(selection_across(&[
+            (2, "ends a"),
+            (3, "sentence"),
+            (4, "x"),
+        ])));
+        assert_eq!(*repainted.borrow(), vec![2, 3, 4]);
+    }
+
     #[gtk::test]
     fn an_empty_selection_has_no_text_to_copy() {
         let state = State::new();
@@ -15,7 +15,8 @@ use gtk::{
 };
 use gtk::{prelude::*, GestureClick};
 
-use crate::page;
+use crate::markup::MarkupKind;
+use crate::page::{self, descendant_page};
 use crate::state::State;
 
 // Time constant of the exponential glide toward the target page position. Larger = slower and
@@ -56,6 +57,11 @@ const ZOOM_STEP: f64 = 1.1;
 // Quiet period after the last keystroke before a search sweep launches, coalescing a burst of typing.
 const SEARCH_DEBOUNCE_MS: u64 = 100;
 
+// How often memory pressure is re-checked (the optional RSS limit, recovery), and how long it must
+// stay quiet before the caches grow back by one level.
+const MEMORY_CHECK_SECS: u32 = 5;
+const MEMORY_RELAX_AFTER: std::time::Duration = std::time::Duration::from_secs(30);
+
 // In-flight state of the animated one-page slide.
 //
 // The end position is recomputed live each tick from the selected page widget's actual geometry, so
@@ -125,6 +131,18 @@ pub struct Window {
     #[template_child]
     pub spin_cache: TemplateChild<gtk::SpinButton>,
     #[template_child]
+    pub spin_graphics_aa: TemplateChild<gtk::SpinButton>,
+    #[template_child]
+    pub spin_text_aa: TemplateChild<gtk::SpinButton>,
+    #[template_child]
+    pub spin_grey_gamma: TemplateChild<gtk::SpinButton>,
+    #[template_child]
+    pub spin_snapshot_dpi: TemplateChild<gtk::SpinButton>,
+    #[template_child]
+    pub btn_pen_color: TemplateChild<gtk::ColorDialogButton>,
+    #[template_child]
+    pub spin_pen_width: TemplateChild<gtk::SpinButton>,
+    #[template_child]
     pub btn_jump_back: TemplateChild<Button>,
     #[template_child]
     pub btn_jump_forward: TemplateChild<Button>,
@@ -155,6 +173,18 @@ pub struct Window {
     #[template_child]
     pub toc_list: TemplateChild<gtk::ListBox>,
     #[template_child]
+    pub btn_comments: TemplateChild<ToggleButton>,
+    #[template_child]
+    pub comments_revealer: TemplateChild<gtk::Revealer>,
+    #[template_child]
+    pub comments_list: TemplateChild<gtk::ListBox>,
+    #[template_child]
+    pub btn_attachments: TemplateChild<ToggleButton>,
+    #[template_child]
+    pub attachments_revealer: TemplateChild<gtk::Revealer>,
+    #[template_child]
+    pub attachments_list: TemplateChild<gtk::ListBox>,
+    #[template_child]
     pub empty_view: TemplateChild<gtk::Box>,
     #[template_child]
     pub loading_overlay: TemplateChild<gtk::Box>,
@@ -163,6 +193,22 @@ pub struct Window {
 
     // target page per outline row (index-aligned), None for non-navigable entries
     toc_pages: RefCell<Vec<Option<i32>>>,
+    // 1-based page per comments row (index-aligned)
+    comment_pages: RefCell<Vec<i32>>,
+    // the comments list is filled on first reveal (it loads every page's annotations), not per load
+    comments_stale: Cell<bool>,
+    // bumped per comments listing and per load, so only the latest listing's result is shown
+    comments_listing: Cell<u64>,
+    // likewise the attachments list, which walks every page's annotations too
+    attachments_stale: Cell<bool>,
+    // bumped per attachments listing and per load, so only the latest listing's result is shown
+    attachments_listing: Cell<u64>,
+    // set once the user has saved or discarded unsaved changes, so the close that follows goes
+    // through instead of asking again
+    close_confirmed: Cell<bool>,
+
+    // printer and options from the last print dialog, offered again next time
+    print_settings: RefCell<Option<gtk::PrintSettings>>,
 
     // set while a re-search is queued, to coalesce keystrokes into one sweep
     search_debounce: RefCell<Option<glib::SourceId>>,
@@ -231,6 +277,12 @@ pub struct Window {
 
     // Vertical list-row padding in logical pixels.
     fit_chrome_height: Cell<Option<f64>>,
+
+    // held so its low-memory-warning handler stays connected
+    memory_monitor: RefCell<Option<gtk::gio::MemoryMonitor>>,
+    // when memory pressure was last raised or stepped down; the next step down waits for
+    // MEMORY_RELAX_AFTER past it
+    memory_pressure_at: Cell<Option<std::time::Instant>>,
 }
 
 // A document point held still across a zoom: which page, where in it (page points from its
@@ -281,11 +333,17 @@ impl ObjectImpl for Window {
         self.setup_cache_setting();
         let cfg = crate::config::load_config();
         self.state.set_preview_cache_pages(cfg.preview_cache_pages);
+        self.setup_memory_pressure(cfg.rss_limit_mb);
         self.setup_animate_scroll();
+        self.setup_render_quality();
+        self.setup_snapshot_settings();
+        self.setup_pen_settings();
         self.setup_fit_height();
         self.setup_text_selection();
         self.setup_search();
         self.setup_toc();
+        self.setup_comments();
+        self.setup_attachments();
         self.setup_drop_target();
 
         // Give keyboard focus to the scroll area rather than the header entry
@@ -296,13 +354,21 @@ impl ObjectImpl for Window {
             scrolledwindow.grab_focus();
         });
 
-        // Drop this window's render-pool state when it closes, so its entries don't linger.
+        // Drop this window's render-pool state when it closes, so its entries don't linger. Unsaved
+        // changes hold the close until the user saves or discards them.
         self.obj().connect_close_request(clone!(
             #[weak(rename_to = imp)]
             self,
             #[upgrade_or]
             glib::Propagation::Proceed,
             move |_| {
+                if !imp.close_confirmed.get() && imp.state.has_unsaved_changes() {
+                    imp.confirm_unsaved_changes(|imp| {
+                        imp.close_confirmed.set(true);
+                        imp.obj().close();
+                    });
+                    return glib::Propagation::Stop;
+                }
                 let client = imp.state.render_client_id();
                 crate::page::clear_all_renders(client);
                 crate::page::set_wanted_pages(client, None);
@@ -940,23 +1006,72 @@ impl Window {
         modifier: ModifierType,
     ) -> glib::Propagation {
         match keyval {
-            Key::c
+            Key::c | Key::C
                 if modifier.contains(ModifierType::CONTROL_MASK) && self.state.has_selection() =>
             {
-                self.copy_selection();
+                self.copy_selection(modifier.contains(ModifierType::SHIFT_MASK));
             }
             Key::Escape if self.state.has_selection() => {
                 self.state.clear_selection();
             }
+            Key::Escape if self.state.snapshot_mode() => {
+                self.state.set_snapshot_mode(false);
+            }
+            Key::Escape if self.state.pen_mode() => {
+                self.state.set_pen_mode(false);
+            }
+            Key::p | Key::P if modifier.contains(ModifierType::CONTROL_MASK) => {
+                self.print_document();
+            }
+            Key::s | Key::S if modifier.contains(ModifierType::CONTROL_MASK) => {
+                self.save_edits(modifier.contains(ModifierType::SHIFT_MASK));
+            }
+            Key::z | Key::Z if modifier.contains(ModifierType::CONTROL_MASK) => {
+                if modifier.contains(ModifierType::SHIFT_MASK) {
+                    self.state.redo_edit();
+                } else {
+                    self.state.undo_edit();
+                }
+            }
+            Key::y if modifier.contains(ModifierType::CONTROL_MASK) => {
+                self.state.redo_edit();
+            }
+            Key::H if self.state.has_selection() && self.state.annotatable() => {
+                self.state.markup_selection(MarkupKind::Highlight);
+            }
+            Key::U if self.state.has_selection() && self.state.annotatable() => {
+                self.state.markup_selection(MarkupKind::Underline);
+            }
+            Key::S if self.state.has_selection() && self.state.annotatable() => {
+                self.state.markup_selection(MarkupKind::StrikeOut);
+            }
+            Key::s => {
+                self.state.set_snapshot_mode(!self.state.snapshot_mode());
+            }
+            Key::p if self.state.annotatable() => {
+                self.state.set_pen_mode(!self.state.pen_mode());
+            }
+            Key::e if self.state.pen_mode() => {
+                self.state.set_eraser(!self.state.eraser());
+            }
             Key::o => {
                 self.open_document();
             }
+            Key::O => {
+                self.open_folder();
+            }
             Key::t => {
                 if self.btn_toc.is_sensitive() {
                     self.toc_revealer
                         .set_reveal_child(!self.toc_revealer.reveals_child());
                 }
             }
+            Key::a => {
+                if self.btn_comments.is_sensitive() {
+                    self.comments_revealer
+                        .set_reveal_child(!self.comments_revealer.reveals_child());
+                }
+            }
             Key::f => {
                 self.open_search();
             }
@@ -1039,9 +1154,116 @@ impl Window {
         glib::Propagation::Stop
     }
 
-    // The only writer of the clipboard; a drag publishes to the primary selection instead.
-    fn copy_selection(&self) {
-        if let Some(text) = self.state.selected_text() {
+    // Write this session's annotations into the document (or, with `save_as`, into a copy chosen
+    // in a dialog), then reload it so MuPDF paints what was saved.
+    fn save_edits(&self, save_as: bool) {
+        if self.state.n_pages() == 0 || !self.state.annotatable() {
+            return;
+        }
+        let current = gtk::gio::File::for_uri(&self.state.uri());
+        if !save_as {
+            if self.state.has_unsaved_changes() {
+                self.write_edits(current.clone(), move |imp| imp.state.load(&current));
+            }
+            return;
+        }
+
+        let pdf = gtk::FileFilter::new();
+        pdf.set_name(Some("PDF document"));
+        pdf.add_suffix("pdf");
+        let filters = gtk::gio::ListStore::new::<gtk::FileFilter>();
+        filters.append(&pdf);
+        let dialog = gtk::FileDialog::builder()
+            .title("Save As")
+            .modal(true)
+            .filters(&filters)
+            .initial_file(&current)
+            .build();
+
+        let obj = self.obj();
+        dialog.save(
+            Some(obj.as_ref()),
+            gtk::gio::Cancellable::NONE,
+            clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |file| {
+                    // dismissing the dialog is not an error
+                    if let Ok(file) = file {
+                        imp.write_edits(file.clone(), move |imp| imp.state.load(&file));
+                    }
+                }
+            ),
+        );
+    }
+
+    // Write the pending edits into `dest` off the main thread, then run `then` if that worked.
+    fn write_edits(&self, dest: gtk::gio::File, then: impl FnOnce(&Self) + 'static) {
+        let uri = self.state.uri();
+        let edits: Vec<_> = self.state.edits().borrow().pending().cloned().collect();
+        let dest_uri = dest.uri();
+        let (tx, rx) = futures::channel::oneshot::channel();
+        crate::bg_job::spawn_document_thread(move || {
+            let dest = gtk::gio::File::for_uri(&dest_uri);
+            let _ = tx.send(crate::markup::save(&uri, &edits, &dest));
+        });
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            async move {
+                match rx.await {
+                    Ok(Ok(())) => then(&imp),
+                    Ok(Err(err)) => imp
+                        .obj()
+                        .show_error_dialog(&format!("Error saving changes: {err}")),
+                    Err(_) => {}
+                }
+            }
+        ));
+    }
+
+    // Run `then` once unsaved annotations and form fields are taken care of: straight away if there
+    // are none, else after the user chooses to save or discard them. Cancel runs nothing.
+    fn confirm_unsaved_changes(&self, then: impl FnOnce(&Self) + 'static) {
+        if !self.state.has_unsaved_changes() {
+            then(self);
+            return;
+        }
+        gtk::AlertDialog::builder()
+            .message("Save changes to the document?")
+            .detail("Annotations and filled-in form fields that aren't saved will be lost.")
+            .buttons(["Cancel", "Discard", "Save"])
+            .default_button(2)
+            .cancel_button(0)
+            .build()
+            .choose(
+                Some(&*self.obj()),
+                gtk::gio::Cancellable::NONE,
+                clone!(
+                    #[weak(rename_to = imp)]
+                    self,
+                    move |result| match result {
+                        Ok(1) => then(&imp),
+                        Ok(2) => {
+                            let current = gtk::gio::File::for_uri(&imp.state.uri());
+                            imp.write_edits(current, then);
+                        }
+                        _ => {}
+                    }
+                ),
+            );
+    }
+
+    // Copy the selected text to the clipboard (a drag publishes it to the primary selection
+    // instead): reflowed paragraphs, or the lines as laid out on the page when `raw` (Ctrl+Shift+C).
+    fn copy_selection(&self, raw: bool) {
+        let text = if raw {
+            self.state.selected_raw_text()
+        } else {
+            self.state.selected_text()
+        };
+        if let Some(text) = text {
             self.obj().clipboard().set_text(&text);
         }
     }
@@ -1531,6 +1753,416 @@ impl Window {
         self.scrolledwindow.add_controller(click);
     }
 
+    // An open list is refilled rather than closed: saving annotations reloads the document, and the
+    // list should show what was saved.
+    fn reset_comments(&self) {
+        self.comments_list.remove_all();
+        self.comment_pages.borrow_mut().clear();
+        self.comments_stale.set(true);
+        self.comments_listing
+            .set(self.comments_listing.get().wrapping_add(1));
+        self.btn_comments.set_sensitive(true);
+        if self.comments_revealer.reveals_child() {
+            self.populate_comments();
+        }
+    }
+
+    // Reads the comments on a thread of their own, as that loads every page's annotations.
+    fn populate_comments(&self) {
+        self.comments_list.remove_all();
+        self.comment_pages.borrow_mut().clear();
+        self.comments_stale.set(false);
+        let listing = self.comments_listing.get().wrapping_add(1);
+        self.comments_listing.set(listing);
+
+        let uri = self.state.uri();
+        let (tx, rx) = futures::channel::oneshot::channel();
+        crate::bg_job::spawn_document_thread(move || {
+            let _ = tx.send(crate::annotations::comments(&uri));
+        });
+        let obj = self.obj().clone();
+        glib::spawn_future_local(async move {
+            let Ok(comments) = rx.await else {
+                return;
+            };
+            let imp = obj.imp();
+            if imp.comments_listing.get() == listing {
+                imp.show_comments(&comments);
+            }
+        });
+    }
+
+    fn show_comments(&self, comments: &[crate::annotations::Annotation]) {
+        let mut pages = Vec::with_capacity(comments.len());
+        for comment in comments {
+            let page = gtk::Label::new(Some(&format!("Page {}", comment.page + 1)));
+            page.set_xalign(0.0);
+            page.add_css_class("heading");
+            let thread = crate::annotations::comment_widget(comment);
+            thread.prepend(&page);
+            thread.set_margin_start(8);
+            thread.set_margin_end(8);
+            thread.set_margin_top(6);
+            thread.set_margin_bottom(6);
+            let row = gtk::ListBoxRow::new();
+            row.set_child(Some(&thread));
+            self.comments_list.append(&row);
+            pages.push(comment.page + 1);
+        }
+        self.comment_pages.replace(pages);
+    }
+
+    #[template_callback]
+    fn comment_row_activated(&self, row: &gtk::ListBoxRow) {
+        let idx = row.index();
+        let page = if idx >= 0 {
+            self.comment_pages.borrow().get(idx as usize).copied()
+        } else {
+            None
+        };
+        if let Some(page) = page {
+            self.goto_page(page as u32);
+        }
+        self.comments_revealer.set_reveal_child(false);
+    }
+
+    // Same focus and dismissal rules as the contents panel (see setup_toc).
+    fn setup_comments(&self) {
+        self.comments_revealer.connect_reveal_child_notify(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |rev| {
+                if rev.reveals_child() {
+                    // the two right-hand panels share the edge
+                    imp.attachments_revealer.set_reveal_child(false);
+                    if imp.comments_stale.get() {
+                        imp.populate_comments();
+                    }
+                    imp.comments_list.grab_focus();
+                } else {
+                    imp.scrolledwindow.grab_focus();
+                }
+            }
+        ));
+
+        let key = gtk::EventControllerKey::new();
+        key.connect_key_pressed(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            #[upgrade_or]
+            glib::Propagation::Proceed,
+            move |_, keyval, _, _| {
+                if keyval == Key::Escape || keyval == Key::a {
+                    imp.comments_revealer.set_reveal_child(false);
+                    glib::Propagation::Stop
+                } else {
+                    glib::Propagation::Proceed
+                }
+            }
+        ));
+        self.comments_revealer.add_controller(key);
+
+        let click = gtk::GestureClick::new();
+        click.set_propagation_phase(gtk::PropagationPhase::Capture);
+        click.connect_pressed(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |gesture, _, _, _| {
+                if imp.comments_revealer.reveals_child() {
+                    imp.comments_revealer.set_reveal_child(false);
+                    gesture.set_state(gtk::EventSequenceState::Claimed);
+                }
+            }
+        ));
+        self.scrolledwindow.add_controller(click);
+    }
+
+    fn reset_attachments(&self) {
+        self.attachments_list.remove_all();
+        self.attachments_stale.set(true);
+        self.attachments_listing
+            .set(self.attachments_listing.get().wrapping_add(1));
+        self.btn_attachments.set_sensitive(true);
+        self.attachments_revealer.set_reveal_child(false);
+    }
+
+    // Lists the attachments on a thread of their own, as finding them loads every page.
+    fn populate_attachments(&self) {
+        self.attachments_list.remove_all();
+        self.attachments_stale.set(false);
+        let listing = self.attachments_listing.get().wrapping_add(1);
+        self.attachments_listing.set(listing);
+
+        let uri = self.state.uri();
+        let (tx, rx) = futures::channel::oneshot::channel();
+        crate::bg_job::spawn_document_thread(move || {
+            let _ = tx.send(crate::attachments::list(&uri));
+        });
+        let obj = self.obj().clone();
+        glib::spawn_future_local(async move {
+            let Ok(attachments) = rx.await else {
+                return;
+            };
+            let imp = obj.imp();
+            if imp.attachments_listing.get() == listing {
+                imp.show_attachments(attachments);
+            }
+        });
+    }
+
+    fn show_attachments(&self, attachments: Vec<crate::attachments::Attachment>) {
+        for attachment in attachments {
+            let entry = gtk::Box::new(gtk::Orientation::Vertical, 4);
+            entry.set_margin_start(8);
+            entry.set_margin_end(8);
+            entry.set_margin_top(6);
+            entry.set_margin_bottom(6);
+
+            let name = gtk::Label::new(Some(&attachment.name));
+            name.set_xalign(0.0);
+            name.set_ellipsize(gtk::pango::EllipsizeMode::Middle);
+            name.set_tooltip_text(Some(&attachment.name));
+            name.add_css_class("heading");
+            entry.append(&name);
+
+            let mut details = Vec::new();
+            if let Some(size) = attachment.size {
+                details.push(glib::format_size(size).to_string());
+            }
+            if let Some(page) = attachment.page {
+                details.push(format!("Page {page}"));
+            }
+            if !details.is_empty() {
+                let label = gtk::Label::new(Some(&details.join(" \u{b7} ")));
+                label.set_xalign(0.0);
+                label.add_css_class("dim-label");
+                entry.append(&label);
+            }
+            if !attachment.description.is_empty() {
+                let label = gtk::Label::new(Some(&attachment.description));
+                label.set_xalign(0.0);
+                label.set_wrap(true);
+                label.set_wrap_mode(gtk::pango::WrapMode::WordChar);
+                entry.append(&label);
+            }
+
+            let buttons = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            let save = gtk::Button::with_label("Save As\u{2026}");
+            let open = gtk::Button::with_label("Open");
+            save.connect_clicked(clone!(
+                #[weak(rename_to = imp)]
+                self,
+                #[strong]
+                attachment,
+                move |_| imp.save_attachment(&attachment)
+            ));
+            open.connect_clicked(clone!(
+                #[weak(rename_to = imp)]
+                self,
+                #[strong]
+                attachment,
+                move |_| imp.open_attachment(&attachment)
+            ));
+            buttons.append(&save);
+            buttons.append(&open);
+            entry.append(&buttons);
+
+            let row = gtk::ListBoxRow::new();
+            row.set_child(Some(&entry));
+            row.set_activatable(false);
+            self.attachments_list.append(&row);
+        }
+    }
+
+    // The attachment's bytes, read off the main thread.
+    async fn attachment_contents(
+        &self,
+        attachment: &crate::attachments::Attachment,
+    ) -> Result<Vec<u8>, String> {
+        let uri = self.state.uri();
+        let attachment = attachment.clone();
+        let (tx, rx) = futures::channel::oneshot::channel();
+        crate::bg_job::spawn_document_thread(move || {
+            let _ = tx.send(crate::attachments::contents(&uri, &attachment));
+        });
+        rx.await.map_err(|err| err.to_string())?
+    }
+
+    // A copy of the attachment for another application, written off the main thread.
+    async fn attachment_copy(
+        &self,
+        attachment: &crate::attachments::Attachment,
+    ) -> Result<std::path::PathBuf, String> {
+        let uri = self.state.uri();
+        let attachment = attachment.clone();
+        let (tx, rx) = futures::channel::oneshot::channel();
+        crate::bg_job::spawn_document_thread(move || {
+            let _ = tx.send(crate::attachments::open_copy(&uri, &attachment));
+        });
+        rx.await.map_err(|err| err.to_string())?
+    }
+
+    fn save_attachment(&self, attachment: &crate::attachments::Attachment) {
+        let dialog = gtk::FileDialog::builder()
+            .title("Save Attachment")
+            .modal(true)
+            .initial_name(attachment.name.as_str())
+            .build();
+        let obj = self.obj();
+        dialog.save(
+            Some(obj.as_ref()),
+            gtk::gio::Cancellable::NONE,
+            clone!(
+                #[strong]
+                obj,
+                #[strong]
+                attachment,
+                move |file| {
+                    // dismissing the dialog is not an error
+                    let Ok(file) = file else {
+                        return;
+                    };
+                    glib::spawn_future_local(async move {
+                        let saved =
+                            obj.imp()
+                                .attachment_contents(&attachment)
+                                .await
+                                .and_then(|data| {
+                                    file.replace_contents(
+                                        &data,
+                                        None,
+                                        false,
+                                        gtk::gio::FileCreateFlags::NONE,
+                                        gtk::gio::Cancellable::NONE,
+                                    )
+                                    .map(|_| ())
+                                    .map_err(|err| err.to_string())
+                                });
+                        if let Err(err) = saved {
+                            obj.show_error_dialog(&format!("Error saving attachment: {err}"));
+                        }
+                    });
+                }
+            ),
+        );
+    }
+
+    // Hands the attachment to the desktop's default application for its type, once the user
+    // confirms. Programs, scripts and launchers are refused (see attachments::is_runnable). The copy
+    // is written off the main thread and removed at quit.
+    fn open_attachment(&self, attachment: &crate::attachments::Attachment) {
+        let obj = self.obj().clone();
+        if crate::attachments::is_runnable(&attachment.name, &[]) {
+            obj.show_error_dialog(&format!(
+                "\"{}\" could run as a program, so it isn't opened. Save it instead.",
+                attachment.name
+            ));
+            return;
+        }
+        let attachment = attachment.clone();
+        gtk::AlertDialog::builder()
+            .message(format!("Open \u{201c}{}\u{201d}?", attachment.name))
+            .detail(
+                "It opens in the application your desktop uses for its type. Attachments come \
+                 with the document and may be harmful; open only those you trust.",
+            )
+            .buttons(["Cancel", "Open"])
+            .default_button(0)
+            .cancel_button(0)
+            .build()
+            .choose(
+                Some(&obj),
+                gtk::gio::Cancellable::NONE,
+                clone!(
+                    #[strong]
+                    obj,
+                    move |result| {
+                        if matches!(result, Ok(1)) {
+                            obj.imp().launch_attachment(attachment);
+                        }
+                    }
+                ),
+            );
+    }
+
+    fn launch_attachment(&self, attachment: crate::attachments::Attachment) {
+        let obj = self.obj().clone();
+        glib::spawn_future_local(async move {
+            let path = match obj.imp().attachment_copy(&attachment).await {
+                Ok(path) => path,
+                Err(err) => {
+                    obj.show_error_dialog(&format!("Error opening attachment: {err}"));
+                    return;
+                }
+            };
+            let launcher = gtk::FileLauncher::new(Some(&gtk::gio::File::for_path(&path)));
+            launcher.launch(
+                Some(&obj),
+                gtk::gio::Cancellable::NONE,
+                clone!(
+                    #[strong]
+                    obj,
+                    move |result| {
+                        if let Err(err) = result {
+                            obj.show_error_dialog(&format!("Error opening attachment: {err}"));
+                        }
+                    }
+                ),
+            );
+        });
+    }
+
+    // Same focus and dismissal rules as the contents panel (see setup_toc).
+    fn setup_attachments(&self) {
+        self.attachments_revealer
+            .connect_reveal_child_notify(clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |rev| {
+                    if rev.reveals_child() {
+                        imp.comments_revealer.set_reveal_child(false);
+                        if imp.attachments_stale.get() {
+                            imp.populate_attachments();
+                        }
+                        imp.attachments_list.grab_focus();
+                    } else {
+                        imp.scrolledwindow.grab_focus();
+                    }
+                }
+            ));
+
+        let key = gtk::EventControllerKey::new();
+        key.connect_key_pressed(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            #[upgrade_or]
+            glib::Propagation::Proceed,
+            move |_, keyval, _, _| {
+                if keyval == Key::Escape {
+                    imp.attachments_revealer.set_reveal_child(false);
+                    glib::Propagation::Stop
+                } else {
+                    glib::Propagation::Proceed
+                }
+            }
+        ));
+        self.attachments_revealer.add_controller(key);
+
+        let click = gtk::GestureClick::new();
+        click.set_propagation_phase(gtk::PropagationPhase::Capture);
+        click.connect_pressed(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |gesture, _, _, _| {
+                if imp.attachments_revealer.reveals_child() {
+                    imp.attachments_revealer.set_reveal_child(false);
+                    gesture.set_state(gtk::EventSequenceState::Claimed);
+                }
+            }
+        ));
+        self.scrolledwindow.add_controller(click);
+    }
+
     fn setup_drop_target(&self) {
         let drop_target = gtk::DropTarget::new(
             gtk::gdk::FileList::static_type(),
@@ -1550,7 +2182,8 @@ impl Window {
                     return false;
                 };
 
-                imp.state.load(&file);
+                // A dropped folder loads like a file: its images become the pages.
+                imp.confirm_unsaved_changes(move |imp| imp.state.load(&file));
                 true
             }
         ));
@@ -1561,8 +2194,9 @@ impl Window {
     #[template_callback]
     fn open_document(&self) {
         const SUPPORTED_SUFFIXES: &[&str] = &[
-            "pdf", "xps", "oxps", "epub", "mobi", "fb2", "cbz", "svg", "txt", "png", "jpg", "jpeg",
-            "jp2", "jpx", "gif", "tif", "tiff", "bmp", "pnm", "pgm", "ppm", "pbm", "pam",
+            "pdf", "xps", "oxps", "epub", "mobi", "fb2", "cbz", "cbr", "cb7", "cbt", "djvu", "djv",
+            "svg", "txt", "png", "jpg", "jpeg", "jp2", "jpx", "gif", "tif", "tiff", "bmp", "pnm",
+            "pgm", "ppm", "pbm", "pam",
         ];
         let supported = gtk::FileFilter::new();
         supported.set_name(Some("Supported documents"));
@@ -1588,20 +2222,56 @@ impl Window {
             Some(obj.as_ref()),
             gtk::gio::Cancellable::NONE,
             clone!(
-                #[strong(rename_to = state)]
-                self.state,
-                #[strong]
-                obj,
+                #[weak(rename_to = imp)]
+                self,
                 move |file| match file {
-                    Ok(file) => state.load(&file),
+                    Ok(file) => imp.confirm_unsaved_changes(move |imp| imp.state.load(&file)),
                     Err(err) => {
-                        obj.show_error_dialog(&format!("Error opening file: {err}"));
+                        imp.obj()
+                            .show_error_dialog(&format!("Error opening file: {err}"));
                     }
                 },
             ),
         );
     }
 
+    // A folder of images opens as a document with one image per page.
+    fn open_folder(&self) {
+        let dialog = gtk::FileDialog::builder()
+            .title("Open Folder")
+            .modal(true)
+            .build();
+
+        let obj = self.obj();
+        dialog.select_folder(
+            Some(obj.as_ref()),
+            gtk::gio::Cancellable::NONE,
+            clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |folder| {
+                    // dismissing the dialog is not an error
+                    if let Ok(folder) = folder {
+                        imp.confirm_unsaved_changes(move |imp| imp.state.load(&folder));
+                    }
+                }
+            ),
+        );
+    }
+
+    // The document's own title if it gives one, else its file name.
+    fn update_title(&self) {
+        let uri = self.state.uri();
+        let name = crate::properties::title(&uri).or_else(|| {
+            gtk::gio::File::for_uri(&uri)
+                .basename()
+                .map(|name| name.to_string_lossy().into_owned())
+        });
+        if let Some(name) = name {
+            self.obj().set_title(Some(&format!("{name} - Scrolex")));
+        }
+    }
+
     #[template_callback]
     fn on_load_started(&self) {
         self.cancel_scroll_motion();
@@ -1630,7 +2300,10 @@ impl Window {
             return;
         }
 
+        self.update_title();
         self.populate_toc();
+        self.reset_comments();
+        self.reset_attachments();
 
         let model = self.model.clone();
         let selection = self.selection.clone();
@@ -1830,35 +2503,187 @@ impl Window {
                         page.queue_draw();
                     }
                 }
-            }
-            child = c.next_sibling();
+            }
+            child = c.next_sibling();
+        }
+        let range = if lo <= hi {
+            let margin = self.state.render_threads() as i32 + 4;
+            Some((lo - margin, hi + margin))
+        } else {
+            None
+        };
+        crate::page::set_wanted_pages(self.state.render_client_id(), range);
+    }
+
+    // Load the render-thread setting into the spin button and pool, and persist any user change.
+    fn setup_thread_setting(&self) {
+        let max = crate::config::max_render_threads();
+        let threads = crate::config::load_config().render_threads;
+        self.spin_threads.set_range(1.0, max as f64);
+        self.spin_threads.set_increments(1.0, 1.0);
+        self.spin_threads.set_value(threads as f64);
+        self.apply_render_threads(threads);
+
+        self.spin_threads.connect_value_changed(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |spin| {
+                let n = spin.value() as usize;
+                imp.apply_render_threads(n);
+                let mut config = crate::config::load_config();
+                config.render_threads = n;
+                if let Err(e) = crate::config::save_config(&config) {
+                    eprintln!("Error saving config: {e}");
+                }
+            }
+        ));
+    }
+
+    fn setup_cache_setting(&self) {
+        let mb = crate::config::load_config().render_cache_mb;
+        self.spin_cache.set_range(
+            crate::config::MIN_RENDER_CACHE_MB as f64,
+            crate::config::MAX_RENDER_CACHE_MB as f64,
+        );
+        self.spin_cache.set_increments(32.0, 64.0);
+        self.spin_cache.set_value(mb as f64);
+        self.state.set_render_cache_mb(mb);
+
+        self.spin_cache.connect_value_changed(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |spin| {
+                let mb = spin.value() as usize;
+                imp.state.set_render_cache_mb(mb);
+                let mut config = crate::config::load_config();
+                config.render_cache_mb = mb;
+                if let Err(e) = crate::config::save_config(&config) {
+                    eprintln!("Error saving config: {e}");
+                }
+            }
+        ));
+    }
+
+    fn setup_animate_scroll(&self) {
+        self.state
+            .set_animate_scroll(crate::config::load_config().animate_scroll);
+
+        self.state
+            .connect_notify_local(Some("animate-scroll"), |state, _| {
+                let mut config = crate::config::load_config();
+                config.animate_scroll = state.animate_scroll();
+                if let Err(e) = crate::config::save_config(&config) {
+                    eprintln!("Error saving config: {e}");
+                }
+            });
+    }
+
+    // Load the anti-aliasing, text darkening and smoothing settings into the renderer, the state and
+    // the menu's pickers. A change persists and drops every window's rendered pages.
+    fn setup_render_quality(&self) {
+        let cfg = crate::config::load_config();
+        crate::mupdf_render::set_render_quality(cfg.render_quality());
+        self.state.set_smooth_images(cfg.smooth_images);
+        let bits = f64::from(crate::config::MAX_AA_BITS);
+        for (spin, value) in [
+            (&self.spin_graphics_aa, cfg.graphics_aa),
+            (&self.spin_text_aa, cfg.text_aa),
+        ] {
+            spin.set_range(0.0, bits);
+            spin.set_value(f64::from(value));
+        }
+        self.spin_grey_gamma
+            .set_range(crate::config::MIN_GREY_GAMMA, crate::config::MAX_GREY_GAMMA);
+        self.spin_grey_gamma.set_value(cfg.grey_gamma);
+
+        for spin in [
+            &self.spin_graphics_aa,
+            &self.spin_text_aa,
+            &self.spin_grey_gamma,
+        ] {
+            spin.connect_value_changed(clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_| imp.apply_render_quality()
+            ));
+        }
+
+        // only how renders are drawn changes, not the renders
+        self.state.connect_notify_local(
+            Some("smooth-images"),
+            clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |state, _| {
+                    imp.redraw_pages();
+                    let mut config = crate::config::load_config();
+                    config.smooth_images = state.smooth_images();
+                    if let Err(e) = crate::config::save_config(&config) {
+                        eprintln!("Error saving config: {e}");
+                    }
+                }
+            ),
+        );
+    }
+
+    fn apply_render_quality(&self) {
+        let mut config = crate::config::load_config();
+        config.graphics_aa = self.spin_graphics_aa.value() as i32;
+        config.text_aa = self.spin_text_aa.value() as i32;
+        config.grey_gamma = self.spin_grey_gamma.value();
+        let quality = config.render_quality();
+        if quality == crate::mupdf_render::render_quality() {
+            return;
         }
-        let range = if lo <= hi {
-            let margin = self.state.render_threads() as i32 + 4;
-            Some((lo - margin, hi + margin))
-        } else {
-            None
+        log::info!("Render quality: {quality:?}");
+        crate::mupdf_render::set_render_quality(quality);
+        if let Err(e) = crate::config::save_config(&config) {
+            eprintln!("Error saving config: {e}");
+        }
+
+        // The setting is the process's: every window's pages were drawn with the old one.
+        let Some(app) = self.obj().application() else {
+            self.obj().rerender();
+            return;
         };
-        crate::page::set_wanted_pages(self.state.render_client_id(), range);
+        for window in app.windows() {
+            if let Ok(window) = window.downcast::<crate::window::Window>() {
+                window.rerender();
+            }
+        }
     }
 
-    // Load the render-thread setting into the spin button and pool, and persist any user change.
-    fn setup_thread_setting(&self) {
-        let max = crate::config::max_render_threads();
-        let threads = crate::config::load_config().render_threads;
-        self.spin_threads.set_range(1.0, max as f64);
-        self.spin_threads.set_increments(1.0, 1.0);
-        self.spin_threads.set_value(threads as f64);
-        self.apply_render_threads(threads);
-
-        self.spin_threads.connect_value_changed(clone!(
+    // Load the pen colour and width into the state and the menu's pickers, and persist any user
+    // change.
+    fn setup_pen_settings(&self) {
+        let cfg = crate::config::load_config();
+        self.state.set_pen_color(cfg.pen_color);
+        self.state.set_pen_width(cfg.pen_width);
+        self.btn_pen_color.set_rgba(&rgba_from_hex(cfg.pen_color));
+        self.spin_pen_width
+            .set_range(crate::config::MIN_PEN_WIDTH, crate::config::MAX_PEN_WIDTH);
+        self.spin_pen_width.set_value(cfg.pen_width);
+
+        self.btn_pen_color.connect_rgba_notify(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |button| {
+                let color = hex_from_rgba(&button.rgba());
+                imp.state.set_pen_color(color);
+                let mut config = crate::config::load_config();
+                config.pen_color = color;
+                if let Err(e) = crate::config::save_config(&config) {
+                    eprintln!("Error saving config: {e}");
+                }
+            }
+        ));
+        self.spin_pen_width.connect_value_changed(clone!(
             #[weak(rename_to = imp)]
             self,
             move |spin| {
-                let n = spin.value() as usize;
-                imp.apply_render_threads(n);
+                imp.state.set_pen_width(spin.value());
                 let mut config = crate::config::load_config();
-                config.render_threads = n;
+                config.pen_width = spin.value();
                 if let Err(e) = crate::config::save_config(&config) {
                     eprintln!("Error saving config: {e}");
                 }
@@ -1866,39 +2691,36 @@ impl Window {
         ));
     }
 
-    fn setup_cache_setting(&self) {
-        let mb = crate::config::load_config().render_cache_mb;
-        self.spin_cache.set_range(
-            crate::config::MIN_RENDER_CACHE_MB as f64,
-            crate::config::MAX_RENDER_CACHE_MB as f64,
+    // Load the snapshot resolution and recolouring into the state, and persist any user change.
+    fn setup_snapshot_settings(&self) {
+        let cfg = crate::config::load_config();
+        self.state.set_snapshot_dpi(cfg.snapshot_dpi);
+        self.state.set_snapshot_dark(cfg.snapshot_dark);
+        self.spin_snapshot_dpi.set_range(
+            f64::from(crate::config::MIN_SNAPSHOT_DPI),
+            f64::from(crate::config::MAX_SNAPSHOT_DPI),
         );
-        self.spin_cache.set_increments(32.0, 64.0);
-        self.spin_cache.set_value(mb as f64);
-        self.state.set_render_cache_mb(mb);
+        self.spin_snapshot_dpi
+            .set_value(f64::from(cfg.snapshot_dpi));
 
-        self.spin_cache.connect_value_changed(clone!(
+        self.spin_snapshot_dpi.connect_value_changed(clone!(
             #[weak(rename_to = imp)]
             self,
             move |spin| {
-                let mb = spin.value() as usize;
-                imp.state.set_render_cache_mb(mb);
+                let dpi = spin.value() as u32;
+                imp.state.set_snapshot_dpi(dpi);
                 let mut config = crate::config::load_config();
-                config.render_cache_mb = mb;
+                config.snapshot_dpi = dpi;
                 if let Err(e) = crate::config::save_config(&config) {
                     eprintln!("Error saving config: {e}");
                 }
             }
         ));
-    }
-
-    fn setup_animate_scroll(&self) {
-        self.state
-            .set_animate_scroll(crate::config::load_config().animate_scroll);
 
         self.state
-            .connect_notify_local(Some("animate-scroll"), |state, _| {
+            .connect_notify_local(Some("snapshot-dark"), |state, _| {
                 let mut config = crate::config::load_config();
-                config.animate_scroll = state.animate_scroll();
+                config.snapshot_dark = state.snapshot_dark();
                 if let Err(e) = crate::config::save_config(&config) {
                     eprintln!("Error saving config: {e}");
                 }
@@ -1929,6 +2751,17 @@ impl Window {
                 self,
                 move |_| imp.queue_fit_height()
             ));
+
+        // A taller page measured after the document showed (see State::measure_pages). The fit
+        // holds the page in view where it is.
+        self.state.connect_notify_local(
+            Some("tallest-page-height"),
+            clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_, _| imp.queue_fit_height()
+            ),
+        );
     }
 
     fn setup_text_selection(&self) {
@@ -1942,15 +2775,29 @@ impl Window {
                 move |_: &State, page: i32| imp.redraw_page(page)
             ),
         );
+        self.state.connect_closure(
+            "edits-changed",
+            false,
+            closure_local!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_: &State, page: i32| imp.redraw_page(page)
+            ),
+        );
 
         // Pages clear the selection themselves (see Page::setup_text_selection); this covers the
         // margins and gaps. Primary button only: right-click keeps the selection, middle pans.
+        // Shift+click extends the selection, so it must survive to reach the page.
         let click = gtk::GestureClick::builder().button(BUTTON_PRIMARY).build();
         click.set_propagation_phase(gtk::PropagationPhase::Capture);
         click.connect_pressed(clone!(
             #[weak(rename_to = imp)]
             self,
-            move |_, _, _, _| imp.state.clear_selection()
+            move |gc, _, _, _| {
+                if !gc.current_event_state().contains(ModifierType::SHIFT_MASK) {
+                    imp.state.clear_selection();
+                }
+            }
         ));
         self.scrolledwindow.add_controller(click);
     }
@@ -1958,7 +2805,69 @@ impl Window {
     fn apply_render_threads(&self, n: usize) {
         log::info!("Render threads: {n}");
         self.state.set_render_threads(n);
-        crate::page::set_render_threads(n);
+        crate::page::set_render_threads(self.state.render_threads());
+    }
+
+    // Shrink the caches and render threads when the system warns it is low on memory, or when our
+    // resident size passes the configured limit; grow them back a level at a time once it has been
+    // quiet for a while.
+    fn setup_memory_pressure(&self, rss_limit_mb: Option<usize>) {
+        // tests may run without a system bus for the monitor to talk to
+        if !cfg!(test) {
+            let monitor = gtk::gio::MemoryMonitor::dup_default();
+            monitor.connect_low_memory_warning(clone!(
+                #[weak(rename_to = imp)]
+                self,
+                move |_, level| imp.raise_memory_pressure(warning_pressure(level))
+            ));
+            self.memory_monitor.replace(Some(monitor));
+        }
+
+        glib::timeout_add_seconds_local(
+            MEMORY_CHECK_SECS,
+            clone!(
+                #[weak(rename_to = imp)]
+                self,
+                #[upgrade_or]
+                glib::ControlFlow::Break,
+                move || {
+                    imp.check_memory_pressure(rss_limit_mb);
+                    glib::ControlFlow::Continue
+                }
+            ),
+        );
+    }
+
+    // Every warning sheds the off-screen tiles, even one that doesn't raise the level: they are the
+    // biggest textures, and a page shows its whole-page texture while they render again.
+    fn raise_memory_pressure(&self, pressure: u32) {
+        self.memory_pressure_at.set(Some(std::time::Instant::now()));
+        self.state
+            .set_memory_pressure(pressure.max(self.state.memory_pressure()));
+        self.state.render_cache().borrow_mut().drop_tiles();
+    }
+
+    fn check_memory_pressure(&self, rss_limit_mb: Option<usize>) {
+        let pressure = self.state.memory_pressure();
+        if let Some(limit) = rss_limit_mb {
+            let rss = page::current_rss_mb();
+            if rss > limit as f64 {
+                log::debug!("memory: rss={rss:.0}MB over the {limit}MB limit");
+                self.raise_memory_pressure(pressure + 1);
+                return;
+            }
+        }
+        if pressure == 0 {
+            return;
+        }
+        let quiet = self
+            .memory_pressure_at
+            .get()
+            .is_none_or(|at| at.elapsed() >= MEMORY_RELAX_AFTER);
+        if quiet {
+            self.state.set_memory_pressure(pressure - 1);
+            self.memory_pressure_at.set(Some(std::time::Instant::now()));
+        }
     }
 
     // Count pages that fit fully across the viewport width; prefetch depth is derived from it.
@@ -2140,6 +3049,252 @@ impl Window {
         self.open_search();
     }
 
+    #[template_callback]
+    fn menu_open_folder(&self, btn: &Button) {
+        dismiss_menu(btn);
+        self.open_folder();
+    }
+
+    // Asks whether to export the current page or all of them, then where to.
+    #[template_callback]
+    fn menu_export_text(&self, btn: &Button) {
+        dismiss_menu(btn);
+        let n_pages = self.state.n_pages();
+        if n_pages == 0 {
+            return;
+        }
+        let current = self.state.page() as i32 + 1;
+        gtk::AlertDialog::builder()
+            .message("Export Text")
+            .detail(format!(
+                "Export the text of page {current}, or of all {n_pages} pages?"
+            ))
+            .buttons(["Cancel", "Current Page", "All Pages"])
+            .default_button(2)
+            .cancel_button(0)
+            .build()
+            .choose(
+                Some(&*self.obj()),
+                gtk::gio::Cancellable::NONE,
+                clone!(
+                    #[weak(rename_to = imp)]
+                    self,
+                    move |result| match result {
+                        Ok(1) => imp.export_text(vec![current]),
+                        Ok(2) => imp.export_text((1..=n_pages).collect()),
+                        _ => {}
+                    }
+                ),
+            );
+    }
+
+    // Writes the text of `pages` (1-based) to a file chosen in a dialog.
+    fn export_text(&self, pages: Vec<i32>) {
+        let text = gtk::FileFilter::new();
+        text.set_name(Some("Plain Text"));
+        text.add_suffix("txt");
+        let markdown = gtk::FileFilter::new();
+        markdown.set_name(Some("Markdown"));
+        markdown.add_suffix("md");
+        let filters = gtk::gio::ListStore::new::<gtk::FileFilter>();
+        filters.append(&text);
+        filters.append(&markdown);
+        let mut title = self
+            .document_title()
+            .unwrap_or_else(|| "Document".to_string());
+        if let [page] = pages[..] {
+            title.push_str(&format!(" p{page}"));
+        }
+        let dialog = gtk::FileDialog::builder()
+            .title("Export Text")
+            .modal(true)
+            .filters(&filters)
+            .initial_name(format!("{title}.txt"))
+            .build();
+
+        let uri = self.state.uri();
+        let obj = self.obj();
+        dialog.save(
+            Some(obj.as_ref()),
+            gtk::gio::Cancellable::NONE,
+            clone!(
+                #[strong]
+                obj,
+                move |file| {
+                    // dismissing the dialog is not an error
+                    let Ok(file) = file else {
+                        return;
+                    };
+                    // a .md file gets the outline's titles as headings
+                    let markdown = file
+                        .basename()
+                        .is_some_and(|name| crate::export::is_markdown(&name.to_string_lossy()));
+                    let (tx, rx) = futures::channel::oneshot::channel();
+                    crate::bg_job::spawn_document_thread(move || {
+                        let _ = tx.send(crate::export::document_text(&uri, &pages, markdown).0);
+                    });
+                    glib::spawn_future_local(async move {
+                        let Ok(text) = rx.await else {
+                            return;
+                        };
+                        if let Err(err) = file.replace_contents(
+                            text.as_bytes(),
+                            None,
+                            false,
+                            gtk::gio::FileCreateFlags::NONE,
+                            gtk::gio::Cancellable::NONE,
+                        ) {
+                            obj.show_error_dialog(&format!("Error exporting text: {err}"));
+                        }
+                    });
+                }
+            ),
+        );
+    }
+
+    #[template_callback]
+    fn menu_export_notes(&self, btn: &Button) {
+        dismiss_menu(btn);
+        if self.state.n_pages() == 0 {
+            return;
+        }
+        let title = self.notes_title();
+        let markdown = gtk::FileFilter::new();
+        markdown.set_name(Some("Markdown"));
+        markdown.add_suffix("md");
+        let filters = gtk::gio::ListStore::new::<gtk::FileFilter>();
+        filters.append(&markdown);
+        let dialog = gtk::FileDialog::builder()
+            .title("Export Notes")
+            .modal(true)
+            .filters(&filters)
+            .initial_name(format!("{title} notes.md"))
+            .build();
+
+        let obj = self.obj();
+        dialog.save(
+            Some(obj.as_ref()),
+            gtk::gio::Cancellable::NONE,
+            clone!(
+                #[strong]
+                obj,
+                move |file| {
+                    // dismissing the dialog is not an error
+                    let Ok(file) = file else {
+                        return;
+                    };
+                    glib::spawn_future_local(async move {
+                        let Some(notes) = obj.imp().notes_markdown(title).await else {
+                            return;
+                        };
+                        if let Err(err) = file.replace_contents(
+                            notes.as_bytes(),
+                            None,
+                            false,
+                            gtk::gio::FileCreateFlags::NONE,
+                            gtk::gio::Cancellable::NONE,
+                        ) {
+                            obj.show_error_dialog(&format!("Error exporting notes: {err}"));
+                        }
+                    });
+                }
+            ),
+        );
+    }
+
+    #[template_callback]
+    fn menu_copy_notes(&self, btn: &Button) {
+        dismiss_menu(btn);
+        if self.state.n_pages() == 0 {
+            return;
+        }
+        let obj = self.obj().clone();
+        let title = self.notes_title();
+        glib::spawn_future_local(async move {
+            if let Some(notes) = obj.imp().notes_markdown(title).await {
+                obj.clipboard().set_text(&notes);
+            }
+        });
+    }
+
+    // The title the open document's notes are headed with.
+    fn notes_title(&self) -> String {
+        self.document_title().unwrap_or_else(|| "Notes".to_string())
+    }
+
+    // The open document's notes as Markdown, unsaved highlights and notes included, put together
+    // off the main thread as they read every page.
+    async fn notes_markdown(&self, title: String) -> Option<String> {
+        let uri = self.state.uri();
+        let pending: Vec<_> = self.state.edits().borrow().pending().cloned().collect();
+        let (tx, rx) = futures::channel::oneshot::channel();
+        crate::bg_job::spawn_document_thread(move || {
+            let _ = tx.send(crate::notes::markdown(&uri, &title, &pending));
+        });
+        rx.await.ok()
+    }
+
+    // The open document's file name without its extension.
+    fn document_title(&self) -> Option<String> {
+        gtk::gio::File::for_uri(&self.state.uri())
+            .basename()
+            .and_then(|name| {
+                name.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            })
+    }
+
+    #[template_callback]
+    fn menu_print(&self, btn: &Button) {
+        dismiss_menu(btn);
+        self.print_document();
+    }
+
+    fn print_document(&self) {
+        if self.state.n_pages() == 0 {
+            return;
+        }
+        let layout = crate::print::Layout {
+            fit: true,
+            crop: self.state.crop(),
+        };
+        let title = self
+            .document_title()
+            .unwrap_or_else(|| "Document".to_string());
+        let pending = self.state.edits().borrow().pending().cloned().collect();
+        let op = crate::print::operation(
+            &self.state.uri(),
+            &title,
+            self.state.page() as i32,
+            layout,
+            pending,
+        );
+        // the last dialog's printer and options, for this session
+        op.set_print_settings(self.print_settings.borrow().as_ref());
+        op.set_allow_async(true);
+        op.connect_done(clone!(
+            #[weak(rename_to = imp)]
+            self,
+            move |op, result| {
+                if result == gtk::PrintOperationResult::Apply {
+                    imp.print_settings.replace(op.print_settings());
+                }
+            }
+        ));
+        if let Err(err) = op.run(gtk::PrintOperationAction::PrintDialog, Some(&*self.obj())) {
+            self.obj()
+                .show_error_dialog(&format!("Error printing: {err}"));
+        }
+    }
+
+    #[template_callback]
+    fn menu_properties(&self, btn: &Button) {
+        dismiss_menu(btn);
+        if self.state.n_pages() > 0 {
+            crate::properties::present(self.obj().upcast_ref(), &self.state.uri());
+        }
+    }
+
     #[template_callback]
     fn menu_about(&self, btn: &Button) {
         dismiss_menu(btn);
@@ -2590,11 +3745,33 @@ fn anchored_scroll(value: f64, origin: f64, screen: f64, offset: f64, zoom: f64)
     value - (screen - offset * zoom - origin)
 }
 
+// Pressure level for a low-memory warning: a level short of the deepest for anything milder than
+// critical, so a critical warning still has room to cut further.
+fn warning_pressure(level: gtk::gio::MemoryMonitorWarningLevel) -> u32 {
+    use gtk::gio::MemoryMonitorWarningLevel as Level;
+    match level {
+        Level::Low => 1,
+        Level::Medium => 2,
+        _ => crate::state::MAX_MEMORY_PRESSURE,
+    }
+}
+
 // Zoom as a percent for the entry, at most two decimals so that it fully fits into entry input
 fn zoom_percent_text(zoom: f64) -> String {
     format!("{}", (zoom * 10_000.0).round() / 100.0)
 }
 
+// Pen colour (0xRRGGBB) to and from the colour picker's RGBA.
+fn rgba_from_hex(color: u32) -> gtk::gdk::RGBA {
+    let channel = |shift: u32| ((color >> shift) & 0xff) as f32 / 255.0;
+    gtk::gdk::RGBA::new(channel(16), channel(8), channel(0), 1.0)
+}
+
+fn hex_from_rgba(rgba: &gtk::gdk::RGBA) -> u32 {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u32;
+    (channel(rgba.red()) << 16) | (channel(rgba.green()) << 8) | channel(rgba.blue())
+}
+
 // Height the overlay scrollbar covers at the bottom of the viewport. Measured, not allocated: GTK
 // drops the allocation of an overlay scrollbar while it conceals it, and a fit that reads the
 // allocation moves with that.
@@ -2621,26 +3798,11 @@ fn dismiss_menu(btn: &Button) {
     }
 }
 
-// Find the Page widget within a list item's widget subtree.
-fn descendant_page(widget: &gtk::Widget) -> Option<page::Page> {
-    if let Some(page) = widget.downcast_ref::<page::Page>() {
-        return Some(page.clone());
-    }
-    let mut child = widget.first_child();
-    while let Some(c) = child {
-        if let Some(page) = descendant_page(&c) {
-            return Some(page);
-        }
-        child = c.next_sibling();
-    }
-    None
-}
-
 #[cfg(test)]
 mod tests {
     use super::{
-        accumulate_step, glide_step, kinetic_step, KINETIC_TAU_US, SCROLL_ANIM_MAX_US,
-        SCROLL_ANIM_TAU_US, WHEEL_NOTCH, WHEEL_TRIGGER,
+        accumulate_step, glide_step, hex_from_rgba, kinetic_step, rgba_from_hex, KINETIC_TAU_US,
+        SCROLL_ANIM_MAX_US, SCROLL_ANIM_TAU_US, WHEEL_NOTCH, WHEEL_TRIGGER,
     };
 
     // Drive the glide toward a fixed target at a steady frame rate; return frames until it settles.
@@ -2917,11 +4079,21 @@ mod tests {
             .collect();
         assert_eq!(fired, vec![0, 4, 8, 12]);
     }
+
+    #[test]
+    fn pen_colors_round_trip_through_rgba() {
+        for color in [0x00_00_00, 0xe0_1b_24, 0x33_66_99, 0xff_ff_ff] {
+            assert_eq!(hex_from_rgba(&rgba_from_hex(color)), color);
+        }
+    }
 }
 
 #[cfg(test)]
 mod widget_tests {
-    use super::{anchored_scroll, zoom_percent_text, KINETIC_MIN_VELOCITY};
+    use super::{
+        anchored_scroll, warning_pressure, zoom_percent_text, KINETIC_MIN_VELOCITY,
+        MEMORY_RELAX_AFTER,
+    };
     use gtk::prelude::*;
     use gtk::subclass::prelude::ObjectSubclassIsExt;
     use std::time::{Duration, Instant};
@@ -2970,6 +4142,54 @@ mod widget_tests {
         }
     }
 
+    #[gtk::test]
+    fn memory_pressure_shrinks_the_caches_and_recovers_a_level_at_a_time() {
+        let window = window();
+        let imp = window.imp();
+        let state = &imp.state;
+        state.set_render_threads(4);
+        let budget = state.render_cache().borrow().budget_bytes();
+        let preview_budget = state.preview_cache().borrow().budget_bytes();
+
+        imp.raise_memory_pressure(warning_pressure(
+            gtk::gio::MemoryMonitorWarningLevel::Medium,
+        ));
+        assert_eq!(state.memory_pressure(), 2);
+        assert_eq!(state.render_cache().borrow().budget_bytes(), budget / 4);
+        assert_eq!(
+            state.preview_cache().borrow().budget_bytes(),
+            preview_budget / 4
+        );
+        assert_eq!(state.render_threads(), 1);
+
+        // a milder warning doesn't undo a deeper one
+        imp.raise_memory_pressure(warning_pressure(gtk::gio::MemoryMonitorWarningLevel::Low));
+        assert_eq!(state.memory_pressure(), 2);
+
+        // still within the quiet period
+        imp.check_memory_pressure(None);
+        assert_eq!(state.memory_pressure(), 2);
+
+        for expected in [1, 0] {
+            imp.memory_pressure_at
+                .set(Some(Instant::now() - MEMORY_RELAX_AFTER));
+            imp.check_memory_pressure(None);
+            assert_eq!(state.memory_pressure(), expected);
+        }
+        assert_eq!(state.render_cache().borrow().budget_bytes(), budget);
+        assert_eq!(
+            state.preview_cache().borrow().budget_bytes(),
+            preview_budget
+        );
+        assert_eq!(state.render_threads(), 4);
+
+        // a resident size over the limit counts as a warning one level deeper
+        imp.check_memory_pressure(Some(0));
+        assert_eq!(state.memory_pressure(), 1);
+
+        window.close();
+    }
+
     #[gtk::test]
     fn empty_view_follows_document_state() {
         let window = window();
@@ -3247,6 +4467,32 @@ mod widget_tests {
 3 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>\nendobj\n\
 4 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 2000 3000] >>\nendobj\n\
 5 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 400 400] >>\nendobj\n\
+trailer\n<< /Root 1 0 R >>\n%%EOF";
+
+    // Twenty pages, the last one much taller than the first pages that are read before it shows.
+    const TALL_LAST_PAGE_PDF: &[u8] = b"%PDF-1.4\n\
+1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+2 0 obj\n<< /Type /Pages /Kids [3 0 R 4 0 R 5 0 R 6 0 R 7 0 R 8 0 R 9 0 R 10 0 R 11 0 R 12 0 R 13 0 R 14 0 R 15 0 R 16 0 R 17 0 R 18 0 R 19 0 R 20 0 R 21 0 R 22 0 R] /Count 20 /MediaBox [0 0 500 200] >>\nendobj\n\
+3 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+4 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+5 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+6 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+7 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+8 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+9 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+10 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+11 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+12 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+13 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+14 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+15 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+16 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+17 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+18 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+19 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+20 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+21 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+22 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 900] >>\nendobj\n\
 trailer\n<< /Root 1 0 R >>\n%%EOF";
 
     const ONE_PAGE_PDF: &[u8] = b"%PDF-1.4\n\
@@ -3264,6 +4510,15 @@ trailer\n<< /Root 1 0 R >>\n%%EOF";
         gtk::gio::File::for_path(path)
     }
 
+    fn tall_last_page_document() -> gtk::gio::File {
+        let dir = std::env::temp_dir().join("scrolex_fit_height_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tall_last_page.pdf");
+        std::fs::write(&path, TALL_LAST_PAGE_PDF).unwrap();
+
+        gtk::gio::File::for_path(path)
+    }
+
     fn one_page_document() -> gtk::gio::File {
         let dir = std::env::temp_dir().join("scrolex_fit_height_test");
         std::fs::create_dir_all(&dir).unwrap();
@@ -3539,6 +4794,30 @@ trailer\n<< /Root 1 0 R >>\n%%EOF";
         window.close();
     }
 
+    // The document shows before its last page is measured; once it is, the fit follows it and the
+    // first page stays in view.
+    #[gtk::test]
+    fn a_taller_page_measured_after_load_refits() {
+        let window = window();
+        window.present();
+        let imp = window.imp();
+        imp.btn_fit_height.set_active(true);
+
+        window.state().load(&tall_last_page_document());
+        wait_until(|| imp.selection.n_items() == 20);
+        wait_until(|| imp.state.tallest_page_height() == 900.0);
+        wait_until(|| !imp.fit_pending.get() && imp.mapped_page(0).is_some());
+
+        let fit = imp.fit_height_zoom().expect("a fit zoom");
+        assert!(
+            (imp.state.zoom() - fit).abs() < 1e-9,
+            "zoom {} is not the fit to the 900pt page, {fit}",
+            imp.state.zoom(),
+        );
+        assert_eq!(imp.state.page(), 0);
+        window.close();
+    }
+
     // Issue #53: a live icon that does nothing reads as broken. Covers the binding wiring;
     // page_jump_enabled_only_where_the_jump_moves covers the decision.
     #[gtk::test]
@@ -33,6 +33,11 @@ impl Window {
         } else {
             self.remove_css_class("dark-mode");
         }
+        self.rerender();
+    }
+
+    // Drop the rendered pages and draw them again, after a setting that changes how they look.
+    pub(crate) fn rerender(&self) {
         self.state().invalidate_rendering();
         self.imp().redraw_pages();
     }